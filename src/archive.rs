@@ -0,0 +1,236 @@
+//! Check source release archives instead of a repository.
+//!
+//! The archive is extracted to a temporary directory with the timestamps of
+//! its entries, which the years are taken from with `mtime_fallback`. The
+//! fixed files can be packed into a new archive of the same kind.
+
+use crate::fs::{modified_time, set_modified_time, walk_files};
+use crate::CError;
+use chrono::{Datelike, NaiveDate, Timelike, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Kind of an archive, detected from its file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let filename = path.file_name()?.to_str()?.to_lowercase();
+        if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if filename.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if filename.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Archive extracted to a temporary directory, which is removed on drop.
+#[derive(Debug)]
+pub struct ExtractedArchive {
+    dir: PathBuf,
+}
+
+impl ExtractedArchive {
+    /// Extract the archive with the timestamps of its entries.
+    pub fn extract(archive_path: &Path) -> Result<Self, CError> {
+        let format = archive_format(archive_path)?;
+        let dir =
+            std::env::temp_dir().join(format!("git_copyright_archive_{}", std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::create_dir_all(&dir)?;
+        let extracted = ExtractedArchive { dir };
+
+        let file = File::open(archive_path)
+            .map_err(|_| CError::ReadError(archive_path.display().to_string()))?;
+        match format {
+            ArchiveFormat::Tar => tar::Archive::new(file).unpack(&extracted.dir)?,
+            ArchiveFormat::TarGz => {
+                tar::Archive::new(GzDecoder::new(file)).unpack(&extracted.dir)?
+            }
+            ArchiveFormat::Zip => extract_zip(file, &extracted.dir)?,
+        }
+        Ok(extracted)
+    }
+
+    /// Directory the archive is extracted to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Pack the extracted files into a new archive, e.g. after fixing them.
+    pub fn pack(&self, archive_path: &Path) -> Result<(), CError> {
+        let format = archive_format(archive_path)?;
+        let file = File::create(archive_path)
+            .map_err(|_| CError::WriteError(archive_path.display().to_string()))?;
+        let files = walk_files(&self.dir)?;
+        match format {
+            ArchiveFormat::Tar => {
+                pack_tar(file, &self.dir, &files)?;
+            }
+            ArchiveFormat::TarGz => {
+                let encoder = pack_tar(
+                    GzEncoder::new(file, Compression::default()),
+                    &self.dir,
+                    &files,
+                )?;
+                encoder.finish()?;
+            }
+            ArchiveFormat::Zip => pack_zip(file, &self.dir, &files)?,
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ExtractedArchive {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            log::warn!("Could not remove {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+fn archive_format(archive_path: &Path) -> Result<ArchiveFormat, CError> {
+    ArchiveFormat::from_path(archive_path).ok_or_else(|| {
+        CError::ConfigError(format!(
+            "Unknown archive format of {}, expected .tar, .tar.gz, .tgz or .zip",
+            archive_path.display()
+        ))
+    })
+}
+
+fn extract_zip(file: File, dir: &Path) -> Result<(), CError> {
+    let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+    for idx in 0..archive.len() {
+        let mut entry = archive.by_index(idx).map_err(zip_error)?;
+        // Skip entries which would be extracted outside of the directory
+        let filepath = match entry.enclosed_name() {
+            Some(name) => dir.join(name),
+            None => continue,
+        };
+        if entry.is_dir() {
+            std::fs::create_dir_all(&filepath)?;
+            continue;
+        }
+
+        if let Some(parent) = filepath.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut entry, &mut File::create(&filepath)?)?;
+        if let Some(time) = zip_to_system_time(entry.last_modified()) {
+            set_modified_time(&filepath, time)?;
+        }
+    }
+    Ok(())
+}
+
+fn pack_tar<W: Write>(writer: W, dir: &Path, files: &[String]) -> Result<W, CError> {
+    let mut builder = tar::Builder::new(writer);
+    for filepath in files {
+        builder.append_path_with_name(dir.join(filepath), filepath)?;
+    }
+    Ok(builder.into_inner()?)
+}
+
+fn pack_zip(file: File, dir: &Path, files: &[String]) -> Result<(), CError> {
+    let mut writer = zip::ZipWriter::new(file);
+    for filepath in files {
+        let mut options = zip::write::FileOptions::default();
+        if let Some(time) = modified_time(&dir.join(filepath)).and_then(system_to_zip_time) {
+            options = options.last_modified_time(time);
+        }
+        writer
+            .start_file(filepath.as_str(), options)
+            .map_err(zip_error)?;
+        writer.write_all(&std::fs::read(dir.join(filepath))?)?;
+    }
+    writer.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+/// Zip timestamps have no time zone, they are taken as UTC.
+fn zip_to_system_time(time: zip::DateTime) -> Option<SystemTime> {
+    let datetime =
+        NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+            .and_hms_opt(
+                time.hour().into(),
+                time.minute().into(),
+                time.second().into(),
+            )?;
+    Some(datetime.and_utc().into())
+}
+
+fn system_to_zip_time(time: SystemTime) -> Option<zip::DateTime> {
+    let datetime = chrono::DateTime::<Utc>::from(time);
+    zip::DateTime::from_date_and_time(
+        datetime.year().try_into().ok()?,
+        datetime.month() as u8,
+        datetime.day() as u8,
+        datetime.hour() as u8,
+        datetime.minute() as u8,
+        datetime.second() as u8,
+    )
+    .ok()
+}
+
+fn zip_error(e: zip::result::ZipError) -> CError {
+    CError::GenericIOError(e.into())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{ArchiveFormat, ExtractedArchive};
+    use std::path::Path;
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("release-1.0.tar.gz")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("release.TGZ")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("release.zip")),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::from_path(Path::new("release.rar")), None);
+    }
+
+    #[test]
+    fn test_zip_roundtrip() {
+        let src = std::env::temp_dir().join("git_copyright_test_zip_src");
+        std::fs::create_dir_all(src.join("src")).unwrap();
+        std::fs::write(src.join("src/main.rs"), "fn main() {}\n").unwrap();
+        let archive = std::env::temp_dir().join("git_copyright_test.zip");
+
+        let packed = ExtractedArchive { dir: src };
+        packed.pack(&archive).unwrap();
+        drop(packed);
+
+        let extracted = ExtractedArchive::extract(&archive).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(extracted.dir().join("src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        std::fs::remove_file(archive).unwrap();
+    }
+}
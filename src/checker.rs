@@ -0,0 +1,666 @@
+//! Check repositories with a config owned by the checker.
+//!
+//! Unlike the free functions like `check_repo_copyright`, which read the
+//! global config, several checkers with different configs can run in the
+//! same process, e.g. in a server handling several repositories or in tests.
+
+use crate::years::{GitYears, YearProvider};
+use crate::{CError, CheckOptions, CheckReport, Config};
+
+/// Checker of copyrights with its own config, name in copyrights and options.
+///
+/// ```no_run
+/// # async fn run(config: git_copyright::Config) -> Result<(), git_copyright::CError> {
+/// use git_copyright::{CheckOptions, CopyrightChecker};
+///
+/// let checker = CopyrightChecker::new(config)
+///     .with_name("DummyCompany Ltd.")
+///     .with_options(CheckOptions::default());
+/// let report = checker.check_repo_report("path/to/repo").await?;
+/// println!("{} files need a fix", report.needing_fix().count());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CopyrightChecker {
+    config: Config,
+    name: Option<String>,
+    options: CheckOptions,
+}
+
+impl CopyrightChecker {
+    /// Create a checker with the config, default options and the `name` of
+    /// the config as name in copyrights.
+    pub fn new(config: Config) -> Self {
+        CopyrightChecker {
+            config,
+            name: None,
+            options: CheckOptions::default(),
+        }
+    }
+
+    /// Use the name in copyrights instead of the `name` of the config.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_options(mut self, options: CheckOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn options(&self) -> &CheckOptions {
+        &self.options
+    }
+
+    /// Name in copyrights given with `with_name` or in the config.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref().or_else(|| self.config.name())
+    }
+
+    fn required_name(&self) -> Result<&str, CError> {
+        self.name().ok_or_else(|| {
+            CError::ConfigError(
+                "No name in copyrights given with `--name` or in the config".to_owned(),
+            )
+        })
+    }
+
+    /// Check and fix the copyrights of the repository like
+    /// `check_repo_copyright`.
+    pub async fn check_repo(&self, repo_path: &str) -> Result<(), CError> {
+        crate::run_check(
+            &self.config,
+            repo_path,
+            self.required_name()?,
+            &self.options,
+            None::<&GitYears>,
+        )
+        .await
+    }
+
+    /// Check the repository and return the outcomes of all files instead of
+    /// failing on missing or outdated copyrights, policy violations or files
+    /// which could not be checked.
+    ///
+    /// Errors are only returned if the repository could not be checked at
+    /// all, e.g. without git. Set `quiet` in the options to render the report
+    /// instead of printing the results, reports are still written as
+    /// configured.
+    pub async fn check_repo_report(&self, repo_path: &str) -> Result<CheckReport, CError> {
+        crate::run_report(
+            &self.config,
+            repo_path,
+            self.required_name()?,
+            &self.options,
+        )
+        .await
+    }
+
+    /// Check the repository with the years of files taken from `years`
+    /// instead of the git history.
+    pub async fn check_repo_with_years(
+        &self,
+        repo_path: &str,
+        years: &impl YearProvider,
+    ) -> Result<(), CError> {
+        crate::run_check(
+            &self.config,
+            repo_path,
+            self.required_name()?,
+            &self.options,
+            Some(years),
+        )
+        .await
+    }
+
+    /// Check and update the years of the top-level license file like
+    /// `check_license_copyright`.
+    pub async fn check_license(&self, repo_path: &str) -> Result<(), CError> {
+        crate::license_copyright(&self.config, repo_path, &self.options).await
+    }
+
+    /// Count the files in which each copyright holder appears in the header
+    /// like `list_holders`.
+    pub async fn list_holders(&self, repo_path: &str) -> Result<Vec<(String, usize)>, CError> {
+        crate::holders(&self.config, repo_path).await
+    }
+
+    /// Remove the copyrights of the name from the file headers like
+    /// `remove_repo_copyright`.
+    pub async fn remove_copyrights(&self, repo_path: &str) -> Result<Vec<(String, usize)>, CError> {
+        crate::remove_copyrights(
+            &self.config,
+            repo_path,
+            self.required_name()?,
+            &self.options,
+        )
+        .await
+    }
+
+    /// Rewrite the copyrights of the holder `from` to the holder `to` like
+    /// `migrate_repo_copyright`.
+    pub async fn migrate_holder(
+        &self,
+        repo_path: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<(String, usize)>, CError> {
+        crate::migrate_holder(&self.config, repo_path, from, to, &self.options).await
+    }
+
+    /// Check the copyrights of a repository on GitHub or GitLab via its API
+    /// like `check_remote_copyright`.
+    #[cfg(feature = "remote")]
+    pub async fn check_remote(&self, remote: crate::remote::RemoteRepo) -> Result<(), CError> {
+        crate::remote_copyright(&self.config, remote, self.required_name()?, &self.options).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::CopyrightChecker;
+    use crate::report::{FileOutcome, FileStatus, OutputFormat};
+    use crate::years::YearProvider;
+    use crate::{CError, CheckOptions, CommentSign, Config, WriteMode};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    fn checker(name: &str) -> CopyrightChecker {
+        let config = Config::new(
+            HashMap::from([("rs".to_owned(), CommentSign::LeftOnly("//".to_owned()))]),
+            Vec::new(),
+            Vec::new(),
+        )
+        .with_mtime_fallback(true)
+        .with_name(Some(name.to_owned()));
+        CopyrightChecker::new(config).with_options(CheckOptions {
+            write_mode: WriteMode::Never,
+            quiet: true,
+            jobs: Some(1),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_checkers_with_own_configs() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_checker");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        std::fs::write(
+            repo_dir.join("main.rs"),
+            "// Copyright (c) Alice 2020\nfn main() {}\n",
+        )
+        .unwrap();
+        let repo_path = repo_dir.to_str().unwrap();
+
+        let alice = checker("Alice").check_repo_report(repo_path).await;
+        let bob = checker("Bob").check_repo_report(repo_path).await;
+        assert!(matches!(
+            alice.unwrap().outcomes[0].status,
+            FileStatus::Outdated { .. }
+        ));
+        assert_eq!(bob.unwrap().outcomes[0].status, FileStatus::Missing);
+
+        let unnamed = CopyrightChecker::new(Config::new(HashMap::new(), Vec::new(), Vec::new()));
+        assert!(matches!(
+            unnamed.check_repo(repo_path).await,
+            Err(CError::ConfigError(_))
+        ));
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    /// Years which record how many files are looked up at once.
+    #[derive(Default)]
+    struct ConcurrentYears {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    impl YearProvider for ConcurrentYears {
+        async fn years(&self, _filepath: &str) -> Result<String, CError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok("2022".to_owned())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jobs() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_jobs");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        for idx in 0..20 {
+            std::fs::write(repo_dir.join(format!("{}.rs", idx)), "fn main() {}\n").unwrap();
+        }
+        let repo_path = repo_dir.to_str().unwrap();
+
+        for jobs in [1, 3] {
+            let options = CheckOptions {
+                jobs: Some(jobs),
+                ..checker("Alice").options().clone()
+            };
+            let years = ConcurrentYears::default();
+            let result = checker("Alice")
+                .with_options(options)
+                .check_repo_with_years(repo_path, &years)
+                .await;
+            assert!(matches!(result, Err(CError::IncorrectCopyrights(20))));
+            assert_eq!(years.max_running.load(Ordering::SeqCst), jobs);
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shards() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_shards");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        for dir in ["a", "b", "c"] {
+            std::fs::create_dir_all(repo_dir.join(dir)).unwrap();
+            for idx in 0..20 {
+                let filepath = repo_dir.join(dir).join(format!("{}.rs", idx));
+                std::fs::write(filepath, "fn main() {}\n").unwrap();
+            }
+        }
+        std::fs::write(repo_dir.join("main.rs"), "fn main() {}\n").unwrap();
+        let repo_path = repo_dir.to_str().unwrap();
+
+        let options = CheckOptions {
+            jobs: None,
+            ..checker("Alice").options().clone()
+        };
+        let years = ConcurrentYears::default();
+        let result = checker("Alice")
+            .with_options(options)
+            .check_repo_with_years(repo_path, &years)
+            .await;
+        assert!(matches!(result, Err(CError::IncorrectCopyrights(61))));
+        // Each directory checks up to 16 files at once, files at the root
+        // form a shard of their own
+        assert_eq!(
+            years.max_running.load(Ordering::SeqCst),
+            3 * crate::CONCURRENT_FILES_PER_SHARD + 1
+        );
+        assert_eq!(crate::top_level_dir("a/b/main.rs"), "a");
+        assert_eq!(crate::top_level_dir("main.rs"), "");
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_preserve_mtime() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_preserve_mtime");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        let filepath = repo_dir.join("main.rs");
+        std::fs::write(&filepath, "fn main() {}\n").unwrap();
+        // Without git, files are always checked with `mtime_fallback`
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=Alice",
+                    "-c",
+                    "user.email=alice@example.com",
+                ])
+                .args(args)
+                .current_dir(&repo_dir)
+                .status();
+            assert!(status.unwrap().success());
+        };
+        git(&["init", "-q"]);
+        git(&["add", "-A"]);
+        git(&["commit", "-q", "-m", "Add main.rs"]);
+        let repo_path = repo_dir.to_str().unwrap();
+        let years = HashMap::from([("main.rs".to_owned(), "2020".to_owned())]);
+        // 2020-01-01
+        let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1577836800);
+
+        for preserve_mtime in [true, false] {
+            std::fs::write(&filepath, "fn main() {}\n").unwrap();
+            let file = std::fs::File::options()
+                .write(true)
+                .open(&filepath)
+                .unwrap();
+            file.set_modified(mtime).unwrap();
+            drop(file);
+
+            let config = Config::new(
+                HashMap::from([("rs".to_owned(), CommentSign::LeftOnly("//".to_owned()))]),
+                Vec::new(),
+                Vec::new(),
+            )
+            .with_preserve_mtime(preserve_mtime)
+            .with_name(Some("Alice".to_owned()));
+            assert_eq!(config.preserve_mtime(), preserve_mtime);
+            let options = CheckOptions {
+                write_mode: WriteMode::All,
+                ..checker("Alice").options().clone()
+            };
+            CopyrightChecker::new(config)
+                .with_options(options)
+                .check_repo_with_years(repo_path, &years)
+                .await
+                .unwrap();
+
+            let content = std::fs::read_to_string(&filepath).unwrap();
+            assert!(content.starts_with("// Copyright (c) Alice 2020\n"));
+            let modified = std::fs::metadata(&filepath).unwrap().modified().unwrap();
+            assert_eq!(modified == mtime, preserve_mtime);
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_header() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_no_header");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("package.json"), "{}\n").unwrap();
+        let repo_path = repo_dir.to_str().unwrap();
+
+        let config = Config::new(HashMap::new(), Vec::new(), Vec::new())
+            .with_no_header("json")
+            .with_mtime_fallback(true)
+            .with_name(Some("Alice".to_owned()));
+        let options = CheckOptions {
+            write_mode: WriteMode::All,
+            ..checker("Alice").options().clone()
+        };
+        let checker = CopyrightChecker::new(config).with_options(options);
+        let report = checker.check_repo_report(repo_path).await.unwrap();
+        assert_eq!(report.outcomes.len(), 1);
+        assert_eq!(report.outcomes[0].status, FileStatus::NoHeader);
+        assert!(report.failures.is_empty());
+        checker.check_repo(repo_path).await.unwrap();
+        let content = std::fs::read_to_string(repo_dir.join("package.json")).unwrap();
+        assert_eq!(content, "{}\n");
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_failures() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_file_failures");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("a.rs"), "// Copyright (c) Alice 2020\n").unwrap();
+        std::fs::write(repo_dir.join("b.rs"), "// Copyright (c) Alice 2020\n").unwrap();
+        let repo_path = repo_dir.to_str().unwrap();
+
+        let years = HashMap::from([("a.rs".to_owned(), "2020".to_owned())]);
+        let result = checker("Alice")
+            .check_repo_with_years(repo_path, &years)
+            .await;
+        let failures = match result {
+            Err(CError::FixError(failures)) => failures,
+            other => panic!("Expected failures, got {:?}", other),
+        };
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].filepath, "b.rs");
+        assert!(matches!(&failures[0].error, CError::NoYears(f) if f == "b.rs"));
+        assert_eq!(
+            CError::FixError(failures).to_string(),
+            "Some copyrights could not be fixed:\n  b.rs: No years known for b.rs"
+        );
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_min_coverage() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_min_coverage");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("a.rs"), "// Copyright (c) Alice 2020\n").unwrap();
+        std::fs::write(repo_dir.join("b.rs"), "fn main() {}\n").unwrap();
+        let repo_path = repo_dir.to_str().unwrap();
+
+        let years = HashMap::from([
+            ("a.rs".to_owned(), "2020".to_owned()),
+            ("b.rs".to_owned(), "2020".to_owned()),
+        ]);
+        let check = |min_coverage| {
+            let options = CheckOptions {
+                min_coverage,
+                ..checker("Alice").options().clone()
+            };
+            checker("Alice").with_options(options)
+        };
+        assert!(matches!(
+            check(None).check_repo_with_years(repo_path, &years).await,
+            Err(CError::IncorrectCopyrights(1))
+        ));
+        check(Some(50.0))
+            .check_repo_with_years(repo_path, &years)
+            .await
+            .unwrap();
+        assert!(matches!(
+            check(Some(60.0)).check_repo_with_years(repo_path, &years).await,
+            Err(CError::CoverageTooLow { coverage, .. }) if coverage == 50.0
+        ));
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_extension_filters() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_extensions");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        for filename in ["a.rs", "b.py", "c.sh"] {
+            std::fs::write(repo_dir.join(filename), "\n").unwrap();
+        }
+        let repo_path = repo_dir.to_str().unwrap();
+
+        let config = || {
+            let comment_signs = [("rs", "//"), ("py", "#"), ("sh", "#")]
+                .map(|(ext, sign)| (ext.to_owned(), CommentSign::LeftOnly(sign.to_owned())));
+            Config::new(HashMap::from(comment_signs), Vec::new(), Vec::new())
+                .with_mtime_fallback(true)
+                .with_name(Some("Alice".to_owned()))
+        };
+        let checked = |extensions: &[&str], excluded_extensions: &[&str]| {
+            let options = CheckOptions {
+                extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+                excluded_extensions: excluded_extensions
+                    .iter()
+                    .map(|ext| ext.to_string())
+                    .collect(),
+                ..checker("Alice").options().clone()
+            };
+            let checker = CopyrightChecker::new(config()).with_options(options);
+            async move {
+                let report = checker.check_repo_report(repo_path).await.unwrap();
+                let mut files: Vec<_> = report
+                    .outcomes
+                    .iter()
+                    .map(|outcome| outcome.filepath.file_name().unwrap().to_owned())
+                    .collect();
+                files.sort();
+                files
+            }
+        };
+        assert_eq!(checked(&[], &[]).await, ["a.rs", "b.py", "c.sh"]);
+        assert_eq!(checked(&["rs", ".py"], &[]).await, ["a.rs", "b.py"]);
+        assert_eq!(checked(&["rs", "py"], &[".py"]).await, ["a.rs"]);
+        assert_eq!(checked(&[], &["sh"]).await, ["a.rs", "b.py"]);
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        let options = |progress, quiet, format| CheckOptions {
+            progress,
+            quiet,
+            format,
+            ..Default::default()
+        };
+        // Hidden progress bars have no length
+        let bar = options(true, false, OutputFormat::Human).progress_bar();
+        assert_eq!(bar.length(), Some(0));
+        let bar = options(false, false, OutputFormat::Human).progress_bar();
+        assert_eq!(bar.length(), None);
+        let bar = options(true, true, OutputFormat::Human).progress_bar();
+        assert_eq!(bar.length(), None);
+        let bar = options(true, false, OutputFormat::Json).progress_bar();
+        assert_eq!(bar.length(), None);
+    }
+
+    #[tokio::test]
+    async fn test_declined_writes() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_declined");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("main.rs"), "fn main() {}\n").unwrap();
+        let repo_path = repo_dir.to_str().unwrap();
+
+        let fix = |confirm_writes: fn(&[&FileOutcome]) -> bool| {
+            let options = CheckOptions {
+                write_mode: WriteMode::All,
+                defer_writes: true,
+                confirm_writes: Some(confirm_writes),
+                ..checker("Alice").options().clone()
+            };
+            checker("Alice").with_options(options)
+        };
+        // Declined modifications fail like a check instead of passing
+        assert!(matches!(
+            fix(|_| false).check_repo(repo_path).await,
+            Err(CError::IncorrectCopyrights(1))
+        ));
+        let content = std::fs::read_to_string(repo_dir.join("main.rs")).unwrap();
+        assert_eq!(content, "fn main() {}\n");
+
+        fix(|pending| pending.len() == 1)
+            .check_repo(repo_path)
+            .await
+            .unwrap();
+        let content = std::fs::read_to_string(repo_dir.join("main.rs")).unwrap();
+        assert!(content.starts_with("// Copyright (c) Alice"));
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_print_results() {
+        static PRINTED: Mutex<String> = Mutex::new(String::new());
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_print_results");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("main.rs"), "fn main() {}\n").unwrap();
+        let repo_path = repo_dir.to_str().unwrap();
+
+        let check = |format, quiet| {
+            let options = CheckOptions {
+                format,
+                quiet,
+                print_results: Some(|text| PRINTED.lock().unwrap().push_str(text)),
+                ..checker("Alice").options().clone()
+            };
+            checker("Alice").with_options(options)
+        };
+        // Lines of machine-readable formats and documents are printed
+        check(OutputFormat::Quickfix, false)
+            .check_repo_report(repo_path)
+            .await
+            .unwrap();
+        let printed = std::mem::take(&mut *PRINTED.lock().unwrap());
+        assert!(
+            printed.contains("main.rs:1: copyright is missing"),
+            "{}",
+            printed
+        );
+        assert_eq!(printed.lines().count(), 1);
+
+        check(OutputFormat::Json, false)
+            .check_repo_report(repo_path)
+            .await
+            .unwrap();
+        let printed = std::mem::take(&mut *PRINTED.lock().unwrap());
+        assert!(printed.contains("\"coverage\""), "{}", printed);
+
+        // Quiet runs print nothing
+        for format in [OutputFormat::Quickfix, OutputFormat::Json] {
+            check(format, true)
+                .check_repo_report(repo_path)
+                .await
+                .unwrap();
+        }
+        assert!(PRINTED.lock().unwrap().is_empty());
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_license() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_dry_run_license");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        let git = |args: &[&str], date: &str| {
+            let status = std::process::Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=Alice",
+                    "-c",
+                    "user.email=alice@example.com",
+                ])
+                .args(args)
+                .current_dir(&repo_dir)
+                .env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"], "2015-01-01T00:00:00+0000");
+        std::fs::write(repo_dir.join("LICENSE"), "Copyright (c) 2015 Alice\n").unwrap();
+        git(&["add", "-A"], "2015-01-01T00:00:00+0000");
+        git(
+            &["commit", "-q", "-m", "Add license"],
+            "2015-01-01T00:00:00+0000",
+        );
+        std::fs::write(repo_dir.join("main.rs"), "fn main() {}\n").unwrap();
+        git(&["add", "-A"], "2017-01-01T00:00:00+0000");
+        git(
+            &["commit", "-q", "-m", "Add main.rs"],
+            "2017-01-01T00:00:00+0000",
+        );
+        let repo_path = repo_dir.to_str().unwrap();
+
+        // Outdated licenses fail a dry run without being written
+        assert!(matches!(
+            checker("Alice").check_license(repo_path).await,
+            Err(CError::IncorrectCopyrights(1))
+        ));
+        let content = std::fs::read_to_string(repo_dir.join("LICENSE")).unwrap();
+        assert_eq!(content, "Copyright (c) 2015 Alice\n");
+
+        // The configured backend reads the repository
+        #[cfg(feature = "git2")]
+        {
+            let config = Config::new(HashMap::new(), Vec::new(), Vec::new())
+                .with_name(Some("Alice".to_owned()))
+                .with_git_backend(crate::config::GitBackend::Libgit2);
+            let libgit2 =
+                CopyrightChecker::new(config).with_options(checker("Alice").options().clone());
+            assert!(matches!(
+                libgit2.check_license(repo_path).await,
+                Err(CError::IncorrectCopyrights(1))
+            ));
+        }
+
+        let options = CheckOptions {
+            write_mode: WriteMode::All,
+            ..checker("Alice").options().clone()
+        };
+        let fix = checker("Alice").with_options(options);
+        fix.check_license(repo_path).await.unwrap();
+        let content = std::fs::read_to_string(repo_dir.join("LICENSE")).unwrap();
+        assert_eq!(content, "Copyright (c) 2015-2017 Alice\n");
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+}
@@ -2,112 +2,1784 @@
 //!
 //! If no custom configuration is specified, we fall back to the default
 //! configuration which is included as bytes in the compiled binary.
+//!
+//! A configuration can extend another configuration from a local path or an
+//! https URL with the key `extends`. Keys of the extending configuration
+//! override those of the base. Remote configurations are cached so that runs
+//! without network access can fall back to the last fetched version.
+//!
+//! Without explicit configuration, a config file at the repository root, see
+//! `REPO_CONFIG_FILES`, is used if present.
+//!
+//! Config files are YAML unless their extension is `.toml` or `.json`, all
+//! formats share the same keys.
+//!
+//! A per-user config at `~/.config/git-copyright/config.yml` is merged under
+//! the config of the repository or, without one, over the default config. It
+//! can hold personal preferences like the `name` in copyrights.
 
+use crate::editorconfig::Charset;
+use crate::file_ops::HEADER_LINES;
+use crate::policy::PolicyRule;
+use crate::templates::REUSE_COPYRIGHT_TEMPLATE;
+use crate::templates::{header_template, validate_copyright_template, validate_header};
+#[cfg(feature = "yaml")]
+use crate::templates::{parse_copyright_template, TemplatePart};
 use crate::CError;
 use crate::CommentSign;
 use glob::Pattern;
-use once_cell::sync::OnceCell;
+use regex::Regex;
 use serde::Deserialize;
+#[cfg(feature = "yaml")]
+use serde_yaml::Value;
+#[cfg(feature = "yaml")]
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::Path;
+#[cfg(feature = "yaml")]
+use std::path::PathBuf;
+#[cfg(feature = "yaml")]
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Global config, replaced when checking several repositories in one run.
+static CFG: RwLock<Option<&'static Config>> = RwLock::new(None);
+
+/// Config files at the repository root which are used without `--config`,
+/// in order of precedence.
+#[cfg(feature = "yaml")]
+pub const REPO_CONFIG_FILES: [&str; 5] = [
+    ".git-copyright.yml",
+    ".git-copyright.yaml",
+    "git-copyright.yaml",
+    ".git-copyright.toml",
+    ".git-copyright.json",
+];
+
+/// Default config included in the binary.
+#[cfg(feature = "yaml")]
+const DEFAULT_CFG: &str = include_str!("./default_cfg.yml");
+
+/// Maximum depth of `extends` chains, guarding against cycles.
+#[cfg(feature = "yaml")]
+const MAX_EXTENDS_DEPTH: usize = 8;
+
+/// Comment sign(s) accepted for an extension or filename.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CommentSignEntry {
+    /// Files are known to have no header, e.g. `json: none`.
+    NoHeader(NoHeader),
+    Single(CommentSign),
+    /// The preferred comment sign is used for new copyrights while existing
+    /// copyrights may also use one of the accepted comment signs. Copyrights
+    /// are searched in the first `scan_lines` lines instead of `HEADER_LINES`.
+    Multiple {
+        preferred: CommentSign,
+        #[serde(default)]
+        accepted: Vec<CommentSign>,
+        #[serde(default)]
+        scan_lines: Option<usize>,
+    },
+}
+
+/// Value `none` of the comment sign map.
+#[derive(Debug, Deserialize)]
+enum NoHeader {
+    #[serde(rename = "none")]
+    None,
+}
+
+impl CommentSignEntry {
+    /// Preferred comment sign, `None` for files without header.
+    fn preferred(&self) -> Option<&CommentSign> {
+        match self {
+            CommentSignEntry::NoHeader(_) => None,
+            CommentSignEntry::Single(comment_sign) => Some(comment_sign),
+            CommentSignEntry::Multiple { preferred, .. } => Some(preferred),
+        }
+    }
+
+    fn all(&self) -> Vec<&CommentSign> {
+        match self {
+            CommentSignEntry::NoHeader(_) => Vec::new(),
+            CommentSignEntry::Single(comment_sign) => vec![comment_sign],
+            CommentSignEntry::Multiple {
+                preferred,
+                accepted,
+                ..
+            } => std::iter::once(preferred).chain(accepted.iter()).collect(),
+        }
+    }
+
+    fn scan_lines(&self) -> Option<usize> {
+        match self {
+            CommentSignEntry::NoHeader(_) | CommentSignEntry::Single(_) => None,
+            CommentSignEntry::Multiple { scan_lines, .. } => *scan_lines,
+        }
+    }
+
+    /// Convert into an entry with multiple comment signs to set its options.
+    ///
+    /// Entries of files without header have no options.
+    fn into_multiple(self) -> Option<(CommentSign, Vec<CommentSign>, Option<usize>)> {
+        match self {
+            CommentSignEntry::NoHeader(_) => None,
+            CommentSignEntry::Single(comment_sign) => Some((comment_sign, Vec::new(), None)),
+            CommentSignEntry::Multiple {
+                preferred,
+                accepted,
+                scan_lines,
+            } => Some((preferred, accepted, scan_lines)),
+        }
+    }
+}
+
+/// Rule to update years embedded in files without copyright header, e.g.
+/// the `copyright = ...` line in `docs/conf.py`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpecialFileRule {
+    /// Glob pattern of the files the rule applies to.
+    pub path: String,
+    /// Regex matching the text to update, capturing the years in the first group.
+    pub pattern: String,
+    /// Replacement of the matched text with the placeholders `{years}` and `{name}`.
+    pub replacement: String,
+    #[serde(skip)]
+    glob: Option<Pattern>,
+}
+
+impl SpecialFileRule {
+    pub fn new(path: &str, pattern: &str, replacement: &str) -> Self {
+        SpecialFileRule {
+            path: path.to_owned(),
+            pattern: pattern.to_owned(),
+            replacement: replacement.to_owned(),
+            glob: None,
+        }
+    }
+
+    /// Replacement text for the given years and name.
+    pub fn replacement(&self, years: &str, name: &str) -> String {
+        self.replacement
+            .replace("{years}", years)
+            .replace("{name}", name)
+    }
+}
+
+/// How files whose name starts with a dot, like `.bashrc`, are handled.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DotfilePolicy {
+    /// Check dotfiles like any other file.
+    #[default]
+    Normal,
+    /// Skip all dotfiles.
+    Skip,
+    /// Check only the listed dotfiles with the given comment sign and skip all others.
+    ByName(HashMap<String, CommentSign>),
+}
+
+/// How the copyright holder of existing copyrights is matched.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HolderMatch {
+    /// The holder must be exactly the name.
+    #[default]
+    Exact,
+    /// Composed and decomposed accented letters and runs of whitespace are
+    /// equivalent, e.g. `Müller` written with a combining diaeresis.
+    Normalized,
+    /// Like `normalized`, ignoring case and commas or periods between and
+    /// after words, e.g. `Foo, Inc.` matches `Foo Inc`.
+    Fuzzy,
+}
+
+/// How the years of a file are written in its copyright.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum YearFormat {
+    /// Range from the year the file was added to the year it was last
+    /// modified, e.g. `2018-2023`.
+    #[default]
+    Range,
+    /// Every year in which the file was modified, e.g. `2018, 2020, 2023`.
+    List,
+    /// Only the year the file was added as year of first publication, e.g.
+    /// `2018`.
+    Single,
+}
+
+/// How files marked read-only on disk are handled when they need a fix.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadOnlyPolicy {
+    /// Fail on the file.
+    #[default]
+    Fail,
+    /// Make the file writable for the write and read-only again afterwards.
+    Write,
+    /// Leave the file untouched and report it as read-only.
+    Skip,
+}
+
+/// How the git history is traversed for the years of files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryTraversal {
+    /// Run `git log --follow` for each file.
+    #[default]
+    PerFile,
+    /// Run `git log` once for all files, following renames.
+    Batched,
+}
+
+/// Timezone in which the year of a commit date is counted, e.g. `utc` or
+/// `+01:00`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum YearTimezone {
+    /// Local time of the committer or author as recorded in the commit.
+    #[default]
+    Commit,
+    Utc,
+    /// Fixed offset east of UTC in minutes.
+    Fixed(i32),
+}
+
+impl TryFrom<String> for YearTimezone {
+    type Error = String;
+
+    fn try_from(timezone: String) -> Result<Self, Self::Error> {
+        match timezone.as_str() {
+            "commit" => Ok(YearTimezone::Commit),
+            "utc" => Ok(YearTimezone::Utc),
+            offset => parse_utc_offset(offset)
+                .map(YearTimezone::Fixed)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid timezone {}, expected commit, utc or an offset like +01:00",
+                        offset
+                    )
+                }),
+        }
+    }
+}
+
+/// Minutes east of UTC of an offset like `+01:00`, `-0530` or `+02`.
+fn parse_utc_offset(offset: &str) -> Option<i32> {
+    let (sign, digits) = match offset.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    let digits = digits.replace(':', "");
+    if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    (hours < 24 && minutes < 60).then_some(sign * (hours * 60 + minutes))
+}
+
+/// Whether moved files keep the history of their previous paths.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Renames {
+    /// Moved files inherit the years of their previous paths, like
+    /// `git log --follow`.
+    #[default]
+    Follow,
+    /// Moved files start a new history, like `git log --no-renames`.
+    Ignore,
+}
+
+/// Whether merge commits count as modifications of the files they change.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeCommits {
+    /// Merges count for the files differing from any parent, like
+    /// `git log -m`.
+    #[default]
+    Count,
+    /// Merges do not count, like `git log --no-merges`.
+    Skip,
+}
+
+/// Which commits of a file its years are taken from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum YearSource {
+    /// All commits changing the file, from `git log`.
+    #[default]
+    Log,
+    /// The commits of the lines which still exist in the file, from
+    /// `git blame`.
+    Blame,
+}
+
+/// Implementation of the git operations of checks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum GitBackend {
+    /// Run the git binary.
+    #[default]
+    Cli,
+    /// Use libgit2, which requires the `git2` feature.
+    Libgit2,
+}
+
+/// Date of commits the years of files are taken from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    /// Date the commit was created, which rebases and cherry-picks move.
+    #[default]
+    Committer,
+    /// Date the change was originally authored.
+    Author,
+}
+
+impl DateSource {
+    /// Placeholder of the date in `git log --pretty` formats, e.g. `%ci`.
+    pub(crate) fn placeholder(self) -> &'static str {
+        match self {
+            DateSource::Committer => "%ci",
+            DateSource::Author => "%ai",
+        }
+    }
+}
+
+/// Number of files excluded by each ignore pattern.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IgnoreStats {
+    pub counts: Vec<(String, usize)>,
+}
+
+impl IgnoreStats {
+    /// Ignore patterns which did not exclude any file.
+    pub fn unused_patterns(&self) -> impl Iterator<Item = &str> {
+        self.counts
+            .iter()
+            .filter(|(_, count)| *count == 0)
+            .map(|(pattern, _)| pattern.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    comment_sign_map: HashMap<String, CommentSignEntry>,
+    ignore_files: Vec<String>,
+    ignore_dirs: Vec<String>,
+    /// Only files matching one of the patterns are checked if any is given.
+    #[serde(default)]
+    include_files: Vec<String>,
+    /// Only files in directories matching one of the patterns are checked if
+    /// any is given.
+    #[serde(default)]
+    include_dirs: Vec<String>,
+    /// Write `©` instead of `(c)` in copyright lines.
+    #[serde(default)]
+    use_copyright_symbol: bool,
+    /// Text appended after the years, e.g. `All rights reserved.`
+    #[serde(default)]
+    suffix: Option<String>,
+    /// Earliest year written in copyrights, e.g. the founding year.
+    #[serde(default)]
+    min_year: Option<u32>,
+    /// Only check files last modified on or after this date, e.g. `2022` or `2022-06-01`.
+    #[serde(default)]
+    modified_since: Option<String>,
+    /// Insert new copyrights after a leading banner comment instead of above it.
+    #[serde(default)]
+    insert_after_banner: bool,
+    /// Regexes per extension or filename of leading lines which new
+    /// copyrights are inserted after, e.g. `<?xml` declarations.
+    #[serde(default)]
+    insert_after: HashMap<String, Vec<String>>,
+    /// Write files with classic Mac OS line endings (lone CR) with LF.
+    #[serde(default)]
+    normalize_cr_line_endings: bool,
+    /// Charset of files which are not valid UTF-8 and have no byte order
+    /// mark, e.g. `latin1`.
+    #[serde(default)]
+    fallback_encoding: Option<Charset>,
+    /// Handling of files marked read-only which need a fix.
+    #[serde(default)]
+    read_only_files: ReadOnlyPolicy,
+    /// Name of a bundled header template or custom multi-line template whose
+    /// lines follow new copyright lines.
+    #[serde(default)]
+    header: Option<String>,
+    /// Template of the copyright text with the placeholders `{holder}`,
+    /// `{years}`, `{filename}` and `{license}`, e.g.
+    /// `Copyright {years} {holder}. Licensed under {license}.`
+    #[serde(default)]
+    copyright_template: Option<String>,
+    /// License substituted for `{license}` in the copyright template, e.g. `Apache-2.0`.
+    #[serde(default)]
+    license: Option<String>,
+    /// SPDX license expression of an `SPDX-License-Identifier` line required
+    /// in headers, e.g. `Apache-2.0` or `MIT OR Apache-2.0`.
+    #[serde(default)]
+    spdx_license: Option<String>,
+    /// Write headers in the format of the REUSE specification and `.license`
+    /// sidecar files for files without header.
+    #[serde(default)]
+    reuse: bool,
+    /// Name in copyrights if none is given on the command line.
+    #[serde(default)]
+    name: Option<String>,
+    /// Holders in copyrights by glob pattern of the files they apply to,
+    /// instead of the name.
+    #[serde(default)]
+    holders: HashMap<String, String>,
+    /// Write one copyright line per author of a file from its history instead
+    /// of the name.
+    #[serde(default)]
+    author_lines: bool,
+    /// Commits an author needs on a file to get a copyright line.
+    #[serde(default)]
+    min_author_commits: usize,
+    /// How the holder of existing copyrights is matched with the name.
+    #[serde(default)]
+    holder_match: HolderMatch,
+    /// How the years of files are written in copyrights.
+    #[serde(default)]
+    year_format: YearFormat,
+    /// Word written instead of the last year of ranges, e.g. `present`.
+    #[serde(default)]
+    open_end: Option<String>,
+    /// Rules to update years in files without copyright header.
+    #[serde(default)]
+    special_files: Vec<SpecialFileRule>,
+    /// Handling of dotfiles.
+    #[serde(default)]
+    dotfiles: DotfilePolicy,
+    /// Commits from which on the history of a directory counts for its years,
+    /// e.g. the import of a vendored library. Subtrees are detected anyway.
+    #[serde(default)]
+    history_cutoffs: HashMap<String, String>,
+    /// Whether the history is traversed once for all files or per file.
+    #[serde(default)]
+    history_traversal: HistoryTraversal,
+    /// Whether years are taken from all commits of a file or from the commits
+    /// of its remaining lines.
+    #[serde(default)]
+    year_source: YearSource,
+    /// Whether git operations run the git binary or use libgit2.
+    #[serde(default)]
+    git_backend: GitBackend,
+    /// Whether years are taken from committer or author dates.
+    #[serde(default)]
+    date_source: DateSource,
+    /// Whether merge commits count for the years of files.
+    #[serde(default)]
+    merge_commits: MergeCommits,
+    /// Skip commits which only change whitespace or blank lines of a file.
+    #[serde(default)]
+    ignore_whitespace_changes: bool,
+    /// Ref whose files are checked and whose history the years are taken
+    /// from, `HEAD` if not given.
+    #[serde(default)]
+    history_ref: Option<String>,
+    /// Timezone in which the years of commit dates are counted.
+    #[serde(default)]
+    year_timezone: YearTimezone,
+    /// Regex of the authors whose commits count for the years, matched
+    /// against `Name <email>` like `git log --author`.
+    #[serde(default)]
+    author_filter: Option<String>,
+    /// Whether moved files keep the years of their previous paths.
+    #[serde(default)]
+    renames: Renames,
+    /// Minimum similarity in percent of a moved file to its previous path,
+    /// 50 if not given like in git.
+    #[serde(default)]
+    rename_similarity: Option<u8>,
+    /// Let copied files inherit the years of their source when traversing
+    /// the history at once.
+    #[serde(default)]
+    detect_copies: bool,
+    /// Read the history without mapping identities with the `.mailmap` of
+    /// the repository.
+    #[serde(default)]
+    ignore_mailmap: bool,
+    /// Take years from file timestamps for files without history and list the
+    /// files of directories which are not git repositories.
+    #[serde(default)]
+    mtime_fallback: bool,
+    /// Keep the modification time of written files, e.g. for build systems
+    /// rebuilding by timestamps.
+    #[serde(default)]
+    preserve_mtime: bool,
+    /// Rules requiring actions for files by path, extension or holder.
+    #[serde(default)]
+    policies: Vec<PolicyRule>,
+    /// Regexes of headers which exempt a file from copyrights, e.g. `Public Domain`.
+    #[serde(default)]
+    keep_headers: Vec<String>,
+    #[serde(skip)]
+    glob_pattern: Option<Vec<Pattern>>,
+    /// Keys of `comment_sign_map` which are glob patterns, compiled.
+    #[serde(skip)]
+    comment_sign_globs: Vec<Pattern>,
+    /// Compiled `holders` patterns with their holder, longest first.
+    #[serde(skip)]
+    holder_globs: Vec<(Pattern, String)>,
+    #[serde(skip)]
+    include_file_patterns: Vec<Pattern>,
+    #[serde(skip)]
+    include_dir_patterns: Vec<Pattern>,
+    #[serde(skip)]
+    keep_regexes: Vec<Arc<Regex>>,
+    #[serde(skip)]
+    insert_after_regexes: HashMap<String, Vec<Arc<Regex>>>,
+    /// Resolved value the config was built from, on which nested configs
+    /// are layered.
+    #[cfg(feature = "yaml")]
+    #[serde(skip)]
+    source: Option<Value>,
+}
+
+impl Config {
+    /// Config assigned with `assign`, read by the free check functions like
+    /// `check_repo_copyright`.
+    pub fn global() -> &'static Config {
+        CFG.read().unwrap().expect("Config is not initialized")
+    }
+
+    /// Assign the global config once.
+    #[deprecated(note = "use `CopyrightChecker`, which owns its config")]
+    pub fn assign(self) {
+        let mut cfg = CFG.write().unwrap();
+        assert!(cfg.is_none(), "Global config is already assigned to");
+        *cfg = Some(Box::leak(Box::new(self)));
+    }
+
+    /// Replace the global config, e.g. with the config of the next repository
+    /// when checking several repositories one after another.
+    ///
+    /// The previous config is leaked since references to it may be alive.
+    #[deprecated(note = "use `CopyrightChecker`, which owns its config")]
+    pub fn replace_global(self) {
+        *CFG.write().unwrap() = Some(Box::leak(Box::new(self)));
+    }
+
+    /// Create a config with the given comment signs and ignore patterns.
+    ///
+    /// All other settings start with their defaults and can be changed with
+    /// the `with_*` methods.
+    pub fn new(
+        comment_sign_map: HashMap<String, CommentSign>,
+        ignore_files: Vec<String>,
+        ignore_dirs: Vec<String>,
+    ) -> Self {
+        let comment_sign_map = comment_sign_map
+            .into_iter()
+            .map(|(key, comment_sign)| (key, CommentSignEntry::Single(comment_sign)))
+            .collect();
+        let mut cfg = Config {
+            comment_sign_map,
+            ignore_files,
+            ignore_dirs,
+            use_copyright_symbol: false,
+            suffix: None,
+            min_year: None,
+            modified_since: None,
+            insert_after_banner: false,
+            insert_after: HashMap::new(),
+            normalize_cr_line_endings: false,
+            fallback_encoding: None,
+            read_only_files: ReadOnlyPolicy::Fail,
+            name: None,
+            holders: HashMap::new(),
+            author_lines: false,
+            min_author_commits: 0,
+            holder_match: HolderMatch::Exact,
+            year_format: YearFormat::Range,
+            open_end: None,
+            header: None,
+            copyright_template: None,
+            license: None,
+            spdx_license: None,
+            reuse: false,
+            special_files: Vec::new(),
+            dotfiles: DotfilePolicy::Normal,
+            mtime_fallback: false,
+            preserve_mtime: false,
+            policies: Vec::new(),
+            keep_headers: Vec::new(),
+            history_cutoffs: HashMap::new(),
+            history_traversal: HistoryTraversal::PerFile,
+            year_source: YearSource::Log,
+            git_backend: GitBackend::Cli,
+            date_source: DateSource::Committer,
+            merge_commits: MergeCommits::Count,
+            ignore_whitespace_changes: false,
+            history_ref: None,
+            year_timezone: YearTimezone::Commit,
+            author_filter: None,
+            renames: Renames::Follow,
+            rename_similarity: None,
+            detect_copies: false,
+            ignore_mailmap: false,
+            include_files: Vec::new(),
+            include_dirs: Vec::new(),
+            glob_pattern: None,
+            comment_sign_globs: Vec::new(),
+            holder_globs: Vec::new(),
+            include_file_patterns: Vec::new(),
+            include_dir_patterns: Vec::new(),
+            keep_regexes: Vec::new(),
+            insert_after_regexes: HashMap::new(),
+            #[cfg(feature = "yaml")]
+            source: None,
+        };
+        cfg.build_glob_pattern();
+        cfg
+    }
+
+    /// Accept additional comment signs for an extension or filename.
+    ///
+    /// The comment sign given in `new` stays the preferred one for new copyrights.
+    pub fn with_accepted_comment_signs(mut self, key: &str, accepted: Vec<CommentSign>) -> Self {
+        if let Some(entry) = self.comment_sign_map.remove(key) {
+            let entry = match entry.into_multiple() {
+                Some((preferred, _, scan_lines)) => CommentSignEntry::Multiple {
+                    preferred,
+                    accepted,
+                    scan_lines,
+                },
+                None => CommentSignEntry::NoHeader(NoHeader::None),
+            };
+            self.comment_sign_map.insert(key.to_owned(), entry);
+        }
+        self
+    }
+
+    /// Mark files with the extension, filename or glob pattern as having no
+    /// header by design.
+    pub fn with_no_header(mut self, key: &str) -> Self {
+        self.comment_sign_map
+            .insert(key.to_owned(), CommentSignEntry::NoHeader(NoHeader::None));
+        self.build_glob_pattern();
+        self
+    }
+
+    /// Search copyrights in the first `scan_lines` lines of files with the
+    /// extension or filename instead of `HEADER_LINES`.
+    pub fn with_scan_lines(mut self, key: &str, scan_lines: usize) -> Self {
+        if let Some(entry) = self.comment_sign_map.remove(key) {
+            let entry = match entry.into_multiple() {
+                Some((preferred, accepted, _)) => CommentSignEntry::Multiple {
+                    preferred,
+                    accepted,
+                    scan_lines: Some(scan_lines),
+                },
+                None => CommentSignEntry::NoHeader(NoHeader::None),
+            };
+            self.comment_sign_map.insert(key.to_owned(), entry);
+        }
+        self
+    }
+
+    pub fn with_copyright_symbol(mut self, use_copyright_symbol: bool) -> Self {
+        self.use_copyright_symbol = use_copyright_symbol;
+        self
+    }
+
+    pub fn with_suffix(mut self, suffix: Option<String>) -> Self {
+        self.suffix = suffix;
+        self
+    }
+
+    pub fn with_min_year(mut self, min_year: Option<u32>) -> Self {
+        self.min_year = min_year;
+        self
+    }
+
+    pub fn with_modified_since(mut self, modified_since: Option<String>) -> Self {
+        self.modified_since = modified_since;
+        self
+    }
+
+    pub fn with_insert_after_banner(mut self, insert_after_banner: bool) -> Self {
+        self.insert_after_banner = insert_after_banner;
+        self
+    }
+
+    pub fn with_normalize_cr_line_endings(mut self, normalize_cr_line_endings: bool) -> Self {
+        self.normalize_cr_line_endings = normalize_cr_line_endings;
+        self
+    }
+
+    /// Read and write files which are not valid UTF-8 with the charset.
+    pub fn with_fallback_encoding(mut self, fallback_encoding: Option<Charset>) -> Self {
+        self.fallback_encoding = fallback_encoding;
+        self
+    }
+
+    pub fn with_read_only_files(mut self, read_only_files: ReadOnlyPolicy) -> Self {
+        self.read_only_files = read_only_files;
+        self
+    }
+
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Use other holders than the name in copyrights of the files matching
+    /// the glob patterns.
+    pub fn with_holders(mut self, holders: HashMap<String, String>) -> Self {
+        self.holders = holders;
+        self.build_glob_pattern();
+        self
+    }
+
+    /// Write one copyright line per author with at least `min_commits`
+    /// commits on the file instead of the name.
+    pub fn with_author_lines(mut self, author_lines: bool, min_commits: usize) -> Self {
+        self.author_lines = author_lines;
+        self.min_author_commits = min_commits;
+        self
+    }
+
+    /// Select a bundled header template, see `templates::TEMPLATE_NAMES`, or
+    /// a custom template spanning several lines.
+    ///
+    /// Unknown template names are rejected like in the config file.
+    pub fn with_header(mut self, header: Option<String>) -> Result<Self, CError> {
+        if let Some(header) = header.as_deref() {
+            validate_header(header)?;
+        }
+        self.header = header;
+        Ok(self)
+    }
+
+    /// Write and match copyright lines following the template, see
+    /// `templates::parse_copyright_template`.
+    pub fn with_copyright_template(
+        mut self,
+        copyright_template: Option<String>,
+    ) -> Result<Self, CError> {
+        if let Some(template) = copyright_template.as_deref() {
+            validate_copyright_template(template)?;
+        }
+        self.copyright_template = copyright_template;
+        Ok(self)
+    }
+
+    pub fn with_license(mut self, license: Option<String>) -> Self {
+        self.license = license;
+        self
+    }
+
+    pub fn with_spdx_license(mut self, spdx_license: Option<String>) -> Self {
+        self.spdx_license = spdx_license;
+        self
+    }
+
+    pub fn with_reuse(mut self, reuse: bool) -> Self {
+        self.reuse = reuse;
+        self
+    }
+
+    pub fn with_special_files(mut self, special_files: Vec<SpecialFileRule>) -> Self {
+        self.special_files = special_files;
+        self.build_glob_pattern();
+        self
+    }
+
+    /// Only check files matching one of the `include_files` patterns or in
+    /// a directory matching one of the `include_dirs` patterns.
+    pub fn with_include(mut self, include_files: Vec<String>, include_dirs: Vec<String>) -> Self {
+        self.include_files = include_files;
+        self.include_dirs = include_dirs;
+        self.build_glob_pattern();
+        self
+    }
+
+    pub fn with_holder_match(mut self, holder_match: HolderMatch) -> Self {
+        self.holder_match = holder_match;
+        self
+    }
+
+    pub fn with_year_format(mut self, year_format: YearFormat) -> Self {
+        self.year_format = year_format;
+        self
+    }
+
+    /// Write ranges open-ended with the word instead of the last year, e.g.
+    /// `2019-present`.
+    pub fn with_open_end(mut self, open_end: Option<String>) -> Self {
+        self.open_end = open_end;
+        self
+    }
+
+    pub fn with_dotfiles(mut self, dotfiles: DotfilePolicy) -> Self {
+        self.dotfiles = dotfiles;
+        self
+    }
+
+    pub fn with_mtime_fallback(mut self, mtime_fallback: bool) -> Self {
+        self.mtime_fallback = mtime_fallback;
+        self
+    }
+
+    pub fn with_preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.preserve_mtime = preserve_mtime;
+        self
+    }
+
+    pub fn with_policies(mut self, policies: Vec<PolicyRule>) -> Self {
+        self.policies = policies;
+        self.build_glob_pattern();
+        self
+    }
+
+    /// Only count the history of directories from the given commits on.
+    pub fn with_history_cutoffs(mut self, history_cutoffs: HashMap<String, String>) -> Self {
+        self.history_cutoffs = history_cutoffs;
+        self
+    }
+
+    pub fn with_history_traversal(mut self, history_traversal: HistoryTraversal) -> Self {
+        self.history_traversal = history_traversal;
+        self
+    }
+
+    pub fn with_year_source(mut self, year_source: YearSource) -> Self {
+        self.year_source = year_source;
+        self
+    }
+
+    pub fn with_git_backend(mut self, git_backend: GitBackend) -> Self {
+        self.git_backend = git_backend;
+        self
+    }
+
+    pub fn with_date_source(mut self, date_source: DateSource) -> Self {
+        self.date_source = date_source;
+        self
+    }
+
+    pub fn with_merge_commits(mut self, merge_commits: MergeCommits) -> Self {
+        self.merge_commits = merge_commits;
+        self
+    }
+
+    /// Check the files on the ref and take years only from its history, e.g.
+    /// a release branch, instead of `HEAD`.
+    pub fn with_history_ref(mut self, history_ref: Option<String>) -> Self {
+        self.history_ref = history_ref;
+        self
+    }
+
+    pub fn with_year_timezone(mut self, year_timezone: YearTimezone) -> Self {
+        self.year_timezone = year_timezone;
+        self
+    }
+
+    /// Only count the commits of authors matching the regex for the years,
+    /// e.g. `@example\.com>$` for the employees of the holder.
+    pub fn with_author_filter(mut self, author_filter: Option<String>) -> Self {
+        self.author_filter = author_filter;
+        self
+    }
+
+    /// Follow moved files with at least `similarity` percent of their
+    /// content, or ignore moves with `Renames::Ignore`.
+    pub fn with_renames(mut self, renames: Renames, similarity: Option<u8>) -> Self {
+        self.renames = renames;
+        self.rename_similarity = similarity;
+        self
+    }
+
+    pub fn with_detect_copies(mut self, detect_copies: bool) -> Self {
+        self.detect_copies = detect_copies;
+        self
+    }
+
+    /// Skip commits which only change whitespace or blank lines of a file,
+    /// e.g. reformatting.
+    pub fn with_ignore_whitespace_changes(mut self, ignore_whitespace_changes: bool) -> Self {
+        self.ignore_whitespace_changes = ignore_whitespace_changes;
+        self
+    }
+
+    pub fn with_ignore_mailmap(mut self, ignore_mailmap: bool) -> Self {
+        self.ignore_mailmap = ignore_mailmap;
+        self
+    }
+
+    /// Insert new copyrights in files with the extension or filename after
+    /// the leading lines matching the regexes one after another.
+    pub fn with_insert_after(mut self, key: &str, patterns: Vec<String>) -> Result<Self, CError> {
+        self.insert_after.insert(key.to_owned(), patterns);
+        self.build_insert_after_regexes()?;
+        Ok(self)
+    }
+
+    /// Exempt files whose header matches one of the regexes from copyrights.
+    pub fn with_keep_headers(mut self, keep_headers: Vec<String>) -> Result<Self, CError> {
+        self.keep_headers = keep_headers;
+        self.build_keep_regexes()?;
+        Ok(self)
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_file(cfg_file: &str) -> Result<Self, CError> {
+        Self::from_resolved(read_value(Path::new(cfg_file))?)
+    }
+
+    /// Load the config from `cfg_file` or the default config, layered with
+    /// the per-user config if it exists.
+    #[cfg(feature = "yaml")]
+    pub fn load(cfg_file: Option<&Path>) -> Result<Self, CError> {
+        let user_file = user_config_path().filter(|user_file| user_file.is_file());
+        Self::load_layered(cfg_file, user_file.as_deref())
+    }
+
+    #[cfg(feature = "yaml")]
+    fn load_layered(cfg_file: Option<&Path>, user_file: Option<&Path>) -> Result<Self, CError> {
+        let value = match (cfg_file, user_file) {
+            (Some(cfg_file), Some(user_file)) => {
+                log::debug!("Using user config {}", user_file.display());
+                merge_values(read_value(user_file)?, read_value(cfg_file)?)
+            }
+            (Some(cfg_file), None) => read_value(cfg_file)?,
+            (None, Some(user_file)) => {
+                log::debug!("Using user config {}", user_file.display());
+                let default = resolve_extends(parse_value(DEFAULT_CFG)?, Path::new(""), 0)?;
+                merge_values(default, read_value(user_file)?)
+            }
+            (None, None) => resolve_extends(parse_value(DEFAULT_CFG)?, Path::new(""), 0)?,
+        };
+        Self::from_resolved(value)
+    }
+
+    /// Config of the files below the directory of a nested config file, whose
+    /// keys override those of this config like a config extending it.
+    ///
+    /// The git backend and the mtime fallback apply to the whole repository
+    /// and are kept, other settings changed with the `with_*` methods are not.
+    #[cfg(feature = "yaml")]
+    pub fn nested(&self, cfg_file: &Path) -> Result<Self, CError> {
+        let base = self.source.clone().ok_or_else(|| {
+            CError::ConfigError(format!(
+                "Nested config {} requires the config of the repository to be loaded from a file",
+                cfg_file.display()
+            ))
+        })?;
+        let mut cfg = Self::from_resolved(merge_values(base, read_value(cfg_file)?))?;
+        cfg.git_backend = self.git_backend;
+        cfg.date_source = self.date_source;
+        cfg.merge_commits = self.merge_commits;
+        cfg.ignore_whitespace_changes = self.ignore_whitespace_changes;
+        cfg.history_ref = self.history_ref.clone();
+        cfg.year_timezone = self.year_timezone;
+        cfg.author_filter = self.author_filter.clone();
+        cfg.renames = self.renames;
+        cfg.rename_similarity = self.rename_similarity;
+        cfg.detect_copies = self.detect_copies;
+        cfg.ignore_mailmap = self.ignore_mailmap;
+        cfg.year_source = self.year_source;
+        cfg.author_lines = self.author_lines;
+        cfg.min_author_commits = self.min_author_commits;
+        cfg.mtime_fallback = self.mtime_fallback;
+        cfg.year_format = self.year_format;
+        Ok(cfg)
+    }
+
+    /// Find the config file at the root of the repository, if any.
+    #[cfg(feature = "yaml")]
+    pub fn discover(repo_path: &str) -> Option<PathBuf> {
+        REPO_CONFIG_FILES
+            .iter()
+            .map(|filename| Path::new(repo_path).join(filename))
+            .find(|cfg_path| cfg_path.is_file())
+    }
+
+    /// Build the config from a YAML value, resolving `extends` relative to `base_dir`.
+    #[cfg(feature = "yaml")]
+    fn from_value(value: Value, base_dir: &Path) -> Result<Self, CError> {
+        Self::from_resolved(resolve_extends(value, base_dir, 0)?)
+    }
+
+    /// Build the config from a YAML value without `extends`.
+    #[cfg(feature = "yaml")]
+    fn from_resolved(value: Value) -> Result<Self, CError> {
+        let mut cfg = serde_yaml::from_value::<Self>(value.clone())
+            .map_err(|e| CError::ConfigError(format!("Could not deserialize config: {}", e)))?;
+        cfg.source = Some(value);
+        if let Some(header) = cfg.header.as_deref() {
+            validate_header(header)?;
+        }
+        if let Some(template) = cfg.copyright_template.as_deref() {
+            validate_copyright_template(template)?;
+            let uses_license = parse_copyright_template(template).contains(&TemplatePart::License);
+            if uses_license && cfg.license.is_none() {
+                return Err(CError::ConfigError(format!(
+                    "Copyright template {} uses {{license}} but no `license` is set",
+                    template
+                )));
+            }
+        }
+        if let Some(author_filter) = cfg.author_filter.as_deref() {
+            Regex::new(author_filter).map_err(|e| {
+                CError::ConfigError(format!("Invalid author_filter {}: {}", author_filter, e))
+            })?;
+        }
+        if let Some(similarity) = cfg.rename_similarity.filter(|similarity| *similarity > 100) {
+            return Err(CError::ConfigError(format!(
+                "Rename similarity {} is not a percentage",
+                similarity
+            )));
+        }
+        if cfg.reuse && cfg.spdx_license.is_none() {
+            return Err(CError::ConfigError(
+                "REUSE mode requires the `spdx_license` of the files".to_owned(),
+            ));
+        }
+        cfg.build_glob_pattern();
+        cfg.build_keep_regexes()?;
+        cfg.build_insert_after_regexes()?;
+        Ok(cfg)
+    }
+
+    /// Sign following `Copyright` in generated copyright lines.
+    pub fn copyright_sign(&self) -> &'static str {
+        match self.use_copyright_symbol {
+            true => "©",
+            false => "(c)",
+        }
+    }
+
+    pub fn suffix(&self) -> Option<&str> {
+        self.suffix.as_deref()
+    }
+
+    pub fn min_year(&self) -> Option<u32> {
+        self.min_year
+    }
+
+    pub fn modified_since(&self) -> Option<&str> {
+        self.modified_since.as_deref()
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Holder in copyrights of the file by the longest matching `holders`
+    /// pattern, `None` if the name applies.
+    pub fn holder_for(&self, filepath: &str) -> Option<&str> {
+        self.holder_globs
+            .iter()
+            .find(|(pattern, _)| pattern.matches(filepath))
+            .map(|(_, holder)| holder.as_str())
+    }
+
+    /// All holders of the `holders` patterns.
+    pub fn holders(&self) -> impl Iterator<Item = &str> {
+        self.holders.values().map(String::as_str)
+    }
+
+    pub fn author_lines(&self) -> bool {
+        self.author_lines
+    }
+
+    /// Commits an author needs on a file to get a copyright line, at least one.
+    pub fn min_author_commits(&self) -> usize {
+        self.min_author_commits.max(1)
+    }
+
+    pub fn holder_match(&self) -> HolderMatch {
+        self.holder_match
+    }
+
+    pub fn year_format(&self) -> YearFormat {
+        self.year_format
+    }
+
+    /// Word ending open-ended ranges, only used with the `range` year format.
+    pub fn open_end(&self) -> Option<&str> {
+        self.open_end
+            .as_deref()
+            .filter(|_| self.year_format == YearFormat::Range)
+    }
+
+    /// Template of the copyright text, the REUSE format in REUSE mode unless
+    /// another template is set.
+    pub fn copyright_template(&self) -> Option<&str> {
+        match (self.copyright_template.as_deref(), self.reuse) {
+            (None, true) => Some(REUSE_COPYRIGHT_TEMPLATE),
+            (template, _) => template,
+        }
+    }
+
+    pub fn license(&self) -> Option<&str> {
+        self.license.as_deref()
+    }
+
+    pub fn spdx_license(&self) -> Option<&str> {
+        self.spdx_license.as_deref()
+    }
+
+    pub fn reuse(&self) -> bool {
+        self.reuse
+    }
+
+    /// Lines of the header template following the copyright line, if any.
+    pub fn header_template(&self) -> Option<&str> {
+        header_template(self.header.as_deref()?)
+    }
+
+    pub fn insert_after_banner(&self) -> bool {
+        self.insert_after_banner
+    }
+
+    pub fn normalize_cr_line_endings(&self) -> bool {
+        self.normalize_cr_line_endings
+    }
+
+    pub fn fallback_encoding(&self) -> Option<Charset> {
+        self.fallback_encoding
+    }
+
+    pub fn read_only_files(&self) -> ReadOnlyPolicy {
+        self.read_only_files
+    }
+
+    pub fn mtime_fallback(&self) -> bool {
+        self.mtime_fallback
+    }
+
+    /// Whether written files keep their modification time, which they always
+    /// do with `mtime_fallback` so that their years stay stable.
+    pub fn preserve_mtime(&self) -> bool {
+        self.preserve_mtime || self.mtime_fallback
+    }
+
+    pub fn policies(&self) -> &[PolicyRule] {
+        &self.policies
+    }
+
+    pub fn history_cutoffs(&self) -> &HashMap<String, String> {
+        &self.history_cutoffs
+    }
+
+    pub fn history_traversal(&self) -> HistoryTraversal {
+        self.history_traversal
+    }
+
+    pub fn year_source(&self) -> YearSource {
+        self.year_source
+    }
+
+    pub fn git_backend(&self) -> GitBackend {
+        self.git_backend
+    }
+
+    pub fn date_source(&self) -> DateSource {
+        self.date_source
+    }
+
+    pub fn merge_commits(&self) -> MergeCommits {
+        self.merge_commits
+    }
+
+    /// Ref whose files are checked and whose history the years are taken
+    /// from.
+    pub fn history_ref(&self) -> &str {
+        self.history_ref.as_deref().unwrap_or("HEAD")
+    }
 
-static CFG: OnceCell<Config> = OnceCell::new();
+    pub fn year_timezone(&self) -> YearTimezone {
+        self.year_timezone
+    }
 
-#[derive(Debug, Deserialize)]
-pub struct Config {
-    comment_sign_map: HashMap<String, CommentSign>,
-    ignore_files: Vec<String>,
-    ignore_dirs: Vec<String>,
-    #[serde(skip)]
-    glob_pattern: Option<Vec<Pattern>>,
-}
+    pub fn author_filter(&self) -> Option<&str> {
+        self.author_filter.as_deref()
+    }
 
-impl Config {
-    pub fn global() -> &'static Config {
-        CFG.get().expect("Config is not initialized")
+    pub fn renames(&self) -> Renames {
+        self.renames
     }
 
-    pub fn assign(self) {
-        CFG.set(self).expect("Global config is already assigned to");
+    pub fn rename_similarity(&self) -> Option<u8> {
+        self.rename_similarity
     }
 
-    pub fn default() -> Self {
-        let cfg_bytes = include_bytes!("./default_cfg.yml");
-        let cfg_str = String::from_utf8_lossy(cfg_bytes);
-        Self::from_str(&cfg_str).expect("Failed to load default config")
+    pub fn detect_copies(&self) -> bool {
+        self.detect_copies
     }
 
-    pub fn from_file(cfg_file: &str) -> Result<Self, CError> {
-        let cfg_str = std::fs::read_to_string(cfg_file)?;
-        Self::from_str(&cfg_str)
+    pub fn ignore_whitespace_changes(&self) -> bool {
+        self.ignore_whitespace_changes
     }
 
-    pub fn from_str(cfg_str: &str) -> Result<Self, CError> {
-        let mut cfg = serde_yaml::from_str::<Self>(&cfg_str)
-            .map_err(|e| CError::ConfigError(format!("Could not deserialize config: {}", e)))?;
-        cfg.build_glob_pattern();
-        return Ok(cfg);
+    pub fn ignore_mailmap(&self) -> bool {
+        self.ignore_mailmap
     }
 
+    /// Compiled `keep_headers` regexes.
+    pub fn keep_regexes(&self) -> &[Arc<Regex>] {
+        &self.keep_regexes
+    }
+
+    /// Compiled `insert_after` regexes of the file, empty if it has none.
+    pub fn get_insert_after(&self, filename: &str) -> &[Arc<Regex>] {
+        ext_or_filename(filename)
+            .and_then(|key| self.insert_after_regexes.get(key))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Preferred comment sign for new copyrights in the file.
     pub fn get_comment_sign(&self, filename: &str) -> Result<&CommentSign, CError> {
-        let filepath = Path::new(filename);
-        let ext_filename = match filepath.extension() {
-            Some(ext) => Some(ext),
-            None => filepath.file_name(),
+        if let Some(comment_sign) = self.get_dotfile_comment_sign(filename) {
+            return Ok(comment_sign);
+        }
+        self.get_comment_sign_entry(filename)?
+            .preferred()
+            .ok_or_else(|| CError::NoHeader(filename.into()))
+    }
+
+    /// All comment signs accepted in the file, with the preferred one first.
+    pub fn get_comment_signs(&self, filename: &str) -> Result<Vec<&CommentSign>, CError> {
+        if let Some(comment_sign) = self.get_dotfile_comment_sign(filename) {
+            return Ok(vec![comment_sign]);
+        }
+        match self.get_comment_sign_entry(filename)? {
+            CommentSignEntry::NoHeader(_) => Err(CError::NoHeader(filename.into())),
+            entry => Ok(entry.all()),
+        }
+    }
+
+    /// Whether files with the extension or filename of the file are mapped to
+    /// `none` since they have no header by design, e.g. JSON files.
+    pub fn has_no_header(&self, filename: &str) -> bool {
+        self.get_dotfile_comment_sign(filename).is_none()
+            && matches!(
+                self.get_comment_sign_entry(filename),
+                Ok(CommentSignEntry::NoHeader(_))
+            )
+    }
+
+    /// Number of lines at the start of the file searched for a copyright.
+    pub fn get_scan_lines(&self, filename: &str) -> usize {
+        if self.get_dotfile_comment_sign(filename).is_some() {
+            return HEADER_LINES;
+        }
+        self.get_comment_sign_entry(filename)
+            .ok()
+            .and_then(CommentSignEntry::scan_lines)
+            .unwrap_or(HEADER_LINES)
+    }
+
+    /// Special file rule applying to the file, if any.
+    pub fn get_special_file_rule(&self, filepath: &str) -> Option<&SpecialFileRule> {
+        self.special_files.iter().find(|rule| {
+            rule.glob
+                .as_ref()
+                .is_some_and(|pattern| pattern.matches(filepath))
+        })
+    }
+
+    /// All comment signs of the config.
+    pub fn comment_signs(&self) -> impl Iterator<Item = &CommentSign> {
+        let dotfile_signs = match &self.dotfiles {
+            DotfilePolicy::ByName(map) => Some(map.values()),
+            _ => None,
         };
+        self.comment_sign_map
+            .values()
+            .flat_map(|entry| entry.all().into_iter())
+            .chain(dotfile_signs.into_iter().flatten())
+    }
 
-        if let Some(ext_filename) = ext_filename {
-            if let Some(ext_filename) = ext_filename.to_str() {
-                if let Some(c_sign) = self.comment_sign_map.get(ext_filename) {
-                    return Ok(c_sign);
-                }
-            }
+    /// Comment sign of a dotfile listed in the dotfile policy.
+    fn get_dotfile_comment_sign(&self, filename: &str) -> Option<&CommentSign> {
+        match &self.dotfiles {
+            DotfilePolicy::ByName(map) => map.get(dotfile_name(filename)?),
+            _ => None,
         }
+    }
 
-        Err(CError::UnknownCommentSign(filename.into()))
+    /// Whether the dotfile policy lets the file be checked.
+    fn dotfile_included(&self, filepath: &str) -> bool {
+        match (&self.dotfiles, dotfile_name(filepath)) {
+            (_, None) | (DotfilePolicy::Normal, _) => true,
+            (DotfilePolicy::Skip, Some(_)) => false,
+            (DotfilePolicy::ByName(map), Some(name)) => map.contains_key(name),
+        }
     }
 
-    pub fn filter_files<'a>(&self, files: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
-        if self.glob_pattern.is_none() {
-            log::warn!("No glob patterns to ignore found");
+    /// Entry of the longest key of the comment sign map matching the file.
+    ///
+    /// Glob patterns are matched against the path or, without `/`, against
+    /// the file name. An extension counts like the pattern `*.ext` and wins
+    /// over a glob pattern of the same length, as does a filename.
+    fn get_comment_sign_entry(&self, filepath: &str) -> Result<&CommentSignEntry, CError> {
+        let path = Path::new(filepath);
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(filepath);
+        let glob_keys = self
+            .comment_sign_globs
+            .iter()
+            .filter(|pattern| match pattern.as_str().contains('/') {
+                true => pattern.matches(filepath),
+                false => pattern.matches(filename),
+            })
+            .map(|pattern| (pattern.as_str().len(), pattern.as_str()));
+        let plain_key = ext_or_filename(filepath)
+            .filter(|key| self.comment_sign_map.contains_key(*key))
+            .map(|key| match path.extension() {
+                Some(_) => (key.len() + 2, key),
+                None => (key.len(), key),
+            });
+        // The last of equally long keys is taken
+        glob_keys
+            .chain(plain_key)
+            .max_by_key(|(len, _)| *len)
+            .and_then(|(_, key)| self.comment_sign_map.get(key))
+            .ok_or_else(|| CError::UnknownCommentSign(filepath.into()))
+    }
+
+    /// Whether the file matches the include patterns, which include all
+    /// files if there are none.
+    ///
+    /// Directory patterns match any parent directory of the file, so that
+    /// `src`, `src/*` and `src/**` all include the files below `src`.
+    fn is_included(&self, filepath: &str) -> bool {
+        if self.include_file_patterns.is_empty() && self.include_dir_patterns.is_empty() {
+            return true;
         }
+        let in_dir = |pattern: &Pattern| {
+            pattern.matches(filepath)
+                || Path::new(filepath)
+                    .ancestors()
+                    .skip(1)
+                    .any(|dir| pattern.matches_path(dir))
+        };
+        self.include_file_patterns
+            .iter()
+            .any(|pattern| pattern.matches(filepath))
+            || self.include_dir_patterns.iter().any(in_dir)
+    }
+
+    /// Whether the file is excluded by the include or ignore patterns or the
+    /// dotfile policy.
+    pub fn is_ignored(&self, filepath: &str) -> bool {
+        !self.dotfile_included(filepath)
+            || !self.is_included(filepath)
+            || self
+                .glob_pattern
+                .iter()
+                .flatten()
+                .any(|pattern| pattern.matches(filepath))
+    }
+
+    pub fn filter_files<'a>(&self, files: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+        self.filter_files_with_stats(files).0
+    }
+
+    /// Filter files and count how many files each ignore pattern excluded.
+    ///
+    /// Files which do not match the include patterns are not counted.
+    pub fn filter_files_with_stats<'a>(
+        &self,
+        files: impl Iterator<Item = &'a String>,
+    ) -> (Vec<&'a String>, IgnoreStats) {
+        let patterns = match self.glob_pattern.as_ref() {
+            Some(patterns) => patterns.as_slice(),
+            None => {
+                log::warn!("No glob patterns to ignore found");
+                &[]
+            }
+        };
+        let mut counts = vec![0; patterns.len()];
 
-        files
-            .filter_map(|filepath| {
-                if let Some(patterns) = self.glob_pattern.as_ref() {
-                    for pattern in patterns {
-                        if pattern.matches(filepath) {
-                            return None;
-                        }
+        let files = files
+            .filter(|filepath| self.dotfile_included(filepath) && self.is_included(filepath))
+            .filter(|filepath| {
+                let mut ignored = false;
+                for (pattern, count) in patterns.iter().zip(counts.iter_mut()) {
+                    if pattern.matches(filepath) {
+                        *count += 1;
+                        ignored = true;
                     }
                 }
 
-                Some(filepath)
+                !ignored
             })
-            .collect()
-    }
+            .collect();
 
-    fn build_glob_pattern(&mut self) {
-        self.glob_pattern = Some(
-            self.ignore_files
+        let stats = IgnoreStats {
+            counts: patterns
                 .iter()
-                .chain(self.ignore_dirs.iter())
-                .filter_map(|expr| match Pattern::new(expr) {
-                    Ok(pattern) => Some(pattern),
-                    Err(_) => {
-                        log::error!("Could not compile pattern {}", expr);
-                        None
-                    }
-                })
+                .map(|pattern| pattern.as_str().to_owned())
+                .zip(counts)
                 .collect(),
-        );
+        };
+        (files, stats)
+    }
+
+    fn build_keep_regexes(&mut self) -> Result<(), CError> {
+        self.keep_regexes = self
+            .keep_headers
+            .iter()
+            .map(|expr| {
+                Regex::new(expr).map(Arc::new).map_err(|e| {
+                    CError::ConfigError(format!("Invalid keep_headers regex {}: {}", expr, e))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    fn build_insert_after_regexes(&mut self) -> Result<(), CError> {
+        self.insert_after_regexes = self
+            .insert_after
+            .iter()
+            .map(|(key, exprs)| {
+                let regexes = exprs
+                    .iter()
+                    .map(|expr| {
+                        Regex::new(expr).map(Arc::new).map_err(|e| {
+                            CError::ConfigError(format!(
+                                "Invalid insert_after regex {} for {}: {}",
+                                expr, key, e
+                            ))
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok((key.clone(), regexes))
+            })
+            .collect::<Result<_, CError>>()?;
+        Ok(())
+    }
+
+    fn build_glob_pattern(&mut self) {
+        self.glob_pattern = Some(compile_patterns(
+            self.ignore_files.iter().chain(self.ignore_dirs.iter()),
+        ));
+        let mut glob_keys: Vec<&String> = self
+            .comment_sign_map
+            .keys()
+            .filter(|key| key.contains(['*', '?', '[']))
+            .collect();
+        glob_keys.sort();
+        self.comment_sign_globs = compile_patterns(glob_keys.into_iter());
+        let mut holder_patterns: Vec<&String> = self.holders.keys().collect();
+        holder_patterns.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        self.holder_globs = compile_patterns(holder_patterns.into_iter())
+            .into_iter()
+            .map(|pattern| {
+                let holder = self.holders[pattern.as_str()].clone();
+                (pattern, holder)
+            })
+            .collect();
+        self.include_file_patterns = compile_patterns(self.include_files.iter());
+        self.include_dir_patterns = compile_patterns(self.include_dirs.iter());
+
+        for rule in self.policies.iter_mut() {
+            rule.build_globs();
+        }
+        for rule in self.special_files.iter_mut() {
+            rule.glob = match Pattern::new(&rule.path) {
+                Ok(pattern) => Some(pattern),
+                Err(_) => {
+                    log::error!("Could not compile pattern {}", rule.path);
+                    None
+                }
+            };
+        }
+    }
+}
+
+/// Compile glob patterns, logging and skipping invalid ones.
+fn compile_patterns<'a>(exprs: impl Iterator<Item = &'a String>) -> Vec<Pattern> {
+    exprs
+        .filter_map(|expr| match Pattern::new(expr) {
+            Ok(pattern) => Some(pattern),
+            Err(_) => {
+                log::error!("Could not compile pattern {}", expr);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Extension of the file or its name if it has none, the key of per-type
+/// settings like `comment_sign_map`.
+fn ext_or_filename(filepath: &str) -> Option<&str> {
+    let path = Path::new(filepath);
+    path.extension().or_else(|| path.file_name())?.to_str()
+}
+
+/// File name of a path if it starts with a dot.
+fn dotfile_name(filepath: &str) -> Option<&str> {
+    Path::new(filepath)
+        .file_name()?
+        .to_str()
+        .filter(|name| name.starts_with('.'))
+}
+
+#[cfg(feature = "yaml")]
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_str(DEFAULT_CFG).expect("Failed to load default config")
+    }
+}
+
+/// Without YAML support, there is no embedded default config and the default
+/// knows no comment signs and ignores nothing.
+#[cfg(not(feature = "yaml"))]
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(HashMap::new(), Vec::new(), Vec::new())
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl FromStr for Config {
+    type Err = CError;
+
+    fn from_str(cfg_str: &str) -> Result<Self, CError> {
+        Self::from_value(parse_value(cfg_str)?, Path::new(""))
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn parse_value(cfg_str: &str) -> Result<Value, CError> {
+    serde_yaml::from_str(cfg_str)
+        .map_err(|e| CError::ConfigError(format!("Could not parse config: {}", e)))
+}
+
+/// Format of a config file.
+#[cfg(feature = "yaml")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+#[cfg(feature = "yaml")]
+impl ConfigFormat {
+    /// Format of the config file at the path or URL by its extension,
+    /// defaulting to YAML.
+    fn detect(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    /// Parse the config into a YAML value, on which `extends` and layering
+    /// work the same for all formats.
+    fn parse(self, cfg_str: &str) -> Result<Value, CError> {
+        let parse_error = |e: &dyn std::fmt::Display| {
+            CError::ConfigError(format!("Could not parse config: {}", e))
+        };
+        match self {
+            ConfigFormat::Yaml => parse_value(cfg_str),
+            #[cfg(feature = "toml")]
+            ConfigFormat::Toml => toml::from_str(cfg_str).map_err(|e| parse_error(&e)),
+            #[cfg(not(feature = "toml"))]
+            ConfigFormat::Toml => Err(CError::ConfigError(
+                "TOML configs require the `toml` feature".to_owned(),
+            )),
+            ConfigFormat::Json => serde_json::from_str(cfg_str).map_err(|e| parse_error(&e)),
+        }
+    }
+}
+
+/// Read a config file, resolving `extends` relative to the file.
+#[cfg(feature = "yaml")]
+fn read_value(cfg_path: &Path) -> Result<Value, CError> {
+    let cfg_str = std::fs::read_to_string(cfg_path)
+        .map_err(|_| CError::ReadError(cfg_path.display().to_string()))?;
+    let base_dir = cfg_path.parent().unwrap_or_else(|| Path::new(""));
+    let format = ConfigFormat::detect(&cfg_path.to_string_lossy());
+    resolve_extends(format.parse(&cfg_str)?, base_dir, 0)
+}
+
+/// Path of the per-user config, `$XDG_CONFIG_HOME/git-copyright/config.yml`.
+#[cfg(feature = "yaml")]
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) => PathBuf::from(config_home),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("git-copyright").join("config.yml"))
+}
+
+/// Replace the key `extends` with the content of the referenced config.
+#[cfg(feature = "yaml")]
+fn resolve_extends(mut value: Value, base_dir: &Path, depth: usize) -> Result<Value, CError> {
+    let extends = match value.as_mapping_mut() {
+        Some(mapping) => mapping.remove(&Value::from("extends")),
+        None => None,
+    };
+    let extends = match extends {
+        Some(Value::String(extends)) => extends,
+        Some(other) => {
+            return Err(CError::ConfigError(format!(
+                "Expected path or URL in `extends`, got {:?}",
+                other
+            )))
+        }
+        None => return Ok(value),
+    };
+
+    if depth >= MAX_EXTENDS_DEPTH {
+        return Err(CError::ConfigError(format!(
+            "Config extends more than {} levels deep, is there a cycle?",
+            MAX_EXTENDS_DEPTH
+        )));
+    }
+
+    log::debug!("Extending config {}", extends);
+    let format = ConfigFormat::detect(&extends);
+    let base = if extends.starts_with("http://") {
+        return Err(CError::ConfigError(format!(
+            "Refusing to extend config {} over plain http, use https",
+            extends
+        )));
+    } else if extends.starts_with("https://") {
+        resolve_extends(
+            format.parse(&fetch_remote_config(&extends)?)?,
+            base_dir,
+            depth + 1,
+        )?
+    } else {
+        let base_path = base_dir.join(&extends);
+        let base_str = std::fs::read_to_string(&base_path)
+            .map_err(|_| CError::ReadError(base_path.display().to_string()))?;
+        let base_dir = base_path.parent().unwrap_or_else(|| Path::new(""));
+        resolve_extends(format.parse(&base_str)?, base_dir, depth + 1)?
+    };
+
+    Ok(merge_values(base, value))
+}
+
+/// Merge `overlay` into `base`, merging mappings recursively.
+#[cfg(feature = "yaml")]
+fn merge_values(base: Value, overlay: Value) -> Value {
+    match (base, overlay) {
+        (Value::Mapping(mut base), Value::Mapping(overlay)) => {
+            for (key, overlay_value) in overlay {
+                let merged = match base.remove(&key) {
+                    Some(base_value) => merge_values(base_value, overlay_value),
+                    None => overlay_value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Mapping(base)
+        }
+        (_, overlay) => overlay,
+    }
+}
+
+/// Fetch a remote config and cache it, falling back to the cache on errors.
+#[cfg(feature = "yaml")]
+fn fetch_remote_config(url: &str) -> Result<String, CError> {
+    let cache_file = remote_cache_dir().map(|dir| dir.join(remote_cache_name(url)));
+
+    let fetched = ureq::get(url)
+        .call()
+        .map_err(|e| e.to_string())
+        .and_then(|response| response.into_string().map_err(|e| e.to_string()));
+
+    match (fetched, cache_file) {
+        (Ok(cfg_str), cache_file) => {
+            if let Some(cache_file) = cache_file {
+                let cached = cache_file
+                    .parent()
+                    .map(std::fs::create_dir_all)
+                    .unwrap_or(Ok(()))
+                    .and_then(|_| std::fs::write(&cache_file, &cfg_str));
+                if let Err(e) = cached {
+                    log::warn!("Could not cache config {}: {}", url, e);
+                }
+            }
+            Ok(cfg_str)
+        }
+        (Err(e), Some(cache_file)) if cache_file.is_file() => {
+            log::warn!(
+                "Could not fetch config {} ({}), using cached version",
+                url,
+                e
+            );
+            Ok(std::fs::read_to_string(cache_file)?)
+        }
+        (Err(e), _) => Err(CError::ConfigError(format!(
+            "Could not fetch config {}: {}",
+            url, e
+        ))),
     }
 }
 
-#[cfg(test)]
+/// Name of the cache file of a remote config, stable across builds and platforms.
+#[cfg(feature = "yaml")]
+fn remote_cache_name(url: &str) -> String {
+    let digest = Sha256::digest(url.as_bytes());
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}.yml", hex)
+}
+
+#[cfg(feature = "yaml")]
+fn remote_cache_dir() -> Option<PathBuf> {
+    let cache_home = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(cache_home) => PathBuf::from(cache_home),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache_home.join("git-copyright").join("extends"))
+}
+
+#[cfg(all(test, feature = "yaml"))]
 mod test {
 
-    use super::{CommentSign, Config};
+    use super::{CommentSign, Config, YearTimezone};
+    use crate::regex_ops::{generate_base_regex, generate_copyright_line, CopyrightCache};
+    use crate::CError;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_config_in_code() {
+        let cfg = Config::new(
+            HashMap::from([("rs".to_owned(), CommentSign::LeftOnly("//".into()))]),
+            vec!["*.txt".to_owned()],
+            vec!["target/*".to_owned()],
+        )
+        .with_copyright_symbol(true)
+        .with_suffix(Some("All rights reserved.".into()));
+
+        assert_eq!(
+            cfg.get_comment_sign("src/main.rs").unwrap(),
+            &CommentSign::LeftOnly("//".into())
+        );
+        assert!(cfg.get_comment_sign("main.py").is_err());
+        assert!(cfg.is_ignored("notes.txt"));
+        assert!(cfg.is_ignored("target/debug/main.rs"));
+        assert!(!cfg.is_ignored("src/main.rs"));
+        assert_eq!(cfg.copyright_sign(), "©");
+        assert_eq!(cfg.suffix(), Some("All rights reserved."));
+    }
 
     #[test]
     fn test_config_from_file() {
@@ -124,13 +1796,330 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_detailed_comment_sign() {
+        let cfg_str = r#"
+ignore_files: []
+ignore_dirs: []
+comment_sign_map:
+  bat: { left: "REM", separator: "  " }
+  ml: { left: "(*", right: "*)" }
+  s: { left: ";", separator: "" }
+  json: none
+  f: { left: "/*", right: "*/", right_separator: "  " }
+"#;
+        let cfg = Config::from_str(cfg_str).unwrap();
+        let comment_sign = cfg.get_comment_sign("run.bat").unwrap();
+        assert_eq!(comment_sign.left(), "REM");
+        assert_eq!(comment_sign.right(), None);
+        assert_eq!(comment_sign.separator(), "  ");
+
+        let comment_sign = cfg.get_comment_sign("main.ml").unwrap();
+        assert_eq!(comment_sign.right(), Some("*)"));
+        assert_eq!(comment_sign.separator(), " ");
+        assert_eq!(comment_sign.right_separator(), " ");
+
+        let comment_sign = cfg.get_comment_sign("boot.s").unwrap();
+        assert_eq!(comment_sign.separator(), "");
+
+        let comment_sign = cfg.get_comment_sign("main.f").unwrap();
+        assert_eq!(comment_sign.separator(), " ");
+        assert_eq!(comment_sign.right_separator(), "  ");
+
+        // Written headers are found again by the generated regex
+        let base_regex = generate_base_regex("DummyCompany Ltd.", &cfg);
+        for filepath in ["run.bat", "main.ml", "boot.s", "main.f"] {
+            let comment_sign = cfg.get_comment_sign(filepath).unwrap();
+            let cache = CopyrightCache::new(&base_regex, [comment_sign].into_iter());
+            let line = futures::executor::block_on(generate_copyright_line(
+                "DummyCompany Ltd.",
+                filepath,
+                comment_sign,
+                &cfg,
+                futures::future::ready("2022".to_owned()),
+            ));
+            assert!(
+                cache.get_regex(comment_sign).unwrap().is_match(&line),
+                "{}",
+                line
+            );
+        }
+
+        assert!(cfg.has_no_header("package.json"));
+        assert!(!cfg.has_no_header("run.bat"));
+        assert!(!cfg.has_no_header("image.png"));
+        assert!(matches!(
+            cfg.get_comment_signs("package.json"),
+            Err(CError::NoHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_accepted_comment_signs() {
+        let cfg_str = r#"
+ignore_files: []
+ignore_dirs: []
+comment_sign_map:
+  c:
+    preferred: ["/*", "*/"]
+    accepted: ["//"]
+  h: "//"
+"#;
+        let cfg = Config::from_str(cfg_str).unwrap();
+        assert_eq!(
+            cfg.get_comment_sign("main.c").unwrap(),
+            &CommentSign::Enclosing("/*".into(), "*/".into())
+        );
+        assert_eq!(
+            cfg.get_comment_signs("main.c").unwrap(),
+            vec![
+                &CommentSign::Enclosing("/*".into(), "*/".into()),
+                &CommentSign::LeftOnly("//".into())
+            ]
+        );
+        assert_eq!(
+            cfg.get_comment_signs("main.h").unwrap(),
+            vec![&CommentSign::LeftOnly("//".into())]
+        );
+    }
+
+    #[test]
+    fn test_extends() {
+        let dir =
+            std::env::temp_dir().join(format!("git_copyright_extends_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(
+            dir.join("shared").join("base.yml"),
+            "ignore_files: [\"*.txt\"]\nignore_dirs: []\ncomment_sign_map: {rs: \"//\", py: \"#\"}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("local.yml"),
+            "extends: shared/base.yml\nsuffix: All rights reserved.\ncomment_sign_map: {py: \"##\"}\n",
+        )
+        .unwrap();
+
+        let cfg = Config::from_file(dir.join("local.yml").to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(cfg.ignore_files, vec!["*.txt".to_owned()]);
+        assert_eq!(cfg.suffix(), Some("All rights reserved."));
+        assert_eq!(
+            cfg.get_comment_sign("main.rs").unwrap(),
+            &CommentSign::LeftOnly("//".into())
+        );
+        assert_eq!(
+            cfg.get_comment_sign("main.py").unwrap(),
+            &CommentSign::LeftOnly("##".into())
+        );
+    }
+
+    #[test]
+    fn test_extends_remote() {
+        let cfg_str = "extends: http://example.com/base.yml\nignore_files: []\n";
+        match Config::from_str(cfg_str) {
+            Err(CError::ConfigError(msg)) => assert!(msg.contains("https"), "{}", msg),
+            other => panic!("Expected config error, got {:?}", other.map(|_| ())),
+        }
+
+        assert_eq!(
+            super::remote_cache_name("https://example.com/base.yml"),
+            "5ba4babb0c5a39b26e90025b58426aa4d3b3fe70f3b9b258a4f9221c9edce978.yml"
+        );
+    }
+
+    #[test]
+    fn test_special_file_rule() {
+        let cfg_str = r#"
+ignore_files: []
+ignore_dirs: []
+comment_sign_map: {}
+special_files:
+  - path: "docs/conf.py"
+    pattern: 'copyright = "(\d{4}(-\d{4})?)'
+    replacement: 'copyright = "{years}'
+"#;
+        let cfg = Config::from_str(cfg_str).unwrap();
+        assert!(cfg.get_special_file_rule("conf.py").is_none());
+        let rule = cfg.get_special_file_rule("docs/conf.py").unwrap();
+        assert_eq!(
+            rule.replacement("2020-2022", "Dummy"),
+            "copyright = \"2020-2022"
+        );
+    }
+
+    #[test]
+    fn test_insert_after() {
+        let cfg = Config::default();
+        assert_eq!(cfg.get_insert_after("docs/index.md").len(), 2);
+        assert!(cfg.get_insert_after("pom.xml")[0].is_match("<?xml version=\"1.0\"?>"));
+        assert!(cfg.get_insert_after("main.rs").is_empty());
+
+        let cfg_str = r#"
+ignore_files: []
+ignore_dirs: []
+comment_sign_map: {}
+insert_after:
+  xml: ['<?xml (']
+"#;
+        assert!(matches!(
+            Config::from_str(cfg_str),
+            Err(CError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_dotfile_policy() {
+        let files: Vec<String> = ["src/.bashrc", ".clang-format", "src/main.rs"]
+            .iter()
+            .map(|&elm| elm.into())
+            .collect();
+
+        let cfg_str = "ignore_files: []\nignore_dirs: []\ncomment_sign_map: {}\ndotfiles: skip\n";
+        let cfg = Config::from_str(cfg_str).unwrap();
+        assert_eq!(cfg.filter_files(files.iter()), vec!["src/main.rs"]);
+
+        let cfg_str = r##"
+ignore_files: []
+ignore_dirs: []
+comment_sign_map: {}
+dotfiles:
+  by_name:
+    .bashrc: "#"
+"##;
+        let cfg = Config::from_str(cfg_str).unwrap();
+        assert_eq!(
+            cfg.filter_files(files.iter()),
+            vec!["src/.bashrc", "src/main.rs"]
+        );
+        assert_eq!(
+            cfg.get_comment_sign("src/.bashrc").unwrap(),
+            &CommentSign::LeftOnly("#".into())
+        );
+        assert_eq!(cfg.comment_signs().count(), 1);
+    }
+
+    #[test]
+    fn test_ignore_stats() {
+        let files: Vec<String> = ["archive.gz", "notes.txt", "more_notes.txt", "src/main.rs"]
+            .iter()
+            .map(|&elm| elm.into())
+            .collect();
+        let cfg = Config::new(
+            HashMap::new(),
+            vec!["*.gz".into(), "*.txt".into(), "*.zip".into()],
+            Vec::new(),
+        );
+
+        let (filtered_files, stats) = cfg.filter_files_with_stats(files.iter());
+        assert_eq!(filtered_files, vec!["src/main.rs"]);
+        assert_eq!(
+            stats.counts,
+            vec![
+                ("*.gz".to_owned(), 1),
+                ("*.txt".to_owned(), 2),
+                ("*.zip".to_owned(), 0)
+            ]
+        );
+        assert_eq!(stats.unused_patterns().collect::<Vec<_>>(), vec!["*.zip"]);
+    }
+
+    #[test]
+    fn test_discover() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_discover");
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        let repo_path = repo_dir.to_str().unwrap();
+        assert_eq!(Config::discover(repo_path), None);
+
+        std::fs::write(repo_dir.join("git-copyright.yaml"), "").unwrap();
+        std::fs::write(repo_dir.join(".git-copyright.yml"), "").unwrap();
+        assert_eq!(
+            Config::discover(repo_path),
+            Some(repo_dir.join(".git-copyright.yml"))
+        );
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_and_json_configs() {
+        let cfg_dir = std::env::temp_dir().join("git_copyright_test_config_formats");
+        std::fs::create_dir_all(&cfg_dir).unwrap();
+        std::fs::write(
+            cfg_dir.join("base.json"),
+            r#"{"comment_sign_map": {"rs": "//", "c": ["/*", "*/"]}, "name": "Base"}"#,
+        )
+        .unwrap();
+        let toml_file = cfg_dir.join("config.toml");
+        std::fs::write(
+            &toml_file,
+            r##"extends = "base.json"
+name = "Dummy"
+ignore_files = ["*.txt"]
+ignore_dirs = []
+
+[comment_sign_map]
+py = "#"
+json = "none"
+"##,
+        )
+        .unwrap();
+
+        let config = Config::from_file(toml_file.to_str().unwrap()).unwrap();
+        assert_eq!(config.name(), Some("Dummy"));
+        assert!(matches!(
+            config.get_comment_sign("main.c"),
+            Ok(CommentSign::Enclosing(_, _))
+        ));
+        assert!(config.get_comment_sign("main.rs").is_ok());
+        assert!(config.get_comment_sign("main.py").is_ok());
+        assert!(matches!(
+            config.get_comment_sign("data.json"),
+            Err(CError::NoHeader(_))
+        ));
+        let files = ["notes.txt".to_owned(), "main.rs".to_owned()];
+        assert_eq!(config.filter_files(files.iter()), vec!["main.rs"]);
+
+        std::fs::write(&toml_file, "name = [").unwrap();
+        assert!(matches!(
+            Config::from_file(toml_file.to_str().unwrap()),
+            Err(CError::ConfigError(_))
+        ));
+        std::fs::remove_dir_all(cfg_dir).unwrap();
+    }
+
+    #[test]
+    fn test_user_config() {
+        let cfg_dir = std::env::temp_dir().join("git_copyright_test_user_config");
+        std::fs::create_dir_all(&cfg_dir).unwrap();
+        let user_file = cfg_dir.join("user.yml");
+        std::fs::write(&user_file, "name: Dummy\nuse_copyright_symbol: true\n").unwrap();
+        let repo_file = cfg_dir.join("repo.yml");
+        std::fs::write(
+            &repo_file,
+            "use_copyright_symbol: false\nignore_files: []\nignore_dirs: []\ncomment_sign_map: {}\n",
+        )
+        .unwrap();
+
+        let cfg = Config::load_layered(None, Some(&user_file)).unwrap();
+        assert_eq!(cfg.name(), Some("Dummy"));
+        assert_eq!(cfg.copyright_sign(), "©");
+        assert!(cfg.get_comment_sign("file.rs").is_ok());
+
+        let cfg = Config::load_layered(Some(&repo_file), Some(&user_file)).unwrap();
+        assert_eq!(cfg.name(), Some("Dummy"));
+        assert_eq!(cfg.copyright_sign(), "(c)");
+        assert!(cfg.get_comment_sign("file.rs").is_err());
+        std::fs::remove_dir_all(cfg_dir).unwrap();
+    }
+
     #[test]
     fn test_filter_files() {
-        let unfiltered: Vec<String> = vec!["dev/myfile.rs", "general/myfile.py", "another_file.py"]
+        let unfiltered: Vec<String> = ["dev/myfile.rs", "general/myfile.py", "another_file.py"]
             .iter()
             .map(|&elm| elm.into())
             .collect();
-        let to_filter: Vec<String> = vec![
+        let to_filter: Vec<String> = [
             "filter_me.txt",
             "./dev/I_want_out.txt",
             "dev/__pycache__/valid_file_in_ignored_folder.py",
@@ -151,4 +2140,105 @@ mod test {
             assert!(!filtered_files.contains(&filename));
         }
     }
+
+    #[test]
+    fn test_comment_sign_globs() {
+        let cfg = Config::from_str(
+            r###"
+ignore_files: []
+ignore_dirs: []
+comment_sign_map:
+  in: "#"
+  Dockerfile: "#"
+  "Dockerfile.*": "#"
+  "*.cmake.in": "##"
+  "Jenkinsfile*": "//"
+  "docs/*.in": none
+"###,
+        )
+        .unwrap();
+        let left = |filepath: &str| {
+            cfg.get_comment_sign(filepath)
+                .map(|sign| sign.left().to_owned())
+        };
+
+        assert_eq!(left("Dockerfile").unwrap(), "#");
+        assert_eq!(left("deploy/Dockerfile.prod").unwrap(), "#");
+        assert_eq!(left("cmake/config.cmake.in").unwrap(), "##");
+        assert_eq!(left("MANIFEST.in").unwrap(), "#");
+        assert_eq!(left("ci/Jenkinsfile.nightly").unwrap(), "//");
+        assert!(cfg.has_no_header("docs/index.in"));
+        assert!(matches!(
+            left("notes.unknown"),
+            Err(CError::UnknownCommentSign(_))
+        ));
+    }
+
+    #[test]
+    fn test_holders() {
+        let cfg = Config::from_str(
+            r#"
+ignore_files: []
+ignore_dirs: []
+comment_sign_map:
+  rs: "//"
+holders:
+  "vendor/*": "Vendor GmbH"
+  "vendor/acme/*": "ACME Corp."
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(cfg.holder_for("vendor/lib.rs"), Some("Vendor GmbH"));
+        assert_eq!(cfg.holder_for("vendor/acme/src/lib.rs"), Some("ACME Corp."));
+        assert_eq!(cfg.holder_for("src/main.rs"), None);
+    }
+
+    #[test]
+    fn test_year_timezone() {
+        let timezone = |value: &str| {
+            Config::from_str(&format!(
+                "ignore_files: []\nignore_dirs: []\ncomment_sign_map: {{}}\nyear_timezone: \"{}\"",
+                value
+            ))
+            .map(|cfg| cfg.year_timezone())
+        };
+        assert_eq!(timezone("utc").unwrap(), YearTimezone::Utc);
+        assert_eq!(timezone("+05:30").unwrap(), YearTimezone::Fixed(330));
+        assert_eq!(timezone("-0800").unwrap(), YearTimezone::Fixed(-480));
+        assert!(matches!(timezone("CET"), Err(CError::ConfigError(_))));
+        assert!(matches!(timezone("+25:00"), Err(CError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_include_patterns() {
+        let files: Vec<String> = [
+            "src/main.rs",
+            "src/nested/lib.rs",
+            "include/api.h",
+            "include/api.txt",
+            "scripts/build.sh",
+            "build.rs",
+            "tools/gen.py",
+        ]
+        .iter()
+        .map(|&elm| elm.into())
+        .collect();
+
+        let cfg = Config::default().with_include(
+            vec!["tools/*.py".to_owned()],
+            vec!["src".to_owned(), "include/**".to_owned()],
+        );
+        assert_eq!(
+            cfg.filter_files(files.iter()),
+            vec![
+                "src/main.rs",
+                "src/nested/lib.rs",
+                "include/api.h",
+                "tools/gen.py"
+            ]
+        );
+        assert!(cfg.is_ignored("build.rs"));
+        assert!(!Config::default().is_ignored("build.rs"));
+    }
 }
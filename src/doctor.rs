@@ -0,0 +1,488 @@
+//! Diagnose the environment of runs with the `doctor` subcommand.
+//!
+//! Runs failing in CI are often caused by the environment rather than the
+//! copyrights, e.g. a missing git, a shallow clone, a repository owned by
+//! another user or an invalid config. Each check yields a finding with an
+//! actionable message.
+
+#[cfg(feature = "yaml")]
+use crate::config::Config;
+use crate::git_ops::{check_git_version, detect_history_rewrites, format_git_version};
+use crate::git_ops::{get_files_on_ref, get_repo_layout};
+use crate::CError;
+use std::path::Path;
+
+/// Name of the file created to verify that the repository is writable.
+const WRITE_PROBE_FILE: &str = ".git-copyright-doctor";
+
+/// Severity of a finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Result of a single check of the environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Finding {
+    fn ok(check: &'static str, message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Ok,
+            check,
+            message: message.into(),
+        }
+    }
+
+    fn warning(check: &'static str, message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Warning,
+            check,
+            message: message.into(),
+        }
+    }
+
+    fn error(check: &'static str, message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Error,
+            check,
+            message: message.into(),
+        }
+    }
+
+    pub fn human_message(&self) -> String {
+        let label = match self.severity {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        format!("[{}] {}: {}", label, self.check, self.message)
+    }
+}
+
+/// Fail if any of the findings of `diagnose` is an error.
+pub fn check_findings(findings: &[Finding]) -> Result<(), CError> {
+    let num_errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    match num_errors {
+        0 => Ok(()),
+        _ => Err(CError::EnvironmentProblems(num_errors)),
+    }
+}
+
+/// Check git, the repository, the config and write permissions.
+pub async fn diagnose(repo_path: &str, cfg_file: Option<&Path>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    match check_git_version().await {
+        Ok(version) => findings.push(Finding::ok(
+            "git",
+            format!("git version {}", format_git_version(version)),
+        )),
+        Err(e @ CError::UnsupportedGitVersion { .. }) => {
+            findings.push(Finding::error("git", format!("{}, update git", e)));
+            return findings;
+        }
+        Err(e) => {
+            findings.push(Finding::error(
+                "git",
+                format!("{}, install git and add it to the PATH", e),
+            ));
+            return findings;
+        }
+    }
+
+    let in_repo = check_repository(repo_path, &mut findings).await;
+    let files = match in_repo {
+        true => get_files_on_ref(repo_path, "HEAD")
+            .await
+            .unwrap_or_default(),
+        false => Vec::new(),
+    };
+    check_config(cfg_file, &files, &mut findings);
+    check_permissions(repo_path, &files, &mut findings);
+
+    findings
+}
+
+/// Check the state of the repository, returning whether it can be used.
+async fn check_repository(repo_path: &str, findings: &mut Vec<Finding>) -> bool {
+    let layout = match get_repo_layout(repo_path).await {
+        Ok(layout) => layout,
+        Err(CError::GitCmdError(stderr)) if stderr.contains("dubious ownership") => {
+            let abs_path = Path::new(repo_path)
+                .canonicalize()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|_| repo_path.to_owned());
+            findings.push(Finding::error(
+                "repository",
+                format!(
+                    "{} is owned by another user, allow it with `git config --global --add safe.directory {}`",
+                    repo_path, abs_path
+                ),
+            ));
+            return false;
+        }
+        Err(e) => {
+            findings.push(Finding::error(
+                "repository",
+                format!(
+                    "{} is not a git repository ({}), pass the repository root with `--repo` or set `mtime_fallback` to check plain directories",
+                    repo_path,
+                    e.to_string().trim()
+                ),
+            ));
+            return false;
+        }
+    };
+
+    if layout.bare {
+        findings.push(Finding::error(
+            "repository",
+            format!("{} is a bare repository without files to check", repo_path),
+        ));
+        return false;
+    }
+    findings.push(Finding::ok(
+        "repository",
+        match layout.linked_worktree {
+            true => format!("{} is a linked worktree", repo_path),
+            false => format!("{} is a git repository", repo_path),
+        },
+    ));
+    if layout.sparse_checkout {
+        findings.push(Finding::warning(
+            "repository",
+            "sparse checkout is enabled, files outside of the checkout cannot be checked",
+        ));
+    }
+
+    match detect_history_rewrites(repo_path).await {
+        Ok(reasons) if reasons.is_empty() => findings.push(Finding::ok("history", "complete")),
+        Ok(reasons) => findings.extend(reasons.into_iter().map(|reason| {
+            let advice = match reason.as_str() {
+                "the clone is shallow" => {
+                    ", fetch the full history with `git fetch --unshallow` or `fetch-depth: 0` of actions/checkout"
+                }
+                _ => "",
+            };
+            Finding::warning(
+                "history",
+                format!("years may be wrong because {}{}", reason, advice),
+            )
+        })),
+        Err(e) => findings.push(Finding::warning("history", e.to_string())),
+    }
+    true
+}
+
+/// Check that the config loads and covers the files of the repository.
+#[cfg(feature = "yaml")]
+fn check_config(cfg_file: Option<&Path>, files: &[String], findings: &mut Vec<Finding>) {
+    let source = match cfg_file {
+        Some(cfg_file) => cfg_file.display().to_string(),
+        None => "the default config".to_owned(),
+    };
+    let config = match Config::load(cfg_file) {
+        Ok(config) => config,
+        Err(e) => {
+            let details = match e {
+                CError::ConfigError(details) => details,
+                e => e.to_string(),
+            };
+            findings.push(Finding::error(
+                "config",
+                format!("{} is invalid: {}", source, details),
+            ));
+            return;
+        }
+    };
+    findings.push(Finding::ok("config", format!("using {}", source)));
+
+    if config.name().is_none() {
+        findings.push(Finding::warning(
+            "config",
+            "no `name` is set, pass the name in copyrights with `--name`",
+        ));
+    }
+
+    let unknown: Vec<&String> = config
+        .filter_files(files.iter())
+        .into_iter()
+        .filter(|f| config.get_special_file_rule(f).is_none())
+        .filter(|f| !config.has_no_header(f) && config.get_comment_signs(f).is_err())
+        .collect();
+    if let Some(example) = unknown.first() {
+        findings.push(Finding::warning(
+            "config",
+            format!(
+                "{} files have no comment sign and are not ignored, e.g. {}, add them to `comment_sign_map` (or map them to `none`) or `ignore_files`",
+                unknown.len(),
+                example
+            ),
+        ));
+    }
+}
+
+#[cfg(not(feature = "yaml"))]
+fn check_config(_cfg_file: Option<&Path>, _files: &[String], _findings: &mut Vec<Finding>) {}
+
+/// Check that files can be written in the repository.
+fn check_permissions(repo_path: &str, files: &[String], findings: &mut Vec<Finding>) {
+    let probe = Path::new(repo_path).join(WRITE_PROBE_FILE);
+    let writable = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .and_then(|_| std::fs::remove_file(&probe));
+    match writable {
+        Ok(_) => findings.push(Finding::ok(
+            "permissions",
+            format!("{} is writable", repo_path),
+        )),
+        Err(e) => findings.push(Finding::error(
+            "permissions",
+            format!(
+                "cannot write to {} ({}), run as the owner of the checkout, e.g. with `-u $(id -u)` in Docker",
+                repo_path, e
+            ),
+        )),
+    }
+
+    let read_only: Vec<&String> = files
+        .iter()
+        .filter(|f| {
+            std::fs::metadata(Path::new(repo_path).join(f))
+                .is_ok_and(|metadata| metadata.permissions().readonly())
+        })
+        .collect();
+    if let Some(example) = read_only.first() {
+        findings.push(Finding::warning(
+            "permissions",
+            format!(
+                "{} files are read-only, e.g. {}, set `read_only_files` to `write` or `skip` to handle them",
+                read_only.len(),
+                example
+            ),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diagnose, Finding, Severity};
+    use std::path::{Path, PathBuf};
+
+    fn init_repo(name: &str) -> PathBuf {
+        let repo_dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        git(&repo_dir, &["init", "-q"]);
+        std::fs::write(repo_dir.join("main.rs"), "fn main() {}\n").unwrap();
+        git(&repo_dir, &["add", "-A"]);
+        git(&repo_dir, &["commit", "-q", "-m", "Initial commit"]);
+        repo_dir
+    }
+
+    fn git(repo_dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=Dummy",
+                "-c",
+                "user.email=dummy@example.com",
+                "-c",
+                "protocol.file.allow=always",
+            ])
+            .args(args)
+            .current_dir(repo_dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// Findings of a check other than the passed ones.
+    fn problems<'a>(findings: &'a [Finding], check: &str) -> Vec<&'a Finding> {
+        findings
+            .iter()
+            .filter(|finding| finding.check == check && finding.severity != Severity::Ok)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_repository() {
+        let repo_dir = init_repo("git_copyright_test_doctor_repository");
+        let repo_path = repo_dir.to_str().unwrap();
+        let findings = diagnose(repo_path, None).await;
+        assert_eq!(findings[0].check, "git");
+        assert_eq!(findings[0].severity, Severity::Ok);
+        assert!(findings.contains(&Finding::ok(
+            "repository",
+            format!("{} is a git repository", repo_path)
+        )));
+        assert!(findings.contains(&Finding::ok("history", "complete")));
+        assert!(problems(&findings, "repository").is_empty());
+        assert!(problems(&findings, "permissions").is_empty());
+
+        let not_a_repo = std::env::temp_dir().join("git_copyright_test_doctor_no_repo");
+        let _ = std::fs::remove_dir_all(&not_a_repo);
+        std::fs::create_dir_all(&not_a_repo).unwrap();
+        let findings = diagnose(not_a_repo.to_str().unwrap(), None).await;
+        let found = problems(&findings, "repository");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Error);
+        assert!(found[0].message.contains("is not a git repository"));
+        assert!(found[0].message.contains("`mtime_fallback`"));
+        assert!(!findings.iter().any(|finding| finding.check == "history"));
+
+        std::fs::remove_dir_all(not_a_repo).unwrap();
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_repo_layout() {
+        let repo_dir = init_repo("git_copyright_test_doctor_layout");
+        let parent = repo_dir.parent().unwrap();
+
+        // Shallow clones lack the history of the years
+        let clone_dir = parent.join("git_copyright_test_doctor_shallow");
+        let _ = std::fs::remove_dir_all(&clone_dir);
+        let url = format!("file://{}", repo_dir.display());
+        let clone_path = clone_dir.to_str().unwrap();
+        git(parent, &["clone", "-q", "--depth", "1", &url, clone_path]);
+        let findings = diagnose(clone_path, None).await;
+        let found = problems(&findings, "history");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Warning);
+        assert!(found[0].message.starts_with(
+            "years may be wrong because the clone is shallow, fetch the full history"
+        ));
+
+        // Linked worktrees can be checked like the main worktree
+        let worktree_dir = parent.join("git_copyright_test_doctor_worktree");
+        let _ = std::fs::remove_dir_all(&worktree_dir);
+        let worktree_path = worktree_dir.to_str().unwrap();
+        git(&repo_dir, &["worktree", "add", "-q", worktree_path]);
+        let findings = diagnose(worktree_path, None).await;
+        assert!(findings.contains(&Finding::ok(
+            "repository",
+            format!("{} is a linked worktree", worktree_path)
+        )));
+        assert!(problems(&findings, "repository").is_empty());
+
+        // Sparse checkouts hide files
+        git(&repo_dir, &["config", "core.sparseCheckout", "true"]);
+        let findings = diagnose(repo_dir.to_str().unwrap(), None).await;
+        assert_eq!(
+            problems(&findings, "repository"),
+            [&Finding::warning(
+                "repository",
+                "sparse checkout is enabled, files outside of the checkout cannot be checked"
+            )]
+        );
+
+        // Bare repositories have no files to check
+        let bare_dir = parent.join("git_copyright_test_doctor_bare");
+        let _ = std::fs::remove_dir_all(&bare_dir);
+        let bare_path = bare_dir.to_str().unwrap();
+        git(parent, &["clone", "-q", "--bare", &url, bare_path]);
+        let findings = diagnose(bare_path, None).await;
+        assert_eq!(
+            problems(&findings, "repository"),
+            [&Finding::error(
+                "repository",
+                format!("{} is a bare repository without files to check", bare_path)
+            )]
+        );
+
+        for dir in [clone_dir, worktree_dir, bare_dir, repo_dir] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[cfg(feature = "yaml")]
+    #[tokio::test]
+    async fn test_config() {
+        let repo_dir = init_repo("git_copyright_test_doctor_config");
+        let repo_path = repo_dir.to_str().unwrap();
+        let cfg_file = repo_dir.join("copyright.yml");
+
+        std::fs::write(&cfg_file, "ignore_files: [\n").unwrap();
+        let findings = diagnose(repo_path, Some(&cfg_file)).await;
+        let found = problems(&findings, "config");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Error);
+        assert!(found[0]
+            .message
+            .starts_with(&format!("{} is invalid: ", cfg_file.display())));
+
+        // Files without comment sign and names are pointed out
+        std::fs::write(
+            &cfg_file,
+            "ignore_files: []\nignore_dirs: []\ncomment_sign_map: {py: \"#\"}\n",
+        )
+        .unwrap();
+        let findings = diagnose(repo_path, Some(&cfg_file)).await;
+        assert!(findings.contains(&Finding::ok(
+            "config",
+            format!("using {}", cfg_file.display())
+        )));
+        let found = problems(&findings, "config");
+        assert_eq!(found.len(), 2);
+        assert!(found
+            .iter()
+            .all(|finding| finding.severity == Severity::Warning));
+        assert!(found[0].message.starts_with("no `name` is set"));
+        assert!(found[1]
+            .message
+            .starts_with("1 files have no comment sign and are not ignored, e.g. main.rs"));
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let repo_dir = init_repo("git_copyright_test_doctor_permissions");
+        let repo_path = repo_dir.to_str().unwrap();
+        let set_mode = |path: &Path, mode| {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+        };
+
+        set_mode(&repo_dir.join("main.rs"), 0o444);
+        set_mode(&repo_dir, 0o555);
+        // Privileged users may write to read-only directories anyway
+        let probe = repo_dir.join("probe");
+        let writable = std::fs::write(&probe, "").is_ok();
+        let _ = std::fs::remove_file(&probe);
+        let findings = diagnose(repo_path, None).await;
+        set_mode(&repo_dir, 0o755);
+
+        let found = problems(&findings, "permissions");
+        let read_only_files = Finding::warning(
+            "permissions",
+            "1 files are read-only, e.g. main.rs, set `read_only_files` to `write` or `skip` to handle them",
+        );
+        match writable {
+            true => assert_eq!(found, [&read_only_files]),
+            false => {
+                assert_eq!(found.len(), 2);
+                assert_eq!(found[0].severity, Severity::Error);
+                assert!(found[0]
+                    .message
+                    .starts_with(&format!("cannot write to {} (", repo_path)));
+                assert_eq!(found[1], &read_only_files);
+            }
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+}
@@ -0,0 +1,425 @@
+//! Read `.editorconfig` files to write files the way the team's editors do.
+//!
+//! Only the properties relevant when rewriting a file are supported:
+//! `end_of_line`, `charset` and `insert_final_newline`. The `.editorconfig`
+//! files are taken from the list of tracked files, so that they are parsed
+//! once per run instead of once per checked file.
+//!
+//! Files without `charset` keep the charset they are detected with, e.g. a
+//! UTF-8 or UTF-16 byte order mark.
+
+use crate::CError;
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use std::path::Path;
+
+const UTF8_BOM: &str = "\u{feff}";
+
+const UTF16LE_BOM: [u8; 2] = [0xff, 0xfe];
+
+const UTF16BE_BOM: [u8; 2] = [0xfe, 0xff];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndOfLine {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl EndOfLine {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EndOfLine::Lf => "\n",
+            EndOfLine::Crlf => "\r\n",
+            EndOfLine::Cr => "\r",
+        }
+    }
+}
+
+/// Charset of a file, named like in `.editorconfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Charset {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-8-bom")]
+    Utf8Bom,
+    #[serde(rename = "latin1")]
+    Latin1,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+}
+
+impl Charset {
+    /// Charset given by the byte order mark at the start of the data, if any.
+    pub fn from_bom(data: &[u8]) -> Option<Charset> {
+        if data.starts_with(UTF8_BOM.as_bytes()) {
+            Some(Charset::Utf8Bom)
+        } else if data.starts_with(&UTF16LE_BOM) {
+            Some(Charset::Utf16Le)
+        } else if data.starts_with(&UTF16BE_BOM) {
+            Some(Charset::Utf16Be)
+        } else {
+            None
+        }
+    }
+
+    /// Charset the data was written with: the one of its byte order mark,
+    /// `fallback` if it is not valid UTF-8 or `None` for plain UTF-8.
+    pub fn detect(data: &[u8], fallback: Option<Charset>) -> Option<Charset> {
+        match Charset::from_bom(data) {
+            Some(charset) => Some(charset),
+            None if std::str::from_utf8(data).is_ok() => None,
+            None => fallback,
+        }
+    }
+}
+
+/// Code units of UTF-16 data, ignoring an incomplete last unit.
+pub fn utf16_units(data: &[u8], charset: Charset) -> impl Iterator<Item = u16> + '_ {
+    data.chunks_exact(2).map(move |unit| match charset {
+        Charset::Utf16Be => u16::from_be_bytes([unit[0], unit[1]]),
+        _ => u16::from_le_bytes([unit[0], unit[1]]),
+    })
+}
+
+/// Settings applied when writing a file.
+///
+/// Settings which are `None` leave the file as it is.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EditorSettings {
+    pub end_of_line: Option<EndOfLine>,
+    pub charset: Option<Charset>,
+    pub insert_final_newline: Option<bool>,
+}
+
+/// All `.editorconfig` files of a repository.
+#[derive(Debug, Default)]
+pub struct EditorConfig {
+    /// Parsed files with the directory they are located in, relative to the repo root.
+    files: Vec<(String, EditorConfigFile)>,
+}
+
+#[derive(Debug, Default)]
+struct EditorConfigFile {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+struct Section {
+    patterns: Vec<Pattern>,
+    properties: Vec<(String, String)>,
+}
+
+impl EditorConfig {
+    /// Load the `.editorconfig` files among the tracked files of the repository.
+    ///
+    /// Files which do not exist in the working tree are skipped.
+    pub fn load<'a>(repo_path: &Path, files: impl Iterator<Item = &'a String>) -> Self {
+        let files = files
+            .filter(|filepath| {
+                Path::new(filepath.as_str()).file_name() == Some(".editorconfig".as_ref())
+                    && repo_path.join(filepath).is_file()
+            })
+            .filter_map(
+                |filepath| match std::fs::read_to_string(repo_path.join(filepath)) {
+                    Ok(content) => {
+                        let dir = filepath.trim_end_matches(".editorconfig").to_owned();
+                        Some((dir, EditorConfigFile::parse(&content)))
+                    }
+                    Err(e) => {
+                        log::warn!("Could not read {}: {}", filepath, e);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        EditorConfig { files }
+    }
+
+    /// Settings for a file given relative to the repository root.
+    pub fn settings_for(&self, filepath: &str) -> EditorSettings {
+        // Files closer to the checked file take precedence, so we collect them
+        // from the closest upwards and apply them in reverse order.
+        let mut applicable: Vec<(&str, &EditorConfigFile)> = self
+            .files
+            .iter()
+            .filter(|(dir, _)| filepath.starts_with(dir.as_str()))
+            .map(|(dir, file)| (dir.as_str(), file))
+            .collect();
+        applicable.sort_by_key(|(dir, _)| std::cmp::Reverse(dir.len()));
+        if let Some(root_idx) = applicable.iter().position(|(_, file)| file.root) {
+            applicable.truncate(root_idx + 1);
+        }
+
+        let mut settings = EditorSettings::default();
+        for (dir, file) in applicable.iter().rev() {
+            let rel_path = &filepath[dir.len()..];
+            for section in file
+                .sections
+                .iter()
+                .filter(|section| section.matches(rel_path))
+            {
+                for (key, value) in section.properties.iter() {
+                    settings.apply(key, value);
+                }
+            }
+        }
+        settings
+    }
+}
+
+impl EditorSettings {
+    /// Decode the content of a file according to `charset`.
+    ///
+    /// With the `utf-8-bom` and UTF-16 charsets, the byte order mark is
+    /// removed so that it does not end up behind an inserted copyright line.
+    pub fn decode(&self, data: &[u8]) -> Result<String, CError> {
+        match self.charset {
+            Some(Charset::Latin1) => Ok(data.iter().map(|&byte| byte as char).collect()),
+            Some(Charset::Utf8Bom) => {
+                let text = std::str::from_utf8(data)?;
+                Ok(text.strip_prefix(UTF8_BOM).unwrap_or(text).to_owned())
+            }
+            Some(charset @ (Charset::Utf16Le | Charset::Utf16Be)) => {
+                let data = match Charset::from_bom(data) {
+                    Some(_) => &data[2..],
+                    None => data,
+                };
+                let units: Vec<u16> = utf16_units(data, charset).collect();
+                String::from_utf16(&units)
+                    .map_err(|_| CError::ReadError("text which is not valid UTF-16".into()))
+            }
+            Some(Charset::Utf8) | None => Ok(std::str::from_utf8(data)?.to_owned()),
+        }
+    }
+
+    /// Settings to read and write the file with content `data`.
+    ///
+    /// Without a charset from `.editorconfig`, the charset is detected from
+    /// the byte order mark or is `fallback` for files which are not valid
+    /// UTF-8, so that files are written back the way they were read.
+    pub fn for_content(&self, data: &[u8], fallback: Option<Charset>) -> EditorSettings {
+        EditorSettings {
+            charset: self.charset.or_else(|| Charset::detect(data, fallback)),
+            ..self.clone()
+        }
+    }
+
+    /// Apply line endings, final newline and charset to `text` for writing.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, CError> {
+        let mut text = match self.end_of_line {
+            Some(eol) => text
+                .replace("\r\n", "\n")
+                .replace('\r', "\n")
+                .replace('\n', eol.as_str()),
+            None => text.to_owned(),
+        };
+
+        match self.insert_final_newline {
+            Some(true) if !text.is_empty() && !text.ends_with(['\n', '\r']) => {
+                let eol = self.end_of_line.map_or("\n", |eol| eol.as_str());
+                text.push_str(eol);
+            }
+            Some(false) => text.truncate(text.trim_end_matches(['\n', '\r']).len()),
+            _ => {}
+        }
+
+        match self.charset {
+            Some(Charset::Latin1) => text
+                .chars()
+                .map(u8::try_from)
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| CError::WriteError("non-latin1 characters as latin1".into())),
+            Some(Charset::Utf8Bom) => Ok(format!("{}{}", UTF8_BOM, text).into_bytes()),
+            Some(Charset::Utf16Le) => Ok(UTF16LE_BOM
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect()),
+            Some(Charset::Utf16Be) => Ok(UTF16BE_BOM
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+                .collect()),
+            Some(Charset::Utf8) | None => Ok(text.into_bytes()),
+        }
+    }
+
+    fn apply(&mut self, key: &str, value: &str) {
+        match key {
+            "end_of_line" => {
+                self.end_of_line = match value {
+                    "lf" => Some(EndOfLine::Lf),
+                    "crlf" => Some(EndOfLine::Crlf),
+                    "cr" => Some(EndOfLine::Cr),
+                    _ => None,
+                }
+            }
+            "charset" => {
+                self.charset = match value {
+                    "utf-8" => Some(Charset::Utf8),
+                    "utf-8-bom" => Some(Charset::Utf8Bom),
+                    "latin1" => Some(Charset::Latin1),
+                    "utf-16le" => Some(Charset::Utf16Le),
+                    "utf-16be" => Some(Charset::Utf16Be),
+                    "unset" => None,
+                    other => {
+                        log::warn!("Charset {} from .editorconfig is not supported", other);
+                        None
+                    }
+                }
+            }
+            "insert_final_newline" => {
+                self.insert_final_newline = match value {
+                    "true" => Some(true),
+                    "false" => Some(false),
+                    _ => None,
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl EditorConfigFile {
+    fn parse(content: &str) -> Self {
+        let mut file = EditorConfigFile::default();
+        for line_ in content.lines().map(str::trim) {
+            if line_.is_empty() || line_.starts_with('#') || line_.starts_with(';') {
+                continue;
+            }
+
+            if let Some(section) = line_.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                file.sections.push(Section {
+                    patterns: section_patterns(section),
+                    properties: Vec::new(),
+                });
+            } else if let Some((key, value)) = line_.split_once('=') {
+                let key = key.trim().to_lowercase();
+                let value = value.trim().to_lowercase();
+                match file.sections.last_mut() {
+                    Some(section) => section.properties.push((key, value)),
+                    None if key == "root" => file.root = value == "true",
+                    None => {}
+                }
+            }
+        }
+        file
+    }
+}
+
+impl Section {
+    fn matches(&self, rel_path: &str) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(rel_path, options))
+    }
+}
+
+/// Translate an editorconfig section name into glob patterns.
+///
+/// Sections without a slash match files in any subdirectory and alternatives
+/// like `*.{js,ts}` are expanded into one pattern each.
+fn section_patterns(section: &str) -> Vec<Pattern> {
+    let section = match section.contains('/') {
+        true => section.trim_start_matches('/').to_owned(),
+        false => format!("**/{}", section),
+    };
+
+    expand_braces(&section)
+        .iter()
+        .flat_map(|expr| {
+            // `**/` should also match files directly in the directory
+            let without_prefix = expr.strip_prefix("**/").map(str::to_owned);
+            std::iter::once(expr.clone()).chain(without_prefix)
+        })
+        .filter_map(|expr| Pattern::new(&expr).ok())
+        .collect()
+}
+
+fn expand_braces(expr: &str) -> Vec<String> {
+    let (start, end) = match (expr.find('{'), expr.find('}')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return vec![expr.to_owned()],
+    };
+
+    expr[start + 1..end]
+        .split(',')
+        .flat_map(|alternative| {
+            expand_braces(&format!(
+                "{}{}{}",
+                &expr[..start],
+                alternative,
+                &expr[end + 1..]
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{Charset, EditorConfig, EditorConfigFile, EditorSettings, EndOfLine};
+
+    #[test]
+    fn test_settings_for() {
+        let root = EditorConfigFile::parse(
+            "root = true\n\n[*]\nend_of_line = lf\ninsert_final_newline = true\n\n[*.{bat,cmd}]\nend_of_line = CRLF\n",
+        );
+        let nested = EditorConfigFile::parse("[scripts/*.sh]\ncharset = utf-8-bom\n");
+        let config = EditorConfig {
+            files: vec![("".into(), root), ("tools/".into(), nested)],
+        };
+
+        assert_eq!(
+            config.settings_for("deep/dir/run.cmd"),
+            EditorSettings {
+                end_of_line: Some(EndOfLine::Crlf),
+                charset: None,
+                insert_final_newline: Some(true),
+            }
+        );
+        assert_eq!(
+            config.settings_for("tools/scripts/run.sh").charset,
+            Some(Charset::Utf8Bom)
+        );
+        assert_eq!(config.settings_for("tools/other/run.sh").charset, None);
+        assert_eq!(
+            config.settings_for("main.rs").end_of_line,
+            Some(EndOfLine::Lf)
+        );
+    }
+
+    #[test]
+    fn test_detect_charset() {
+        let settings = EditorSettings::default();
+        let bom = b"\xef\xbb\xbfecho\n";
+        let detected = settings.for_content(bom, None);
+        assert_eq!(detected.charset, Some(Charset::Utf8Bom));
+        assert_eq!(detected.decode(bom).unwrap(), "echo\n");
+        assert_eq!(detected.encode("echo\n").unwrap(), bom);
+
+        let utf16 = b"\xff\xfee\x00\xe4\x00\n\x00";
+        let detected = settings.for_content(utf16, None);
+        assert_eq!(detected.charset, Some(Charset::Utf16Le));
+        assert_eq!(detected.decode(utf16).unwrap(), "e\u{e4}\n");
+        assert_eq!(detected.encode("e\u{e4}\n").unwrap(), utf16);
+
+        let latin1 = b"M\xfcller\n";
+        assert_eq!(settings.for_content(latin1, None).charset, None);
+        let detected = settings.for_content(latin1, Some(Charset::Latin1));
+        assert_eq!(detected.decode(latin1).unwrap(), "M\u{fc}ller\n");
+        assert_eq!(
+            settings
+                .for_content(b"echo\n", Some(Charset::Latin1))
+                .charset,
+            None
+        );
+    }
+}
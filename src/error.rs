@@ -6,6 +6,9 @@ pub enum CError {
     #[error("No comment sign found for file {0}")]
     UnknownCommentSign(String),
 
+    #[error("File {0} is configured to have no header")]
+    NoHeader(String),
+
     #[error("Error while running git subcommand: {0}")]
     GitCmdError(String),
 
@@ -18,12 +21,53 @@ pub enum CError {
     #[error("Could not write {0}")]
     WriteError(String),
 
-    #[error("Some copyrights could not be fixed, please check the output")]
-    FixError,
+    #[error("No years known for {0}")]
+    NoYears(String),
+
+    #[error("Could not write {0} since it stays locked by another process")]
+    FileLocked(String),
+
+    #[error(
+        "{0} is read-only, set `read_only_files` to `write` or `skip` in the config to fix it"
+    )]
+    ReadOnlyFile(String),
+
+    #[error("Some copyrights could not be fixed:{}", format_failures(.0))]
+    FixError(Vec<FileFailure>),
 
     #[error("The copyright job changed tracked files that should be committed")]
     FilesChanged,
 
+    #[error("{0} files have a missing or outdated copyright")]
+    IncorrectCopyrights(usize),
+
+    #[error("Copyright coverage of {coverage:.1}% is below the minimum of {min_coverage}%")]
+    CoverageTooLow { coverage: f64, min_coverage: f64 },
+
+    #[error("{0} policy violations found")]
+    PolicyViolations(usize),
+
+    #[error("Computed years may be unreliable because {0}")]
+    HistoryRewritten(String),
+
+    #[error("git {found} is not supported, git_copyright requires git {required} or newer")]
+    UnsupportedGitVersion { found: String, required: String },
+
+    #[error("Some repositories failed:{}", format_failures(.0))]
+    RepositoriesFailed(Vec<FileFailure>),
+
+    #[error("{0} problems found in the environment")]
+    EnvironmentProblems(usize),
+
+    #[error("Error while querying the hosting API: {0}")]
+    RemoteError(String),
+
+    #[error("{what} requires building git_copyright with the `{feature}` feature")]
+    MissingFeature { what: String, feature: String },
+
+    #[error("Language server protocol error: {0}")]
+    LspError(String),
+
     #[error(transparent)]
     GenericIOError(#[from] std::io::Error),
 
@@ -33,3 +77,18 @@ pub enum CError {
     #[error(transparent)]
     RegexError(#[from] regex::Error),
 }
+
+/// Error while checking or fixing the copyright of a single file.
+#[derive(thiserror::Error, Debug)]
+#[error("{filepath}: {error}")]
+pub struct FileFailure {
+    pub filepath: String,
+    pub error: CError,
+}
+
+fn format_failures(failures: &[FileFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| format!("\n  {}", failure))
+        .collect()
+}
@@ -1,93 +1,1728 @@
 //! Check and update copyright of file.
 
-use crate::CError;
+use crate::config::ReadOnlyPolicy;
+use crate::editorconfig::{Charset, EditorSettings};
+use crate::fs::{split_lines, FileSystem};
+use crate::report::{FileOutcome, FileStatus};
+use crate::{CError, CommentSign};
 use futures::join;
 use futures::Future;
 use regex::Regex;
-use std::io::{BufRead, BufReader};
 use std::sync::Arc;
 use std::{path::Path, path::PathBuf};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+/// Number of lines at the start of a file that are searched for a copyright.
+pub const HEADER_LINES: usize = 3;
+
+/// Maximum number of lines of a leading banner comment that is skipped.
+pub const MAX_BANNER_LINES: usize = 20;
+
+/// Maximum number of lines searched for each `insert_after` regex following
+/// the first one, e.g. for the end of Markdown front matter.
+pub const MAX_ANCHOR_LINES: usize = 50;
+
+/// Which files are written if their copyright is not correct.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Add missing copyrights and update outdated ones.
+    #[default]
+    All,
+    /// Only add missing copyrights and never modify existing ones.
+    MissingOnly,
+    /// Only check copyrights and never write files, e.g. for `--dry-run`.
+    Never,
+}
+
+impl WriteMode {
+    /// Whether a file with the given status is written.
+    pub fn writes(&self, status: &FileStatus) -> bool {
+        match self {
+            WriteMode::All => status.needs_fix(),
+            WriteMode::MissingOnly => *status == FileStatus::Missing,
+            WriteMode::Never => false,
+        }
+    }
+}
+
+/// Options for checking and writing a single file.
+#[derive(Debug, Default, Clone)]
+pub struct WriteOptions {
+    pub write_mode: WriteMode,
+    /// Settings from `.editorconfig` applied when writing the file.
+    pub editor_settings: EditorSettings,
+    /// Comment sign of a leading banner comment, e.g. ASCII art or mode lines,
+    /// which new copyrights are inserted after. Without it, new copyrights are
+    /// inserted at the top of the file.
+    pub banner_sign: Option<CommentSign>,
+    /// Regexes of leading lines, e.g. an `<?xml` declaration, which new
+    /// copyrights are inserted after. The first one must match the first line
+    /// (after a shebang), each further one a line following the previous.
+    pub insert_after: Vec<Arc<Regex>>,
+    /// Regexes of headers which exempt the file from copyrights.
+    pub keep_regexes: Vec<Arc<Regex>>,
+    /// Number of lines searched for a copyright, `HEADER_LINES` if not given.
+    pub scan_lines: Option<usize>,
+    /// Write files with lone CR line endings with LF instead of keeping them.
+    pub normalize_cr: bool,
+    /// Charset of files which are neither valid UTF-8 nor have a byte order
+    /// mark. Without it, such files cannot be checked.
+    pub fallback_encoding: Option<Charset>,
+    /// Handling of files marked read-only.
+    pub read_only_files: ReadOnlyPolicy,
+    /// Verify that the lines of the header template follow existing copyright
+    /// lines verbatim and restore them when writing.
+    pub verify_template: bool,
+    /// `SPDX-License-Identifier` line for each comment sign, in the order of
+    /// the regexes, which is expected in the header and written above the
+    /// copyright line. Empty if no SPDX license is configured.
+    pub spdx_lines: Vec<String>,
+}
+
+/// Check the copyright of a file and fix it according to `write_mode`.
+///
+/// `regexes` and the copyright lines returned by `copyright_lines` belong to
+/// the accepted comment signs of the file, with the preferred sign first. An
+/// outdated copyright is rewritten with the comment sign it was found with
+/// while missing copyrights are inserted with the preferred sign. Further
+/// lines of a header template following the copyright line are only inserted
+/// with missing copyrights, unless `verify_template` is set. Then the lines
+/// following an existing copyright must match the template and are restored
+/// if they drifted.
+///
+/// With `spdx_lines`, a missing or disagreeing `SPDX-License-Identifier`
+/// line is reported if the copyright is correct and fixed with the copyright.
+///
+/// If the copyright is missing but the lines of the header template are
+/// already there, e.g. a pasted license preamble, the copyright line is
+/// written above them instead of inserting the template a second time.
+///
+/// Files whose header matches a keep pattern are reported as exempt and never
+/// written. Written files follow the `.editorconfig` settings of the file and
+/// otherwise keep their line endings, including a missing final newline.
 pub async fn read_write_copyright(
+    fs: &impl FileSystem,
     filepath: PathBuf,
-    regex: Arc<Regex>,
+    regexes: Vec<Arc<Regex>>,
     years_fut: impl Future<Output = String>,
-    copyright_line: impl Future<Output = String>,
-) -> Result<(), CError> {
-    let (years, copyright_line) = join!(years_fut, copyright_line);
-
-    // This could be re-written to read the file asynchronously until EOF or the first n
-    // newlines are found.
-    let file = std::fs::File::open(&filepath)
-        .map_err(|_| CError::ReadError(filepath.display().to_string()))?;
-    let file_header = BufReader::new(file).lines().take(3);
-
-    for (line_nr, line_) in file_header.enumerate() {
-        if let Ok(line_) = line_ {
-            if let Some(cap) = regex.captures_iter(&line_).take(1).next() {
-                if years == &cap[1] {
-                    log::debug!(
-                        "File {} has correct copyright with years {}",
-                        filepath.display(),
-                        years
-                    );
-                    return Ok(());
-                } else {
-                    println!(
-                        "File {} has copyright with year(s) {} on line {} but should have {}",
-                        filepath.display(),
-                        &cap[1],
-                        line_nr,
-                        years
-                    );
-                    return write_copyright(&filepath, &copyright_line, Some(line_nr)).await;
-                }
+    copyright_lines: impl Future<Output = Vec<String>>,
+    options: &WriteOptions,
+) -> Result<FileOutcome, CError> {
+    let (years, copyright_lines) = join!(years_fut, copyright_lines);
+
+    let scan_lines = options.scan_lines.unwrap_or(HEADER_LINES);
+    let template_lines = match copyright_lines.first() {
+        Some(copyright_line) => copyright_line.split('\n').count() - 1,
+        None => 0,
+    };
+    let max_lines = match options.banner_sign {
+        Some(_) => MAX_BANNER_LINES + scan_lines,
+        None => scan_lines,
+    } + anchor_lines(&options.insert_after)
+        + template_lines;
+    let file_header = read_header(fs, &filepath, max_lines, options).await?;
+    let header_lines = header_lines(
+        &file_header,
+        options.banner_sign.as_ref(),
+        &options.insert_after,
+        scan_lines,
+    );
+
+    if let Some((line_nr, pattern)) =
+        find_keep_pattern(&file_header, header_lines, &options.keep_regexes)
+    {
+        return Ok(FileOutcome {
+            filepath,
+            status: FileStatus::Exempt { pattern },
+            line_nr: Some(line_nr),
+            expected_years: years,
+        });
+    }
+
+    let (mut outcome, sign_idx) =
+        check_header(filepath, file_header.iter(), header_lines, &regexes, years);
+    let drift = match (options.verify_template, outcome.line_nr) {
+        (true, Some(line_nr)) => template_drift(&file_header, line_nr, &copyright_lines[sign_idx]),
+        _ => None,
+    };
+    let spdx_line = options.spdx_lines.get(sign_idx);
+    let spdx_mismatch = spdx_line.and_then(|spdx_line| {
+        let expected = spdx_license(spdx_line).unwrap_or_default();
+        let found = file_header.iter().find_map(|line_| spdx_license(line_));
+        (found.as_deref() != Some(expected.as_str())).then_some((found, expected))
+    });
+    match (&drift, spdx_mismatch, &outcome.status) {
+        (Some((line_nr, expected)), _, FileStatus::Correct) => {
+            outcome.status = FileStatus::TemplateDrift {
+                line_nr: *line_nr,
+                expected: expected.clone(),
+            };
+        }
+        (None, Some((found, expected)), FileStatus::Correct) => {
+            outcome.status = FileStatus::SpdxMismatch { found, expected };
+        }
+        _ => {}
+    }
+    if !options.write_mode.writes(&outcome.status) {
+        return Ok(outcome);
+    }
+    let position = match outcome.line_nr {
+        Some(line_nr) => HeaderPosition::Existing {
+            line_nr,
+            regex: &regexes[sign_idx],
+            years: &outcome.expected_years,
+        },
+        None => match find_template_block(&file_header, &copyright_lines[sign_idx]) {
+            Some((line_nr, placeholder)) => HeaderPosition::Block {
+                line_nr,
+                placeholder,
+            },
+            None => HeaderPosition::New,
+        },
+    };
+    if skips_read_only(fs, &outcome.filepath, options) {
+        outcome.status = FileStatus::ReadOnly;
+    } else {
+        write_copyright(
+            fs,
+            &outcome.filepath,
+            &copyright_lines[sign_idx],
+            position,
+            drift.is_some(),
+            spdx_line.map(String::as_str),
+            options,
+        )
+        .await?;
+    }
+
+    Ok(outcome)
+}
+
+/// Read up to `max_lines` lines from the start of the file.
+///
+/// Files which may not be UTF-8, with a fallback encoding or the `latin1`
+/// charset, are decoded as a whole instead.
+async fn read_header(
+    fs: &impl FileSystem,
+    filepath: &Path,
+    max_lines: usize,
+    options: &WriteOptions,
+) -> Result<Vec<String>, CError> {
+    let settings = &options.editor_settings;
+    if options.fallback_encoding.is_none() && settings.charset != Some(Charset::Latin1) {
+        return fs.read_header(filepath, max_lines).await;
+    }
+    let data = fs.read(filepath).await?;
+    let text = settings
+        .for_content(&data, options.fallback_encoding)
+        .decode(&data)?;
+    Ok(split_lines(text.as_bytes())
+        .into_iter()
+        .take(max_lines)
+        .map(|line_| String::from_utf8_lossy(line_).into_owned())
+        .collect())
+}
+
+/// Check and fix the `.license` sidecar file holding the header of a file
+/// which cannot carry comments, as in REUSE.
+///
+/// A missing sidecar is created with the copyright line and the first of the
+/// `spdx_lines`, an existing one is checked like any other file.
+pub async fn read_write_sidecar(
+    fs: &impl FileSystem,
+    sidecar: PathBuf,
+    regex: Arc<Regex>,
+    years: String,
+    copyright_line: String,
+    options: &WriteOptions,
+) -> Result<FileOutcome, CError> {
+    if fs.read_header(&sidecar, 1).await.is_ok() {
+        return read_write_copyright(
+            fs,
+            sidecar,
+            vec![regex],
+            futures::future::ready(years),
+            futures::future::ready(vec![copyright_line]),
+            options,
+        )
+        .await;
+    }
+
+    let outcome = FileOutcome {
+        filepath: sidecar,
+        status: FileStatus::Missing,
+        line_nr: None,
+        expected_years: years,
+    };
+    if options.write_mode.writes(&outcome.status) {
+        let mut lines = vec![copyright_line];
+        if let Some(spdx_line) = options.spdx_lines.first() {
+            lines.extend([String::new(), spdx_line.clone()]);
+        }
+        let separator = written_separator("\n", options);
+        let data = format!("{}{}", lines.join(separator), separator);
+        fs.write(&outcome.filepath, &options.editor_settings.encode(&data)?)
+            .await?;
+    }
+    Ok(outcome)
+}
+
+/// First line following the copyright line on `line_nr` which differs from
+/// the template lines of `copyright_line`, with the expected line.
+fn template_drift<S: AsRef<str>>(
+    lines: &[S],
+    line_nr: usize,
+    copyright_line: &str,
+) -> Option<(usize, String)> {
+    copyright_line
+        .split('\n')
+        .skip(1)
+        .enumerate()
+        .find_map(|(offset, expected)| {
+            let template_line_nr = line_nr + 1 + offset;
+            match lines.get(template_line_nr) {
+                Some(found) if found.as_ref() == expected => None,
+                _ => Some((template_line_nr, expected.to_owned())),
+            }
+        })
+}
+
+/// Line of the copyright line of an existing block of template lines without
+/// copyright, and whether that line holds a placeholder copyright without
+/// years, e.g. `Copyright [yyyy] [name of copyright owner]`, to be replaced.
+///
+/// The block is found by the first template line with text since separator
+/// lines like `//` are ambiguous.
+fn find_template_block<S: AsRef<str>>(lines: &[S], copyright_line: &str) -> Option<(usize, bool)> {
+    let (offset, text_line) = copyright_line
+        .split('\n')
+        .skip(1)
+        .enumerate()
+        .find(|(_, line_)| line_.chars().any(char::is_alphanumeric))?;
+    let found = lines
+        .iter()
+        .position(|line_| line_.as_ref().trim_end() == text_line.trim_end())?;
+    let block_start = found.checked_sub(offset)?;
+    match block_start
+        .checked_sub(1)
+        .map(|line_nr| lines[line_nr].as_ref())
+    {
+        Some(line_) if is_placeholder_copyright(line_) => Some((block_start - 1, true)),
+        _ => Some((block_start, false)),
+    }
+}
+
+/// License expression of an `SPDX-License-Identifier` line, e.g. `MIT` of
+/// `/* SPDX-License-Identifier: MIT */`.
+///
+/// Trailing words without letters or digits are taken as closing comment signs.
+pub fn spdx_license(line_: &str) -> Option<String> {
+    let (_, expression) = line_.split_once("SPDX-License-Identifier:")?;
+    let mut words: Vec<&str> = expression.split_whitespace().collect();
+    while words
+        .last()
+        .is_some_and(|word| !word.chars().any(char::is_alphanumeric))
+    {
+        words.pop();
+    }
+    Some(words.join(" "))
+}
+
+/// Whether the line is a copyright without years, like the placeholder in
+/// license appendices.
+fn is_placeholder_copyright(line_: &str) -> bool {
+    let has_year = line_
+        .as_bytes()
+        .windows(4)
+        .any(|window| window.iter().all(u8::is_ascii_digit));
+    line_.to_lowercase().contains("copyright") && !has_year
+}
+
+/// Replace the comment lines following the copyright line on `line_nr` with
+/// the `template` lines.
+///
+/// Up to as many lines as the template has are replaced, as long as they
+/// start with the comment sign which all template lines start with. Missing
+/// and edited lines are thereby restored without touching the code below.
+fn restore_template<'a>(data: &mut Vec<&'a str>, line_nr: usize, template: &'a [String]) {
+    let comment_sign = template
+        .iter()
+        .map(String::as_str)
+        .reduce(|prefix, line_| {
+            let len = prefix
+                .char_indices()
+                .zip(line_.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(prefix.len().min(line_.len()), |((idx, _), _)| idx);
+            &prefix[..len]
+        })
+        .unwrap_or_default()
+        .trim();
+    let num_lines = data
+        .iter()
+        .skip(line_nr + 1)
+        .take(template.len())
+        .take_while(|line_| {
+            !comment_sign.is_empty() && line_.trim_start().starts_with(comment_sign)
+        })
+        .count();
+    data.splice(
+        line_nr + 1..line_nr + 1 + num_lines,
+        template.iter().map(String::as_str),
+    );
+}
+
+/// Find the first of the `header_lines` lines matching a keep pattern.
+///
+/// Returns the line number and the matching pattern.
+pub fn find_keep_pattern<S: AsRef<str>>(
+    lines: &[S],
+    header_lines: usize,
+    keep_regexes: &[Arc<Regex>],
+) -> Option<(usize, String)> {
+    lines
+        .iter()
+        .take(header_lines)
+        .enumerate()
+        .find_map(|(line_nr, line_)| {
+            keep_regexes
+                .iter()
+                .find(|regex| regex.is_match(line_.as_ref()))
+                .map(|regex| (line_nr, regex.as_str().to_owned()))
+        })
+}
+
+/// Check the first `header_lines` lines of a file for a copyright with the
+/// expected years.
+///
+/// Each line is matched against all `regexes`. Besides the outcome, the index
+/// of the regex that matched is returned, or zero if no copyright was found.
+///
+/// If a regex expects a `suffix` group, a copyright without the suffix is
+/// considered outdated even if the years are correct.
+///
+/// This does not touch the filesystem so that it can also be used on buffers
+/// which are not yet written to disk.
+pub fn check_header<S: AsRef<str>>(
+    filepath: PathBuf,
+    lines: impl Iterator<Item = S>,
+    header_lines: usize,
+    regexes: &[Arc<Regex>],
+    years: String,
+) -> (FileOutcome, usize) {
+    for (line_nr, line_) in lines.take(header_lines).enumerate() {
+        for (sign_idx, regex) in regexes.iter().enumerate() {
+            if let Some(cap) = regex.captures(line_.as_ref()) {
+                let expects_suffix = regex.capture_names().any(|name| name == Some("suffix"));
+                let has_suffix = cap.name("suffix").is_some();
+                let status = match years == cap[1] && (has_suffix || !expects_suffix) {
+                    true => FileStatus::Correct,
+                    false => FileStatus::Outdated {
+                        found_years: cap[1].to_owned(),
+                    },
+                };
+                let outcome = FileOutcome {
+                    filepath,
+                    status,
+                    line_nr: Some(line_nr),
+                    expected_years: years,
+                };
+                return (outcome, sign_idx);
             }
         }
     }
 
-    println!(
-        "File {} has no copyright but should have {}",
-        filepath.display(),
-        years
+    let outcome = FileOutcome {
+        filepath,
+        status: FileStatus::Missing,
+        line_nr: None,
+        expected_years: years,
+    };
+    (outcome, 0)
+}
+
+/// Check and update years embedded in a file according to a special file rule.
+///
+/// The years captured by the first match of `regex` in the file are compared
+/// with the expected years. If they differ, the match is replaced with
+/// `replacement` or, without replacement, only the captured years are
+/// replaced. Files without match are reported as missing but never written
+/// since there is no place to insert the years.
+pub async fn read_write_special_file(
+    fs: &impl FileSystem,
+    filepath: PathBuf,
+    regex: &Regex,
+    years: String,
+    replacement: Option<&str>,
+    options: &WriteOptions,
+) -> Result<FileOutcome, CError> {
+    let data = fs.read(&filepath).await?;
+    let settings = &options
+        .editor_settings
+        .for_content(&data, options.fallback_encoding);
+    let data = settings.decode(&data)?;
+    let separator = line_separator(&data);
+    let mut lines: Vec<String> = data.split(separator).map(str::to_owned).collect();
+
+    let found = lines.iter().enumerate().find_map(|(line_nr, line_)| {
+        let cap = regex.captures(line_)?;
+        let range = match replacement {
+            Some(_) => cap.get(0)?.range(),
+            None => cap.get(1)?.range(),
+        };
+        Some((line_nr, range, cap.get(1)?.as_str().to_owned()))
+    });
+
+    let (line_nr, range, found_years) = match found {
+        Some(found) => found,
+        None => {
+            return Ok(FileOutcome {
+                filepath,
+                status: FileStatus::Missing,
+                line_nr: None,
+                expected_years: years,
+            })
+        }
+    };
+
+    let mut status = match found_years == years {
+        true => FileStatus::Correct,
+        false => FileStatus::Outdated { found_years },
+    };
+
+    if options.write_mode.writes(&status) {
+        if skips_read_only(fs, &filepath, options) {
+            status = FileStatus::ReadOnly;
+        } else {
+            lines[line_nr].replace_range(range, replacement.unwrap_or(&years));
+            let separator = written_separator(separator, options);
+            let data = settings.encode(&lines.join(separator))?;
+            write_file(fs, &filepath, &data, options).await?;
+        }
+    }
+
+    Ok(FileOutcome {
+        filepath,
+        status,
+        line_nr: Some(line_nr),
+        expected_years: years,
+    })
+}
+
+/// Remove the copyright lines matching `regexes` from the header of a file,
+/// e.g. when moving to a central license file.
+///
+/// Each copyright line is removed together with a blank line following it so
+/// that no gap is left at the top of the file. Files whose header matches a
+/// keep pattern are left untouched and nothing is written with
+/// `WriteMode::Never`. Returns the zero-based line numbers of the copyrights
+/// found, empty for read-only files skipped.
+pub async fn read_remove_copyright(
+    fs: &impl FileSystem,
+    filepath: &Path,
+    regexes: &[Arc<Regex>],
+    options: &WriteOptions,
+) -> Result<Vec<usize>, CError> {
+    let data = fs.read(filepath).await?;
+    let settings = &options
+        .editor_settings
+        .for_content(&data, options.fallback_encoding);
+    let data = settings.decode(&data)?;
+    let separator = line_separator(&data);
+    let lines: Vec<&str> = data.split(separator).collect();
+    let found: Vec<usize> = find_copyrights(&lines, regexes, options)
+        .into_iter()
+        .map(|(line_nr, _)| line_nr)
+        .collect();
+    if found.is_empty() || options.write_mode == WriteMode::Never {
+        return Ok(found);
+    }
+    if skips_read_only(fs, filepath, options) {
+        log::warn!("Skipping read-only file {}", filepath.display());
+        return Ok(Vec::new());
+    }
+
+    let mut removed = vec![false; lines.len()];
+    for line_nr in found.iter().copied() {
+        removed[line_nr] = true;
+        // The last element is the end of the file, not a line
+        let next = line_nr + 1;
+        if next + 1 < lines.len() && !found.contains(&next) && lines[next].trim().is_empty() {
+            removed[next] = true;
+        }
+    }
+    let lines: Vec<&str> = lines
+        .into_iter()
+        .zip(removed)
+        .filter_map(|(line_, removed)| (!removed).then_some(line_))
+        .collect();
+
+    let separator = written_separator(separator, options);
+    let data = settings.encode(&lines.join(separator))?;
+    write_file(fs, filepath, &data, options).await?;
+    Ok(found)
+}
+
+/// Rewrite the copyright lines matching `regexes` in the header of a file,
+/// e.g. to another holder after a corporate rename.
+///
+/// `copyright_line` generates the new line from the index of the regex which
+/// matched and the years found, only its first line is written. Files whose
+/// header matches a keep pattern are left untouched and nothing is written
+/// with `WriteMode::Never`. Returns the zero-based line numbers of the
+/// copyrights which change, empty for read-only files skipped.
+pub async fn read_migrate_copyright<F, Fut>(
+    fs: &impl FileSystem,
+    filepath: &Path,
+    regexes: &[Arc<Regex>],
+    copyright_line: F,
+    options: &WriteOptions,
+) -> Result<Vec<usize>, CError>
+where
+    F: Fn(usize, String) -> Fut,
+    Fut: Future<Output = String>,
+{
+    let data = fs.read(filepath).await?;
+    let settings = &options
+        .editor_settings
+        .for_content(&data, options.fallback_encoding);
+    let data = settings.decode(&data)?;
+    let separator = line_separator(&data);
+    let mut lines: Vec<String> = data.split(separator).map(str::to_owned).collect();
+
+    let mut migrated = Vec::new();
+    for (line_nr, sign_idx) in find_copyrights(&lines, regexes, options) {
+        // CRLF line endings leave the carriage return on the lines
+        let (line_, line_end) = match lines[line_nr].strip_suffix('\r') {
+            Some(line_) => (line_, "\r"),
+            None => (lines[line_nr].as_str(), ""),
+        };
+        let Some(years) = regexes[sign_idx]
+            .captures(line_)
+            .and_then(|cap| cap.get(1))
+            .map(|years| years.as_str().to_owned())
+        else {
+            continue;
+        };
+        let header = copyright_line(sign_idx, years).await;
+        let new_line = header.split('\n').next().unwrap_or_default();
+        if new_line != line_ {
+            lines[line_nr] = format!("{}{}", new_line, line_end);
+            migrated.push(line_nr);
+        }
+    }
+    if migrated.is_empty() || options.write_mode == WriteMode::Never {
+        return Ok(migrated);
+    }
+    if skips_read_only(fs, filepath, options) {
+        log::warn!("Skipping read-only file {}", filepath.display());
+        return Ok(Vec::new());
+    }
+
+    let separator = written_separator(separator, options);
+    let data = settings.encode(&lines.join(separator))?;
+    write_file(fs, filepath, &data, options).await?;
+    Ok(migrated)
+}
+
+/// Line numbers of the copyrights in the header with the index of the regex
+/// matching each, none if the header matches a keep pattern.
+fn find_copyrights<S: AsRef<str>>(
+    lines: &[S],
+    regexes: &[Arc<Regex>],
+    options: &WriteOptions,
+) -> Vec<(usize, usize)> {
+    // CRLF line endings leave the carriage return on the lines
+    let lines: Vec<&str> = lines
+        .iter()
+        .map(|line_| line_.as_ref().strip_suffix('\r').unwrap_or(line_.as_ref()))
+        .collect();
+    let header_lines = header_lines(
+        &lines,
+        options.banner_sign.as_ref(),
+        &options.insert_after,
+        options.scan_lines.unwrap_or(HEADER_LINES),
     );
-    write_copyright(&filepath, &copyright_line, None).await
+    if find_keep_pattern(&lines, header_lines, &options.keep_regexes).is_some() {
+        return Vec::new();
+    }
+    lines
+        .iter()
+        .take(header_lines)
+        .enumerate()
+        .filter_map(|(line_nr, line_)| {
+            let sign_idx = regexes.iter().position(|regex| regex.is_match(line_))?;
+            Some((line_nr, sign_idx))
+        })
+        .collect()
+}
+
+/// Replace only the years of an existing copyright line.
+///
+/// This keeps the rest of legacy copyrights which `regex` accepts as they
+/// are, e.g. the casing of `COPYRIGHT ©`. Returns `None` if the line does not
+/// match or lacks the suffix expected by `regex`, which requires the line to
+/// be regenerated.
+pub fn replace_years(line_: &str, regex: &Regex, years: &str) -> Option<String> {
+    let cap = regex.captures(line_)?;
+    let expects_suffix = regex.capture_names().any(|name| name == Some("suffix"));
+    if expects_suffix && cap.name("suffix").is_none() {
+        return None;
+    }
+
+    let mut line_ = line_.to_owned();
+    line_.replace_range(cap.get(1)?.range(), years);
+    Some(line_)
 }
 
+/// Number of lines at the start of a file that are searched for a copyright,
+/// given the `scan_lines` of the file type.
+///
+/// With a banner comment sign or `insert_after` regexes, the lines before
+/// the insertion line are searched in addition so that a copyright inserted
+/// after them is found.
+pub fn header_lines<S: AsRef<str>>(
+    lines: &[S],
+    banner_sign: Option<&CommentSign>,
+    insert_after: &[Arc<Regex>],
+    scan_lines: usize,
+) -> usize {
+    match (banner_sign, insert_after.is_empty()) {
+        (None, true) => scan_lines,
+        _ => insertion_line_nr(lines, banner_sign, insert_after) + scan_lines,
+    }
+}
+
+/// Number of lines read in addition to find a copyright inserted after the
+/// lines matching the `insert_after` regexes.
+pub fn anchor_lines(insert_after: &[Arc<Regex>]) -> usize {
+    insert_after.len() * MAX_ANCHOR_LINES
+}
+
+/// Line number at which a missing copyright is inserted.
+///
+/// The copyright is inserted after the lines matching the `insert_after`
+/// regexes if all of them match. With a banner comment sign, it is inserted
+/// after a leading comment block of up to `MAX_BANNER_LINES` lines following
+/// them.
+pub fn insertion_line_nr<S: AsRef<str>>(
+    lines: &[S],
+    banner_sign: Option<&CommentSign>,
+    insert_after: &[Arc<Regex>],
+) -> usize {
+    let start = match lines.first() {
+        // Insert copyright on the second line for shell scripts
+        // that might have a shebang line
+        Some(line_) if line_.as_ref().starts_with("#!") => 1,
+        _ => 0,
+    };
+    let start = anchor_end(lines, start, insert_after);
+
+    match banner_sign {
+        Some(comment_sign) => banner_end(lines, start, comment_sign),
+        None => start,
+    }
+}
+
+/// Line number after the lines matching the `insert_after` regexes one after
+/// another, the first one on line `start`, or `start` if one does not match.
+fn anchor_end<S: AsRef<str>>(lines: &[S], start: usize, insert_after: &[Arc<Regex>]) -> usize {
+    let mut end = start;
+    for (idx, regex) in insert_after.iter().enumerate() {
+        let searched = match idx {
+            0 => 1,
+            _ => MAX_ANCHOR_LINES,
+        };
+        match lines
+            .iter()
+            .enumerate()
+            .skip(end)
+            .take(searched)
+            .find(|(_, line_)| regex.is_match(line_.as_ref()))
+        {
+            Some((line_nr, _)) => end = line_nr + 1,
+            None => return start,
+        }
+    }
+    end
+}
+
+/// Line number after a banner comment starting at line `start`.
+fn banner_end<S: AsRef<str>>(lines: &[S], start: usize, comment_sign: &CommentSign) -> usize {
+    let mut end = start;
+    let mut in_block = false;
+    for line_ in lines.iter().skip(start).take(MAX_BANNER_LINES) {
+        let line_ = line_.as_ref().trim();
+        if !in_block && !line_.starts_with(comment_sign.left()) {
+            break;
+        }
+
+        end += 1;
+        // Enclosing comment signs span the banner until the closing sign
+        if let Some(right) = comment_sign.right() {
+            in_block = !line_.ends_with(right);
+        }
+    }
+
+    // Do not split a block comment which is longer than the limit
+    match in_block {
+        true => start,
+        false => end,
+    }
+}
+
+/// Where a copyright is written.
+enum HeaderPosition<'a> {
+    /// Existing copyright whose years are replaced in place.
+    Existing {
+        line_nr: usize,
+        regex: &'a Regex,
+        years: &'a str,
+    },
+    /// Existing block of template lines which the copyright line is written
+    /// above, replacing a placeholder copyright on `line_nr`.
+    Block { line_nr: usize, placeholder: bool },
+    /// No header, the copyright and template lines are inserted.
+    New,
+}
+
+/// Write a copyright line to the file.
+///
+/// With `restore_template`, the template lines following an existing
+/// copyright line are restored as well. An `spdx_line` replaces the
+/// `SPDX-License-Identifier` line of the header or is inserted above the
+/// copyright line.
 async fn write_copyright(
+    fs: &impl FileSystem,
     filepath: &Path,
     copyright_line: &str,
-    line_nr: Option<usize>,
+    position: HeaderPosition<'_>,
+    restore_template: bool,
+    spdx_line: Option<&str>,
+    options: &WriteOptions,
 ) -> Result<(), CError> {
-    let mut file = tokio::fs::File::open(filepath)
-        .await
-        .map_err(|_| CError::ReadError(filepath.display().to_string()))?;
-    let mut data = Vec::new();
-    file.read_to_end(&mut data).await?;
-    let mut data: Vec<&str> = std::str::from_utf8(&data)?.split("\n").collect();
+    let data = fs.read(filepath).await?;
+    let settings = &options
+        .editor_settings
+        .for_content(&data, options.fallback_encoding);
+    let data = settings.decode(&data)?;
+    let separator = line_separator(&data);
+    let last_line;
+    let mut data: Vec<&str> = data.split(separator).collect();
 
-    match line_nr {
-        Some(line_nr) => {
-            data[line_nr] = &copyright_line;
+    // Keep CRLF line endings, `split` leaves the carriage return on the line
+    let line_end = match data.first() {
+        Some(first_line) if first_line.ends_with('\r') => "\r",
+        _ => "",
+    };
+    // Lines are written as if the last line had a line ending, which is
+    // removed again afterwards to keep the end of the file as it was
+    let unterminated = data.last().is_some_and(|last| !last.is_empty());
+    if unterminated {
+        last_line = format!("{}{}", data.pop().unwrap_or_default(), line_end);
+        data.extend([last_line.as_str(), ""]);
+    }
+    let header: Vec<String> = copyright_line
+        .split('\n')
+        .map(|line_| format!("{}{}", line_, line_end))
+        .collect();
+
+    let spdx_line = spdx_line.map(|spdx_line| format!("{}{}", spdx_line, line_end));
+
+    let updated;
+    let copyright_nr = match position {
+        HeaderPosition::Existing {
+            line_nr,
+            regex,
+            years,
+        } => {
+            // Only the copyright line of an existing header is updated
+            // The year regex is anchored at the end, before the line ending
+            let line_ = data[line_nr].strip_suffix('\r').unwrap_or(data[line_nr]);
+            updated = replace_years(line_, regex, years).map(|line_| line_ + line_end);
+            data[line_nr] = updated.as_deref().unwrap_or(&header[0]);
+            if restore_template {
+                self::restore_template(&mut data, line_nr, &header[1..]);
+            }
+            line_nr
         }
-        None => {
-            if data.len() >= 1 && data[0].starts_with("#!") {
-                // Insert copyright on the second line for shell scripts
-                // that might have a shebang line
-                data.insert(1, copyright_line);
-            } else {
-                data.insert(0, copyright_line);
+        HeaderPosition::Block {
+            line_nr,
+            placeholder,
+        } => {
+            let replaced = match placeholder {
+                true => line_nr..line_nr + 1,
+                false => line_nr..line_nr,
+            };
+            data.splice(replaced, std::iter::once(header[0].as_str()));
+            line_nr
+        }
+        HeaderPosition::New => {
+            let line_nr =
+                insertion_line_nr(&data, options.banner_sign.as_ref(), &options.insert_after);
+            data.splice(line_nr..line_nr, header.iter().map(String::as_str));
+            line_nr
+        }
+    };
+
+    if let Some(spdx_line) = spdx_line.as_deref() {
+        let header_end = copyright_nr + header.len() + options.scan_lines.unwrap_or(HEADER_LINES);
+        match data
+            .iter()
+            .take(header_end)
+            .position(|line_| spdx_license(line_).is_some())
+        {
+            Some(line_nr) => data[line_nr] = spdx_line,
+            None => data.insert(copyright_nr, spdx_line),
+        }
+    }
+
+    if unterminated {
+        data.pop();
+        if let Some(last) = data.last_mut() {
+            *last = last.strip_suffix(line_end).unwrap_or(last);
+        }
+    }
+
+    let separator = written_separator(separator, options);
+    let data = settings.encode(&data.join(separator))?;
+    write_file(fs, filepath, &data, options).await
+}
+
+/// Whether the file is read-only and skipped instead of written.
+fn skips_read_only(fs: &impl FileSystem, filepath: &Path, options: &WriteOptions) -> bool {
+    options.read_only_files == ReadOnlyPolicy::Skip && fs.is_read_only(filepath)
+}
+
+/// Write the file, handling read-only files according to `read_only_files`.
+async fn write_file(
+    fs: &impl FileSystem,
+    filepath: &Path,
+    data: &[u8],
+    options: &WriteOptions,
+) -> Result<(), CError> {
+    if !fs.is_read_only(filepath) {
+        return fs.write(filepath, data).await;
+    }
+    match options.read_only_files {
+        ReadOnlyPolicy::Write => fs.write_read_only(filepath, data).await,
+        ReadOnlyPolicy::Fail | ReadOnlyPolicy::Skip => {
+            Err(CError::ReadOnlyFile(filepath.display().to_string()))
+        }
+    }
+}
+
+/// Separator to split the lines of `text` at.
+///
+/// This is a lone CR for files with classic Mac OS line endings and LF
+/// otherwise, which leaves the CR of CRLF line endings on the lines.
+fn line_separator(text: &str) -> &'static str {
+    match text.find(['\r', '\n']) {
+        Some(idx) if text[idx..].starts_with('\r') && !text[idx..].starts_with("\r\n") => "\r",
+        _ => "\n",
+    }
+}
+
+/// Separator to join the lines split at `separator` with for writing.
+fn written_separator(separator: &'static str, options: &WriteOptions) -> &'static str {
+    match (separator, options.normalize_cr) {
+        ("\r", true) => "\n",
+        _ => separator,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{
+        header_lines, insertion_line_nr, read_migrate_copyright, read_remove_copyright,
+        read_write_copyright, read_write_sidecar, read_write_special_file, spdx_license, WriteMode,
+        WriteOptions, HEADER_LINES,
+    };
+    use crate::config::ReadOnlyPolicy;
+    use crate::editorconfig::{Charset, EditorSettings, EndOfLine};
+    use crate::fs::MemoryFs;
+    use crate::regex_ops::{generate_base_regex, CopyrightCache};
+    use crate::report::FileStatus;
+    use crate::{CError, CommentSign, Config};
+    use futures::future::ready;
+    use regex::Regex;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn fix_file(content: &str) -> (FileStatus, String) {
+        fix_file_with_mode(content, WriteMode::All)
+    }
+
+    fn fix_file_with_mode(content: &str, write_mode: WriteMode) -> (FileStatus, String) {
+        let options = WriteOptions {
+            write_mode,
+            ..Default::default()
+        };
+        fix_file_with_options(content, &options)
+    }
+
+    fn fix_file_with_options(content: &str, options: &WriteOptions) -> (FileStatus, String) {
+        let fs = MemoryFs::new();
+        fs.insert("script.sh", content);
+        fix_file_on(&fs, options).unwrap()
+    }
+
+    fn fix_file_on(fs: &MemoryFs, options: &WriteOptions) -> Result<(FileStatus, String), CError> {
+        let (status, data) = fix_bytes_on(fs, options)?;
+        Ok((status, String::from_utf8(data).unwrap()))
+    }
+
+    fn fix_bytes_on(
+        fs: &MemoryFs,
+        options: &WriteOptions,
+    ) -> Result<(FileStatus, Vec<u8>), CError> {
+        let filepath = Path::new("script.sh");
+
+        let comment_signs = [
+            CommentSign::LeftOnly("#".into()),
+            CommentSign::Enclosing("<#".into(), "#>".into()),
+        ];
+        let base_regex = generate_base_regex("DummyCompany", &Config::default());
+        let cache = CopyrightCache::new(&base_regex, comment_signs.iter());
+        let regexes = comment_signs
+            .iter()
+            .map(|comment_sign| cache.get_regex(comment_sign).unwrap())
+            .collect();
+        let outcome = futures::executor::block_on(read_write_copyright(
+            fs,
+            filepath.into(),
+            regexes,
+            ready("2022".to_owned()),
+            ready(vec![
+                "# Copyright (c) DummyCompany 2022".to_owned(),
+                "<# Copyright (c) DummyCompany 2022 #>".to_owned(),
+            ]),
+            options,
+        ))?;
+
+        Ok((outcome.status, fs.get(filepath).unwrap()))
+    }
+
+    #[test]
+    fn test_insert_copyright() {
+        let (status, content) = fix_file("echo\n");
+        assert_eq!(status, FileStatus::Missing);
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\necho\n");
+
+        let (_, content) = fix_file("#!/bin/sh\necho\n");
+        assert_eq!(
+            content,
+            "#!/bin/sh\n# Copyright (c) DummyCompany 2022\necho\n"
+        );
+
+        let (_, content) = fix_file("");
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\n");
+    }
+
+    #[test]
+    fn test_replace_copyright() {
+        let (status, content) = fix_file("#!/bin/sh\n# Copyright (c) DummyCompany 2020\necho\n");
+        assert_eq!(
+            status,
+            FileStatus::Outdated {
+                found_years: "2020".into()
+            }
+        );
+        assert_eq!(
+            content,
+            "#!/bin/sh\n# Copyright (c) DummyCompany 2022\necho\n"
+        );
+
+        let (status, content) = fix_file("# Copyright (c) DummyCompany 2022\necho\n");
+        assert_eq!(status, FileStatus::Correct);
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\necho\n");
+
+        // Only the years of legacy copyrights are replaced
+        let (_, content) = fix_file("# COPYRIGHT © DummyCompany 2019-2020\necho\n");
+        assert_eq!(content, "# COPYRIGHT © DummyCompany 2022\necho\n");
+    }
+
+    #[test]
+    fn test_scan_lines() {
+        let content = "#!/bin/sh\n\n# Preamble\n\n# Copyright (c) DummyCompany 2020\n";
+        let (status, _) = fix_file_with_mode(content, WriteMode::Never);
+        assert_eq!(status, FileStatus::Missing);
+
+        let options = WriteOptions {
+            scan_lines: Some(5),
+            ..Default::default()
+        };
+        let (status, content) = fix_file_with_options(content, &options);
+        assert_eq!(
+            status,
+            FileStatus::Outdated {
+                found_years: "2020".into()
+            }
+        );
+        assert!(content.ends_with("\n# Copyright (c) DummyCompany 2022\n"));
+    }
+
+    #[test]
+    fn test_keep_accepted_comment_sign() {
+        let (status, content) = fix_file("<# Copyright (c) DummyCompany 2020 #>\necho\n");
+        assert_eq!(
+            status,
+            FileStatus::Outdated {
+                found_years: "2020".into()
+            }
+        );
+        assert_eq!(content, "<# Copyright (c) DummyCompany 2022 #>\necho\n");
+    }
+
+    #[test]
+    fn test_add_missing_only() {
+        let (_, content) = fix_file_with_mode("echo\n", WriteMode::MissingOnly);
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\necho\n");
+
+        let original = "# Copyright (c) DummyCompany 2020\necho\n";
+        let (status, content) = fix_file_with_mode(original, WriteMode::MissingOnly);
+        assert_eq!(
+            status,
+            FileStatus::Outdated {
+                found_years: "2020".into()
+            }
+        );
+        assert_eq!(content, original);
+    }
+
+    #[test]
+    fn test_check_only() {
+        let (status, content) = fix_file_with_mode("echo\n", WriteMode::Never);
+        assert_eq!(status, FileStatus::Missing);
+        assert_eq!(content, "echo\n");
+
+        let original = "# Copyright (c) DummyCompany 2020\necho\n";
+        let (status, content) = fix_file_with_mode(original, WriteMode::Never);
+        assert_eq!(
+            status,
+            FileStatus::Outdated {
+                found_years: "2020".into()
+            }
+        );
+        assert_eq!(content, original);
+    }
+
+    #[test]
+    fn test_special_file() {
+        let fs = MemoryFs::new();
+        let filepath = Path::new("docs/conf.py");
+        fs.insert(
+            filepath,
+            "project = \"dummy\"\ncopyright = \"2019, DummyCompany\"\n",
+        );
+        let regex = Regex::new(r#"copyright = "(\d{4}(-\d{4})?)"#).unwrap();
+
+        let outcome = futures::executor::block_on(read_write_special_file(
+            &fs,
+            filepath.into(),
+            &regex,
+            "2019-2022".into(),
+            Some("copyright = \"2019-2022"),
+            &WriteOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(
+            outcome.status,
+            FileStatus::Outdated {
+                found_years: "2019".into()
             }
+        );
+        assert_eq!(outcome.line_nr, Some(1));
+        assert_eq!(
+            String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+            "project = \"dummy\"\ncopyright = \"2019-2022, DummyCompany\"\n"
+        );
+
+        // Without replacement, only the captured years are replaced
+        let filepath = Path::new("LICENSE");
+        fs.insert(filepath, "MIT License\n\nCopyright (c) 2019 Alice\n");
+        let regex = Regex::new(r"^\s*(?i:Copyright)(?: \(c\))? (\d{4}(?:-\d{4})?)\b").unwrap();
+        let outcome = futures::executor::block_on(read_write_special_file(
+            &fs,
+            filepath.into(),
+            &regex,
+            "2019-2022".into(),
+            None,
+            &WriteOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(outcome.line_nr, Some(2));
+        assert_eq!(
+            String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+            "MIT License\n\nCopyright (c) 2019-2022 Alice\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_copyright() {
+        let remove = |content: &str, write_mode| {
+            let fs = MemoryFs::new();
+            let filepath = Path::new("script.sh");
+            fs.insert(filepath, content);
+            let base_regex = generate_base_regex("DummyCompany", &Config::default());
+            let comment_sign = CommentSign::LeftOnly("#".into());
+            let cache = CopyrightCache::new(&base_regex, [&comment_sign].into_iter());
+            let options = WriteOptions {
+                write_mode,
+                ..Default::default()
+            };
+            let line_nrs = futures::executor::block_on(read_remove_copyright(
+                &fs,
+                filepath,
+                &[cache.get_regex(&comment_sign).unwrap()],
+                &options,
+            ))
+            .unwrap();
+            (
+                line_nrs,
+                String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+            )
+        };
+
+        let content = "#!/bin/sh\r\n# Copyright (c) DummyCompany 2020\r\n\r\necho\r\n";
+        assert_eq!(
+            remove(content, WriteMode::All),
+            (vec![1], "#!/bin/sh\r\necho\r\n".into())
+        );
+        assert_eq!(remove(content, WriteMode::Never), (vec![1], content.into()));
+        // Copyrights of other holders and blank lines after code are kept
+        let content = "# Copyright (c) Other 2020\n# Copyright (c) DummyCompany 2020\necho\n\n";
+        assert_eq!(
+            remove(content, WriteMode::All),
+            (vec![1], "# Copyright (c) Other 2020\necho\n\n".into())
+        );
+        assert_eq!(
+            remove("# Copyright (c) DummyCompany 2020\n", WriteMode::All),
+            (vec![0], String::new())
+        );
+    }
+
+    #[test]
+    fn test_migrate_copyright() {
+        let fs = MemoryFs::new();
+        let filepath = Path::new("script.sh");
+        fs.insert(
+            filepath,
+            "# Copyright (c) OldCo GmbH 2019-2021\r\n# Copyright (c) Other 2020\r\necho\r\n",
+        );
+        let base_regex = generate_base_regex("OldCo GmbH", &Config::default());
+        let comment_sign = CommentSign::LeftOnly("#".into());
+        let cache = CopyrightCache::new(&base_regex, [&comment_sign].into_iter());
+
+        let line_nrs = futures::executor::block_on(read_migrate_copyright(
+            &fs,
+            filepath,
+            &[cache.get_regex(&comment_sign).unwrap()],
+            |_, years| ready(format!("# Copyright (c) NewCo AG {}\n# Template", years)),
+            &WriteOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(line_nrs, [0]);
+        assert_eq!(
+            String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+            "# Copyright (c) NewCo AG 2019-2021\r\n# Copyright (c) Other 2020\r\necho\r\n"
+        );
+    }
+
+    #[test]
+    fn test_keep_crlf() {
+        let (_, content) = fix_file("echo\r\n");
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\r\necho\r\n");
+
+        let (_, content) = fix_file("# Copyright (c) DummyCompany 2020\r\necho\r\n");
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\r\necho\r\n");
+
+        // Only the years of legacy copyrights are replaced
+        let (_, content) = fix_file("# COPYRIGHT © DummyCompany 2019\r\necho\r\n");
+        assert_eq!(content, "# COPYRIGHT © DummyCompany 2022\r\necho\r\n");
+    }
+
+    #[test]
+    fn test_keep_final_newline() {
+        let fs = MemoryFs::new();
+        let filepath = Path::new("script.sh");
+        let regex = Regex::new(
+            r"^# Copyright \(c\) DummyCompany (\d{4}(-\d{4}){0,1})(?P<suffix> Dummy\.)?$",
+        )
+        .unwrap();
+        let header = "# Copyright (c) DummyCompany 2022 Dummy.\n# Licensed under the License.";
+        for (content, expected) in [
+            // The regenerated copyright line and the restored template lines
+            // end the file
+            (
+                "echo\r\n# Copyright (c) DummyCompany 2020",
+                "echo\r\n# Copyright (c) DummyCompany 2022 Dummy.\r\n# Licensed under the License.",
+            ),
+            (
+                "echo\r\n# Copyright (c) DummyCompany 2020\r\n",
+                "echo\r\n# Copyright (c) DummyCompany 2022 Dummy.\r\n# Licensed under the License.\r\n",
+            ),
+            (
+                "echo\r\n# Copyright (c) DummyCompany 2022 Dummy.",
+                "echo\r\n# Copyright (c) DummyCompany 2022 Dummy.\r\n# Licensed under the License.",
+            ),
+            (
+                "",
+                "# Copyright (c) DummyCompany 2022 Dummy.\n# Licensed under the License.\n",
+            ),
+            (
+                "echo",
+                "# Copyright (c) DummyCompany 2022 Dummy.\n# Licensed under the License.\necho",
+            ),
+        ] {
+            fs.insert(filepath, content);
+            futures::executor::block_on(read_write_copyright(
+                &fs,
+                filepath.into(),
+                vec![regex.clone().into()],
+                ready("2022".to_owned()),
+                ready(vec![header.to_owned()]),
+                &WriteOptions {
+                    verify_template: true,
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+            assert_eq!(
+                String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+                expected
+            );
         }
     }
 
-    let mut file = tokio::fs::File::create(filepath)
-        .await
-        .map_err(|_| CError::WriteError(filepath.display().to_string()))?;
-    file.write_all(data.join("\n").as_bytes())
-        .await
-        .map_err(|_| CError::WriteError(filepath.display().to_string()))?;
+    #[test]
+    fn test_read_only_files() {
+        let fix_read_only = |read_only_files| {
+            let fs = MemoryFs::new();
+            fs.insert("script.sh", "echo\n");
+            fs.set_read_only("script.sh");
+            let options = WriteOptions {
+                read_only_files,
+                ..Default::default()
+            };
+            fix_file_on(&fs, &options)
+        };
 
-    Ok(())
+        assert!(matches!(
+            fix_read_only(ReadOnlyPolicy::Fail),
+            Err(CError::ReadOnlyFile(_))
+        ));
+        let (status, content) = fix_read_only(ReadOnlyPolicy::Write).unwrap();
+        assert_eq!(status, FileStatus::Missing);
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\necho\n");
+        let (status, content) = fix_read_only(ReadOnlyPolicy::Skip).unwrap();
+        assert_eq!(status, FileStatus::ReadOnly);
+        assert_eq!(content, "echo\n");
+    }
+
+    #[test]
+    fn test_cr_line_endings() {
+        let (_, content) = fix_file("#!/bin/sh\recho\r");
+        assert_eq!(
+            content,
+            "#!/bin/sh\r# Copyright (c) DummyCompany 2022\recho\r"
+        );
+
+        let (status, content) = fix_file("# Copyright (c) DummyCompany 2020\recho\r");
+        assert_eq!(
+            status,
+            FileStatus::Outdated {
+                found_years: "2020".into()
+            }
+        );
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\recho\r");
+
+        let options = WriteOptions {
+            normalize_cr: true,
+            ..Default::default()
+        };
+        let (_, content) = fix_file_with_options("echo\recho\r", &options);
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\necho\necho\n");
+    }
+
+    #[test]
+    fn test_editorconfig_settings() {
+        let options = WriteOptions {
+            editor_settings: EditorSettings {
+                end_of_line: Some(EndOfLine::Crlf),
+                charset: Some(Charset::Utf8Bom),
+                insert_final_newline: Some(true),
+            },
+            ..Default::default()
+        };
+        let (_, content) = fix_file_with_options("\u{feff}echo\necho", &options);
+        assert_eq!(
+            content,
+            "\u{feff}# Copyright (c) DummyCompany 2022\r\necho\r\necho\r\n"
+        );
+
+        let options = WriteOptions {
+            editor_settings: EditorSettings {
+                insert_final_newline: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (_, content) = fix_file_with_options("echo\n", &options);
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\necho");
+    }
+
+    #[test]
+    fn test_detected_encodings() {
+        let fix_bytes = |content: &[u8], options: &WriteOptions| {
+            let fs = MemoryFs::new();
+            fs.insert("script.sh", content);
+            fix_bytes_on(&fs, options)
+        };
+
+        // The copyright goes after the byte order mark, which is kept
+        let (_, content) = fix_file("\u{feff}echo\n");
+        assert_eq!(content, "\u{feff}# Copyright (c) DummyCompany 2022\necho\n");
+        let (status, _) = fix_file(&content);
+        assert_eq!(status, FileStatus::Correct);
+
+        let utf16: Vec<u8> = [0xfe, 0xff]
+            .into_iter()
+            .chain("echo\n".encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        let (status, content) = fix_bytes(&utf16, &WriteOptions::default()).unwrap();
+        assert_eq!(status, FileStatus::Missing);
+        let expected: Vec<u8> = [0xfe, 0xff]
+            .into_iter()
+            .chain(
+                "# Copyright (c) DummyCompany 2022\necho\n"
+                    .encode_utf16()
+                    .flat_map(u16::to_be_bytes),
+            )
+            .collect();
+        assert_eq!(content, expected);
+
+        let latin1 = b"# Copyright (c) DummyCompany 2020\necho \"Gr\xfc\xdfe\"\n";
+        assert!(matches!(
+            fix_bytes(latin1, &WriteOptions::default()),
+            Err(CError::Utf8Error(_))
+        ));
+        let options = WriteOptions {
+            fallback_encoding: Some(Charset::Latin1),
+            ..Default::default()
+        };
+        let (status, content) = fix_bytes(latin1, &options).unwrap();
+        assert!(matches!(status, FileStatus::Outdated { .. }));
+        assert_eq!(
+            content,
+            b"# Copyright (c) DummyCompany 2022\necho \"Gr\xfc\xdfe\"\n"
+        );
+    }
+
+    #[test]
+    fn test_header_lines_only_inserted() {
+        let fs = MemoryFs::new();
+        let filepath = Path::new("script.sh");
+        let regex = Regex::new(r"^# Copyright \(c\) DummyCompany (\d{4}(-\d{4}){0,1})$").unwrap();
+        let header = "# Copyright (c) DummyCompany 2022\n#\n# All rights reserved.";
+        for (content, expected) in [
+            (
+                "echo\r\n",
+                "# Copyright (c) DummyCompany 2022\r\n#\r\n# All rights reserved.\r\necho\r\n",
+            ),
+            (
+                "# Copyright (c) DummyCompany 2020\necho\n",
+                "# Copyright (c) DummyCompany 2022\necho\n",
+            ),
+        ] {
+            fs.insert(filepath, content);
+            futures::executor::block_on(read_write_copyright(
+                &fs,
+                filepath.into(),
+                vec![regex.clone().into()],
+                ready("2022".to_owned()),
+                ready(vec![header.to_owned()]),
+                &WriteOptions::default(),
+            ))
+            .unwrap();
+            assert_eq!(
+                String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_template() {
+        let fs = MemoryFs::new();
+        let filepath = Path::new("script.sh");
+        let regex = Regex::new(r"^# Copyright \(c\) DummyCompany (\d{4}(-\d{4}){0,1})$").unwrap();
+        let header = "# Copyright (c) DummyCompany 2022\n#\n# All rights reserved.";
+        let canonical = "# Copyright (c) DummyCompany 2022\n#\n# All rights reserved.\necho\n";
+        for (content, expected_status) in [
+            (canonical, FileStatus::Correct),
+            (
+                "# Copyright (c) DummyCompany 2022\n#\n# Some rights reserved.\necho\n",
+                FileStatus::TemplateDrift {
+                    line_nr: 2,
+                    expected: "# All rights reserved.".to_owned(),
+                },
+            ),
+            (
+                "# Copyright (c) DummyCompany 2022\n# All rights reserved.\necho\n",
+                FileStatus::TemplateDrift {
+                    line_nr: 1,
+                    expected: "#".to_owned(),
+                },
+            ),
+            (
+                "# Copyright (c) DummyCompany 2020\necho\n",
+                FileStatus::Outdated {
+                    found_years: "2020".to_owned(),
+                },
+            ),
+        ] {
+            fs.insert(filepath, content);
+            let outcome = futures::executor::block_on(read_write_copyright(
+                &fs,
+                filepath.into(),
+                vec![regex.clone().into()],
+                ready("2022".to_owned()),
+                ready(vec![header.to_owned()]),
+                &WriteOptions {
+                    verify_template: true,
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+            assert_eq!(outcome.status, expected_status);
+            assert_eq!(
+                String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+                canonical
+            );
+        }
+    }
+
+    #[test]
+    fn test_spdx_line() {
+        let fs = MemoryFs::new();
+        let filepath = Path::new("script.sh");
+        let regex = Regex::new(r"^# Copyright \(c\) DummyCompany (\d{4}(-\d{4}){0,1})$").unwrap();
+        let canonical = "# SPDX-License-Identifier: MIT\n# Copyright (c) DummyCompany 2022\necho\n";
+        for (content, expected_status) in [
+            (canonical, FileStatus::Correct),
+            (
+                "# Copyright (c) DummyCompany 2022\necho\n",
+                FileStatus::SpdxMismatch {
+                    found: None,
+                    expected: "MIT".to_owned(),
+                },
+            ),
+            (
+                "# SPDX-License-Identifier: GPL-2.0-only\n# Copyright (c) DummyCompany 2022\necho\n",
+                FileStatus::SpdxMismatch {
+                    found: Some("GPL-2.0-only".to_owned()),
+                    expected: "MIT".to_owned(),
+                },
+            ),
+            ("echo\n", FileStatus::Missing),
+        ] {
+            fs.insert(filepath, content);
+            let outcome = futures::executor::block_on(read_write_copyright(
+                &fs,
+                filepath.into(),
+                vec![regex.clone().into()],
+                ready("2022".to_owned()),
+                ready(vec!["# Copyright (c) DummyCompany 2022".to_owned()]),
+                &WriteOptions {
+                    spdx_lines: vec!["# SPDX-License-Identifier: MIT".to_owned()],
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+            assert_eq!(outcome.status, expected_status);
+            assert_eq!(
+                String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+                canonical
+            );
+        }
+
+        assert_eq!(
+            spdx_license("/* SPDX-License-Identifier: MIT OR Apache-2.0 */").as_deref(),
+            Some("MIT OR Apache-2.0")
+        );
+    }
+
+    #[test]
+    fn test_sidecar() {
+        let fs = MemoryFs::new();
+        let sidecar = Path::new("logo.png.license");
+        let regex =
+            Regex::new(r"^SPDX-FileCopyrightText: (\d{4}(-\d{4}){0,1}) DummyCompany$").unwrap();
+        let options = WriteOptions {
+            spdx_lines: vec!["SPDX-License-Identifier: MIT".to_owned()],
+            ..Default::default()
+        };
+        let check = |years: &str| {
+            futures::executor::block_on(read_write_sidecar(
+                &fs,
+                sidecar.into(),
+                regex.clone().into(),
+                years.to_owned(),
+                format!("SPDX-FileCopyrightText: {} DummyCompany", years),
+                &options,
+            ))
+            .unwrap()
+            .status
+        };
+
+        assert_eq!(check("2022"), FileStatus::Missing);
+        assert_eq!(
+            String::from_utf8(fs.get(sidecar).unwrap()).unwrap(),
+            "SPDX-FileCopyrightText: 2022 DummyCompany\n\nSPDX-License-Identifier: MIT\n"
+        );
+        assert_eq!(check("2022"), FileStatus::Correct);
+        assert_eq!(
+            check("2022-2023"),
+            FileStatus::Outdated {
+                found_years: "2022".to_owned()
+            }
+        );
+        assert_eq!(
+            String::from_utf8(fs.get(sidecar).unwrap()).unwrap(),
+            "SPDX-FileCopyrightText: 2022-2023 DummyCompany\n\nSPDX-License-Identifier: MIT\n"
+        );
+    }
+
+    #[test]
+    fn test_template_block() {
+        let fs = MemoryFs::new();
+        let filepath = Path::new("script.sh");
+        let regex = Regex::new(r"^# Copyright \(c\) DummyCompany (\d{4}(-\d{4}){0,1})$").unwrap();
+        let header =
+            "# Copyright (c) DummyCompany 2022\n#\n# Licensed under the License.\n# See LICENSE.";
+        for (content, expected) in [
+            (
+                "#!/bin/sh\n# Copyright [yyyy] [name of copyright owner]\n#\n# Licensed under the License.\n# See LICENSE.\necho\n",
+                "#!/bin/sh\n# Copyright (c) DummyCompany 2022\n#\n# Licensed under the License.\n# See LICENSE.\necho\n",
+            ),
+            (
+                "# Copyright 2019 Other Corp.\n#\n# Licensed under the License.\n# See LICENSE.\necho\n",
+                "# Copyright 2019 Other Corp.\n# Copyright (c) DummyCompany 2022\n#\n# Licensed under the License.\n# See LICENSE.\necho\n",
+            ),
+            (
+                "echo\n",
+                "# Copyright (c) DummyCompany 2022\n#\n# Licensed under the License.\n# See LICENSE.\necho\n",
+            ),
+        ] {
+            fs.insert(filepath, content);
+            let outcome = futures::executor::block_on(read_write_copyright(
+                &fs,
+                filepath.into(),
+                vec![regex.clone().into()],
+                ready("2022".to_owned()),
+                ready(vec![header.to_owned()]),
+                &WriteOptions::default(),
+            ))
+            .unwrap();
+            assert_eq!(outcome.status, FileStatus::Missing);
+            assert_eq!(
+                String::from_utf8(fs.get(filepath).unwrap()).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert_after_banner() {
+        let options = WriteOptions {
+            banner_sign: Some(CommentSign::LeftOnly("#".into())),
+            ..Default::default()
+        };
+        let banner = "#!/bin/sh\n# -*- mode: sh -*-\n#  _  _\n# | || |\n#\n";
+        let (status, content) = fix_file_with_options(&format!("{}\necho\n", banner), &options);
+        assert_eq!(status, FileStatus::Missing);
+        let expected = format!("{}# Copyright (c) DummyCompany 2022\n\necho\n", banner);
+        assert_eq!(content, expected);
+
+        // The copyright after the banner is found on the next run
+        let (status, content) = fix_file_with_options(&expected, &options);
+        assert_eq!(status, FileStatus::Correct);
+        assert_eq!(content, expected);
+
+        let (_, content) = fix_file_with_options("echo\n", &options);
+        assert_eq!(content, "# Copyright (c) DummyCompany 2022\necho\n");
+    }
+
+    #[test]
+    fn test_keep_headers() {
+        let options = WriteOptions {
+            keep_regexes: vec![Arc::new(Regex::new("Public Domain|CC0").unwrap())],
+            ..Default::default()
+        };
+        let content = "#!/bin/sh\n# Released into the Public Domain\necho\n";
+        let (status, new_content) = fix_file_with_options(content, &options);
+        assert_eq!(
+            status,
+            FileStatus::Exempt {
+                pattern: "Public Domain|CC0".into()
+            }
+        );
+        assert_eq!(new_content, content);
+
+        // Keep patterns are only searched in the header
+        let (status, _) = fix_file_with_options("echo\necho\necho\n# CC0\n", &options);
+        assert_eq!(status, FileStatus::Missing);
+    }
+
+    #[test]
+    fn test_block_banner_end() {
+        let comment_sign = CommentSign::Enclosing("/*".into(), "*/".into());
+        let lines = ["/* Tool banner", " * generated", " */", "int x;"];
+        assert_eq!(insertion_line_nr(&lines, Some(&comment_sign), &[]), 3);
+        assert_eq!(insertion_line_nr(&lines, None, &[]), 0);
+        assert_eq!(
+            header_lines(&lines, Some(&comment_sign), &[], HEADER_LINES),
+            6
+        );
+    }
+
+    #[test]
+    fn test_insert_after() {
+        let regexes = |exprs: &[&str]| -> Vec<Arc<Regex>> {
+            exprs
+                .iter()
+                .map(|expr| Arc::new(Regex::new(expr).unwrap()))
+                .collect()
+        };
+        let options = WriteOptions {
+            insert_after: regexes(&["^#.*coding[:=]"]),
+            ..Default::default()
+        };
+        let content = "#!/usr/bin/env python\n# -*- coding: latin-1 -*-\nprint()\n";
+        let (status, new_content) = fix_file_with_options(content, &options);
+        assert_eq!(status, FileStatus::Missing);
+        let expected = "#!/usr/bin/env python\n# -*- coding: latin-1 -*-\n\
+                        # Copyright (c) DummyCompany 2022\nprint()\n";
+        assert_eq!(new_content, expected);
+
+        // The copyright after the anchor is found on the next run
+        let (status, _) = fix_file_with_options(expected, &options);
+        assert_eq!(status, FileStatus::Correct);
+
+        // Without a match, the copyright goes to the top
+        let (_, new_content) = fix_file_with_options("print()\n", &options);
+        assert_eq!(new_content, "# Copyright (c) DummyCompany 2022\nprint()\n");
+
+        // Front matter ends at the second `---`
+        let front_matter = regexes(&["^---$", "^---$"]);
+        let lines = ["---", "title: Docs", "---", "# Docs"];
+        assert_eq!(insertion_line_nr(&lines, None, &front_matter), 3);
+        assert_eq!(header_lines(&lines, None, &front_matter, HEADER_LINES), 6);
+        let unclosed = ["---", "title: Docs"];
+        assert_eq!(insertion_line_nr(&unclosed, None, &front_matter), 0);
+        let xml = regexes(&[r"^<\?xml"]);
+        let lines = ["<svg>", "<?xml version=\"1.0\"?>"];
+        assert_eq!(insertion_line_nr(&lines, None, &xml), 0);
+    }
 }
@@ -0,0 +1,690 @@
+//! Abstract file access of the copyright check.
+//!
+//! The check only needs to read the header of a file, read its full content
+//! and write it back. `LocalFs` does this on disk while `MemoryFs` keeps files
+//! in memory, which allows testing the insertion and replacement logic
+//! without temporary directories.
+//!
+//! On Windows, `LocalFs` prefixes long and UNC paths with `\\?\` so that
+//! files in deep directory trees beyond `MAX_PATH` can be opened.
+//!
+//! Headers are read in chunks of a few KB instead of line by line, which
+//! saves syscalls, especially on network filesystems. Files are written to a
+//! temporary file which is renamed over them.
+
+use crate::editorconfig::{utf16_units, Charset};
+use crate::CError;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Length from which Windows paths need the verbatim prefix.
+///
+/// `MAX_PATH` is 260 but directories are limited to 248 characters to leave
+/// room for a file name.
+const MAX_PATH_WITHOUT_PREFIX: usize = 248;
+
+/// Number of times a write to a file locked by another process is retried.
+const WRITE_RETRIES: u32 = 5;
+
+/// Delay before the first retry of a write, doubled for every further retry.
+const WRITE_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Number of bytes read at once when scanning the header of a file.
+const HEADER_CHUNK_SIZE: u64 = 4096;
+
+pub trait FileSystem: Sync {
+    /// Read up to `max_lines` lines from the start of the file.
+    fn read_header(
+        &self,
+        filepath: &Path,
+        max_lines: usize,
+    ) -> impl Future<Output = Result<Vec<String>, CError>> + Send;
+
+    /// Read the full content of the file.
+    fn read(&self, filepath: &Path) -> impl Future<Output = Result<Vec<u8>, CError>> + Send;
+
+    /// Replace the content of the file.
+    fn write(
+        &self,
+        filepath: &Path,
+        data: &[u8],
+    ) -> impl Future<Output = Result<(), CError>> + Send;
+
+    /// Whether the file is marked read-only.
+    fn is_read_only(&self, _filepath: &Path) -> bool {
+        false
+    }
+
+    /// Replace the content of a read-only file which stays read-only.
+    fn write_read_only(
+        &self,
+        filepath: &Path,
+        data: &[u8],
+    ) -> impl Future<Output = Result<(), CError>> + Send {
+        self.write(filepath, data)
+    }
+}
+
+/// Files on the local disk.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalFs;
+
+impl FileSystem for LocalFs {
+    async fn read_header(&self, filepath: &Path, max_lines: usize) -> Result<Vec<String>, CError> {
+        let file = std::fs::File::open(long_path(filepath))
+            .map_err(|_| CError::ReadError(filepath.display().to_string()))?;
+        Ok(read_header_lines(file, max_lines)?)
+    }
+
+    async fn read(&self, filepath: &Path) -> Result<Vec<u8>, CError> {
+        let mut file = tokio::fs::File::open(long_path(filepath))
+            .await
+            .map_err(|_| CError::ReadError(filepath.display().to_string()))?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    async fn write(&self, filepath: &Path, data: &[u8]) -> Result<(), CError> {
+        let mut delay = WRITE_RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            match write_file(filepath, data).await {
+                Ok(()) => return Ok(()),
+                Err(e) if is_lock_error(&e) && attempt < WRITE_RETRIES => {
+                    log::debug!("{} is locked, retrying in {:?}", filepath.display(), delay);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) if is_lock_error(&e) => {
+                    return Err(CError::FileLocked(filepath.display().to_string()))
+                }
+                Err(_) => return Err(CError::WriteError(filepath.display().to_string())),
+            }
+        }
+    }
+
+    fn is_read_only(&self, filepath: &Path) -> bool {
+        std::fs::metadata(long_path(filepath))
+            .is_ok_and(|metadata| metadata.permissions().readonly())
+    }
+
+    async fn write_read_only(&self, filepath: &Path, data: &[u8]) -> Result<(), CError> {
+        let permissions = std::fs::metadata(long_path(filepath))?.permissions();
+        std::fs::set_permissions(long_path(filepath), writable(&permissions))?;
+        let written = self.write(filepath, data).await;
+        std::fs::set_permissions(long_path(filepath), permissions)?;
+        written
+    }
+}
+
+/// Replace the file via a temporary file in the same directory which is
+/// renamed over it, so that a crash never leaves a half-written file.
+///
+/// The temporary file gets the permissions of the file. Symlinks are
+/// resolved so that their target is replaced instead of the link.
+async fn write_file(filepath: &Path, data: &[u8]) -> std::io::Result<()> {
+    let filepath = match tokio::fs::symlink_metadata(long_path(filepath)).await {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            tokio::fs::canonicalize(long_path(filepath)).await?
+        }
+        _ => filepath.to_owned(),
+    };
+    let tmp_path = temp_path(&filepath);
+    let written = match write_temp_file(&tmp_path, &filepath, data).await {
+        Ok(()) => tokio::fs::rename(long_path(&tmp_path), long_path(&filepath)).await,
+        Err(e) => Err(e),
+    };
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(long_path(&tmp_path)).await;
+    }
+    written
+}
+
+/// Write the data to the temporary file `tmp_path` replacing `filepath`.
+async fn write_temp_file(tmp_path: &Path, filepath: &Path, data: &[u8]) -> std::io::Result<()> {
+    let permissions = match tokio::fs::metadata(long_path(filepath)).await {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let mut file = tokio::fs::File::create(long_path(tmp_path)).await?;
+    file.write_all(data).await?;
+    // The data must be on disk before the rename makes it visible
+    file.sync_all().await?;
+    drop(file);
+    match permissions {
+        Some(permissions) => tokio::fs::set_permissions(long_path(tmp_path), permissions).await,
+        None => Ok(()),
+    }
+}
+
+/// Path of the temporary file written to replace the file.
+fn temp_path(filepath: &Path) -> PathBuf {
+    let filename = filepath.file_name().unwrap_or_default().to_string_lossy();
+    filepath.with_file_name(format!(
+        ".{}.git-copyright-{}.tmp",
+        filename,
+        std::process::id()
+    ))
+}
+
+/// Whether the error is caused by another process locking the file.
+///
+/// Editors, indexers and virus scanners on Windows briefly open files without
+/// sharing them, which makes writes fail with a sharing or lock violation.
+fn is_lock_error(error: &std::io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    cfg!(windows)
+        && matches!(
+            error.raw_os_error(),
+            Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+        )
+}
+
+/// Permissions which allow the owner to write the file.
+///
+/// On Unix, only the write bit of the owner is set instead of making the file
+/// writable for everyone. On Windows, the read-only attribute is cleared.
+#[cfg(unix)]
+fn writable(permissions: &std::fs::Permissions) -> std::fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::Permissions::from_mode(permissions.mode() | 0o200)
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::permissions_set_readonly_false)]
+fn writable(permissions: &std::fs::Permissions) -> std::fs::Permissions {
+    let mut permissions = permissions.clone();
+    permissions.set_readonly(false);
+    permissions
+}
+
+/// Read up to `max_lines` lines in chunks of `HEADER_CHUNK_SIZE` bytes.
+///
+/// Like `BufRead::lines`, line endings are removed and reading stops at the
+/// first line which is not valid UTF-8. Besides LF and CRLF, lines may end
+/// with a lone CR as in files from classic Mac OS. A UTF-8 byte order mark is
+/// skipped and files with a UTF-16 byte order mark are decoded.
+pub(crate) fn read_header_lines(
+    mut reader: impl Read,
+    max_lines: usize,
+) -> std::io::Result<Vec<String>> {
+    let mut data = Vec::new();
+    let eof = loop {
+        let num_read = (&mut reader)
+            .take(HEADER_CHUNK_SIZE)
+            .read_to_end(&mut data)?;
+        if (num_read as u64) < HEADER_CHUNK_SIZE {
+            break true;
+        }
+        if split_lines(&without_bom(&data)).len() > max_lines {
+            break false;
+        }
+    };
+
+    let data = without_bom(&data);
+    let mut lines = split_lines(&data);
+    // The last part is either empty after a final newline or an incomplete line
+    if let Some(last) = lines.pop() {
+        if eof && !last.is_empty() {
+            lines.push(last);
+        }
+    }
+
+    Ok(lines
+        .into_iter()
+        .take(max_lines)
+        .map_while(|line_| std::str::from_utf8(line_).ok())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Data as UTF-8 without byte order mark, data with a UTF-16 byte order mark
+/// is decoded.
+fn without_bom(data: &[u8]) -> Cow<'_, [u8]> {
+    match Charset::from_bom(data) {
+        Some(Charset::Utf8Bom) => Cow::Borrowed(&data[3..]),
+        Some(charset) => {
+            let units = utf16_units(&data[2..], charset);
+            let text: String = char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect();
+            Cow::Owned(text.into_bytes())
+        }
+        None => Cow::Borrowed(data),
+    }
+}
+
+/// Split data at LF, CRLF and lone CR line endings.
+pub(crate) fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut idx = 0;
+    while idx < data.len() {
+        match data[idx] {
+            b'\n' => {
+                lines.push(&data[start..idx]);
+                start = idx + 1;
+            }
+            b'\r' => {
+                lines.push(&data[start..idx]);
+                if data.get(idx + 1) == Some(&b'\n') {
+                    idx += 1;
+                }
+                start = idx + 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+    lines.push(&data[start..]);
+    lines
+}
+
+/// Make a path usable for file operations beyond `MAX_PATH` on Windows.
+///
+/// Long absolute paths and UNC paths get the verbatim prefix `\\?\`. On other
+/// platforms and for short local paths, the path is returned unchanged.
+pub fn long_path(filepath: &Path) -> Cow<'_, Path> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(filepath);
+    }
+
+    // Verbatim paths are not normalized by Windows, so we resolve `.`, `..`
+    // and forward slashes first.
+    let absolute = match std::path::absolute(filepath) {
+        Ok(absolute) => absolute,
+        Err(_) => return Cow::Borrowed(filepath),
+    };
+    let absolute_str = absolute.to_string_lossy();
+    if absolute_str.len() < MAX_PATH_WITHOUT_PREFIX && !absolute_str.starts_with(r"\\") {
+        return Cow::Borrowed(filepath);
+    }
+
+    match verbatim_path(&absolute_str) {
+        Some(verbatim) => Cow::Owned(PathBuf::from(verbatim)),
+        None => Cow::Borrowed(filepath),
+    }
+}
+
+/// Add the verbatim prefix to an absolute Windows path.
+///
+/// Returns `None` for relative paths and paths which are already verbatim.
+fn verbatim_path(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") {
+        return None;
+    }
+
+    let path = path.replace('/', "\\");
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{}", unc));
+    }
+
+    match path.as_bytes() {
+        [drive, b':', b'\\', ..] if drive.is_ascii_alphabetic() => Some(format!(r"\\?\{}", path)),
+        _ => None,
+    }
+}
+
+/// Resolve drive-relative repository paths like `C:repo` on Windows.
+///
+/// These are relative to the current directory of the drive and cannot be
+/// passed to git as working directory. Other paths are returned unchanged.
+pub fn normalize_repo_path(repo_path: &str) -> String {
+    if !cfg!(windows) || !is_drive_relative(repo_path) {
+        return repo_path.to_owned();
+    }
+
+    match std::path::absolute(repo_path) {
+        Ok(absolute) => absolute.to_string_lossy().into_owned(),
+        Err(_) => repo_path.to_owned(),
+    }
+}
+
+/// List all files below `root` relative to it with `/` as separator, for
+/// directories which are not git repositories.
+///
+/// `.git` directories are skipped.
+pub fn walk_files(root: &Path) -> Result<Vec<String>, CError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let rel_path = dir.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() && entry.file_name() != ".git" {
+                dirs.push(rel_path);
+            } else if file_type.is_file() {
+                let components: Vec<_> = rel_path
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect();
+                files.push(components.join("/"));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Git repositories in and below `root`, sorted by path.
+///
+/// Repositories are recognized by their `.git` directory or file. Submodules,
+/// whose `.git` file points into the `modules` of the superproject, are
+/// skipped since they are checked in their own project.
+pub fn find_repos(root: &Path) -> Result<Vec<PathBuf>, CError> {
+    let mut repos = Vec::new();
+    let mut dirs = vec![root.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let dot_git = dir.join(".git");
+        let is_repo = match std::fs::read_to_string(&dot_git) {
+            Ok(gitdir) => !gitdir.replace('\\', "/").contains("/modules/"),
+            Err(_) => dot_git.is_dir(),
+        };
+        if is_repo {
+            repos.push(dir.clone());
+        }
+
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && entry.file_name() != ".git" {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    repos.sort();
+    Ok(repos)
+}
+
+/// Years from creating to last modifying the file according to its
+/// timestamps, if the filesystem provides them.
+pub fn timestamp_years(filepath: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(long_path(filepath)).ok()?;
+    let modified = metadata.modified().ok()?;
+    let created = metadata.created().unwrap_or(modified).min(modified);
+    let year = |time| {
+        chrono::DateTime::<chrono::Utc>::from(time)
+            .format("%Y")
+            .to_string()
+    };
+    match (year(created), year(modified)) {
+        (created, modified) if created == modified => Some(created),
+        (created, modified) => Some(format!("{}-{}", created, modified)),
+    }
+}
+
+/// Modification time of the file, if the filesystem provides it.
+pub fn modified_time(filepath: &Path) -> Option<SystemTime> {
+    std::fs::metadata(long_path(filepath)).ok()?.modified().ok()
+}
+
+/// Set the modification time of the file.
+///
+/// This also works for read-only files owned by the user.
+pub fn set_modified_time(filepath: &Path, time: SystemTime) -> Result<(), CError> {
+    std::fs::File::options()
+        .write(true)
+        .open(long_path(filepath))
+        .or_else(|_| open_for_attributes(&long_path(filepath)))
+        .and_then(|file| file.set_modified(time))
+        .map_err(|_| CError::WriteError(filepath.display().to_string()))
+}
+
+/// Open a file to change its timestamps without write access to its content.
+#[cfg(windows)]
+fn open_for_attributes(filepath: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_WRITE_ATTRIBUTES: u32 = 0x100;
+    std::fs::File::options()
+        .access_mode(FILE_WRITE_ATTRIBUTES)
+        .open(filepath)
+}
+
+#[cfg(not(windows))]
+fn open_for_attributes(filepath: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::File::open(filepath)
+}
+
+fn is_drive_relative(path: &str) -> bool {
+    match path.as_bytes() {
+        [drive, b':'] => drive.is_ascii_alphabetic(),
+        [drive, b':', next, ..] => drive.is_ascii_alphabetic() && *next != b'\\' && *next != b'/',
+        _ => false,
+    }
+}
+
+/// Files kept in memory, mainly for tests.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+    read_only: Mutex<HashSet<PathBuf>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, filepath: impl Into<PathBuf>, data: impl Into<Vec<u8>>) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(filepath.into(), data.into());
+    }
+
+    pub fn get(&self, filepath: &Path) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(filepath).cloned()
+    }
+
+    /// Mark the file as read-only so that plain writes fail.
+    pub fn set_read_only(&self, filepath: impl Into<PathBuf>) {
+        self.read_only.lock().unwrap().insert(filepath.into());
+    }
+}
+
+impl FileSystem for MemoryFs {
+    async fn read_header(&self, filepath: &Path, max_lines: usize) -> Result<Vec<String>, CError> {
+        let data = self.read(filepath).await?;
+        Ok(read_header_lines(data.as_slice(), max_lines)?)
+    }
+
+    async fn read(&self, filepath: &Path) -> Result<Vec<u8>, CError> {
+        self.get(filepath)
+            .ok_or_else(|| CError::ReadError(filepath.display().to_string()))
+    }
+
+    async fn write(&self, filepath: &Path, data: &[u8]) -> Result<(), CError> {
+        if self.is_read_only(filepath) {
+            return Err(CError::WriteError(filepath.display().to_string()));
+        }
+        self.insert(filepath, data);
+        Ok(())
+    }
+
+    fn is_read_only(&self, filepath: &Path) -> bool {
+        self.read_only.lock().unwrap().contains(filepath)
+    }
+
+    async fn write_read_only(&self, filepath: &Path, data: &[u8]) -> Result<(), CError> {
+        self.insert(filepath, data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{
+        find_repos, is_drive_relative, is_lock_error, read_header_lines, timestamp_years,
+        verbatim_path, walk_files, FileSystem, LocalFs,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn test_walk_files() {
+        let root = std::env::temp_dir().join("git_copyright_test_walk_files");
+        std::fs::create_dir_all(root.join("src/bin")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        for filepath in ["main.rs", "src/lib.rs", "src/bin/tool.rs", ".git/HEAD"] {
+            std::fs::write(root.join(filepath), "").unwrap();
+        }
+
+        assert_eq!(
+            walk_files(&root).unwrap(),
+            vec!["main.rs", "src/bin/tool.rs", "src/lib.rs"]
+        );
+        let year = chrono::Utc::now().format("%Y").to_string();
+        assert_eq!(timestamp_years(&root.join("main.rs")), Some(year));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_replaces_file() {
+        let root = std::env::temp_dir().join("git_copyright_test_write");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let filepath = root.join("main.rs");
+        std::fs::write(&filepath, "fn main() {}\n").unwrap();
+
+        LocalFs.write(&filepath, b"// Copyright\n").await.unwrap();
+        assert_eq!(std::fs::read(&filepath).unwrap(), b"// Copyright\n");
+        LocalFs
+            .write(&root.join("new.rs"), b"// New\n")
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(root.join("new.rs")).unwrap(), b"// New\n");
+
+        // Permissions and the target of a symlink are kept
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let script = root.join("run.sh");
+            std::fs::write(&script, "echo\n").unwrap();
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o750)).unwrap();
+            LocalFs
+                .write(&script, b"# Copyright\necho\n")
+                .await
+                .unwrap();
+            let mode = std::fs::metadata(&script).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o750);
+
+            let link = root.join("link.rs");
+            std::os::unix::fs::symlink(&filepath, &link).unwrap();
+            LocalFs.write(&link, b"// Linked\n").await.unwrap();
+            assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+            assert_eq!(std::fs::read(&filepath).unwrap(), b"// Linked\n");
+        }
+
+        // No temporary files are left behind
+        assert!(!std::fs::read_dir(&root)
+            .unwrap()
+            .any(|entry| entry.unwrap().path().extension() == Some("tmp".as_ref())));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_find_repos() {
+        let root = std::env::temp_dir().join("git_copyright_test_find_repos");
+        for dir in [
+            "app/.git",
+            "libs/core/.git",
+            "libs/core/vendored/.git",
+            "docs",
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::create_dir_all(root.join("app/ext")).unwrap();
+        std::fs::write(root.join("app/ext/.git"), "gitdir: ../.git/modules/ext\n").unwrap();
+
+        assert_eq!(
+            find_repos(&root).unwrap(),
+            vec![
+                root.join("app"),
+                root.join("libs/core"),
+                root.join("libs/core/vendored")
+            ]
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_verbatim_path() {
+        assert_eq!(
+            verbatim_path(r"C:\repo\src/main.rs"),
+            Some(r"\\?\C:\repo\src\main.rs".to_owned())
+        );
+        assert_eq!(
+            verbatim_path(r"\\server\share\repo\main.rs"),
+            Some(r"\\?\UNC\server\share\repo\main.rs".to_owned())
+        );
+        assert_eq!(verbatim_path(r"\\?\C:\repo\main.rs"), None);
+        assert_eq!(verbatim_path(r"repo\main.rs"), None);
+        assert_eq!(verbatim_path("/home/user/repo/main.rs"), None);
+    }
+
+    #[test]
+    fn test_is_lock_error() {
+        let sharing_violation = std::io::Error::from_raw_os_error(32);
+        assert_eq!(is_lock_error(&sharing_violation), cfg!(windows));
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(!is_lock_error(&not_found));
+    }
+
+    #[test]
+    fn test_read_header_lines() {
+        let lines = read_header_lines(Cursor::new("a\r\nb\nc\nd"), 3).unwrap();
+        assert_eq!(lines, vec!["a", "b", "c"]);
+
+        let lines = read_header_lines(Cursor::new("a\nb"), 3).unwrap();
+        assert_eq!(lines, vec!["a", "b"]);
+
+        let lines = read_header_lines(Cursor::new("a\rb\rc\rd"), 3).unwrap();
+        assert_eq!(lines, vec!["a", "b", "c"]);
+
+        // Lines spanning several chunks are read completely
+        let long_line = "x".repeat(10_000);
+        let content = format!("{}\nb\n", long_line);
+        let lines = read_header_lines(Cursor::new(content), 3).unwrap();
+        assert_eq!(lines, vec![long_line.as_str(), "b"]);
+
+        let lines = read_header_lines(Cursor::new(b"a\n\xff\nc\n".to_vec()), 3).unwrap();
+        assert_eq!(lines, vec!["a"]);
+
+        let lines = read_header_lines(Cursor::new(b"\xef\xbb\xbfa\nb\n".to_vec()), 3).unwrap();
+        assert_eq!(lines, vec!["a", "b"]);
+        let utf16 = b"\xfe\xff\x00a\x00\n\x00b\x00\n".to_vec();
+        let lines = read_header_lines(Cursor::new(utf16), 3).unwrap();
+        assert_eq!(lines, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_is_drive_relative() {
+        assert!(is_drive_relative("C:"));
+        assert!(is_drive_relative("C:repo"));
+        assert!(!is_drive_relative(r"C:\repo"));
+        assert!(!is_drive_relative("C:/repo"));
+        assert!(!is_drive_relative("./repo"));
+    }
+
+    #[test]
+    fn test_deep_path_unchanged_on_unix() {
+        let deep_path = (0..40)
+            .map(|i| format!("dir{}", i))
+            .collect::<Vec<_>>()
+            .join("/");
+        let deep_path = std::path::Path::new(&deep_path);
+        if !cfg!(windows) {
+            assert_eq!(super::long_path(deep_path), deep_path);
+        }
+    }
+}
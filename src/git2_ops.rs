@@ -0,0 +1,519 @@
+//! Read files and history with libgit2 instead of the git binary.
+//!
+//! Used with `git_backend: libgit2` in the config, e.g. in minimal containers
+//! without git or to save the overhead of git processes. The files on a ref,
+//! the years of files, history cutoffs, rewritten history, last modification
+//! dates, files changed since a base ref and changed files are covered, other
+//! features like `--incremental` still run git.
+
+use crate::config::{DateSource, MergeCommits, Renames};
+use crate::git_ops::CommitChanges;
+use crate::git_ops::{dates_by_file, format_commit_time, parse_subtree_imports, rewrite_reasons};
+use crate::git_ops::{ChangedFiles, HistoryCutoffs, IgnoredRevs, LogOptions, IGNORE_REVS_FILE};
+use crate::CError;
+use git2::{Delta, DiffFindOptions, DiffOptions, ObjectType, Patch, Repository, Sort};
+use git2::{TreeWalkMode, TreeWalkResult};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+impl From<git2::Error> for CError {
+    fn from(e: git2::Error) -> Self {
+        CError::GitCmdError(e.message().to_owned())
+    }
+}
+
+pub fn is_git_repo(repo_path: &str) -> bool {
+    Repository::open(repo_path).is_ok()
+}
+
+/// Files on the ref, like `git ls-tree -r --name-only`.
+pub fn get_files_on_ref(repo_path: &str, ref_name: &str) -> Result<Vec<String>, CError> {
+    let repo = Repository::open(repo_path)?;
+    let tree = repo.revparse_single(ref_name)?.peel_to_tree()?;
+    let mut files = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if let (Some(ObjectType::Blob), Some(name)) = (entry.kind(), entry.name()) {
+            files.push(format!("{}{}", dir, name));
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(files)
+}
+
+/// Commit dates of all files on the ref of the log options, newest first, like
+/// `git_ops::get_commit_dates_by_file`.
+pub fn get_commit_dates_by_file(
+    repo_path: &str,
+    log_options: &LogOptions,
+    ignored_revs: &IgnoredRevs,
+) -> Result<HashMap<String, Vec<String>>, CError> {
+    let repo = Repository::open(repo_path)?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    revwalk.push(
+        repo.revparse_single(&log_options.history_ref)?
+            .peel_to_commit()?
+            .id(),
+    )?;
+
+    let follow = log_options.renames == Renames::Follow;
+    let mut find_options = DiffFindOptions::new();
+    find_options
+        .renames(follow)
+        .copies(follow && log_options.detect_copies)
+        .copies_from_unmodified(follow && log_options.detect_copies);
+    if let Some(similarity) = log_options.rename_similarity {
+        find_options
+            .rename_threshold(similarity.into())
+            .copy_threshold(similarity.into());
+    }
+
+    let mut diff_options = DiffOptions::new();
+    diff_options
+        .ignore_whitespace(log_options.ignore_whitespace)
+        .ignore_blank_lines(log_options.ignore_whitespace)
+        // Sources of copies may be unmodified in the commit
+        .include_unmodified(follow && log_options.detect_copies);
+
+    let author_regex = log_options.author_regex()?;
+    let mailmap = match log_options.use_mailmap {
+        true => Some(repo.mailmap()?),
+        false => None,
+    };
+
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() > 1 && log_options.merge_commits == MergeCommits::Skip {
+            continue;
+        }
+        if let Some(author_regex) = &author_regex {
+            let author = match &mailmap {
+                Some(mailmap) => commit.author_with_mailmap(mailmap)?,
+                None => commit.author(),
+            };
+            let author = format!(
+                "{} <{}>",
+                String::from_utf8_lossy(author.name_bytes()),
+                String::from_utf8_lossy(author.email_bytes())
+            );
+            if !author_regex.is_match(&author) {
+                continue;
+            }
+        }
+        let tree = commit.tree()?;
+        let mut changes = CommitChanges {
+            date: commit_date(&commit, log_options.date_source),
+            hash: commit.id().to_string(),
+            changes: Vec::new(),
+        };
+        // Merges are compared with each parent like with `git log -m`
+        let parent_trees = match commit.parent_count() {
+            0 => vec![None],
+            _ => commit
+                .parents()
+                .map(|parent| parent.tree().map(Some))
+                .collect::<Result<_, _>>()?,
+        };
+        for parent_tree in parent_trees {
+            let mut diff =
+                repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut diff_options))?;
+            // Without any flags, libgit2 detects renames by the git config
+            if follow {
+                diff.find_similar(Some(&mut find_options))?;
+            }
+            for (idx, delta) in diff.deltas().enumerate() {
+                let path = |file: git2::DiffFile| {
+                    file.path()
+                        .map(|path| path.to_string_lossy().replace('\\', "/"))
+                };
+                let (status, old_path, new_path) = match delta.status() {
+                    Delta::Added => ('A', path(delta.new_file()), None),
+                    Delta::Deleted => ('D', path(delta.old_file()), None),
+                    Delta::Modified => ('M', path(delta.new_file()), None),
+                    Delta::Typechange => ('T', path(delta.new_file()), None),
+                    Delta::Renamed => ('R', path(delta.old_file()), path(delta.new_file())),
+                    Delta::Copied => ('C', path(delta.old_file()), path(delta.new_file())),
+                    _ => continue,
+                };
+                // Modifications of only whitespace have no hunks, like with `-w`
+                if status == 'M' && log_options.ignore_whitespace {
+                    let patch = Patch::from_diff(&diff, idx)?;
+                    if patch.is_some_and(|patch| patch.num_hunks() == 0) {
+                        continue;
+                    }
+                }
+                if let Some(old_path) = old_path {
+                    changes.changes.push((status, old_path, new_path));
+                }
+            }
+        }
+        commits.push(changes);
+    }
+    Ok(dates_by_file(commits, ignored_revs))
+}
+
+/// Commits which do not count for the years of files, like
+/// `IgnoredRevs::load`.
+pub fn load_ignored_revs(repo_path: &str) -> Result<IgnoredRevs, CError> {
+    let ignore_revs_file = std::path::Path::new(repo_path).join(IGNORE_REVS_FILE);
+    if ignore_revs_file.is_file() {
+        return IgnoredRevs::read(&ignore_revs_file);
+    }
+
+    let repo = Repository::open(repo_path)?;
+    match repo.config()?.get_path("blame.ignoreRevsFile") {
+        Ok(blame_file) => IgnoredRevs::read(&std::path::Path::new(repo_path).join(blame_file)),
+        Err(_) => Ok(IgnoredRevs::default()),
+    }
+}
+
+/// Cutoffs of subtree imports and the cutoff commits of the config, like
+/// `HistoryCutoffs::load`.
+pub fn load_history_cutoffs(
+    repo_path: &str,
+    cutoff_commits: &HashMap<String, String>,
+    log_options: &LogOptions,
+) -> Result<HistoryCutoffs, CError> {
+    let repo = Repository::open(repo_path)?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    revwalk.push(
+        repo.revparse_single(&log_options.history_ref)?
+            .peel_to_commit()?
+            .id(),
+    )?;
+
+    let mut log = String::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let message = commit.message().unwrap_or_default();
+        if message
+            .lines()
+            .any(|line_| line_.starts_with("git-subtree-dir:"))
+        {
+            log.push_str(&format!(
+                "{}\n{}\0",
+                commit_date(&commit, log_options.date_source),
+                message
+            ));
+        }
+    }
+
+    let mut cutoff_dates = Vec::new();
+    for (dir, rev) in cutoff_commits.iter() {
+        let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+        cutoff_dates.push((dir.clone(), commit_date(&commit, log_options.date_source)));
+    }
+    Ok(HistoryCutoffs::from_dates(
+        parse_subtree_imports(&log),
+        cutoff_dates,
+    ))
+}
+
+/// Reasons why the history may not reflect when files were added or
+/// modified, like `git_ops::detect_history_rewrites`.
+pub fn detect_history_rewrites(repo_path: &str) -> Result<Vec<String>, CError> {
+    let repo = Repository::open(repo_path)?;
+    let git_paths = ["shallow", "info/grafts", "filter-repo"].map(|name| repo.path().join(name));
+    let mut refs = Vec::new();
+    for glob in ["refs/replace/*", "refs/original/*"] {
+        for reference in repo.references_glob(glob)? {
+            if let Some(name) = reference?.name() {
+                refs.push(name.to_owned());
+            }
+        }
+    }
+    Ok(rewrite_reasons(&git_paths, &refs))
+}
+
+/// Files added or modified on `HEAD` since it diverged from `base_ref`, like
+/// `git_ops::get_changed_since_merge_base`.
+pub fn get_changed_since_merge_base(
+    repo_path: &str,
+    base_ref: &str,
+) -> Result<HashSet<String>, CError> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head()?.peel_to_commit()?;
+    let base = repo.revparse_single(base_ref)?.peel_to_commit()?;
+    let merge_base = repo.find_commit(repo.merge_base(base.id(), head.id())?)?;
+    let mut diff = repo.diff_tree_to_tree(Some(&merge_base.tree()?), Some(&head.tree()?), None)?;
+    diff.find_similar(None)?;
+    Ok(diff
+        .deltas()
+        .filter(|delta| {
+            matches!(
+                delta.status(),
+                Delta::Added | Delta::Copied | Delta::Modified | Delta::Renamed
+            )
+        })
+        .filter_map(|delta| delta.new_file().path())
+        .map(|path| path.to_string_lossy().replace('\\', "/"))
+        .collect())
+}
+
+/// Date of the last commit modifying the file on `HEAD` as `YYYY-MM-DD`, like
+/// `git_ops::get_last_modified_date`.
+pub fn get_last_modified_date(repo_path: &str, filepath: &str) -> Result<Option<String>, CError> {
+    let repo = Repository::open(repo_path)?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    revwalk.push_head()?;
+
+    let entry_id = |tree: git2::Tree| tree.get_path(Path::new(filepath)).ok().map(|e| e.id());
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let id = entry_id(commit.tree()?);
+        // Merges only modify the file if it differs from all parents
+        let mut modified = id.is_some();
+        for parent in commit.parents() {
+            modified &= entry_id(parent.tree()?) != id;
+        }
+        if modified {
+            let date = commit_date(&commit, DateSource::Committer);
+            return Ok(Some(date.chars().take(10).collect()));
+        }
+    }
+    Ok(None)
+}
+
+/// Tracked files changed in the working tree, like `git diff --name-only`.
+fn get_diffs(repo_path: &str) -> Result<Vec<String>, CError> {
+    let repo = Repository::open(repo_path)?;
+    let diff = repo.diff_index_to_workdir(None, None)?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+        .map(|path| path.to_string_lossy().replace('\\', "/"))
+        .collect())
+}
+
+/// Record tracked files which are already changed before the run.
+pub fn snapshot_changes(repo_path: &str) -> Result<ChangedFiles, CError> {
+    Ok(ChangedFiles::from_diffs(repo_path, get_diffs(repo_path)?))
+}
+
+/// List the tracked files changed by the run and optionally fail, like
+/// `git_ops::check_for_changes`.
+pub fn check_for_changes(
+    repo_path: &str,
+    before: &ChangedFiles,
+    fail_on_diff: bool,
+) -> Result<(), CError> {
+    crate::git_ops::report_changes(repo_path, get_diffs(repo_path)?, before, fail_on_diff)
+}
+
+/// Committer or author date of a commit like `%ci` or `%ai` of git, e.g.
+/// `2022-06-01 12:00:00 +0200`.
+fn commit_date(commit: &git2::Commit, date_source: DateSource) -> String {
+    let time = match date_source {
+        DateSource::Committer => commit.committer().when(),
+        DateSource::Author => commit.author().when(),
+    };
+    format_commit_time(time.seconds(), time.offset_minutes())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{get_commit_dates_by_file, get_files_on_ref};
+    use crate::config::{DateSource, MergeCommits};
+    use crate::git_ops::LogOptions;
+    use git2::{Repository, Signature, Time};
+
+    /// Commit the files in the working tree of the repository at `seconds`.
+    fn commit_all(repo: &Repository, seconds: i64, message: &str) {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature =
+            Signature::new("Dummy", "dummy@example.com", &Time::new(seconds, 60)).unwrap();
+        let parents = match repo.head() {
+            Ok(head) => vec![head.peel_to_commit().unwrap()],
+            Err(_) => Vec::new(),
+        };
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_renamed_file_history() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_git2");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        let repo = Repository::init(&repo_dir).unwrap();
+        let content = "fn main() {\n    println!(\"Hello\");\n}\n";
+        std::fs::write(repo_dir.join("a.rs"), content).unwrap();
+        // 2015-01-01 and 2017-01-01
+        commit_all(&repo, 1420070400, "Add a.rs");
+        std::fs::rename(repo_dir.join("a.rs"), repo_dir.join("b.rs")).unwrap();
+        commit_all(&repo, 1483228800, "Rename a.rs");
+
+        let repo_path = repo_dir.to_str().unwrap();
+        assert_eq!(get_files_on_ref(repo_path, "HEAD").unwrap(), ["b.rs"]);
+        let dates =
+            get_commit_dates_by_file(repo_path, &Default::default(), &Default::default()).unwrap();
+        assert_eq!(
+            dates["b.rs"],
+            ["2017-01-01 01:00:00 +0100", "2015-01-01 01:00:00 +0100"]
+        );
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_skipped_merges() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_git2_merges");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        let repo = Repository::init(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        // 2015-01-01
+        commit_all(&repo, 1420070400, "Add a.rs");
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        std::fs::write(repo_dir.join("b.rs"), "fn main() {}\n").unwrap();
+        // 2016-01-01
+        commit_all(&repo, 1451606400, "Add b.rs");
+        let second = repo.head().unwrap().peel_to_commit().unwrap();
+
+        // Merge adding a file of its own on 2017-01-01
+        std::fs::write(repo_dir.join("c.rs"), "fn main() {}\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("c.rs")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature =
+            Signature::new("Dummy", "dummy@example.com", &Time::new(1483228800, 60)).unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Merge",
+            &tree,
+            &[&second, &first],
+        )
+        .unwrap();
+
+        let repo_path = repo_dir.to_str().unwrap();
+        let dates = |merge_commits| {
+            let log_options = LogOptions {
+                merge_commits,
+                ..Default::default()
+            };
+            get_commit_dates_by_file(repo_path, &log_options, &Default::default()).unwrap()
+        };
+        assert_eq!(
+            dates(MergeCommits::Count)["c.rs"],
+            ["2017-01-01 01:00:00 +0100"]
+        );
+        assert!(!dates(MergeCommits::Skip).contains_key("c.rs"));
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_whitespace_changes() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_git2_whitespace");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        let repo = Repository::init(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {\n  run();\n}\n").unwrap();
+        // 2015-01-01 and 2017-01-01
+        commit_all(&repo, 1420070400, "Add a.rs");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {\n    run();\n}\n\n").unwrap();
+        commit_all(&repo, 1483228800, "Reformat");
+
+        let repo_path = repo_dir.to_str().unwrap();
+        let log_options = LogOptions {
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        let dates = get_commit_dates_by_file(repo_path, &log_options, &Default::default());
+        assert_eq!(dates.unwrap()["a.rs"], ["2015-01-01 01:00:00 +0100"]);
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_author_filter() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_git2_author_filter");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        let repo = Repository::init(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        // 2015-01-01
+        commit_all(&repo, 1420070400, "Add a.rs");
+
+        // Contribution of someone else on 2017-01-01
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("a.rs")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature =
+            Signature::new("Other", "other@example.org", &Time::new(1483228800, 60)).unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Run",
+            &tree,
+            &[&parent],
+        )
+        .unwrap();
+
+        let repo_path = repo_dir.to_str().unwrap();
+        let log_options = LogOptions {
+            author_filter: Some("@example\\.com>$".to_owned()),
+            ..Default::default()
+        };
+        let dates = get_commit_dates_by_file(repo_path, &log_options, &Default::default());
+        assert_eq!(dates.unwrap()["a.rs"], ["2015-01-01 01:00:00 +0100"]);
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_author_dates() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_git2_author");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        let repo = Repository::init(&repo_dir).unwrap();
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("a.rs")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        // Authored 2015-01-01, rebased 2017-01-01
+        let author = Signature::new("Dummy", "dummy@example.com", &Time::new(1420070400, 0));
+        let committer = Signature::new("Dummy", "dummy@example.com", &Time::new(1483228800, 0));
+        repo.commit(
+            Some("HEAD"),
+            &author.unwrap(),
+            &committer.unwrap(),
+            "Add a.rs",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        let repo_path = repo_dir.to_str().unwrap();
+        let dates = |date_source| {
+            let log_options = LogOptions {
+                date_source,
+                ..Default::default()
+            };
+            get_commit_dates_by_file(repo_path, &log_options, &Default::default()).unwrap()
+        };
+        assert_eq!(
+            dates(DateSource::Author)["a.rs"],
+            ["2015-01-01 00:00:00 +0000"]
+        );
+        assert_eq!(
+            dates(DateSource::Committer)["a.rs"],
+            ["2017-01-01 00:00:00 +0000"]
+        );
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+}
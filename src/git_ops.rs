@@ -1,17 +1,195 @@
 //! Extract added/modified times from git history.
 //!
 
-use crate::CError;
-use chrono::Utc;
+use crate::config::{DateSource, GitBackend, MergeCommits, Renames, YearFormat, YearTimezone};
+use crate::fs::timestamp_years;
+#[cfg(feature = "git2")]
+use crate::git2_ops;
+use crate::{get_hash, CError, Config};
+use chrono::{DateTime, Datelike, FixedOffset, Utc};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 
+/// Create a git command running in `cwd`.
+///
+/// On Windows, git is told to support paths longer than `MAX_PATH`.
+fn git_command(cwd: &str) -> Command {
+    let mut command = Command::new("git");
+    if cfg!(windows) {
+        command.arg("-c").arg("core.longpaths=true");
+    }
+    command.current_dir(cwd);
+    command
+}
+
+/// How `git log` reads the history for the years of files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogOptions {
+    /// Date of commits the years are taken from.
+    pub date_source: DateSource,
+    /// Map authors and committers with the `.mailmap` of the repository so
+    /// that renamed or merged identities are treated as one author.
+    pub use_mailmap: bool,
+    /// Whether merge commits count as modifications.
+    pub merge_commits: MergeCommits,
+    /// Whether moved files keep the history of their previous paths.
+    pub renames: Renames,
+    /// Minimum similarity in percent of moved or copied files, see `-M`.
+    pub rename_similarity: Option<u8>,
+    /// Detect copied files when listing the changes of all files, see `-C`.
+    pub detect_copies: bool,
+    /// Ref whose history is read, e.g. a release branch.
+    pub history_ref: String,
+    /// Timezone in which the years of commit dates are counted.
+    pub timezone: YearTimezone,
+    /// Skip commits which only change whitespace or blank lines of a file.
+    pub ignore_whitespace: bool,
+    /// Regex of the authors whose commits are read, see `--author`.
+    pub author_filter: Option<String>,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        LogOptions {
+            date_source: DateSource::Committer,
+            use_mailmap: true,
+            merge_commits: MergeCommits::Count,
+            renames: Renames::Follow,
+            rename_similarity: None,
+            detect_copies: false,
+            history_ref: "HEAD".to_owned(),
+            timezone: YearTimezone::Commit,
+            ignore_whitespace: false,
+            author_filter: None,
+        }
+    }
+}
+
+impl LogOptions {
+    pub fn from_config(config: &Config) -> Self {
+        LogOptions {
+            date_source: config.date_source(),
+            use_mailmap: !config.ignore_mailmap(),
+            merge_commits: config.merge_commits(),
+            renames: config.renames(),
+            rename_similarity: config.rename_similarity(),
+            detect_copies: config.detect_copies(),
+            history_ref: config.history_ref().to_owned(),
+            timezone: config.year_timezone(),
+            ignore_whitespace: config.ignore_whitespace_changes(),
+            author_filter: config.author_filter().map(str::to_owned),
+        }
+    }
+
+    /// Argument of `git log` for merge commits when listing the changes of
+    /// files.
+    fn merge_arg(&self) -> &'static str {
+        match self.merge_commits {
+            MergeCommits::Count => "-m",
+            MergeCommits::Skip => "--no-merges",
+        }
+    }
+
+    /// Arguments of `git log` for moved and copied files when listing the
+    /// changes of files, with `follow` for the history of a single file.
+    ///
+    /// `--follow` also follows copies, so `detect_copies` only matters for
+    /// the changes of all files.
+    fn rename_args(&self, follow: bool) -> Vec<String> {
+        let similarity = self
+            .rename_similarity
+            .map(|similarity| format!("{}%", similarity))
+            .unwrap_or_default();
+        if self.renames == Renames::Ignore {
+            return vec!["--no-renames".to_owned()];
+        }
+        let mut args = vec![format!("-M{}", similarity)];
+        if follow {
+            args.push("--follow".to_owned());
+        }
+        // Like `--follow`, copies of files not changed in the commit count
+        if self.detect_copies {
+            args.push(format!("-C{}", similarity));
+            args.push("--find-copies-harder".to_owned());
+        }
+        args
+    }
+
+    /// Argument of `git log` reading only the commits of the filtered authors.
+    fn author_arg(&self) -> Option<String> {
+        self.author_filter
+            .as_ref()
+            .map(|author_filter| format!("--author={}", author_filter))
+    }
+
+    /// Regex of the author filter for histories not read with `git log`,
+    /// matched against `Name <email>` of authors.
+    ///
+    /// It uses the regex syntax of this crate instead of the one of git, which
+    /// agree for common patterns like domains.
+    pub(crate) fn author_regex(&self) -> Result<Option<Regex>, CError> {
+        self.author_filter
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| CError::ConfigError(format!("Invalid author filter: {}", e)))
+    }
+
+    /// Arguments of `git log` listing the lines changed by each commit apart
+    /// from whitespace, see `substantial_commits`.
+    fn whitespace_args(&self) -> &'static [&'static str] {
+        match self.ignore_whitespace {
+            true => &["--numstat", "-w", "--ignore-blank-lines"],
+            false => &[],
+        }
+    }
+}
+
+/// Commits in a log of a file whose pretty format starts with NUL, without
+/// the NUL.
+///
+/// With `numstat`, commits without a `--numstat` line, which only changed
+/// whitespace with `-w`, are skipped unless no other commits are left.
+fn substantial_commits(log: &str, numstat: bool) -> Vec<&str> {
+    let mut commits: Vec<(&str, bool)> = Vec::new();
+    for line_ in log.lines() {
+        match (line_.strip_prefix('\0'), commits.last_mut()) {
+            (Some(commit), _) => commits.push((commit, !numstat)),
+            (None, Some((_, changed))) if !line_.is_empty() => *changed = true,
+            _ => {}
+        }
+    }
+    let changed: Vec<&str> = commits
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(commit, _)| *commit)
+        .collect();
+    match changed.is_empty() {
+        true => commits.into_iter().map(|(commit, _)| commit).collect(),
+        false => changed,
+    }
+}
+
+/// Create a `git log` command running in `cwd` with the log options.
+fn git_log(cwd: &str, log_options: &LogOptions) -> Command {
+    let mut command = git_command(cwd);
+    command.arg("log").arg(match log_options.use_mailmap {
+        true => "--use-mailmap",
+        false => "--no-use-mailmap",
+    });
+    command
+}
+
 pub async fn get_files_on_ref(repo_path: &str, ref_name: &str) -> Result<Vec<String>, CError> {
-    let output = Command::new("git")
+    let output = git_command(repo_path)
         .arg("ls-tree")
         .arg("-r")
         .arg(ref_name)
         .arg("--name-only")
-        .current_dir(repo_path)
         .output();
 
     let output = output.await?;
@@ -21,59 +199,1074 @@ pub async fn get_files_on_ref(repo_path: &str, ref_name: &str) -> Result<Vec<Str
         ));
     }
 
-    Ok(parse_cmd_output(&output)?)
+    parse_cmd_output(&output)
 }
 
-pub async fn get_added_mod_times_for_file(filepath: &str, cwd: &str) -> String {
-    let output = Command::new("git")
-        .arg("log")
-        .arg("--follow")
-        .arg("-m")
-        .arg("--pretty=%ci")
+/// Years from adding to last modifying the file.
+///
+/// Commits before `cutoff`, a commit date like `2022-06-01 12:00:00 +0200`,
+/// are not taken into account, e.g. the foreign history of a subtree. Files
+/// without commits get the current year or, with `mtime_fallback`, the years
+/// of their timestamps. The years are written in `year_format` and taken from
+/// the dates of the log options, skipping `ignored_revs`.
+pub async fn get_added_mod_times_for_file(
+    filepath: &str,
+    cwd: &str,
+    cutoff: Option<&str>,
+    mtime_fallback: bool,
+    year_format: YearFormat,
+    log_options: &LogOptions,
+    ignored_revs: &IgnoredRevs,
+) -> String {
+    let output = git_log(cwd, log_options)
+        .args(log_options.rename_args(true))
+        .arg(log_options.merge_arg())
+        .args(log_options.author_arg())
+        .args(log_options.whitespace_args())
+        .arg(format!(
+            "--pretty=%x00%H {}",
+            log_options.date_source.placeholder()
+        ))
+        .arg(&log_options.history_ref)
+        .arg("--")
         .arg(filepath)
-        .current_dir(cwd)
         .output();
     let output = output.await.unwrap().stdout;
-    let commit_years: Vec<String> = std::str::from_utf8(&output)
-        .unwrap()
-        .split('\n')
-        .filter_map(|s| {
-            // Take only first four chars (the year) from strings that are longer than zero
-            let s = s.to_owned();
-            match s.len() {
-                0 => None,
-                _ => Some(s.chars().take(4).collect()),
+    let log = std::str::from_utf8(&output).unwrap();
+    let commits: Vec<(&str, &str)> = substantial_commits(log, log_options.ignore_whitespace)
+        .into_iter()
+        .filter_map(|commit| commit.split_once(' '))
+        .collect();
+    years_from_commit_dates(
+        ignored_revs.filter(commits).into_iter(),
+        filepath,
+        cwd,
+        cutoff,
+        mtime_fallback,
+        year_format,
+        log_options.timezone,
+    )
+}
+
+/// Years of the commits of the lines which still exist in the file, like
+/// `get_added_mod_times_for_file` but from `git blame --line-porcelain`.
+///
+/// Commits whose changes were all replaced later do not count. Lines of
+/// `ignored_revs` are skipped unless all lines come from ignored commits, and
+/// uncommitted lines do not count. With `author_regex`, the compiled author
+/// filter of the log options, only the lines of matching authors count.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_blame_years_for_file(
+    filepath: &str,
+    cwd: &str,
+    cutoff: Option<&str>,
+    mtime_fallback: bool,
+    year_format: YearFormat,
+    log_options: &LogOptions,
+    author_regex: Option<&Regex>,
+    ignored_revs: &IgnoredRevs,
+) -> Result<String, CError> {
+    let output = git_command(cwd)
+        .arg("blame")
+        .arg("--line-porcelain")
+        .args(log_options.ignore_whitespace.then_some("-w"))
+        .arg(&log_options.history_ref)
+        .arg("--")
+        .arg(filepath)
+        .output();
+    let output = output.await?;
+    // Files without history, e.g. not yet committed, cannot be blamed
+    if !output.status.success() {
+        log::debug!(
+            "Could not blame {}: {}",
+            filepath,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // The porcelain repeats the lines of the file, which may not be UTF-8
+    let commits = parse_blame_dates(
+        &String::from_utf8_lossy(&output.stdout),
+        log_options.date_source,
+        author_regex,
+    );
+    let commits: Vec<(&str, &str)> = commits
+        .iter()
+        .map(|(hash, date)| (hash.as_str(), date.as_str()))
+        .collect();
+    Ok(years_from_commit_dates(
+        ignored_revs.filter(commits).into_iter(),
+        filepath,
+        cwd,
+        cutoff,
+        mtime_fallback,
+        year_format,
+        log_options.timezone,
+    ))
+}
+
+/// Hashes and dates of the commits in the output of
+/// `git blame --line-porcelain`, newest first. With an author regex, only
+/// commits whose author matches it as `Name <email>` are kept.
+fn parse_blame_dates(
+    porcelain: &str,
+    date_source: DateSource,
+    author_regex: Option<&Regex>,
+) -> Vec<(String, String)> {
+    let (time_key, tz_key) = match date_source {
+        DateSource::Committer => ("committer-time ", "committer-tz "),
+        DateSource::Author => ("author-time ", "author-tz "),
+    };
+    let mut commits: Vec<(String, i64, i32)> = Vec::new();
+    let mut hash: Option<&str> = None;
+    let mut time = None;
+    let mut tz = None;
+    let mut author = "";
+    let mut author_mail = "";
+    for line_ in porcelain.lines() {
+        // The content of the line ends the entry
+        if line_.starts_with('\t') {
+            let author = std::mem::take(&mut author);
+            let author_mail = std::mem::take(&mut author_mail);
+            if let (Some(hash), Some(time), Some(tz)) = (hash.take(), time.take(), tz.take()) {
+                let uncommitted = hash.bytes().all(|byte| byte == b'0');
+                let filtered = author_regex
+                    .is_some_and(|regex| !regex.is_match(&format!("{} {}", author, author_mail)));
+                if !uncommitted && !filtered && !commits.iter().any(|(known, _, _)| known == hash) {
+                    commits.push((hash.to_owned(), time, tz));
+                }
             }
+        } else if hash.is_none() {
+            hash = line_.split(' ').next();
+        } else if let Some(name) = line_.strip_prefix("author ") {
+            author = name;
+        } else if let Some(mail) = line_.strip_prefix("author-mail ") {
+            author_mail = mail;
+        } else if let Some(seconds) = line_.strip_prefix(time_key) {
+            time = seconds.parse::<i64>().ok();
+        } else if let Some(offset) = line_.strip_prefix(tz_key) {
+            tz = Some(offset_minutes(offset));
+        }
+    }
+
+    commits.sort_by_key(|(_, time, _)| std::cmp::Reverse(*time));
+    commits
+        .into_iter()
+        .map(|(hash, time, tz)| (hash, format_commit_time(time, tz)))
+        .collect()
+}
+
+/// Minutes of a timezone offset like `+0130`.
+fn offset_minutes(offset: &str) -> i32 {
+    let (sign, digits) = match offset.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, offset.trim_start_matches('+')),
+    };
+    let hhmm: i32 = digits.parse().unwrap_or_default();
+    sign * (hhmm / 100 * 60 + hhmm % 100)
+}
+
+/// Commit time in seconds since the epoch with its timezone offset, formatted
+/// like `%ci` of git, e.g. `2022-06-01 12:00:00 +0200`.
+pub(crate) fn format_commit_time(seconds: i64, offset_minutes: i32) -> String {
+    let offset = FixedOffset::east_opt(offset_minutes * 60)
+        .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    DateTime::from_timestamp(seconds, 0)
+        .map(|date| {
+            date.with_timezone(&offset)
+                .format("%Y-%m-%d %H:%M:%S %z")
+                .to_string()
         })
+        .unwrap_or_default()
+}
+
+/// Commit dates of the file by author, newest first, following renames like
+/// `get_added_mod_times_for_file`.
+///
+/// Authors are named as in the history, mapped with the `.mailmap` unless
+/// disabled in the log options, and ordered by their first commit.
+pub async fn get_author_dates_for_file(
+    filepath: &str,
+    cwd: &str,
+    log_options: &LogOptions,
+    ignored_revs: &IgnoredRevs,
+) -> Result<Vec<(String, Vec<String>)>, CError> {
+    let output = git_log(cwd, log_options)
+        .args(log_options.rename_args(true))
+        .arg(log_options.merge_arg())
+        .args(log_options.author_arg())
+        .args(log_options.whitespace_args())
+        .arg(format!(
+            "--pretty=%x00%H%x09{}%x09{}",
+            log_options.date_source.placeholder(),
+            // Not all gits map `%an` with `--use-mailmap`
+            match log_options.use_mailmap {
+                true => "%aN",
+                false => "%an",
+            }
+        ))
+        .arg(&log_options.history_ref)
+        .arg("--")
+        .arg(filepath)
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+    let log = std::str::from_utf8(&output.stdout)?;
+    Ok(parse_author_log(
+        &substantial_commits(log, log_options.ignore_whitespace),
+        ignored_revs,
+    ))
+}
+
+/// Group commits of hashes, dates and authors separated by tabs, newest
+/// first, by author in the order of their first commit. Ignored commits are
+/// skipped.
+fn parse_author_log(commits: &[&str], ignored_revs: &IgnoredRevs) -> Vec<(String, Vec<String>)> {
+    let mut authors: Vec<(String, Vec<String>)> = Vec::new();
+    let mut last_hash = None;
+    for line_ in commits.iter().rev() {
+        let mut fields = line_.splitn(3, '\t');
+        let (Some(hash), Some(date), Some(author)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        // Merges are listed once per parent
+        if last_hash == Some(hash) || ignored_revs.contains(hash) {
+            continue;
+        }
+        last_hash = Some(hash);
+        match authors.iter_mut().find(|(name, _)| name == author) {
+            Some((_, dates)) => dates.insert(0, date.to_owned()),
+            None => authors.push((author.to_owned(), vec![date.to_owned()])),
+        }
+    }
+    authors
+}
+
+/// Years of a file from its commit dates, newest first, see
+/// `get_added_mod_times_for_file`.
+pub(crate) fn years_from_commit_dates<'a>(
+    commit_dates: impl Iterator<Item = &'a str>,
+    filepath: &str,
+    cwd: &str,
+    cutoff: Option<&str>,
+    mtime_fallback: bool,
+    year_format: YearFormat,
+    timezone: YearTimezone,
+) -> String {
+    let commit_years: Vec<String> = commit_dates
+        .filter(|s| cutoff.is_none_or(|cutoff| modified_since(s, cutoff)))
+        .filter_map(|s| commit_year(s, timezone))
         .collect();
 
+    if commit_years.is_empty() && mtime_fallback {
+        if let Some(years) = timestamp_years(&Path::new(cwd).join(filepath)) {
+            log::debug!("File {} has no history, using its timestamps", filepath);
+            return format_years(&parse_years(&years), year_format);
+        }
+    }
+    years_from_commit_years(&commit_years, &format!("File {}", filepath), year_format)
+}
+
+/// Commit dates of all files on the ref of the log options, newest first,
+/// from a single traversal of its history.
+///
+/// Like `git log --follow -m` per file, but a single git process lists the
+/// files changed by each commit, which is much faster for many files.
+pub async fn get_commit_dates_by_file(
+    repo_path: &str,
+    log_options: &LogOptions,
+    ignored_revs: &IgnoredRevs,
+) -> Result<HashMap<String, Vec<String>>, CError> {
+    let output = git_log(repo_path, log_options)
+        .arg(log_options.merge_arg())
+        .args(log_options.author_arg())
+        .args(log_options.rename_args(false))
+        .arg("--name-status")
+        .arg(format!(
+            "--pretty=format:%x00{}%n%H",
+            log_options.date_source.placeholder()
+        ))
+        .arg(&log_options.history_ref)
+        .arg("--")
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    let mut commits = parse_name_status_log(std::str::from_utf8(&output.stdout)?);
+    if log_options.ignore_whitespace {
+        let changed = get_changed_lines_by_commit(repo_path, log_options).await?;
+        for commit in commits.iter_mut() {
+            let changed = changed.get(&commit.hash);
+            commit.changes.retain(|(status, path, _)| {
+                *status != 'M' || changed.is_some_and(|changed| changed.contains(path))
+            });
+        }
+    }
+    Ok(dates_by_file(commits, ignored_revs))
+}
+
+/// Files of each commit, by hash, with changes apart from whitespace.
+async fn get_changed_lines_by_commit(
+    repo_path: &str,
+    log_options: &LogOptions,
+) -> Result<HashMap<String, HashSet<String>>, CError> {
+    let output = git_log(repo_path, log_options)
+        .arg(log_options.merge_arg())
+        .args(log_options.author_arg())
+        .args(log_options.rename_args(false))
+        .args(log_options.whitespace_args())
+        .arg("--pretty=format:%x00%H")
+        .arg(&log_options.history_ref)
+        .arg("--")
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+    Ok(parse_numstat_log(std::str::from_utf8(&output.stdout)?))
+}
+
+/// Paths in the `--numstat` of each commit of a log of hashes preceded by
+/// NUL.
+fn parse_numstat_log(log: &str) -> HashMap<String, HashSet<String>> {
+    let mut changed: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut hash = None;
+    for line_ in log.lines() {
+        if let Some(commit) = line_.strip_prefix('\0') {
+            hash = Some(commit);
+        } else if let (Some(hash), Some(path)) = (hash, line_.splitn(3, '\t').nth(2)) {
+            changed
+                .entry(hash.to_owned())
+                .or_default()
+                .insert(path.to_owned());
+        }
+    }
+    changed
+}
+
+/// Files changed by a commit with its date, like an entry of
+/// `git log --name-status`.
+#[derive(Debug, Default)]
+pub(crate) struct CommitChanges {
+    pub date: String,
+    /// Full hash of the commit, if known.
+    pub hash: String,
+    /// Status letter like `M` or `R`, path and, for renames and copies, new
+    /// path of each changed file.
+    pub changes: Vec<(char, String, Option<String>)>,
+}
+
+/// Parse a log of commit dates, each preceded by NUL and optionally followed
+/// by the hash on the next line, and the `--name-status` of the commits.
+fn parse_name_status_log(log: &str) -> Vec<CommitChanges> {
+    let mut commits = Vec::new();
+    for commit in log.split('\0') {
+        let mut lines = commit.lines().peekable();
+        let date = match lines.next() {
+            Some(date) if !date.is_empty() => date,
+            _ => continue,
+        };
+        let hash = lines
+            .next_if(|line_| !line_.contains('\t'))
+            .unwrap_or_default();
+        let changes = lines
+            .filter_map(|line_| {
+                let mut fields = line_.split('\t');
+                match (fields.next()?.chars().next(), fields.next()) {
+                    (Some(status), Some(path)) => {
+                        Some((status, path.to_owned(), fields.next().map(str::to_owned)))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        commits.push(CommitChanges {
+            date: date.to_owned(),
+            hash: hash.to_owned(),
+            changes,
+        });
+    }
+    commits
+}
+
+/// Map the paths of files to their commit dates from the changes of commits,
+/// newest first.
+///
+/// Renames are followed: commits to the old path count for the new path.
+/// Like with `--follow`, commits to a path count for the file at it even if
+/// another file was deleted there before. Deletions are skipped. Ignored
+/// commits only count for files which were changed by no other commit.
+pub(crate) fn dates_by_file(
+    commits: impl IntoIterator<Item = CommitChanges>,
+    ignored_revs: &IgnoredRevs,
+) -> HashMap<String, Vec<String>> {
+    let mut dates: HashMap<String, Vec<String>> = HashMap::new();
+    let mut ignored_dates: HashMap<String, Vec<String>> = HashMap::new();
+    // Paths in older commits mapped to the paths on `HEAD`, several for
+    // copied files
+    let mut renamed: HashMap<String, Vec<String>> = HashMap::new();
+    let current_paths = |renamed: &HashMap<String, Vec<String>>, path: &str| {
+        renamed
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| vec![path.to_owned()])
+    };
+
+    for commit in commits {
+        let ignored = ignored_revs.contains(&commit.hash);
+        for (status, path, new_path) in commit.changes {
+            let current = match (status, new_path) {
+                ('D', _) => continue,
+                ('R', Some(new_path)) => {
+                    let current = current_paths(&renamed, &new_path);
+                    renamed.insert(path, current.clone());
+                    current
+                }
+                // Copies inherit the history of their source, which keeps it
+                ('C', Some(new_path)) => {
+                    let current = current_paths(&renamed, &new_path);
+                    let mut source = current_paths(&renamed, &path);
+                    source.extend(current.iter().cloned());
+                    renamed.insert(path, source);
+                    current
+                }
+                _ => current_paths(&renamed, &path),
+            };
+            for current in current {
+                let file_dates = match ignored {
+                    true => ignored_dates.entry(current).or_default(),
+                    false => dates.entry(current).or_default(),
+                };
+                // Merges are listed once per parent
+                if file_dates.last() != Some(&commit.date) {
+                    file_dates.push(commit.date.clone());
+                }
+            }
+        }
+    }
+    for (path, file_dates) in ignored_dates {
+        dates.entry(path).or_insert(file_dates);
+    }
+    dates
+}
+
+/// Version of git as `(major, minor, patch)`.
+pub type GitVersion = (u32, u32, u32);
+
+/// Oldest supported git version, which introduced `rev-parse --git-path`
+/// and `--git-common-dir`.
+pub const MIN_GIT_VERSION: GitVersion = (2, 5, 0);
+
+/// Version of the installed git, detected on first use.
+static INSTALLED_GIT_VERSION: tokio::sync::OnceCell<Option<GitVersion>> =
+    tokio::sync::OnceCell::const_new();
+
+/// Fail with a clear message if git cannot be run or is older than
+/// `MIN_GIT_VERSION`, instead of failing later on unknown flags.
+pub async fn check_git_version() -> Result<GitVersion, CError> {
+    let version = match installed_git_version().await {
+        Some(version) => version,
+        None => git_version().await?,
+    };
+    match version >= MIN_GIT_VERSION {
+        true => Ok(version),
+        false => Err(CError::UnsupportedGitVersion {
+            found: format_git_version(version),
+            required: format_git_version(MIN_GIT_VERSION),
+        }),
+    }
+}
+
+/// Whether the installed git has a feature introduced in `version`.
+///
+/// Gits with unknown version are assumed to have all features.
+async fn git_supports(version: GitVersion) -> bool {
+    installed_git_version()
+        .await
+        .is_none_or(|installed| installed >= version)
+}
+
+async fn installed_git_version() -> Option<GitVersion> {
+    *INSTALLED_GIT_VERSION
+        .get_or_init(|| async { git_version().await.ok() })
+        .await
+}
+
+pub fn format_git_version((major, minor, patch): GitVersion) -> String {
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+/// Version of the installed git, e.g. `(2, 39, 2)` for `git version 2.39.2`.
+pub async fn git_version() -> Result<GitVersion, CError> {
+    let output = Command::new("git")
+        .arg("--version")
+        .output()
+        .await
+        .map_err(|e| CError::GitCmdError(format!("git could not be run: {}", e)))?;
+    let output = std::str::from_utf8(&output.stdout)?;
+    parse_git_version(output)
+        .ok_or_else(|| CError::GitCmdError(format!("Unknown git version {}", output.trim())))
+}
+
+/// Parse the output of `git --version`, e.g. `git version 2.39.2.windows.1`.
+fn parse_git_version(output: &str) -> Option<GitVersion> {
+    let version = output
+        .strip_prefix("git version ")?
+        .split_whitespace()
+        .next()?;
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+    Some((
+        parts.next()??,
+        parts.next()??,
+        parts.next().flatten().unwrap_or(0),
+    ))
+}
+
+/// Layout of a repository which affects runs.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepoLayout {
+    pub bare: bool,
+    /// The repository is a worktree added with `git worktree add`.
+    pub linked_worktree: bool,
+    pub sparse_checkout: bool,
+}
+
+/// Layout of the repository, failing with the error of git if the path is
+/// not in a repository or git refuses to use it.
+pub async fn get_repo_layout(repo_path: &str) -> Result<RepoLayout, CError> {
+    let output = git_command(repo_path)
+        .arg("rev-parse")
+        .arg("--is-bare-repository")
+        .arg("--git-dir")
+        .arg("--git-common-dir")
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    let rev_parse = parse_cmd_output(&output)?;
+    let (bare, git_dir, common_dir) = match rev_parse.as_slice() {
+        [bare, git_dir, common_dir] => (bare == "true", git_dir, common_dir),
+        _ => return Err(CError::GitCmdError(rev_parse.join("\n"))),
+    };
+    // The directories are relative to the working directory unless absolute
+    let git_dir = Path::new(repo_path).join(git_dir);
+    let common_dir = Path::new(repo_path).join(common_dir);
+    let linked_worktree = match (git_dir.canonicalize(), common_dir.canonicalize()) {
+        (Ok(git_dir), Ok(common_dir)) => git_dir != common_dir,
+        _ => false,
+    };
+
+    let output = git_command(repo_path)
+        .arg("config")
+        .arg("--bool")
+        .arg("core.sparseCheckout")
+        .output();
+    let sparse_checkout = parse_cmd_output(&output.await?)?
+        .first()
+        .map(String::as_str)
+        == Some("true");
+
+    Ok(RepoLayout {
+        bare,
+        linked_worktree,
+        sparse_checkout,
+    })
+}
+
+/// Whether the path is inside a git repository.
+pub async fn is_git_repo(repo_path: &str) -> bool {
+    git_command(repo_path)
+        .arg("rev-parse")
+        .arg("--git-dir")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
+
+/// Date of the last commit modifying the file as `YYYY-MM-DD`.
+pub async fn get_last_modified_date(filepath: &str, cwd: &str) -> Result<Option<String>, CError> {
+    // Short dates are only supported from git 2.21 on
+    let format = match git_supports((2, 21, 0)).await {
+        true => "--pretty=%cs",
+        false => "--pretty=%ci",
+    };
+    let output = git_command(cwd)
+        .arg("log")
+        .arg("-1")
+        .arg(format)
+        .arg("--")
+        .arg(filepath)
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    Ok(parse_cmd_output(&output)?
+        .into_iter()
+        .next()
+        .map(|date| date.chars().take(10).collect()))
+}
+
+/// Dates from which on the history of files below a directory is our own.
+///
+/// Directories imported with `git subtree` bring the history of another
+/// repository whose commits would otherwise yield misleading early years.
+#[derive(Debug, Default)]
+pub struct HistoryCutoffs(Vec<(String, String)>);
+
+impl HistoryCutoffs {
+    /// Detect `git subtree` imports and add the cutoff commits of the config,
+    /// given by directory.
+    ///
+    /// Cutoff commits of the config take precedence over detected imports.
+    /// Their dates are of the date source of the log options like the dates
+    /// compared with them.
+    pub async fn load(
+        repo_path: &str,
+        cutoff_commits: &HashMap<String, String>,
+        log_options: &LogOptions,
+    ) -> Result<Self, CError> {
+        let output = git_log(repo_path, log_options)
+            .arg("--grep=^git-subtree-dir:")
+            .arg(format!(
+                "--pretty=format:{}%n%B%x00",
+                log_options.date_source.placeholder()
+            ))
+            .arg(&log_options.history_ref)
+            .output();
+
+        let output = output.await?;
+        if !output.status.success() {
+            return Err(CError::GitCmdError(
+                String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+            ));
+        }
+
+        let imports = parse_subtree_imports(std::str::from_utf8(&output.stdout)?);
+        let mut cutoff_dates = Vec::new();
+        for (dir, commit) in cutoff_commits.iter() {
+            cutoff_dates.push((
+                dir.clone(),
+                get_commit_date(repo_path, commit, log_options).await?,
+            ));
+        }
+        Ok(HistoryCutoffs::from_dates(imports, cutoff_dates))
+    }
+
+    /// Cutoffs from the dates of detected subtree imports and of the cutoff
+    /// commits of the config, see `load`.
+    pub(crate) fn from_dates(
+        mut cutoffs: HashMap<String, String>,
+        cutoff_dates: Vec<(String, String)>,
+    ) -> Self {
+        for (dir, date) in cutoffs.iter() {
+            log::debug!("Directory {} was imported as subtree on {}", dir, date);
+        }
+        for (dir, date) in cutoff_dates {
+            cutoffs.insert(dir.trim_end_matches('/').to_owned(), date);
+        }
+        HistoryCutoffs(cutoffs.into_iter().collect())
+    }
+
+    /// Cutoff date of the innermost directory containing the file.
+    pub fn cutoff_for(&self, filepath: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .filter(|(dir, _)| {
+                filepath
+                    .strip_prefix(dir.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|(dir, _)| dir.len())
+            .map(|(_, date)| date.as_str())
+    }
+}
+
+/// Name of the file at the repository root listing commits which do not
+/// count for the years of files.
+pub const IGNORE_REVS_FILE: &str = ".git-copyright-ignore-revs";
+
+/// Commits which do not count for the years of files, e.g. bulk reformatting.
+///
+/// They are listed in `.git-copyright-ignore-revs` at the repository root or
+/// otherwise in the `blame.ignoreRevsFile` of the git config, one full commit
+/// hash per line with `#` comments like for `git blame`. Files changed only
+/// by ignored commits keep their years.
+#[derive(Debug, Default, Clone)]
+pub struct IgnoredRevs(HashSet<String>);
+
+impl IgnoredRevs {
+    /// Read the ignored commits of the repository, if any are listed.
+    pub async fn load(repo_path: &str) -> Result<Self, CError> {
+        let ignore_revs_file = Path::new(repo_path).join(IGNORE_REVS_FILE);
+        if ignore_revs_file.is_file() {
+            return Self::read(&ignore_revs_file);
+        }
+
+        let output = git_command(repo_path)
+            .arg("config")
+            .arg("--path")
+            .arg("blame.ignoreRevsFile")
+            .output();
+        match parse_cmd_output(&output.await?)?.first() {
+            Some(blame_file) => Self::read(&Path::new(repo_path).join(blame_file)),
+            None => Ok(IgnoredRevs::default()),
+        }
+    }
+
+    pub(crate) fn read(filepath: &Path) -> Result<Self, CError> {
+        log::debug!("Ignoring the commits listed in {}", filepath.display());
+        Ok(Self::parse(&std::fs::read_to_string(filepath)?))
+    }
+
+    /// Parse a list of commit hashes with `#` comments.
+    pub fn parse(content: &str) -> Self {
+        IgnoredRevs(
+            content
+                .lines()
+                .map(|line_| line_.split('#').next().unwrap_or_default().trim())
+                .filter(|hash| !hash.is_empty())
+                .map(str::to_lowercase)
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.0.contains(hash)
+    }
+
+    /// Dates of the commits given with their hash which are not ignored, or
+    /// all dates if every commit is ignored.
+    fn filter<'a>(&self, commits: Vec<(&str, &'a str)>) -> Vec<&'a str> {
+        let dates: Vec<&str> = commits
+            .iter()
+            .filter(|(hash, _)| !self.contains(hash))
+            .map(|(_, date)| *date)
+            .collect();
+        match dates.is_empty() {
+            true => commits.into_iter().map(|(_, date)| date).collect(),
+            false => dates,
+        }
+    }
+}
+
+/// Map directories imported with `git subtree` to the date of their import.
+///
+/// `log` holds commit dates followed by the commit message, separated by NUL
+/// and newest first, so that later pulls of a subtree are overridden by the
+/// commit which added it.
+pub(crate) fn parse_subtree_imports(log: &str) -> HashMap<String, String> {
+    let mut imports = HashMap::new();
+    for commit in log.split('\0') {
+        let mut lines = commit.trim_start_matches('\n').lines();
+        let date = match lines.next() {
+            Some(date) if !date.is_empty() => date,
+            _ => continue,
+        };
+        if let Some(dir) = lines.find_map(|line_| line_.strip_prefix("git-subtree-dir:")) {
+            imports.insert(dir.trim().trim_end_matches('/').to_owned(), date.to_owned());
+        }
+    }
+    imports
+}
+
+/// Commit date of a revision like `2022-06-01 12:00:00 +0200`.
+async fn get_commit_date(
+    repo_path: &str,
+    rev: &str,
+    log_options: &LogOptions,
+) -> Result<String, CError> {
+    let output = git_log(repo_path, log_options)
+        .arg("-1")
+        .arg(format!(
+            "--pretty={}",
+            log_options.date_source.placeholder()
+        ))
+        .arg(rev)
+        .arg("--")
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    parse_cmd_output(&output)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CError::GitCmdError(format!("No commit found for {}", rev)))
+}
+
+/// Whether a commit date `YYYY-MM-DD` is on or after `since`.
+///
+/// `since` may be a full date or only its start like the year `2022`.
+pub fn modified_since(date: &str, since: &str) -> bool {
+    let len = since.len().min(date.len());
+    date[..len] >= since[..len]
+}
+
+/// Years from the first to the last commit on `ref_name` in the repository.
+pub async fn get_repo_years(
+    repo_path: &str,
+    ref_name: &str,
+    year_format: YearFormat,
+    log_options: &LogOptions,
+    ignored_revs: &IgnoredRevs,
+) -> Result<String, CError> {
+    let output = git_log(repo_path, log_options)
+        .args((log_options.merge_commits == MergeCommits::Skip).then_some("--no-merges"))
+        .args(log_options.author_arg())
+        .arg(format!(
+            "--pretty=%H {}",
+            log_options.date_source.placeholder()
+        ))
+        .arg(ref_name)
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    let lines = parse_cmd_output(&output)?;
+    let commits = lines
+        .iter()
+        .filter_map(|line_| line_.split_once(' '))
+        .collect();
+    let commit_years: Vec<String> = ignored_revs
+        .filter(commits)
+        .iter()
+        .filter_map(|date| commit_year(date, log_options.timezone))
+        .collect();
+    Ok(years_from_commit_years(
+        &commit_years,
+        "Repository",
+        year_format,
+    ))
+}
+
+/// Year of a commit date like `2022-06-01 12:00:00 +0200` or
+/// `2022-06-01T12:00:00Z` in the timezone, `None` for empty dates.
+pub(crate) fn commit_year(date: &str, timezone: YearTimezone) -> Option<String> {
+    let offset = match timezone {
+        YearTimezone::Commit => None,
+        YearTimezone::Utc => Some(0),
+        YearTimezone::Fixed(offset) => Some(offset),
+    };
+    let parsed = DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z")
+        .or_else(|_| DateTime::parse_from_rfc3339(date));
+    match (
+        offset.and_then(|offset| FixedOffset::east_opt(offset * 60)),
+        parsed,
+    ) {
+        (Some(offset), Ok(parsed)) => Some(parsed.with_timezone(&offset).year().to_string()),
+        // Take only the first four chars (the year) of the date as recorded
+        _ => (!date.is_empty()).then(|| date.chars().take(4).collect()),
+    }
+}
+
+/// Turn commit years, newest first, into years in `year_format`, e.g.
+/// `added-last_modified` years.
+pub(crate) fn years_from_commit_years(
+    commit_years: &[String],
+    subject: &str,
+    year_format: YearFormat,
+) -> String {
     match commit_years.len() {
         0 => {
-            log::debug!("File {} is untracked, add current year", filepath);
-            Utc::now().date().format("%Y").to_string()
+            log::debug!("{} is untracked, add current year", subject);
+            Utc::now().format("%Y").to_string()
         }
         1 => {
-            log::debug!("File {} was only committed once", filepath);
+            log::debug!("{} was only committed once", subject);
             commit_years[0].clone()
         }
         num_commits => {
-            log::debug!("File {} was modified {} times", filepath, num_commits);
-            let added = commit_years[commit_years.len() - 1].clone();
-            let last_modified = commit_years[0].clone();
-            match added == last_modified {
-                true => added,
-                false => format!("{}-{}", added, last_modified),
-            }
+            log::debug!("{} was modified {} times", subject, num_commits);
+            let years: Vec<u32> = commit_years
+                .iter()
+                .rev()
+                .filter_map(|year| year.parse().ok())
+                .collect();
+            format_years(&years, year_format)
+        }
+    }
+}
+
+/// Write years, oldest first, in `year_format`.
+///
+/// A range spans the first to the last year, a list names every distinct
+/// year and a single year is the first one.
+pub(crate) fn format_years(years: &[u32], year_format: YearFormat) -> String {
+    let (first, last) = match (years.iter().min(), years.iter().max()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return String::new(),
+    };
+    match year_format {
+        YearFormat::Range if first == last => first.to_string(),
+        YearFormat::Range => format!("{}-{}", first, last),
+        YearFormat::List => {
+            let mut years = years.to_vec();
+            years.sort_unstable();
+            years.dedup();
+            years
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
         }
+        YearFormat::Single => first.to_string(),
+    }
+}
+
+/// Years of a years string like `2019`, `2019-2021` or `2018, 2020`, with
+/// the first and last year of ranges.
+fn parse_years(years: &str) -> Vec<u32> {
+    years
+        .split([',', '-'])
+        .filter_map(|year| year.trim().parse().ok())
+        .collect()
+}
+
+/// First year of a years string like `2019`, `2019-2021` or `2018, 2020`.
+pub fn start_year(years: &str) -> Option<u32> {
+    parse_years(years).into_iter().min()
+}
+
+/// Replace the last year of the years with `open_end`, e.g. `2019-present`
+/// for `2019-2022`. Single years also become open-ended ranges, lists are
+/// kept.
+pub fn open_years(years: String, open_end: Option<&str>) -> String {
+    match (open_end, start_year(&years)) {
+        (Some(open_end), Some(start)) if !years.contains(',') => {
+            format!("{}-{}", start, open_end)
+        }
+        _ => years,
+    }
+}
+
+/// Raise years before `min_year` to `min_year`.
+///
+/// Lists like `2005, 2012` keep their format, e.g. `2010, 2012` with
+/// `min_year` 2010.
+pub fn clamp_years(years: String, min_year: Option<u32>) -> String {
+    let min_year = match min_year {
+        Some(min_year) => min_year,
+        None => return years,
+    };
+
+    let clamped: Vec<u32> = parse_years(&years)
+        .into_iter()
+        .map(|year| year.max(min_year))
+        .collect();
+    let year_format = match years.contains(',') {
+        true => YearFormat::List,
+        false => YearFormat::Range,
+    };
+    match clamped.len() {
+        0 => years,
+        _ => format_years(&clamped, year_format),
+    }
+}
+
+/// Tracked files with uncommitted changes and the hash of their content.
+#[derive(Debug, Default)]
+pub struct ChangedFiles(HashMap<String, Option<u64>>);
+
+impl ChangedFiles {
+    pub fn contains(&self, filepath: &str) -> bool {
+        self.0.contains_key(filepath)
     }
 }
 
-pub async fn check_for_changes(repo_path: &str, fail_on_diff: bool) -> Result<(), CError> {
-    let diff_files = get_diffs(repo_path).await?;
-    if diff_files.len() > 0 {
-        println!("Files changed:");
+/// Record tracked files which are already changed before the run.
+pub async fn snapshot_changes(repo_path: &str) -> Result<ChangedFiles, CError> {
+    Ok(ChangedFiles::from_diffs(
+        repo_path,
+        get_diffs(repo_path).await?,
+    ))
+}
+
+impl ChangedFiles {
+    /// Record the changed tracked files with the hash of their content.
+    pub(crate) fn from_diffs(repo_path: &str, diff_files: Vec<String>) -> Self {
+        ChangedFiles(
+            diff_files
+                .into_iter()
+                .map(|filepath| {
+                    let hash = content_hash(repo_path, &filepath);
+                    (filepath, hash)
+                })
+                .collect(),
+        )
+    }
+}
+
+fn content_hash(repo_path: &str, filepath: &str) -> Option<u64> {
+    std::fs::read(Path::new(repo_path).join(filepath))
+        .ok()
+        .map(|data| get_hash(&data))
+}
+
+/// List the tracked files changed by the run and optionally fail.
+///
+/// Files which were already changed before the run, see `snapshot_changes`,
+/// are only listed if the run changed them further.
+pub async fn check_for_changes(
+    repo_path: &str,
+    before: &ChangedFiles,
+    fail_on_diff: bool,
+) -> Result<(), CError> {
+    report_changes(repo_path, get_diffs(repo_path).await?, before, fail_on_diff)
+}
+
+/// List the changed tracked files `diff_files` which the run changed, see
+/// `check_for_changes`.
+pub(crate) fn report_changes(
+    repo_path: &str,
+    diff_files: Vec<String>,
+    before: &ChangedFiles,
+    fail_on_diff: bool,
+) -> Result<(), CError> {
+    let diff_files: Vec<String> = diff_files
+        .into_iter()
+        .filter(|filepath| match before.0.get(filepath) {
+            Some(hash) => *hash != content_hash(repo_path, filepath),
+            None => true,
+        })
+        .collect();
+    if !diff_files.is_empty() {
+        log::info!("Files changed:");
         for filepath in diff_files.iter() {
-            println!("{}", filepath);
+            log::info!("{}", filepath);
         }
 
         if fail_on_diff {
@@ -84,11 +1277,10 @@ pub async fn check_for_changes(repo_path: &str, fail_on_diff: bool) -> Result<()
     Ok(())
 }
 
-async fn get_diffs<'a>(repo_path: &str) -> Result<Vec<String>, CError> {
-    let output = Command::new("git")
+async fn get_diffs(repo_path: &str) -> Result<Vec<String>, CError> {
+    let output = git_command(repo_path)
         .arg("diff")
         .arg("--name-only")
-        .current_dir(repo_path)
         .output();
 
     let output = output.await?;
@@ -98,7 +1290,405 @@ async fn get_diffs<'a>(repo_path: &str) -> Result<Vec<String>, CError> {
         ));
     }
 
-    Ok(parse_cmd_output(&output)?)
+    parse_cmd_output(&output)
+}
+
+/// Reasons why the history of the repository may not reflect when files were
+/// really added or modified, e.g. replace refs, grafts or rewritten history.
+pub async fn detect_history_rewrites(repo_path: &str) -> Result<Vec<String>, CError> {
+    // `--is-shallow-repository` is only supported from git 2.15 on while the
+    // file listing the shallow commits is found with any git
+    let output = git_command(repo_path)
+        .arg("rev-parse")
+        .arg("--git-path")
+        .arg("shallow")
+        .arg("--git-path")
+        .arg("info/grafts")
+        .arg("--git-path")
+        .arg("filter-repo")
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    let rev_parse = parse_cmd_output(&output)?;
+    let git_paths = match rev_parse.as_slice() {
+        [shallow, grafts, filter_repo] => [shallow, grafts, filter_repo]
+            .map(|git_path| Path::new(repo_path).join(git_path))
+            .to_vec(),
+        _ => Vec::new(),
+    };
+
+    let output = git_command(repo_path)
+        .arg("for-each-ref")
+        .arg("--format=%(refname)")
+        .arg("refs/replace/")
+        .arg("refs/original/")
+        .output();
+
+    let output = output.await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    Ok(rewrite_reasons(&git_paths, &parse_cmd_output(&output)?))
+}
+
+/// Reasons of `detect_history_rewrites` from the paths of the `shallow`,
+/// `info/grafts` and `filter-repo` files in the git directory and the names
+/// of the refs under `refs/replace/` and `refs/original/`.
+pub(crate) fn rewrite_reasons(git_paths: &[PathBuf], refs: &[String]) -> Vec<String> {
+    let mut reasons = Vec::new();
+    if let [shallow, grafts, filter_repo] = git_paths {
+        if shallow.exists() {
+            reasons.push("the clone is shallow".to_owned());
+        }
+        if grafts.exists() {
+            reasons.push("grafts are configured in info/grafts".to_owned());
+        }
+        if filter_repo.exists() {
+            reasons.push("the history was rewritten with git filter-repo".to_owned());
+        }
+    }
+    if refs
+        .iter()
+        .any(|ref_name| ref_name.starts_with("refs/replace/"))
+    {
+        reasons.push("replace refs exist".to_owned());
+    }
+    if refs
+        .iter()
+        .any(|ref_name| ref_name.starts_with("refs/original/"))
+    {
+        reasons.push("the history was rewritten with git filter-branch".to_owned());
+    }
+    reasons
+}
+
+/// Notes ref which marks commits whose files were fully checked.
+const CHECKED_NOTES_REF: &str = "refs/notes/git-copyright";
+
+/// Files with a correct copyright at a fully checked commit.
+///
+/// Stored as note of the commit in `CHECKED_NOTES_REF` so that it travels
+/// with clones which fetch the notes.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CheckedCommit {
+    pub commit: String,
+    /// Years of the files with a correct copyright by path.
+    pub years: HashMap<String, String>,
+}
+
+impl CheckedCommit {
+    /// Parse a note with one `years<TAB>path` line per file.
+    fn parse(commit: &str, note: &str) -> Self {
+        let years = note
+            .lines()
+            .filter_map(|line_| line_.split_once('\t'))
+            .map(|(years, filepath)| (filepath.to_owned(), years.to_owned()))
+            .collect();
+        CheckedCommit {
+            commit: commit.to_owned(),
+            years,
+        }
+    }
+
+    fn to_note(&self) -> String {
+        let mut lines: Vec<String> = self
+            .years
+            .iter()
+            .map(|(filepath, years)| format!("{}\t{}\n", years, filepath))
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+
+    /// Read the note of the closest checked ancestor of `HEAD`, if any.
+    pub async fn read(repo_path: &str) -> Result<Option<Self>, CError> {
+        let output = git_command(repo_path)
+            .arg("notes")
+            .arg("--ref")
+            .arg(CHECKED_NOTES_REF)
+            .arg("list")
+            .output()
+            .await?;
+        // Without notes ref, git fails to list notes
+        let noted: HashSet<String> = match output.status.success() {
+            true => parse_cmd_output(&output)?
+                .iter()
+                .filter_map(|line_| Some(line_.split_once(' ')?.1.to_owned()))
+                .collect(),
+            false => return Ok(None),
+        };
+        if noted.is_empty() {
+            return Ok(None);
+        }
+
+        let output = git_command(repo_path)
+            .arg("rev-list")
+            .arg("HEAD")
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(CError::GitCmdError(
+                String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+            ));
+        }
+        let commit = match parse_cmd_output(&output)?
+            .into_iter()
+            .find(|commit| noted.contains(commit))
+        {
+            Some(commit) => commit,
+            None => return Ok(None),
+        };
+
+        let output = git_command(repo_path)
+            .arg("notes")
+            .arg("--ref")
+            .arg(CHECKED_NOTES_REF)
+            .arg("show")
+            .arg(&commit)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(CError::GitCmdError(
+                String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+            ));
+        }
+        Ok(Some(Self::parse(
+            &commit,
+            std::str::from_utf8(&output.stdout)?,
+        )))
+    }
+
+    /// Store the note on the commit, replacing an existing one.
+    pub async fn write(&self, repo_path: &str) -> Result<(), CError> {
+        let mut child = git_command(repo_path)
+            .arg("notes")
+            .arg("--ref")
+            .arg(CHECKED_NOTES_REF)
+            .arg("add")
+            .arg("-f")
+            .arg("-F")
+            .arg("-")
+            .arg(&self.commit)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(self.to_note().as_bytes()).await?;
+        }
+
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(CError::GitCmdError(
+                String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Commit hash of a revision.
+pub async fn get_commit_hash(repo_path: &str, rev: &str) -> Result<String, CError> {
+    let output = git_command(repo_path)
+        .arg("rev-parse")
+        .arg("--verify")
+        .arg(rev)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    parse_cmd_output(&output)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CError::GitCmdError(format!("No commit found for {}", rev)))
+}
+
+/// Files changed between `commit` and `HEAD`.
+pub async fn get_changed_since(repo_path: &str, commit: &str) -> Result<HashSet<String>, CError> {
+    let output = git_command(repo_path)
+        .arg("diff")
+        .arg("--name-only")
+        .arg(commit)
+        .arg("HEAD")
+        .arg("--")
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    Ok(parse_cmd_output(&output)?.into_iter().collect())
+}
+
+/// Files added or modified on `HEAD` since it diverged from `base_ref`, e.g.
+/// the target branch of a pull request.
+///
+/// Deleted files are left out, renamed and copied files are listed by their
+/// new path.
+pub async fn get_changed_since_merge_base(
+    repo_path: &str,
+    base_ref: &str,
+) -> Result<HashSet<String>, CError> {
+    let output = git_command(repo_path)
+        .arg("diff")
+        .arg("--name-only")
+        .arg("--diff-filter=ACMR")
+        .arg(format!("{}...HEAD", base_ref))
+        .arg("--")
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(CError::GitCmdError(
+            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
+        ));
+    }
+
+    Ok(parse_cmd_output(&output)?.into_iter().collect())
+}
+
+/// Git operations of checks, run with the git binary or libgit2.
+impl GitBackend {
+    /// Whether libgit2 is used, failing if it is configured but not built in.
+    fn libgit2(&self) -> Result<bool, CError> {
+        match self {
+            GitBackend::Cli => Ok(false),
+            GitBackend::Libgit2 if cfg!(feature = "git2") => Ok(true),
+            GitBackend::Libgit2 => Err(CError::MissingFeature {
+                what: "The libgit2 backend".to_owned(),
+                feature: "git2".to_owned(),
+            }),
+        }
+    }
+
+    pub async fn is_git_repo(&self, repo_path: &str) -> bool {
+        if let Ok(true) = self.libgit2() {
+            #[cfg(feature = "git2")]
+            return git2_ops::is_git_repo(repo_path);
+        }
+        is_git_repo(repo_path).await
+    }
+
+    /// Check the version of the git binary, which libgit2 does not need.
+    pub async fn check_git_version(&self) -> Result<(), CError> {
+        match self.libgit2()? {
+            true => Ok(()),
+            false => check_git_version().await.map(|_| ()),
+        }
+    }
+
+    pub async fn get_files_on_ref(
+        &self,
+        repo_path: &str,
+        ref_name: &str,
+    ) -> Result<Vec<String>, CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::get_files_on_ref(repo_path, ref_name);
+        }
+        get_files_on_ref(repo_path, ref_name).await
+    }
+
+    pub async fn get_commit_dates_by_file(
+        &self,
+        repo_path: &str,
+        log_options: &LogOptions,
+        ignored_revs: &IgnoredRevs,
+    ) -> Result<HashMap<String, Vec<String>>, CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::get_commit_dates_by_file(repo_path, log_options, ignored_revs);
+        }
+        get_commit_dates_by_file(repo_path, log_options, ignored_revs).await
+    }
+
+    pub async fn load_ignored_revs(&self, repo_path: &str) -> Result<IgnoredRevs, CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::load_ignored_revs(repo_path);
+        }
+        IgnoredRevs::load(repo_path).await
+    }
+
+    pub async fn load_history_cutoffs(
+        &self,
+        repo_path: &str,
+        cutoff_commits: &HashMap<String, String>,
+        log_options: &LogOptions,
+    ) -> Result<HistoryCutoffs, CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::load_history_cutoffs(repo_path, cutoff_commits, log_options);
+        }
+        HistoryCutoffs::load(repo_path, cutoff_commits, log_options).await
+    }
+
+    pub async fn detect_history_rewrites(&self, repo_path: &str) -> Result<Vec<String>, CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::detect_history_rewrites(repo_path);
+        }
+        detect_history_rewrites(repo_path).await
+    }
+
+    pub async fn get_changed_since_merge_base(
+        &self,
+        repo_path: &str,
+        base_ref: &str,
+    ) -> Result<HashSet<String>, CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::get_changed_since_merge_base(repo_path, base_ref);
+        }
+        get_changed_since_merge_base(repo_path, base_ref).await
+    }
+
+    pub async fn get_last_modified_date(
+        &self,
+        filepath: &str,
+        repo_path: &str,
+    ) -> Result<Option<String>, CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::get_last_modified_date(repo_path, filepath);
+        }
+        get_last_modified_date(filepath, repo_path).await
+    }
+
+    pub async fn snapshot_changes(&self, repo_path: &str) -> Result<ChangedFiles, CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::snapshot_changes(repo_path);
+        }
+        snapshot_changes(repo_path).await
+    }
+
+    pub async fn check_for_changes(
+        &self,
+        repo_path: &str,
+        before: &ChangedFiles,
+        fail_on_diff: bool,
+    ) -> Result<(), CError> {
+        if self.libgit2()? {
+            #[cfg(feature = "git2")]
+            return git2_ops::check_for_changes(repo_path, before, fail_on_diff);
+        }
+        check_for_changes(repo_path, before, fail_on_diff).await
+    }
 }
 
 fn parse_cmd_output(output: &std::process::Output) -> Result<Vec<String>, CError> {
@@ -115,3 +1705,686 @@ fn parse_cmd_output(output: &std::process::Output) -> Result<Vec<String>, CError
         .collect();
     Ok(lines)
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::LogOptions;
+    use super::{check_git_version, detect_history_rewrites, format_git_version, git_supports};
+    use super::{
+        clamp_years, commit_year, dates_by_file, format_years, modified_since, open_years,
+        parse_author_log, parse_blame_dates, parse_git_version, parse_name_status_log,
+        parse_numstat_log, parse_subtree_imports, start_year, substantial_commits, CheckedCommit,
+        HistoryCutoffs, IgnoredRevs, MIN_GIT_VERSION,
+    };
+    use super::{get_added_mod_times_for_file, get_author_dates_for_file};
+    use super::{get_blame_years_for_file, get_repo_years};
+    use crate::config::{DateSource, GitBackend, Renames, YearFormat, YearTimezone};
+    use crate::CError;
+    use std::path::{Path, PathBuf};
+
+    /// Create an empty repository in the temporary directory.
+    fn init_repo(name: &str) -> PathBuf {
+        let repo_dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        git(&repo_dir, &["init", "-q"]);
+        repo_dir
+    }
+
+    /// Run git in the repository with a dummy identity and all dates at
+    /// `date`, e.g. `2015-01-01T00:00:00+0000`.
+    fn git_at(repo_dir: &Path, date: &str, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=Dummy",
+                "-c",
+                "user.email=dummy@example.com",
+            ])
+            .args(args)
+            .current_dir(repo_dir)
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn git(repo_dir: &Path, args: &[&str]) {
+        git_at(repo_dir, "2015-01-01T00:00:00+0000", args);
+    }
+
+    /// Commit all files of the working tree at `date`.
+    fn commit_all(repo_dir: &Path, date: &str, message: &str) {
+        git(repo_dir, &["add", "-A"]);
+        git_at(repo_dir, date, &["commit", "-q", "-m", message]);
+    }
+
+    #[test]
+    fn test_parse_git_version() {
+        assert_eq!(parse_git_version("git version 2.39.2\n"), Some((2, 39, 2)));
+        assert_eq!(
+            parse_git_version("git version 2.37.1.windows.1\n"),
+            Some((2, 37, 1))
+        );
+        assert_eq!(
+            parse_git_version("git version 2.39.3 (Apple Git-146)\n"),
+            Some((2, 39, 3))
+        );
+        assert_eq!(parse_git_version("git version 1.8\n"), Some((1, 8, 0)));
+        assert_eq!(parse_git_version("not git"), None);
+    }
+
+    #[tokio::test]
+    async fn test_git_version() {
+        let version = check_git_version().await.unwrap();
+        assert!(version >= MIN_GIT_VERSION);
+        assert!(git_supports(MIN_GIT_VERSION).await);
+        assert!(!git_supports((u32::MAX, 0, 0)).await);
+        assert_eq!(format_git_version((2, 5, 0)), "2.5.0");
+
+        // Shallow clones are detected without `--is-shallow-repository`
+        let repo_dir = init_repo("git_copyright_test_shallow");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add a.rs");
+        let head = std::process::Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(&repo_dir)
+            .output()
+            .unwrap();
+        std::fs::write(repo_dir.join(".git/shallow"), head.stdout).unwrap();
+        let reasons = detect_history_rewrites(repo_dir.to_str().unwrap()).await;
+        assert_eq!(reasons.unwrap(), ["the clone is shallow"]);
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_clamp_years() {
+        assert_eq!(clamp_years("2005-2020".into(), Some(2010)), "2010-2020");
+        assert_eq!(clamp_years("2005-2008".into(), Some(2010)), "2010");
+        assert_eq!(clamp_years("2005".into(), Some(2010)), "2010");
+        assert_eq!(clamp_years("2015-2020".into(), Some(2010)), "2015-2020");
+        assert_eq!(clamp_years("2005-2020".into(), None), "2005-2020");
+        assert_eq!(start_year("2005-2020"), Some(2005));
+        assert_eq!(
+            clamp_years("2005, 2008, 2012".into(), Some(2010)),
+            "2010, 2012"
+        );
+        assert_eq!(start_year("2018, 2020"), Some(2018));
+    }
+
+    #[test]
+    fn test_format_years() {
+        let years = [2018, 2020, 2020, 2023];
+        assert_eq!(format_years(&years, YearFormat::Range), "2018-2023");
+        assert_eq!(format_years(&years, YearFormat::List), "2018, 2020, 2023");
+        assert_eq!(format_years(&years, YearFormat::Single), "2018");
+        assert_eq!(format_years(&[2021, 2021], YearFormat::Range), "2021");
+    }
+
+    #[test]
+    fn test_open_years() {
+        assert_eq!(
+            open_years("2019-2022".into(), Some("present")),
+            "2019-present"
+        );
+        assert_eq!(open_years("2022".into(), Some("present")), "2022-present");
+        assert_eq!(
+            open_years("2018, 2020".into(), Some("present")),
+            "2018, 2020"
+        );
+        assert_eq!(open_years("2019-2022".into(), None), "2019-2022");
+    }
+
+    #[test]
+    fn test_subtree_imports() {
+        let log = "2023-05-01 10:00:00 +0200\nMerge commit 'abc'\n\ngit-subtree-dir: vendor/lib\ngit-subtree-split: abc\n\0\n\
+                   2021-02-01 10:00:00 +0100\nAdd 'vendor/lib/' from commit 'def'\n\ngit-subtree-dir: vendor/lib/\ngit-subtree-split: def\n\0";
+        let imports = parse_subtree_imports(log);
+        assert_eq!(imports.len(), 1);
+        assert_eq!(imports["vendor/lib"], "2021-02-01 10:00:00 +0100");
+
+        let cutoffs = HistoryCutoffs(imports.into_iter().collect());
+        assert_eq!(
+            cutoffs.cutoff_for("vendor/lib/src/a.c"),
+            Some("2021-02-01 10:00:00 +0100")
+        );
+        assert_eq!(cutoffs.cutoff_for("vendor/library.c"), None);
+        assert!(modified_since(
+            "2021-02-01 10:00:00 +0100",
+            "2021-02-01 10:00:00 +0100"
+        ));
+        assert!(!modified_since(
+            "2019-07-01 10:00:00 +0100",
+            "2021-02-01 10:00:00 +0100"
+        ));
+    }
+
+    #[test]
+    fn test_name_status_log() {
+        let log = "\x002023-05-01 10:00:00 +0200\n\nM\tsrc/main.rs\nD\tsrc/old.rs\n\
+                   \x002022-03-01 10:00:00 +0100\n\nR087\tmain.rs\tsrc/main.rs\nA\tsrc/old.rs\n\
+                   \x002021-02-01 10:00:00 +0100\n\nC100\tmain.rs\tcopy.rs\n\
+                   \x002020-01-01 10:00:00 +0100\n\nA\tmain.rs\n";
+        let dates = dates_by_file(parse_name_status_log(log), &IgnoredRevs::default());
+        assert_eq!(
+            dates["src/main.rs"],
+            [
+                "2023-05-01 10:00:00 +0200",
+                "2022-03-01 10:00:00 +0100",
+                "2020-01-01 10:00:00 +0100",
+            ]
+        );
+        assert_eq!(dates["src/old.rs"], ["2022-03-01 10:00:00 +0100"]);
+        assert_eq!(
+            dates["copy.rs"],
+            ["2021-02-01 10:00:00 +0100", "2020-01-01 10:00:00 +0100"]
+        );
+        assert!(!dates.contains_key("main.rs"));
+    }
+
+    #[test]
+    fn test_ignored_revs() {
+        let reformat = "0123456789abcdef0123456789abcdef01234567";
+        let ignored_revs = IgnoredRevs::parse(&format!(
+            "# Reformat with rustfmt\n{}  # 2023\n\n",
+            reformat.to_uppercase()
+        ));
+        assert!(ignored_revs.contains(reformat));
+
+        let log = format!(
+            "\x002023-05-01 10:00:00 +0200\n{}\n\nM\tsrc/main.rs\nA\tsrc/fmt.rs\n\
+             \x002021-02-01 10:00:00 +0100\nfedcba\n\nA\tsrc/main.rs\n",
+            reformat
+        );
+        let dates = dates_by_file(parse_name_status_log(&log), &ignored_revs);
+        assert_eq!(dates["src/main.rs"], ["2021-02-01 10:00:00 +0100"]);
+        assert_eq!(dates["src/fmt.rs"], ["2023-05-01 10:00:00 +0200"]);
+    }
+
+    #[test]
+    fn test_commit_year() {
+        let date = "2021-12-31 23:30:00 -0800";
+        assert_eq!(commit_year(date, YearTimezone::Commit).unwrap(), "2021");
+        assert_eq!(commit_year(date, YearTimezone::Utc).unwrap(), "2022");
+        assert_eq!(
+            commit_year(date, YearTimezone::Fixed(-600)).unwrap(),
+            "2021"
+        );
+        assert_eq!(
+            commit_year("2022-01-01T00:30:00+01:00", YearTimezone::Utc).unwrap(),
+            "2021"
+        );
+        assert_eq!(commit_year("", YearTimezone::Utc), None);
+    }
+
+    #[test]
+    fn test_blame_dates() {
+        let porcelain = "\
+aaaa 1 1 2
+author Alice
+author-mail <alice@example.com>
+author-time 1420070400
+author-tz +0100
+committer Alice
+committer-time 1483228800
+committer-tz -0230
+summary Add a.rs
+filename a.rs
+\tfn main() {
+bbbb 2 2 1
+author Bob
+author-mail <bob@example.org>
+author-time 1577836800
+author-tz +0000
+committer Bob
+committer-time 1577836800
+committer-tz +0000
+summary Print
+filename a.rs
+\t    println!(\"Hello\");
+aaaa 3 3
+author Alice
+author-mail <alice@example.com>
+author-time 1420070400
+author-tz +0100
+committer Alice
+committer-time 1483228800
+committer-tz -0230
+summary Add a.rs
+filename a.rs
+\t}
+0000000000000000000000000000000000000000 4 4 1
+author Not Committed Yet
+author-time 1700000000
+author-tz +0000
+committer Not Committed Yet
+committer-time 1700000000
+committer-tz +0000
+summary Version of a.rs from a.rs
+filename a.rs
+\t
+";
+        assert_eq!(
+            parse_blame_dates(porcelain, DateSource::Author, None),
+            [
+                ("bbbb".to_owned(), "2020-01-01 00:00:00 +0000".to_owned()),
+                ("aaaa".to_owned(), "2015-01-01 01:00:00 +0100".to_owned()),
+            ]
+        );
+        assert_eq!(
+            parse_blame_dates(porcelain, DateSource::Committer, None)[1].1,
+            "2016-12-31 21:30:00 -0230"
+        );
+        let author_regex = regex::Regex::new("@example\\.com>$").unwrap();
+        assert_eq!(
+            parse_blame_dates(porcelain, DateSource::Author, Some(&author_regex)),
+            [("aaaa".to_owned(), "2015-01-01 01:00:00 +0100".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blame_non_utf8_file() {
+        let repo_dir = init_repo("git_copyright_test_blame_latin1");
+        std::fs::write(repo_dir.join("a.txt"), b"caf\xe9\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add a.txt");
+
+        let (log_options, ignored_revs) = (LogOptions::default(), IgnoredRevs::default());
+        let years = |filepath| {
+            get_blame_years_for_file(
+                filepath,
+                repo_dir.to_str().unwrap(),
+                None,
+                false,
+                YearFormat::Range,
+                &log_options,
+                None,
+                &ignored_revs,
+            )
+        };
+        assert_eq!(years("a.txt").await.unwrap(), "2015");
+        // Files without history get the current year
+        let current_year = chrono::Utc::now().format("%Y").to_string();
+        assert_eq!(years("b.txt").await.unwrap(), current_year);
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_renames_and_copies() {
+        let repo_dir = init_repo("git_copyright_test_renames");
+        let content = "fn main() {\n    println!(\"Hello\");\n}\n";
+        std::fs::write(repo_dir.join("a.rs"), content).unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add a.rs");
+        git(&repo_dir, &["mv", "a.rs", "b.rs"]);
+        commit_all(&repo_dir, "2017-01-01T00:00:00+0000", "Rename a.rs");
+        std::fs::write(repo_dir.join("c.rs"), content).unwrap();
+        commit_all(&repo_dir, "2019-01-01T00:00:00+0000", "Copy b.rs");
+
+        let repo_path = repo_dir.to_str().unwrap();
+        for backend in [
+            GitBackend::Cli,
+            #[cfg(feature = "git2")]
+            GitBackend::Libgit2,
+        ] {
+            let years = |renames, detect_copies, filepath: &str| {
+                let log_options = LogOptions {
+                    renames,
+                    detect_copies,
+                    ..Default::default()
+                };
+                let ignored_revs = IgnoredRevs::default();
+                let filepath = filepath.to_owned();
+                async move {
+                    let dates = backend
+                        .get_commit_dates_by_file(repo_path, &log_options, &ignored_revs)
+                        .await
+                        .unwrap();
+                    dates[&filepath]
+                        .iter()
+                        .map(|date| date[..4].to_owned())
+                        .collect::<Vec<_>>()
+                }
+            };
+            assert_eq!(
+                years(Renames::Follow, false, "b.rs").await,
+                ["2017", "2015"]
+            );
+            assert_eq!(years(Renames::Ignore, false, "b.rs").await, ["2017"]);
+            assert_eq!(years(Renames::Follow, false, "c.rs").await, ["2019"]);
+            assert_eq!(
+                years(Renames::Follow, true, "c.rs").await,
+                ["2019", "2017", "2015"]
+            );
+            // The source of a copy keeps its history
+            assert_eq!(years(Renames::Follow, true, "b.rs").await, ["2017", "2015"]);
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_for_changes() {
+        let repo_dir = init_repo("git_copyright_test_changes");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(repo_dir.join("b.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add files");
+
+        let repo_path = repo_dir.to_str().unwrap();
+        for backend in [
+            GitBackend::Cli,
+            #[cfg(feature = "git2")]
+            GitBackend::Libgit2,
+        ] {
+            // Changes from before the run are not attributed to it
+            std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n\n").unwrap();
+            let before = backend.snapshot_changes(repo_path).await.unwrap();
+            assert!(before.contains("a.rs") && !before.contains("b.rs"));
+            let check = |fail_on_diff| backend.check_for_changes(repo_path, &before, fail_on_diff);
+            check(true).await.unwrap();
+
+            std::fs::write(repo_dir.join("b.rs"), "// Copyright\nfn main() {}\n").unwrap();
+            assert!(matches!(check(true).await, Err(CError::FilesChanged)));
+            check(false).await.unwrap();
+            git(&repo_dir, &["checkout", "--", "b.rs"]);
+
+            // Further changes of files changed before the run are attributed to it
+            std::fs::write(repo_dir.join("a.rs"), "// Copyright\nfn main() {}\n\n").unwrap();
+            assert!(matches!(check(true).await, Err(CError::FilesChanged)));
+            git(&repo_dir, &["checkout", "--", "a.rs"]);
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_history_rewrites() {
+        let repo_dir = init_repo("git_copyright_test_history_rewrites");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add a.rs");
+
+        let repo_path = repo_dir.to_str().unwrap();
+        let options = |fail_on_rewritten_history| crate::CheckOptions {
+            fail_on_rewritten_history,
+            ..Default::default()
+        };
+        for backend in [
+            GitBackend::Cli,
+            #[cfg(feature = "git2")]
+            GitBackend::Libgit2,
+        ] {
+            assert!(backend
+                .detect_history_rewrites(repo_path)
+                .await
+                .unwrap()
+                .is_empty());
+            options(true)
+                .check_history(repo_path, backend)
+                .await
+                .unwrap();
+        }
+
+        git(
+            &repo_dir,
+            &["update-ref", "refs/original/refs/heads/main", "HEAD"],
+        );
+        std::fs::write(repo_dir.join(".git/filter-repo"), "").unwrap();
+        for backend in [
+            GitBackend::Cli,
+            #[cfg(feature = "git2")]
+            GitBackend::Libgit2,
+        ] {
+            assert_eq!(
+                backend.detect_history_rewrites(repo_path).await.unwrap(),
+                [
+                    "the history was rewritten with git filter-repo",
+                    "the history was rewritten with git filter-branch"
+                ]
+            );
+            options(false)
+                .check_history(repo_path, backend)
+                .await
+                .unwrap();
+            assert!(matches!(
+                options(true).check_history(repo_path, backend).await,
+                Err(CError::HistoryRewritten(_))
+            ));
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mailmap() {
+        let repo_dir = init_repo("git_copyright_test_mailmap");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(repo_dir.join(".mailmap"), "New Name <dummy@example.com>\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add a.rs");
+
+        let repo_path = repo_dir.to_str().unwrap();
+        let ignored_revs = IgnoredRevs::default();
+        for (use_mailmap, author) in [(true, "New Name"), (false, "Dummy")] {
+            let log_options = LogOptions {
+                use_mailmap,
+                ..Default::default()
+            };
+            let authors = get_author_dates_for_file("a.rs", repo_path, &log_options, &ignored_revs);
+            let authors = authors.await.unwrap();
+            assert_eq!(authors.len(), 1);
+            assert_eq!(authors[0].0, author);
+
+            // Authors are filtered by their mapped identity
+            let log_options = LogOptions {
+                author_filter: Some("^New Name ".to_owned()),
+                ..log_options
+            };
+            for backend in [
+                GitBackend::Cli,
+                #[cfg(feature = "git2")]
+                GitBackend::Libgit2,
+            ] {
+                let dates = backend
+                    .get_commit_dates_by_file(repo_path, &log_options, &ignored_revs)
+                    .await
+                    .unwrap();
+                assert_eq!(dates.contains_key("a.rs"), use_mailmap);
+            }
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_repo_years() {
+        let repo_dir = init_repo("git_copyright_test_repo_years");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add a.rs");
+        std::fs::write(repo_dir.join("b.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo_dir, "2018-01-01T00:00:00+0000", "Add b.rs");
+
+        let (log_options, ignored_revs) = (LogOptions::default(), IgnoredRevs::default());
+        let years = |year_format| {
+            get_repo_years(
+                repo_dir.to_str().unwrap(),
+                "HEAD",
+                year_format,
+                &log_options,
+                &ignored_revs,
+            )
+        };
+        assert_eq!(years(YearFormat::Range).await.unwrap(), "2015-2018");
+        assert_eq!(years(YearFormat::List).await.unwrap(), "2015, 2018");
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_history_ref() {
+        let repo_dir = init_repo("git_copyright_test_history_ref");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add a.rs");
+        git(&repo_dir, &["branch", "release"]);
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        commit_all(&repo_dir, "2018-01-01T00:00:00+0000", "Run");
+
+        let repo_path = repo_dir.to_str().unwrap();
+        let ignored_revs = IgnoredRevs::default();
+        for (history_ref, years, commit_years) in [
+            ("HEAD", "2015-2018", vec!["2018", "2015"]),
+            ("release", "2015", vec!["2015"]),
+        ] {
+            let log_options = LogOptions {
+                history_ref: history_ref.to_owned(),
+                ..Default::default()
+            };
+            let file_years = get_added_mod_times_for_file(
+                "a.rs",
+                repo_path,
+                None,
+                false,
+                YearFormat::Range,
+                &log_options,
+                &ignored_revs,
+            );
+            assert_eq!(file_years.await, years);
+            for backend in [
+                GitBackend::Cli,
+                #[cfg(feature = "git2")]
+                GitBackend::Libgit2,
+            ] {
+                let dates = backend
+                    .get_commit_dates_by_file(repo_path, &log_options, &ignored_revs)
+                    .await
+                    .unwrap();
+                let dates: Vec<_> = dates["a.rs"].iter().map(|date| &date[..4]).collect();
+                assert_eq!(dates, commit_years);
+            }
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_last_modified_date() {
+        let repo_dir = init_repo("git_copyright_test_last_modified");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(repo_dir.join("b.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add files");
+        std::fs::write(repo_dir.join("b.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        commit_all(&repo_dir, "2021-06-01T00:00:00+0000", "Run");
+
+        let repo_path = repo_dir.to_str().unwrap();
+        for backend in [
+            GitBackend::Cli,
+            #[cfg(feature = "git2")]
+            GitBackend::Libgit2,
+        ] {
+            let date = |filepath| backend.get_last_modified_date(filepath, repo_path);
+            assert_eq!(date("a.rs").await.unwrap().as_deref(), Some("2015-01-01"));
+            assert_eq!(date("b.rs").await.unwrap().as_deref(), Some("2021-06-01"));
+            assert_eq!(date("c.rs").await.unwrap(), None);
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_changed_since_merge_base() {
+        let repo_dir = init_repo("git_copyright_test_merge_base");
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(repo_dir.join("b.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(repo_dir.join("c.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        commit_all(&repo_dir, "2015-01-01T00:00:00+0000", "Add files");
+        git(&repo_dir, &["branch", "base"]);
+        git(&repo_dir, &["checkout", "-q", "-b", "feature"]);
+        std::fs::write(repo_dir.join("a.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        std::fs::write(repo_dir.join("d.rs"), "fn main() {}\n").unwrap();
+        std::fs::rename(repo_dir.join("c.rs"), repo_dir.join("e.rs")).unwrap();
+        std::fs::remove_file(repo_dir.join("b.rs")).unwrap();
+        commit_all(&repo_dir, "2016-01-01T00:00:00+0000", "Change files");
+        // Changes on the base after the branch diverged do not count
+        git(&repo_dir, &["checkout", "-q", "base"]);
+        std::fs::write(repo_dir.join("f.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo_dir, "2017-01-01T00:00:00+0000", "Add f.rs");
+        git(&repo_dir, &["checkout", "-q", "feature"]);
+
+        let repo_path = repo_dir.to_str().unwrap();
+        for backend in [
+            GitBackend::Cli,
+            #[cfg(feature = "git2")]
+            GitBackend::Libgit2,
+        ] {
+            let changed = backend.get_changed_since_merge_base(repo_path, "base");
+            let mut changed: Vec<_> = changed.await.unwrap().into_iter().collect();
+            changed.sort();
+            assert_eq!(changed, ["a.rs", "d.rs", "e.rs"]);
+        }
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_whitespace_changes() {
+        let log = "\0aaa 2023-05-01 10:00:00 +0200\n\n\
+                   \0bbb 2022-03-01 10:00:00 +0100\n\n1\t1\tsrc/main.rs\n\
+                   \0ccc 2021-02-01 10:00:00 +0100\n\n3\t0\tmain.rs\n";
+        assert_eq!(
+            substantial_commits(log, true),
+            [
+                "bbb 2022-03-01 10:00:00 +0100",
+                "ccc 2021-02-01 10:00:00 +0100"
+            ]
+        );
+        assert_eq!(substantial_commits(log, false).len(), 3);
+        assert_eq!(
+            substantial_commits("\0aaa 2023-05-01 10:00:00 +0200\n", true),
+            ["aaa 2023-05-01 10:00:00 +0200"]
+        );
+
+        let changed = parse_numstat_log("\0aaa\n\0bbb\n1\t1\tsrc/main.rs\n-\t-\tlogo.png\n");
+        assert!(!changed.contains_key("aaa"));
+        assert!(changed["bbb"].contains("src/main.rs") && changed["bbb"].contains("logo.png"));
+    }
+
+    #[test]
+    fn test_author_log() {
+        let reformat = "0123456789abcdef0123456789abcdef01234567";
+        let log = format!(
+            "{reformat}\t2024-01-01 10:00:00 +0100\tCarol\n\
+             ccc\t2023-05-01 10:00:00 +0200\tAlice\n\
+             bbb\t2022-03-01 10:00:00 +0100\tBob\n\
+             bbb\t2022-03-01 10:00:00 +0100\tBob\n\
+             aaa\t2021-02-01 10:00:00 +0100\tAlice\n"
+        );
+        let commits: Vec<&str> = log.lines().collect();
+        let authors = parse_author_log(&commits, &IgnoredRevs::parse(reformat));
+        assert_eq!(
+            authors,
+            [
+                (
+                    "Alice".to_owned(),
+                    vec![
+                        "2023-05-01 10:00:00 +0200".to_owned(),
+                        "2021-02-01 10:00:00 +0100".to_owned()
+                    ]
+                ),
+                (
+                    "Bob".to_owned(),
+                    vec!["2022-03-01 10:00:00 +0100".to_owned()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_checked_commit_note() {
+        let checked = CheckedCommit::parse("abc", "2019-2022\tsrc/main.rs\n2022\tmy file.sh\n");
+        assert_eq!(checked.years["src/main.rs"], "2019-2022");
+        assert_eq!(checked.years["my file.sh"], "2022");
+        assert_eq!(
+            checked.to_note(),
+            "2019-2022\tsrc/main.rs\n2022\tmy file.sh\n"
+        );
+        assert_eq!(CheckedCommit::parse("abc", &checked.to_note()), checked);
+    }
+
+    #[test]
+    fn test_modified_since() {
+        assert!(modified_since("2022-03-01", "2022"));
+        assert!(modified_since("2023-01-01", "2022-06-01"));
+        assert!(!modified_since("2022-03-01", "2022-06-01"));
+        assert!(!modified_since("2021-12-31", "2022"));
+    }
+}
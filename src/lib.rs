@@ -1,84 +1,1870 @@
 //! Extract added/last modified times from git history and add/update copyright note.
 
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod checker;
 pub mod config;
+pub mod doctor;
+pub mod editorconfig;
 pub mod error;
 pub mod file_ops;
+pub mod fs;
+#[cfg(feature = "git2")]
+pub mod git2_ops;
 pub mod git_ops;
+pub mod lsp;
+pub mod nested;
+pub mod policy;
 pub mod regex_ops;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod report;
+pub mod sarif;
+pub mod templates;
+pub mod years;
 
+pub use checker::CopyrightChecker;
 pub use config::Config;
+use config::{GitBackend, HistoryTraversal, YearSource};
+use editorconfig::{EditorConfig, EditorSettings};
 pub use error::CError;
-use file_ops::read_write_copyright;
+pub use error::FileFailure;
+pub use file_ops::WriteMode;
+use file_ops::{anchor_lines, MAX_BANNER_LINES};
+use file_ops::{read_migrate_copyright, read_remove_copyright, read_write_copyright};
+use file_ops::{read_write_sidecar, read_write_special_file, WriteOptions};
+use fs::{modified_time, set_modified_time, walk_files, FileSystem, LocalFs};
 use futures::future::join_all;
-use futures::FutureExt;
-use git_ops::check_for_changes;
-use git_ops::get_added_mod_times_for_file;
-use git_ops::get_files_on_ref;
-use regex_ops::CopyrightCache;
-use regex_ops::{generate_base_regex, generate_copyright_line};
+use futures::{stream, StreamExt};
+#[cfg(feature = "remote")]
+use git_ops::years_from_commit_years;
+use git_ops::{clamp_years, get_repo_years, modified_since};
+use git_ops::{get_changed_since, get_commit_hash, CheckedCommit};
+use git_ops::{open_years, start_year};
+use git_ops::{ChangedFiles, HistoryCutoffs, IgnoredRevs, LogOptions};
+use indicatif::{ProgressBar, ProgressStyle};
+use nested::{closest_config, load_nested_configs, NestedConfig};
+use policy::{PolicyRule, PolicyViolation};
+use regex::Regex;
+use regex_ops::{generate_base_regex, CopyrightCache, HolderRegexes};
+use regex_ops::{generate_copyright_line, generate_spdx_line, years_regex, HolderParser};
+pub use report::CheckReport;
+pub use report::OutputFormat;
+use report::{coverage, format_report, write_report, FileOutcome, FileStatus};
+use report::{markdown_summary, write_step_summary};
 use serde::Deserialize;
 use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::Hasher;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tokio::sync::Semaphore;
+use years::{BatchedGitYears, GitYears, YearProvider};
 
-#[derive(Debug, Deserialize, Hash, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Hash, PartialEq)]
 #[serde(untagged)]
 pub enum CommentSign {
     LeftOnly(String),
     Enclosing(String, String),
+    /// Comment sign(s) with a custom separator to the copyright text.
+    Detailed {
+        left: String,
+        #[serde(default)]
+        right: Option<String>,
+        #[serde(default = "default_separator")]
+        separator: String,
+        /// Separator between the copyright text and the right comment sign,
+        /// `separator` if not given.
+        #[serde(default)]
+        right_separator: Option<String>,
+    },
 }
 
+impl CommentSign {
+    pub fn left(&self) -> &str {
+        match self {
+            CommentSign::LeftOnly(left) => left,
+            CommentSign::Enclosing(left, _) => left,
+            CommentSign::Detailed { left, .. } => left,
+        }
+    }
+
+    pub fn right(&self) -> Option<&str> {
+        match self {
+            CommentSign::LeftOnly(_) => None,
+            CommentSign::Enclosing(_, right) => Some(right),
+            CommentSign::Detailed { right, .. } => right.as_deref(),
+        }
+    }
+
+    /// Separator between the comment sign(s) and the copyright text.
+    pub fn separator(&self) -> &str {
+        match self {
+            CommentSign::Detailed { separator, .. } => separator,
+            _ => " ",
+        }
+    }
+
+    /// Separator between the copyright text and the right comment sign.
+    pub fn right_separator(&self) -> &str {
+        match self {
+            CommentSign::Detailed {
+                right_separator: Some(right_separator),
+                ..
+            } => right_separator,
+            _ => self.separator(),
+        }
+    }
+}
+
+fn default_separator() -> String {
+    " ".into()
+}
+
+/// Options of a copyright check run.
+#[derive(Debug, Default, Clone)]
+pub struct CheckOptions {
+    /// Fail if the run changed tracked files, e.g. to verify in CI that
+    /// copyrights were fixed locally.
+    pub fail_on_diff: bool,
+    pub format: OutputFormat,
+    pub write_mode: WriteMode,
+    /// Write the results in `format` to this file and print human-readable
+    /// results to stdout instead.
+    pub report_file: Option<PathBuf>,
+    /// Minimum percentage of files with a correct copyright. If set, the
+    /// check fails only if the coverage is below it, not on changed files.
+    pub min_coverage: Option<f64>,
+    /// Check all files first and write them only after listing the pending
+    /// modifications and asking for confirmation.
+    pub defer_writes: bool,
+    /// Write deferred modifications without asking for confirmation.
+    pub assume_yes: bool,
+    /// Asked whether to write the pending modifications unless `assume_yes`
+    /// is set, e.g. with a prompt on the terminal. Without it, deferred
+    /// modifications are only written with `assume_yes`.
+    pub confirm_writes: Option<fn(&[&FileOutcome]) -> bool>,
+    /// Only check files with one of these extensions if not empty.
+    pub extensions: Vec<String>,
+    /// Do not check files with one of these extensions.
+    pub excluded_extensions: Vec<String>,
+    /// Fail instead of warning if the history was rewritten or grafted so
+    /// that the computed years may be wrong.
+    pub fail_on_rewritten_history: bool,
+    /// Append a markdown summary to `$GITHUB_STEP_SUMMARY` in GitHub Actions.
+    pub step_summary: bool,
+    /// Skip files which are unchanged since the last fully checked commit
+    /// and note `HEAD` as checked, see `CheckedCommit`.
+    pub incremental: bool,
+    /// Check that the lines of the header template follow the copyright
+    /// verbatim and restore them when fixing.
+    pub verify_template: bool,
+    /// Only check files added or modified since `HEAD` diverged from this
+    /// ref, e.g. the target branch of a pull request.
+    pub since_ref: Option<String>,
+    /// Print nothing to stdout, e.g. when rendering the `CheckReport` of
+    /// `check_repo_report` instead.
+    pub quiet: bool,
+    /// Prints the text of machine-readable results and documents as is, e.g.
+    /// to stdout in the CLI. Without it, they are only written to the
+    /// `report_file` or rendered from the `CheckReport`.
+    pub print_results: Option<fn(&str)>,
+    /// Maximum number of files checked at once, which bounds the number of
+    /// git processes and open files, e.g. to stay below `ulimit -n`.
+    pub jobs: Option<usize>,
+    /// Show a progress bar of the checked files on stderr if it is a
+    /// terminal and results are printed as human-readable sentences.
+    pub progress: bool,
+}
+
+impl CheckOptions {
+    /// Format of the results printed to stdout.
+    fn stdout_format(&self) -> OutputFormat {
+        match self.report_file {
+            Some(_) => OutputFormat::Human,
+            None => self.format,
+        }
+    }
+
+    /// Whether progress and results are printed as human-readable sentences.
+    fn prints_progress(&self) -> bool {
+        !self.quiet && self.stdout_format() == OutputFormat::Human
+    }
+
+    /// Progress bar of checked files, hidden without `progress` or if results
+    /// are not printed as human-readable sentences.
+    fn progress_bar(&self) -> ProgressBar {
+        match self.progress && self.prints_progress() {
+            true => ProgressBar::new(0).with_style(
+                ProgressStyle::with_template("{bar:40} {pos}/{len} files ({eta} left)")
+                    .expect("Invalid progress bar template"),
+            ),
+            false => ProgressBar::hidden(),
+        }
+    }
+
+    /// Maximum number of files checked at once, at least one.
+    fn max_jobs(&self) -> usize {
+        self.jobs
+            .unwrap_or(CONCURRENT_SHARDS * CONCURRENT_FILES_PER_SHARD)
+            .max(1)
+    }
+
+    /// Whether the extension filters let the file be checked.
+    fn extension_included(&self, filepath: &str) -> bool {
+        let ext = Path::new(filepath)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let matches = |extensions: &[String]| {
+            extensions
+                .iter()
+                .any(|filter_ext| filter_ext.trim_start_matches('.') == ext)
+        };
+        (self.extensions.is_empty() || matches(&self.extensions))
+            && !matches(&self.excluded_extensions)
+    }
+
+    /// Warn or fail if the history of the repository may yield wrong years.
+    async fn check_history(&self, repo_path: &str, backend: GitBackend) -> Result<(), CError> {
+        let reasons = backend.detect_history_rewrites(repo_path).await?;
+        if reasons.is_empty() {
+            return Ok(());
+        }
+
+        let reasons = reasons.join(", ");
+        match self.fail_on_rewritten_history {
+            true => Err(CError::HistoryRewritten(reasons)),
+            false => {
+                log::warn!("Computed years may be unreliable because {}", reasons);
+                Ok(())
+            }
+        }
+    }
+
+    /// Print all outcomes at once if the stdout format is a document.
+    fn print_document(&self, outcomes: &[FileOutcome]) {
+        let format = self.stdout_format();
+        if format.is_document() {
+            self.print(&format_report(
+                outcomes,
+                format,
+                self.write_mode != WriteMode::Never,
+            ));
+        }
+    }
+
+    /// Print the outcome of a file in the stdout format.
+    ///
+    /// Human-readable messages are logged like other progress, lines of
+    /// machine-readable formats are printed with `print_results`. Files with a
+    /// correct copyright are only logged at debug level.
+    fn print_outcome(&self, outcome: &FileOutcome) {
+        if self.quiet {
+            return;
+        }
+        let format = self.stdout_format();
+        match (outcome.message(format), format) {
+            (Some(message), OutputFormat::Human) => log::info!("{}", message),
+            (Some(message), _) => self.print(&format!("{}\n", message)),
+            (None, _) => log::debug!("{}", outcome.human_message()),
+        }
+    }
+
+    fn print(&self, text: &str) {
+        if let (Some(print_results), false) = (self.print_results, self.quiet) {
+            print_results(text);
+        }
+    }
+
+    fn write_report(&self, outcomes: &[FileOutcome]) -> Result<(), CError> {
+        match &self.report_file {
+            Some(report_file) => write_report(
+                report_file,
+                outcomes,
+                self.format,
+                self.write_mode != WriteMode::Never,
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CheckOptions {
+    /// Print and write the documents, reports and summaries of a run.
+    fn report_results(&self, results: &CheckReport) -> Result<(), CError> {
+        self.print_document(&results.outcomes);
+        self.write_report(&results.outcomes)?;
+        if self.step_summary {
+            let fixed = self.write_mode != WriteMode::Never;
+            write_step_summary(&markdown_summary(
+                &results.outcomes,
+                &results.violations,
+                fixed,
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the repository with the global config.
+///
+/// Prefer `CopyrightChecker::check_repo`, which owns its config.
 pub async fn check_repo_copyright(
     repo_path_str: &str,
     name: &str,
-    fail_on_diff: bool,
+    options: &CheckOptions,
+) -> Result<(), CError> {
+    run_check(
+        Config::global(),
+        repo_path_str,
+        name,
+        options,
+        None::<&GitYears>,
+    )
+    .await
+}
+
+/// Check the repository with the global config and return the outcomes of
+/// all files, see `CopyrightChecker::check_repo_report`.
+pub async fn check_repo_report(
+    repo_path_str: &str,
+    name: &str,
+    options: &CheckOptions,
+) -> Result<CheckReport, CError> {
+    run_report(Config::global(), repo_path_str, name, options).await
+}
+
+/// Check the repository with the years of files taken from `years` instead of
+/// the git history, with the global config.
+pub async fn check_repo_copyright_with_years(
+    repo_path_str: &str,
+    name: &str,
+    options: &CheckOptions,
+    years: &impl YearProvider,
+) -> Result<(), CError> {
+    run_check(Config::global(), repo_path_str, name, options, Some(years)).await
+}
+
+/// Check the repository and report the results.
+async fn run_check<Y: YearProvider>(
+    config: &Config,
+    repo_path_str: &str,
+    name: &str,
+    options: &CheckOptions,
+    years: Option<&Y>,
+) -> Result<(), CError> {
+    let mut results = CheckReport::default();
+    let result = check_repo(config, repo_path_str, name, options, years, &mut results).await;
+    options.report_results(&results)?;
+    result
+}
+
+/// Check the repository and return the outcomes of all files instead of
+/// failing on missing or outdated copyrights, policy violations or files
+/// which could not be checked.
+async fn run_report(
+    config: &Config,
+    repo_path_str: &str,
+    name: &str,
+    options: &CheckOptions,
+) -> Result<CheckReport, CError> {
+    let mut report = CheckReport::default();
+    let result = check_repo(
+        config,
+        repo_path_str,
+        name,
+        options,
+        None::<&GitYears>,
+        &mut report,
+    )
+    .await;
+    options.report_results(&report)?;
+    match result {
+        Err(CError::FixError(failures)) => report.failures = failures,
+        Err(
+            CError::PolicyViolations(_)
+            | CError::IncorrectCopyrights(_)
+            | CError::CoverageTooLow { .. },
+        ) => {}
+        result => result?,
+    }
+    Ok(report)
+}
+
+/// Check several repositories one after another with their own config and
+/// report the results together.
+///
+/// The name in copyrights is `name` or, if not given, the `name` of the
+/// config of each repository. A failing repository does not stop the checks
+/// of the others.
+pub async fn check_repos_copyright(
+    repos: Vec<(String, Config)>,
+    name: Option<&str>,
+    options: &CheckOptions,
+) -> Result<(), CError> {
+    let mut results = CheckReport::default();
+    let mut failed = Vec::new();
+    for (repo_path, config) in repos {
+        if options.prints_progress() {
+            log::info!("Checking repository {}", repo_path);
+        }
+
+        let result = match name.or_else(|| config.name()) {
+            Some(name) => {
+                check_repo(
+                    &config,
+                    &repo_path,
+                    name,
+                    options,
+                    None::<&GitYears>,
+                    &mut results,
+                )
+                .await
+            }
+            None => Err(CError::ConfigError(
+                "No name in copyrights given with `--name` or in the config".to_owned(),
+            )),
+        };
+        if let Err(error) = result {
+            failed.push(FileFailure {
+                filepath: repo_path,
+                error,
+            });
+        }
+    }
+    options.report_results(&results)?;
+
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(CError::RepositoriesFailed(failed)),
+    }
+}
+
+async fn check_repo<Y: YearProvider>(
+    config: &Config,
+    repo_path_str: &str,
+    name: &str,
+    options: &CheckOptions,
+    years: Option<&Y>,
+    results: &mut CheckReport,
 ) -> Result<(), CError> {
-    let config = Config::global();
     let repo_path = Path::new(repo_path_str);
-    let files_to_check = get_files_on_ref(repo_path_str, "HEAD").await?;
-    let files_to_check: Vec<&String> = config
-        .filter_files(files_to_check.iter())
-        .into_iter()
+    // Without git repository, files are listed from disk and get the years
+    // of their timestamps
+    let backend = config.git_backend();
+    let in_git = !config.mtime_fallback() || backend.is_git_repo(repo_path_str).await;
+    let (changed_before, files_on_ref) = match in_git {
+        true => {
+            backend.check_git_version().await?;
+            options.check_history(repo_path_str, backend).await?;
+            (
+                backend.snapshot_changes(repo_path_str).await?,
+                backend
+                    .get_files_on_ref(repo_path_str, config.history_ref())
+                    .await?,
+            )
+        }
+        false => {
+            log::info!(
+                "{} is not a git repository, using file timestamps",
+                repo_path_str
+            );
+            (ChangedFiles::default(), walk_files(repo_path)?)
+        }
+    };
+
+    // Special files are handled by their rule even if they would be ignored
+    let (mut special_files, other_files): (Vec<&String>, Vec<&String>) = files_on_ref
+        .iter()
         .filter(|f| repo_path.join(Path::new(f)).is_file())
+        .partition(|f| config.get_special_file_rule(f).is_some());
+    // Files below nested configs are only filtered by their ignore patterns
+    let nested = load_nested_configs(config, repo_path, &files_on_ref)?;
+    let (root_files, ignore_stats) = config.filter_files_with_stats(
+        other_files
+            .iter()
+            .copied()
+            .filter(|f| closest_config(&nested, f).is_none()),
+    );
+    let root_files: BTreeSet<&String> = root_files.into_iter().collect();
+    let mut files_to_check: Vec<&String> = other_files
+        .into_iter()
+        .filter(|f| match closest_config(&nested, f) {
+            Some(nested) => !nested
+                .config
+                .is_ignored(nested.relative_path(f).unwrap_or(f)),
+            None => root_files.contains(f),
+        })
         .collect();
+    for (pattern, count) in ignore_stats.counts.iter() {
+        log::debug!("Ignore pattern {} excluded {} files", pattern, count);
+    }
+    for pattern in ignore_stats.unused_patterns() {
+        log::debug!("Ignore pattern {} did not exclude any file", pattern);
+    }
+    files_to_check.retain(|f| options.extension_included(f));
+    // Sidecar files are checked with the file they belong to
+    if config.reuse() {
+        files_to_check.retain(|f| !f.ends_with(&format!(".{}", SIDECAR_EXTENSION)));
+    }
+    // Files which must not have a header are only verified by their policy
+    let (forbidden_files, mut files_to_check): (Vec<&String>, Vec<&String>) =
+        files_to_check.into_iter().partition(|f| {
+            let holder = config.holder_for(f).unwrap_or(name);
+            policy::forbids_header(config.policies(), f, holder)
+        });
+    if let (Some(since), true) = (config.modified_since(), in_git) {
+        files_to_check = filter_modified_since(
+            files_to_check,
+            repo_path_str,
+            since,
+            backend,
+            options.max_jobs(),
+        )
+        .await?;
+    }
+    if let (Some(since_ref), true) = (&options.since_ref, in_git) {
+        let changed = backend
+            .get_changed_since_merge_base(repo_path_str, since_ref)
+            .await?;
+        files_to_check.retain(|f| changed.contains(f.as_str()));
+        special_files.retain(|f| changed.contains(f.as_str()));
+    }
+    let editorconfig = EditorConfig::load(repo_path, files_on_ref.iter());
 
-    println!("Checking {} files", files_to_check.len());
+    let mut skipped = Vec::new();
+    if options.incremental && in_git {
+        if let Some(checked) = CheckedCommit::read(repo_path_str).await? {
+            let changed = get_changed_since(repo_path_str, &checked.commit).await?;
+            files_to_check.retain(|f| match checked.years.get(f.as_str()) {
+                Some(years) if !changed.contains(f.as_str()) && !changed_before.contains(f) => {
+                    skipped.push(FileOutcome {
+                        filepath: repo_path.join(f),
+                        status: FileStatus::Correct,
+                        line_nr: None,
+                        expected_years: years.clone(),
+                    });
+                    false
+                }
+                _ => true,
+            });
+            if options.prints_progress() {
+                log::info!(
+                    "Skipping {} files unchanged since checked commit {}",
+                    skipped.len(),
+                    checked.commit
+                );
+            }
+        }
+    }
 
-    let base_regex = generate_base_regex(name);
-    let regex_cache = CopyrightCache::new(&base_regex);
+    if options.prints_progress() {
+        log::info!(
+            "Checking {} files",
+            files_to_check.len() + special_files.len()
+        );
+    }
 
-    let check_and_fix_futures: Vec<_> = files_to_check
-        .iter()
-        .map(|filepath| check_file_copyright(filepath, repo_path_str, name, &regex_cache))
+    let nested = nested
+        .into_iter()
+        .map(|nested| {
+            let regexes = HolderRegexes::new(name, &nested.config);
+            (nested, regexes)
+        })
         .collect();
+    let log_options = LogOptions::from_config(config);
+    let run = RepoRun {
+        config,
+        repo_path: repo_path_str,
+        regexes: HolderRegexes::new(name, config),
+        nested,
+        editorconfig,
+        // Blame runs git for each file, libgit2 otherwise always traverses the
+        // history once
+        years: match (years, in_git, config.history_traversal(), backend) {
+            (Some(years), _, _, _) => RunYears::Custom(years),
+            (None, true, HistoryTraversal::Batched, _) | (None, true, _, GitBackend::Libgit2)
+                if config.year_source() == YearSource::Log =>
+            {
+                RunYears::Batched(
+                    BatchedGitYears::load(
+                        repo_path_str,
+                        backend,
+                        &log_options,
+                        &backend.load_ignored_revs(repo_path_str).await?,
+                        backend
+                            .load_history_cutoffs(
+                                repo_path_str,
+                                config.history_cutoffs(),
+                                &log_options,
+                            )
+                            .await?,
+                        config.mtime_fallback(),
+                    )
+                    .await?
+                    .with_year_format(config.year_format()),
+                )
+            }
+            (None, true, _, _) => RunYears::Git(
+                GitYears::new(
+                    repo_path_str,
+                    backend
+                        .load_history_cutoffs(repo_path_str, config.history_cutoffs(), &log_options)
+                        .await?,
+                    config.mtime_fallback(),
+                )
+                .with_year_format(config.year_format())
+                .with_year_source(config.year_source())
+                .with_log_options(log_options.clone())
+                .with_ignored_revs(backend.load_ignored_revs(repo_path_str).await?),
+            ),
+            (None, false, _, _) => RunYears::Git(
+                GitYears::new(
+                    repo_path_str,
+                    HistoryCutoffs::default(),
+                    config.mtime_fallback(),
+                )
+                .with_year_format(config.year_format()),
+            ),
+        },
+        // Authors are always read with git
+        authors: match (years, in_git && config.author_lines()) {
+            (None, true) => Some(
+                GitYears::new(
+                    repo_path_str,
+                    backend
+                        .load_history_cutoffs(repo_path_str, config.history_cutoffs(), &log_options)
+                        .await?,
+                    false,
+                )
+                .with_year_format(config.year_format())
+                .with_log_options(log_options.clone())
+                .with_ignored_revs(backend.load_ignored_revs(repo_path_str).await?),
+            ),
+            _ => None,
+        },
+        verify_template: options.verify_template,
+        jobs: Semaphore::new(options.max_jobs()),
+        progress: options.progress_bar(),
+    };
 
-    let results = join_all(check_and_fix_futures).await;
-    let failed: Vec<_> = results.iter().filter(|res| res.is_err()).collect();
-    failed.iter().for_each(|res_err| {
-        println!("Error: {}", res_err.as_ref().unwrap_err());
-    });
+    let write_mode = match options.defer_writes {
+        true => WriteMode::Never,
+        false => options.write_mode,
+    };
+    let (mut outcomes, failures) = run
+        .check_files(&files_to_check, &special_files, write_mode)
+        .await;
+    outcomes.extend(skipped);
+    for outcome in outcomes.iter() {
+        print_outcome(outcome, options, config);
+    }
+    results.outcomes.extend(outcomes.iter().cloned());
+
+    if !failures.is_empty() {
+        return Err(CError::FixError(failures));
+    }
+
+    // Declined modifications stay unfixed, which fails like a check
+    let written = match options.defer_writes {
+        true => {
+            let pending: Vec<&FileOutcome> = outcomes
+                .iter()
+                .filter(|outcome| options.write_mode.writes(&outcome.status))
+                .collect();
+            write_pending(&run, &pending, options).await?
+        }
+        false => true,
+    };
+
+    if options.incremental && in_git && written {
+        note_checked_commit(repo_path, &outcomes, &changed_before).await?;
+    }
+
+    let violations = check_policies(
+        config,
+        repo_path,
+        name,
+        files_to_check.iter().chain(forbidden_files.iter()),
+    )
+    .await?;
+    for violation in violations.iter().filter(|_| !options.quiet) {
+        log::warn!("{}", violation.human_message());
+    }
+    results.violations.extend(violations.iter().cloned());
+
+    if in_git {
+        backend
+            .check_for_changes(
+                repo_path_str,
+                &changed_before,
+                options.fail_on_diff && options.min_coverage.is_none(),
+            )
+            .await?;
+    }
 
-    if !failed.is_empty() {
-        return Err(CError::FixError);
+    if !violations.is_empty() {
+        return Err(CError::PolicyViolations(violations.len()));
     }
 
-    check_for_changes(repo_path_str, fail_on_diff).await?;
+    check_coverage(
+        &outcomes,
+        options.min_coverage,
+        options.write_mode == WriteMode::Never || !written,
+    )
+}
+
+/// Fail if the coverage is below the minimum or, when only checking, on any
+/// missing or outdated copyright.
+fn check_coverage(
+    outcomes: &[FileOutcome],
+    min_coverage: Option<f64>,
+    check_only: bool,
+) -> Result<(), CError> {
+    if let Some(min_coverage) = min_coverage {
+        let coverage = coverage(outcomes);
+        log::info!("Copyright coverage is {:.1}%", coverage);
+        if coverage < min_coverage {
+            return Err(CError::CoverageTooLow {
+                coverage,
+                min_coverage,
+            });
+        }
+    } else if check_only {
+        let num_incorrect = outcomes
+            .iter()
+            .filter(|outcome| outcome.status.needs_fix())
+            .count();
+        if num_incorrect > 0 {
+            return Err(CError::IncorrectCopyrights(num_incorrect));
+        }
+    }
 
     Ok(())
 }
 
-async fn check_file_copyright(
+/// Check the copyrights of a repository on GitHub or GitLab via its API.
+///
+/// Remote repositories are never written, so the run fails on any missing or
+/// outdated copyright unless `min_coverage` is given. Special files are not
+/// checked since they need to be fixed locally. Uses the global config, see
+/// `CopyrightChecker::check_remote`.
+#[cfg(feature = "remote")]
+pub async fn check_remote_copyright(
+    remote: remote::RemoteRepo,
+    name: &str,
+    options: &CheckOptions,
+) -> Result<(), CError> {
+    remote_copyright(Config::global(), remote, name, options).await
+}
+
+#[cfg(feature = "remote")]
+async fn remote_copyright(
+    config: &Config,
+    remote: remote::RemoteRepo,
+    name: &str,
+    options: &CheckOptions,
+) -> Result<(), CError> {
+    let remote_fs = remote::RemoteFs::new(remote);
+    let files = remote_fs.query(|repo| repo.list_files()).await?;
+
+    let (mut files_to_check, _) = config.filter_files_with_stats(
+        files
+            .iter()
+            .filter(|f| config.get_special_file_rule(f).is_none()),
+    );
+    files_to_check.retain(|f| {
+        let holder = config.holder_for(f).unwrap_or(name);
+        options.extension_included(f) && !policy::forbids_header(config.policies(), f, holder)
+    });
+    if options.prints_progress() {
+        log::info!("Checking {} files", files_to_check.len());
+    }
+
+    let regexes = HolderRegexes::new(name, config);
+    let results: Vec<Result<FileOutcome, CError>> = stream::iter(files_to_check.iter())
+        .map(|filepath| check_remote_file(config, &remote_fs, filepath, &regexes))
+        .buffered(options.max_jobs().min(CONCURRENT_FILES_PER_SHARD))
+        .collect()
+        .await;
+
+    let mut outcomes = Vec::new();
+    let mut failures = Vec::new();
+    for (filepath, result) in files_to_check.iter().zip(results) {
+        match result {
+            Ok(outcome) => outcomes.push(outcome),
+            Err(error) => failures.push(FileFailure {
+                filepath: filepath.to_string(),
+                error,
+            }),
+        }
+    }
+    for outcome in outcomes.iter() {
+        print_outcome(outcome, options, config);
+    }
+    options.print_document(&outcomes);
+    options.write_report(&outcomes)?;
+    if options.step_summary {
+        write_step_summary(&markdown_summary(&outcomes, &[], false))?;
+    }
+
+    if !failures.is_empty() {
+        return Err(CError::FixError(failures));
+    }
+    check_coverage(&outcomes, options.min_coverage, true)
+}
+
+#[cfg(feature = "remote")]
+async fn check_remote_file(
+    config: &Config,
+    remote_fs: &remote::RemoteFs,
     filepath: &str,
-    repo_path: &str,
+    regexes: &HolderRegexes,
+) -> Result<FileOutcome, CError> {
+    if config.has_no_header(filepath) {
+        return Ok(no_header_outcome(PathBuf::from(filepath)));
+    }
+    let comment_signs = config.get_comment_signs(filepath)?;
+    let (name, regex_cache) = regexes.for_file(config, filepath);
+    let years = match policy::fixed_years(config.policies(), filepath, name) {
+        Some(years) => years.to_owned(),
+        None => {
+            let remote_path = filepath.to_owned();
+            let (date_source, timezone) = (config.date_source(), config.year_timezone());
+            let commit_years = remote_fs
+                .query(move |repo| repo.commit_years(&remote_path, date_source, timezone))
+                .await?;
+            let years = years_from_commit_years(
+                &commit_years,
+                &format!("File {}", filepath),
+                config.year_format(),
+            );
+            open_years(clamp_years(years, config.min_year()), config.open_end())
+        }
+    };
+    let copyright_lines_fut = join_all(comment_signs.iter().map(|comment_sign| {
+        generate_copyright_line(
+            name,
+            filepath,
+            comment_sign,
+            config,
+            futures::future::ready(years.clone()),
+        )
+    }));
+    let regexes = comment_signs
+        .iter()
+        .map(|comment_sign| regex_cache.get_regex(comment_sign))
+        .collect::<Result<Vec<_>, CError>>()?;
+    read_write_copyright(
+        remote_fs,
+        PathBuf::from(filepath),
+        regexes,
+        futures::future::ready(years.clone()),
+        copyright_lines_fut,
+        &WriteOptions {
+            write_mode: WriteMode::Never,
+            banner_sign: config
+                .insert_after_banner()
+                .then(|| comment_signs[0].clone()),
+            insert_after: config.get_insert_after(filepath).to_vec(),
+            keep_regexes: config.keep_regexes().to_vec(),
+            scan_lines: Some(config.get_scan_lines(filepath)),
+            spdx_lines: spdx_lines(config, &comment_signs),
+            fallback_encoding: config.fallback_encoding(),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// State shared by the checks of all files in a repository.
+struct RepoRun<'a, Y> {
+    config: &'a Config,
+    repo_path: &'a str,
+    /// Copyright regexes of the name and the holders of the config.
+    regexes: HolderRegexes,
+    /// Nested configs with their copyright regexes, see `nested`.
+    nested: Vec<(NestedConfig, HolderRegexes)>,
+    editorconfig: EditorConfig,
+    years: RunYears<'a, Y>,
+    /// Authors of the files with `author_lines` in the config.
+    authors: Option<GitYears>,
+    verify_template: bool,
+    /// Permits for the files checked at once, see `CheckOptions::jobs`.
+    jobs: Semaphore,
+    /// Progress bar of the checked files, see `CheckOptions::progress`.
+    progress: ProgressBar,
+}
+
+/// Settings applying to a file of a run.
+struct FileScope<'r> {
+    config: &'r Config,
+    /// Holder in the copyright of the file.
+    holder: &'r str,
+    regex_cache: &'r CopyrightCache,
+}
+
+/// Years of the files of a run, from the history or a custom provider.
+enum RunYears<'a, Y> {
+    Git(GitYears),
+    Batched(BatchedGitYears),
+    Custom(&'a Y),
+}
+
+impl<Y: YearProvider> YearProvider for RunYears<'_, Y> {
+    async fn years(&self, filepath: &str) -> Result<String, CError> {
+        match self {
+            RunYears::Git(git_years) => git_years.years(filepath).await,
+            RunYears::Batched(git_years) => git_years.years(filepath).await,
+            RunYears::Custom(years) => years.years(filepath).await,
+        }
+    }
+}
+
+impl<Y: YearProvider> RepoRun<'_, Y> {
+    /// Check regular and special files concurrently.
+    ///
+    /// The files are sharded by their top-level directory. Up to
+    /// `CONCURRENT_SHARDS` shards are checked at once and each takes the next
+    /// pending shard when done, so that a few large directories do not hold up
+    /// the others. Within a shard, up to `CONCURRENT_FILES_PER_SHARD` files are
+    /// checked at once, which bounds the number of git processes and open files.
+    /// All shards together check no more files at once than `jobs` permits.
+    ///
+    /// Outcomes and failures are returned in the order of the files.
+    async fn check_files(
+        &self,
+        files: &[&String],
+        special_files: &[&String],
+        write_mode: WriteMode,
+    ) -> (Vec<FileOutcome>, Vec<FileFailure>) {
+        let all_files: Vec<(&String, bool)> = files
+            .iter()
+            .map(|filepath| (*filepath, false))
+            .chain(special_files.iter().map(|filepath| (*filepath, true)))
+            .collect();
+
+        let all_files = &all_files;
+        let progress = &self.progress;
+        progress.reset();
+        progress.set_length(all_files.len() as u64);
+        let mut shards: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (idx, (filepath, _)) in all_files.iter().enumerate() {
+            shards.entry(top_level_dir(filepath)).or_default().push(idx);
+        }
+
+        let shard_results: Vec<Vec<(usize, Result<FileOutcome, CError>)>> =
+            stream::iter(shards.into_values())
+                .map(|shard| {
+                    stream::iter(shard)
+                        .map(|idx| async move {
+                            let (filepath, special) = all_files[idx];
+                            // The semaphore is never closed, so acquiring cannot fail
+                            let _permit = self.jobs.acquire().await;
+                            let result = self.check_file(filepath, special, write_mode).await;
+                            progress.inc(1);
+                            (idx, result)
+                        })
+                        .buffer_unordered(CONCURRENT_FILES_PER_SHARD)
+                        .collect::<Vec<_>>()
+                })
+                .buffer_unordered(CONCURRENT_SHARDS)
+                .collect()
+                .await;
+        progress.finish_and_clear();
+        let mut results: Vec<(usize, Result<FileOutcome, CError>)> =
+            shard_results.into_iter().flatten().collect();
+        results.sort_by_key(|(idx, _)| *idx);
+
+        let mut outcomes = Vec::new();
+        let mut failures = Vec::new();
+        for (idx, result) in results {
+            let filepath = all_files[idx].0;
+            match result {
+                Ok(outcome) => outcomes.push(outcome),
+                Err(error) => failures.push(FileFailure {
+                    filepath: filepath.to_string(),
+                    error,
+                }),
+            }
+        }
+        (outcomes, failures)
+    }
+
+    /// Config, holder and copyright regexes of the file, from the closest
+    /// nested config if there is one.
+    fn file_scope(&self, filepath: &str) -> FileScope<'_> {
+        let (config, regexes) = self
+            .nested
+            .iter()
+            .filter(|(nested, _)| nested.relative_path(filepath).is_some())
+            .max_by_key(|(nested, _)| nested.dir.len())
+            .map_or((self.config, &self.regexes), |(nested, regexes)| {
+                (&nested.config, regexes)
+            });
+        let (holder, regex_cache) = regexes.for_file(config, filepath);
+        FileScope {
+            config,
+            holder,
+            regex_cache,
+        }
+    }
+
+    /// Check a regular or special file.
+    async fn check_file(
+        &self,
+        filepath: &str,
+        special: bool,
+        write_mode: WriteMode,
+    ) -> Result<FileOutcome, CError> {
+        let settings = self.editorconfig.settings_for(filepath);
+        let abs_path = Path::new(self.repo_path).join(filepath);
+        let mtime = match self.file_scope(filepath).config.preserve_mtime() {
+            true => modified_time(&abs_path),
+            false => None,
+        };
+        let result = match special {
+            true => check_special_file(self, filepath, settings, write_mode).await,
+            false => check_file_copyright(self, filepath, settings, write_mode).await,
+        };
+
+        if let (Some(mtime), Ok(outcome)) = (mtime, &result) {
+            if write_mode.writes(&outcome.status) {
+                set_modified_time(&abs_path, mtime)?;
+            }
+        }
+        result
+    }
+}
+
+/// Number of shards of files checked concurrently, see `RepoRun::check_files`.
+const CONCURRENT_SHARDS: usize = 8;
+
+/// Number of files checked concurrently within a shard.
+const CONCURRENT_FILES_PER_SHARD: usize = 16;
+
+/// Top-level directory of a path relative to the repository, or an empty
+/// string for files at the root.
+fn top_level_dir(filepath: &str) -> &str {
+    match filepath.split_once('/') {
+        Some((dir, _)) => dir,
+        None => "",
+    }
+}
+
+/// Verify the policy rules applying to the files on their current header.
+async fn check_policies<'a>(
+    config: &Config,
+    repo_path: &Path,
     name: &str,
-    regex_cache: &CopyrightCache,
+    files: impl Iterator<Item = &'a &'a String>,
+) -> Result<Vec<PolicyViolation>, CError> {
+    let rules = config.policies();
+    let mut violations = Vec::new();
+    for filepath in files {
+        let holder = config.holder_for(filepath).unwrap_or(name);
+        let applying: Vec<&PolicyRule> = rules
+            .iter()
+            .filter(|rule| rule.applies_to(filepath, holder))
+            .collect();
+        if applying.is_empty() {
+            continue;
+        }
+
+        let max_lines = MAX_BANNER_LINES
+            + anchor_lines(config.get_insert_after(filepath))
+            + config.get_scan_lines(filepath);
+        let filepath = repo_path.join(filepath);
+        let lines = LocalFs.read_header(&filepath, max_lines).await?;
+        violations.extend(
+            applying
+                .iter()
+                .filter_map(|rule| rule.check(&filepath, &lines)),
+        );
+    }
+    Ok(violations)
+}
+
+/// Note `HEAD` as checked with the files whose copyright was correct.
+///
+/// Files with uncommitted changes before the run are left out since their
+/// outcome does not describe their content at `HEAD`.
+async fn note_checked_commit(
+    repo_path: &Path,
+    outcomes: &[FileOutcome],
+    changed_before: &ChangedFiles,
+) -> Result<(), CError> {
+    let repo_path_str = repo_path.to_str().unwrap_or_default();
+    let years = outcomes
+        .iter()
+        .filter(|outcome| outcome.status == FileStatus::Correct)
+        .filter_map(|outcome| {
+            let filepath = outcome.filepath.strip_prefix(repo_path).ok()?.to_str()?;
+            (!changed_before.contains(filepath))
+                .then(|| (filepath.to_owned(), outcome.expected_years.clone()))
+        })
+        .collect();
+    let checked = CheckedCommit {
+        commit: get_commit_hash(repo_path_str, "HEAD").await?,
+        years,
+    };
+    checked.write(repo_path_str).await
+}
+
+/// List pending modifications and write them after confirmation.
+///
+/// Returns whether the modifications were written.
+async fn write_pending<Y: YearProvider>(
+    run: &RepoRun<'_, Y>,
+    pending: &[&FileOutcome],
+    options: &CheckOptions,
+) -> Result<bool, CError> {
+    if pending.is_empty() {
+        return Ok(true);
+    }
+
+    log::info!("Pending modifications:");
+    for outcome in pending.iter() {
+        log::info!(
+            "  {}: {}",
+            outcome.filepath.display(),
+            outcome.short_message()
+        );
+    }
+    let confirmed = options.assume_yes
+        || options
+            .confirm_writes
+            .is_some_and(|confirm_writes| confirm_writes(pending));
+    if !confirmed {
+        log::info!("No files written");
+        return Ok(false);
+    }
+
+    let config = run.config;
+    let rel_paths: Vec<String> = pending
+        .iter()
+        .filter_map(|outcome| outcome.filepath.strip_prefix(run.repo_path).ok())
+        .map(|rel_path| rel_path.to_string_lossy().into_owned())
+        .collect();
+    let (special_files, files): (Vec<&String>, Vec<&String>) = rel_paths
+        .iter()
+        .partition(|f| config.get_special_file_rule(f).is_some());
+    let (_, failures) = run
+        .check_files(&files, &special_files, options.write_mode)
+        .await;
+
+    match failures.is_empty() {
+        true => Ok(true),
+        false => Err(CError::FixError(failures)),
+    }
+}
+
+/// Print the outcome of a file and notice copyrights before the minimum year.
+fn print_outcome(outcome: &FileOutcome, options: &CheckOptions, config: &Config) {
+    if options.quiet {
+        return;
+    }
+    options.print_outcome(outcome);
+
+    if let (FileStatus::Outdated { found_years }, Some(min_year)) =
+        (&outcome.status, config.min_year())
+    {
+        if start_year(found_years).is_some_and(|year| year < min_year) {
+            log::info!(
+                "File {} has copyright starting before the minimum year {}: {}",
+                outcome.filepath.display(),
+                min_year,
+                found_years
+            );
+        }
+    }
+}
+
+/// Keep only files last modified on or after `since`, looking up the dates of
+/// up to `jobs` files at once.
+async fn filter_modified_since<'a>(
+    files: Vec<&'a String>,
+    repo_path: &str,
+    since: &str,
+    backend: GitBackend,
+    jobs: usize,
+) -> Result<Vec<&'a String>, CError> {
+    let dates: Vec<_> = stream::iter(files.iter())
+        .map(|filepath| backend.get_last_modified_date(filepath, repo_path))
+        .buffered(jobs)
+        .collect()
+        .await;
+
+    let mut recent_files = Vec::new();
+    for (filepath, date) in files.into_iter().zip(dates) {
+        match date? {
+            Some(date) if !modified_since(&date, since) => {
+                log::debug!("Skipping {} last modified on {}", filepath, date)
+            }
+            _ => recent_files.push(filepath),
+        }
+    }
+    Ok(recent_files)
+}
+
+async fn check_file_copyright<Y: YearProvider>(
+    run: &RepoRun<'_, Y>,
+    filepath: &str,
+    settings: EditorSettings,
+    write_mode: WriteMode,
+) -> Result<FileOutcome, CError> {
+    let FileScope {
+        config,
+        holder,
+        regex_cache,
+    } = run.file_scope(filepath);
+    if config.has_no_header(filepath) {
+        return match config.reuse() {
+            true => check_license_sidecar(run, filepath, write_mode).await,
+            false => Ok(no_header_outcome(Path::new(run.repo_path).join(filepath))),
+        };
+    }
+    let comment_signs = config.get_comment_signs(filepath)?;
+    let options = WriteOptions {
+        write_mode,
+        editor_settings: settings,
+        banner_sign: config
+            .insert_after_banner()
+            .then(|| comment_signs[0].clone()),
+        insert_after: config.get_insert_after(filepath).to_vec(),
+        keep_regexes: config.keep_regexes().to_vec(),
+        scan_lines: Some(config.get_scan_lines(filepath)),
+        normalize_cr: config.normalize_cr_line_endings(),
+        fallback_encoding: config.fallback_encoding(),
+        read_only_files: config.read_only_files(),
+        verify_template: run.verify_template,
+        spdx_lines: spdx_lines(config, &comment_signs),
+    };
+    if let Some(authors) = &run.authors {
+        if policy::fixed_years(config.policies(), filepath, holder).is_none() {
+            let author_years = authors
+                .author_years(filepath, config.min_author_commits())
+                .await?;
+            if !author_years.is_empty() {
+                return check_author_copyrights(
+                    run,
+                    filepath,
+                    &comment_signs,
+                    author_years,
+                    options,
+                )
+                .await;
+            }
+        }
+    }
+
+    let years = file_years(run, filepath, holder).await?;
+    let copyright_lines_fut = join_all(comment_signs.iter().map(|comment_sign| {
+        generate_copyright_line(
+            holder,
+            filepath,
+            comment_sign,
+            config,
+            futures::future::ready(years.clone()),
+        )
+    }));
+    let filepath = Path::new(run.repo_path).join(filepath);
+    let regexes = comment_signs
+        .iter()
+        .map(|comment_sign| regex_cache.get_regex(comment_sign))
+        .collect::<Result<Vec<_>, CError>>()?;
+    let outcome = read_write_copyright(
+        &LocalFs,
+        filepath,
+        regexes,
+        futures::future::ready(years),
+        copyright_lines_fut,
+        &options,
+    )
+    .await?;
+
+    Ok(outcome)
+}
+
+/// Check the copyright lines of the authors of a file, see `author_lines` in
+/// the config.
+///
+/// Authors are checked from last to first since missing lines are inserted
+/// above the existing ones. Header templates are not written with author
+/// lines. The outcome is the one of the first author whose line needs a fix,
+/// otherwise the one of the first author.
+async fn check_author_copyrights<Y: YearProvider>(
+    run: &RepoRun<'_, Y>,
+    filepath: &str,
+    comment_signs: &[&CommentSign],
+    author_years: Vec<(String, String)>,
+    options: WriteOptions,
+) -> Result<FileOutcome, CError> {
+    let config = run.file_scope(filepath).config;
+    // Lines of the other authors precede the line of an author
+    let scan_lines = options
+        .scan_lines
+        .map(|scan_lines| scan_lines + author_years.len());
+    let mut outcomes = Vec::new();
+    for (idx, (author, years)) in author_years.into_iter().enumerate().rev() {
+        let regex_cache = CopyrightCache::new(
+            &generate_base_regex(&author, config),
+            comment_signs.iter().copied(),
+        );
+        let regexes = comment_signs
+            .iter()
+            .map(|comment_sign| regex_cache.get_regex(comment_sign))
+            .collect::<Result<Vec<_>, CError>>()?;
+        let years = open_years(
+            clamp_years(years, run.config.min_year()),
+            run.config.open_end(),
+        );
+        let copyright_lines = join_all(comment_signs.iter().map(|comment_sign| async {
+            let copyright_line = generate_copyright_line(
+                &author,
+                filepath,
+                comment_sign,
+                config,
+                futures::future::ready(years.clone()),
+            )
+            .await;
+            copyright_line.lines().next().unwrap_or_default().to_owned()
+        }));
+        let outcome = read_write_copyright(
+            &LocalFs,
+            Path::new(run.repo_path).join(filepath),
+            regexes,
+            futures::future::ready(years.clone()),
+            copyright_lines,
+            &WriteOptions {
+                scan_lines,
+                verify_template: false,
+                // The SPDX line precedes the line of the first author
+                spdx_lines: match idx {
+                    0 => options.spdx_lines.clone(),
+                    _ => Vec::new(),
+                },
+                ..options.clone()
+            },
+        )
+        .await?;
+        outcomes.push(outcome);
+    }
+
+    outcomes.reverse();
+    let idx = outcomes
+        .iter()
+        .position(|outcome| outcome.status.needs_fix())
+        .unwrap_or_default();
+    Ok(outcomes.swap_remove(idx))
+}
+
+/// Years expected in the copyright of a file.
+async fn file_years<Y: YearProvider>(
+    run: &RepoRun<'_, Y>,
+    filepath: &str,
+    holder: &str,
+) -> Result<String, CError> {
+    Ok(
+        match policy::fixed_years(run.config.policies(), filepath, holder) {
+            Some(years) => years.to_owned(),
+            None => open_years(
+                clamp_years(run.years.years(filepath).await?, run.config.min_year()),
+                run.config.open_end(),
+            ),
+        },
+    )
+}
+
+/// Check the `.license` sidecar file of a file which cannot carry a header,
+/// as required by REUSE.
+async fn check_license_sidecar<Y: YearProvider>(
+    run: &RepoRun<'_, Y>,
+    filepath: &str,
+    write_mode: WriteMode,
+) -> Result<FileOutcome, CError> {
+    let FileScope {
+        config,
+        holder,
+        regex_cache,
+    } = run.file_scope(filepath);
+    let years = file_years(run, filepath, holder).await?;
+    let sidecar = format!("{}.{}", filepath, SIDECAR_EXTENSION);
+    // Sidecar files hold the header lines without comment signs
+    let comment_sign = CommentSign::Detailed {
+        left: String::new(),
+        right: None,
+        separator: String::new(),
+        right_separator: None,
+    };
+    let copyright_line = generate_copyright_line(
+        holder,
+        filepath,
+        &comment_sign,
+        config,
+        futures::future::ready(years.clone()),
+    )
+    .await;
+    // Only the copyright line, header templates are for source files
+    let copyright_line = copyright_line.lines().next().unwrap_or_default().to_owned();
+    read_write_sidecar(
+        &LocalFs,
+        Path::new(run.repo_path).join(&sidecar),
+        regex_cache.get_regex(&comment_sign)?,
+        years,
+        copyright_line,
+        &WriteOptions {
+            write_mode,
+            editor_settings: run.editorconfig.settings_for(&sidecar),
+            normalize_cr: config.normalize_cr_line_endings(),
+            fallback_encoding: config.fallback_encoding(),
+            read_only_files: config.read_only_files(),
+            spdx_lines: spdx_lines(config, &[&comment_sign]),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Extension of REUSE sidecar files, e.g. `logo.png.license`.
+const SIDECAR_EXTENSION: &str = "license";
+
+/// `SPDX-License-Identifier` lines for the comment signs if the config sets
+/// an SPDX license.
+fn spdx_lines(config: &Config, comment_signs: &[&CommentSign]) -> Vec<String> {
+    match config.spdx_license() {
+        Some(spdx_license) => comment_signs
+            .iter()
+            .map(|comment_sign| generate_spdx_line(comment_sign, spdx_license))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Outcome of a file without header by configuration.
+fn no_header_outcome(filepath: PathBuf) -> FileOutcome {
+    FileOutcome {
+        filepath,
+        status: FileStatus::NoHeader,
+        line_nr: None,
+        expected_years: String::new(),
+    }
+}
+
+/// Files at the repository root which may hold the license, in order of precedence.
+const LICENSE_FILES: [&str; 8] = [
+    "LICENSE",
+    "LICENSE.md",
+    "LICENSE.txt",
+    "LICENCE",
+    "LICENCE.md",
+    "COPYING",
+    "COPYING.md",
+    "COPYING.txt",
+];
+
+/// Check and update the years of the top-level license file.
+///
+/// The years span the first to the last commit of the repository. They are
+/// updated in the first line starting with `Copyright`, e.g.
+/// `Copyright (c) 2019-2021 MyCompany Ltd.`, keeping the rest of the line.
+/// Uses the global config, see `CopyrightChecker::check_license`.
+pub async fn check_license_copyright(
+    repo_path_str: &str,
+    options: &CheckOptions,
+) -> Result<(), CError> {
+    license_copyright(Config::global(), repo_path_str, options).await
+}
+
+async fn license_copyright(
+    config: &Config,
+    repo_path_str: &str,
+    options: &CheckOptions,
 ) -> Result<(), CError> {
-    let comment_sign = Config::global().get_comment_sign(filepath)?;
-    let years_fut = get_added_mod_times_for_file(filepath, repo_path).shared();
-    let copyright_line_fut = generate_copyright_line(name, comment_sign, years_fut.clone());
-    let filepath = Path::new(repo_path).join(filepath);
-    let regex = regex_cache.get_regex(comment_sign)?;
-    read_write_copyright(filepath, regex, years_fut, copyright_line_fut).await
+    let repo_path = Path::new(repo_path_str);
+    let backend = config.git_backend();
+    backend.check_git_version().await?;
+    options.check_history(repo_path_str, backend).await?;
+    let changed_before = backend.snapshot_changes(repo_path_str).await?;
+    let license_file = LICENSE_FILES
+        .iter()
+        .map(|filename| repo_path.join(filename))
+        .find(|filepath| filepath.is_file())
+        .ok_or_else(|| {
+            CError::ReadError(format!("LICENSE or COPYING file in {}", repo_path_str))
+        })?;
+
+    let license_name = license_file
+        .strip_prefix(repo_path)
+        .unwrap_or(&license_file)
+        .to_string_lossy()
+        .into_owned();
+    let settings = EditorConfig::load(repo_path, [".editorconfig".to_owned()].iter())
+        .settings_for(&license_name);
+
+    let years = clamp_years(
+        get_repo_years(
+            repo_path_str,
+            config.history_ref(),
+            config.year_format(),
+            &LogOptions::from_config(config),
+            &IgnoredRevs::load(repo_path_str).await?,
+        )
+        .await?,
+        config.min_year(),
+    );
+    let years = open_years(years, config.open_end());
+    let regex = Regex::new(&format!(
+        r"^\s*(?i:Copyright)(?: (?:\([cC]\)|©))? {}\b",
+        years_regex(config)
+    ))?;
+    let outcome = read_write_special_file(
+        &LocalFs,
+        license_file,
+        &regex,
+        years,
+        None,
+        &WriteOptions {
+            write_mode: options.write_mode,
+            editor_settings: settings,
+            banner_sign: None,
+            insert_after: Vec::new(),
+            keep_regexes: Vec::new(),
+            scan_lines: None,
+            normalize_cr: config.normalize_cr_line_endings(),
+            fallback_encoding: config.fallback_encoding(),
+            read_only_files: config.read_only_files(),
+            verify_template: false,
+            spdx_lines: Vec::new(),
+        },
+    )
+    .await?;
+    options.print_outcome(&outcome);
+    options.print_document(std::slice::from_ref(&outcome));
+    options.write_report(std::slice::from_ref(&outcome))?;
+
+    backend
+        .check_for_changes(repo_path_str, &changed_before, options.fail_on_diff)
+        .await?;
+
+    check_coverage(
+        std::slice::from_ref(&outcome),
+        options.min_coverage,
+        options.write_mode == WriteMode::Never,
+    )
+}
+
+/// Count the files in which each copyright holder appears in the header.
+///
+/// All files of the repository are scanned regardless of the ignore patterns
+/// so that third-party code shows up. Nothing is written. The holders are
+/// sorted by descending file count. Uses the global config, see
+/// `CopyrightChecker::list_holders`.
+pub async fn list_holders(repo_path_str: &str) -> Result<Vec<(String, usize)>, CError> {
+    holders(Config::global(), repo_path_str).await
+}
+
+async fn holders(config: &Config, repo_path_str: &str) -> Result<Vec<(String, usize)>, CError> {
+    let repo_path = Path::new(repo_path_str);
+    let backend = config.git_backend();
+    let files = match !config.mtime_fallback() || backend.is_git_repo(repo_path_str).await {
+        true => {
+            backend.check_git_version().await?;
+            backend
+                .get_files_on_ref(repo_path_str, config.history_ref())
+                .await?
+        }
+        false => walk_files(repo_path)?,
+    };
+
+    let parser = HolderParser::new();
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for filepath in files.iter() {
+        let max_lines = MAX_BANNER_LINES
+            + anchor_lines(config.get_insert_after(filepath))
+            + config.get_scan_lines(filepath);
+        // Unreadable files like broken symlinks have no header to report
+        let lines = match LocalFs
+            .read_header(&repo_path.join(filepath), max_lines)
+            .await
+        {
+            Ok(lines) => lines,
+            Err(_) => continue,
+        };
+        let holders: BTreeSet<String> = lines
+            .iter()
+            .filter_map(|line_| parser.parse(line_))
+            .collect();
+        for holder in holders {
+            *counts.entry(holder).or_default() += 1;
+        }
+    }
+
+    let mut holders: Vec<(String, usize)> = counts.into_iter().collect();
+    holders.sort_by(|(_, a), (_, b)| b.cmp(a));
+    Ok(holders)
+}
+
+/// Remove the copyrights of the name from the headers of the files of the
+/// repository, e.g. when moving to a central license file.
+///
+/// The files are selected by the ignore patterns and nested configs like for
+/// checking. Copyright lines matching the regexes of the holder of a file are
+/// removed with a blank line following them, other holders and the lines of
+/// `author_lines` are kept. Nothing is written with `WriteMode::Never`.
+/// Returns each file with the zero-based line number of a copyright found.
+/// Uses the global config, see `CopyrightChecker::remove_copyrights`.
+pub async fn remove_repo_copyright(
+    repo_path_str: &str,
+    name: &str,
+    options: &CheckOptions,
+) -> Result<Vec<(String, usize)>, CError> {
+    remove_copyrights(Config::global(), repo_path_str, name, options).await
+}
+
+async fn remove_copyrights(
+    config: &Config,
+    repo_path_str: &str,
+    name: &str,
+    options: &CheckOptions,
+) -> Result<Vec<(String, usize)>, CError> {
+    let repo_path = Path::new(repo_path_str);
+    let headers = HeaderFiles::load(config, repo_path_str, |config| {
+        HolderRegexes::new(name, config)
+    })
+    .await?;
+
+    let mut removed = Vec::new();
+    for (filepath, config, regexes, comment_signs, write_options) in
+        headers.files(config, repo_path, options)
+    {
+        let (_, regex_cache) = regexes.for_file(config, filepath);
+        let file_regexes = comment_signs
+            .iter()
+            .map(|comment_sign| regex_cache.get_regex(comment_sign))
+            .collect::<Result<Vec<_>, CError>>()?;
+        let line_nrs = read_remove_copyright(
+            &LocalFs,
+            &repo_path.join(filepath),
+            &file_regexes,
+            &write_options,
+        )
+        .await?;
+        removed.extend(
+            line_nrs
+                .into_iter()
+                .map(|line_nr| (filepath.clone(), line_nr)),
+        );
+    }
+    Ok(removed)
+}
+
+/// Rewrite the copyrights of a former holder to a new one in the headers of
+/// the files of the repository, e.g. after a corporate rename.
+///
+/// The files are selected like for checking. Copyright lines matching the
+/// regex of `from` are generated anew with the holder `to` and the years
+/// found, lines of header templates following them are kept. Nothing is
+/// written with `WriteMode::Never`. Returns each file with the zero-based
+/// line number of a copyright rewritten. Uses the global config, see
+/// `CopyrightChecker::migrate_holder`.
+pub async fn migrate_repo_copyright(
+    repo_path_str: &str,
+    from: &str,
+    to: &str,
+    options: &CheckOptions,
+) -> Result<Vec<(String, usize)>, CError> {
+    migrate_holder(Config::global(), repo_path_str, from, to, options).await
+}
+
+async fn migrate_holder(
+    config: &Config,
+    repo_path_str: &str,
+    from: &str,
+    to: &str,
+    options: &CheckOptions,
+) -> Result<Vec<(String, usize)>, CError> {
+    let repo_path = Path::new(repo_path_str);
+    let headers = HeaderFiles::load(config, repo_path_str, |config| {
+        CopyrightCache::new(&generate_base_regex(from, config), config.comment_signs())
+    })
+    .await?;
+
+    let mut migrated = Vec::new();
+    for (filepath, config, regex_cache, comment_signs, write_options) in
+        headers.files(config, repo_path, options)
+    {
+        let file_regexes = comment_signs
+            .iter()
+            .map(|comment_sign| regex_cache.get_regex(comment_sign))
+            .collect::<Result<Vec<_>, CError>>()?;
+        let line_nrs = read_migrate_copyright(
+            &LocalFs,
+            &repo_path.join(filepath),
+            &file_regexes,
+            |sign_idx, years| {
+                generate_copyright_line(
+                    to,
+                    filepath,
+                    comment_signs[sign_idx],
+                    config,
+                    futures::future::ready(years),
+                )
+            },
+            &write_options,
+        )
+        .await?;
+        migrated.extend(
+            line_nrs
+                .into_iter()
+                .map(|line_nr| (filepath.clone(), line_nr)),
+        );
+    }
+    Ok(migrated)
+}
+
+/// Files of a repository whose headers are rewritten instead of checked, by
+/// `remove_repo_copyright` and `migrate_repo_copyright`, with the copyright
+/// regexes of the config and of each nested config.
+struct HeaderFiles<R> {
+    files: Vec<String>,
+    editorconfig: EditorConfig,
+    regexes: R,
+    nested: Vec<(NestedConfig, R)>,
+}
+
+impl<R> HeaderFiles<R> {
+    async fn load(
+        config: &Config,
+        repo_path_str: &str,
+        regexes: impl Fn(&Config) -> R,
+    ) -> Result<Self, CError> {
+        let repo_path = Path::new(repo_path_str);
+        let backend = config.git_backend();
+        let files = match !config.mtime_fallback() || backend.is_git_repo(repo_path_str).await {
+            true => {
+                backend.check_git_version().await?;
+                backend
+                    .get_files_on_ref(repo_path_str, config.history_ref())
+                    .await?
+            }
+            false => walk_files(repo_path)?,
+        };
+        let nested = load_nested_configs(config, repo_path, &files)?
+            .into_iter()
+            .map(|nested| {
+                let nested_regexes = regexes(&nested.config);
+                (nested, nested_regexes)
+            })
+            .collect();
+        Ok(HeaderFiles {
+            editorconfig: EditorConfig::load(repo_path, files.iter()),
+            regexes: regexes(config),
+            nested,
+            files,
+        })
+    }
+
+    /// Files selected like for checking with their config, regexes, comment
+    /// signs and write options, from the closest nested config if any.
+    ///
+    /// Special files and files without header or comment sign are skipped.
+    fn files<'a>(
+        &'a self,
+        config: &'a Config,
+        repo_path: &'a Path,
+        options: &'a CheckOptions,
+    ) -> impl Iterator<
+        Item = (
+            &'a String,
+            &'a Config,
+            &'a R,
+            Vec<&'a CommentSign>,
+            WriteOptions,
+        ),
+    > {
+        self.files.iter().filter_map(move |filepath| {
+            let (config, relative_path, regexes) = match self
+                .nested
+                .iter()
+                .filter(|(nested, _)| nested.relative_path(filepath).is_some())
+                .max_by_key(|(nested, _)| nested.dir.len())
+            {
+                Some((nested, regexes)) => (
+                    &nested.config,
+                    nested.relative_path(filepath).unwrap_or(filepath),
+                    regexes,
+                ),
+                None => (config, filepath.as_str(), &self.regexes),
+            };
+            if config.is_ignored(relative_path)
+                || !options.extension_included(filepath)
+                || config.get_special_file_rule(filepath).is_some()
+                || config.has_no_header(filepath)
+                || !repo_path.join(filepath).is_file()
+            {
+                return None;
+            }
+            let comment_signs = config.get_comment_signs(filepath).ok()?;
+            let write_options = WriteOptions {
+                write_mode: options.write_mode,
+                editor_settings: self.editorconfig.settings_for(filepath),
+                banner_sign: config
+                    .insert_after_banner()
+                    .then(|| comment_signs[0].clone()),
+                insert_after: config.get_insert_after(filepath).to_vec(),
+                keep_regexes: config.keep_regexes().to_vec(),
+                scan_lines: Some(config.get_scan_lines(filepath)),
+                normalize_cr: config.normalize_cr_line_endings(),
+                fallback_encoding: config.fallback_encoding(),
+                read_only_files: config.read_only_files(),
+                verify_template: false,
+                spdx_lines: Vec::new(),
+            };
+            Some((filepath, config, regexes, comment_signs, write_options))
+        })
+    }
+}
+
+async fn check_special_file<Y: YearProvider>(
+    run: &RepoRun<'_, Y>,
+    filepath: &str,
+    settings: EditorSettings,
+    write_mode: WriteMode,
+) -> Result<FileOutcome, CError> {
+    let config = run.config;
+    let rule = config
+        .get_special_file_rule(filepath)
+        .ok_or_else(|| CError::ConfigError(format!("No special file rule for {}", filepath)))?;
+    let regex = Regex::new(&rule.pattern)?;
+    let years = open_years(
+        clamp_years(run.years.years(filepath).await?, config.min_year()),
+        config.open_end(),
+    );
+    let replacement = rule.replacement(&years, run.file_scope(filepath).holder);
+    let filepath = Path::new(run.repo_path).join(filepath);
+    let outcome = read_write_special_file(
+        &LocalFs,
+        filepath,
+        &regex,
+        years,
+        Some(&replacement),
+        &WriteOptions {
+            write_mode,
+            editor_settings: settings,
+            banner_sign: None,
+            insert_after: Vec::new(),
+            keep_regexes: Vec::new(),
+            scan_lines: None,
+            normalize_cr: config.normalize_cr_line_endings(),
+            fallback_encoding: config.fallback_encoding(),
+            read_only_files: config.read_only_files(),
+            verify_template: false,
+            spdx_lines: Vec::new(),
+        },
+    )
+    .await?;
+    Ok(outcome)
 }
 
 pub fn get_hash<T: std::hash::Hash>(obj: &T) -> u64 {
@@ -0,0 +1,450 @@
+//! Minimal language server publishing copyright diagnostics.
+//!
+//! The server speaks JSON-RPC over stdin/stdout, keeps the content of open
+//! documents in memory and checks them with the same per-file logic as a
+//! regular run. Missing or outdated copyrights are published as warnings and
+//! a code action offers to insert or update the copyright line.
+
+use crate::config::YearSource;
+use crate::file_ops::replace_years;
+use crate::file_ops::{check_header, find_keep_pattern, header_lines, insertion_line_nr};
+use crate::git_ops::{
+    check_git_version, clamp_years, get_added_mod_times_for_file, get_blame_years_for_file,
+    open_years, HistoryCutoffs, IgnoredRevs, LogOptions,
+};
+use crate::policy;
+use crate::regex_ops::{generate_copyright_line, HolderRegexes};
+use crate::report::{FileOutcome, FileStatus};
+use crate::{CError, Config};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Serve diagnostics for the repository on stdin/stdout until `exit` is received.
+pub async fn run_server(repo_path: &str, name: &str, config: &Config) -> Result<(), CError> {
+    check_git_version().await?;
+    let log_options = LogOptions::from_config(config);
+    let history_cutoffs =
+        HistoryCutoffs::load(repo_path, config.history_cutoffs(), &log_options).await?;
+    let ignored_revs = IgnoredRevs::load(repo_path).await?;
+    let mut server = Server::new(repo_path, name, config, history_cutoffs, ignored_revs)?;
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(message) = read_message(&mut stdin).await? {
+        let method = message["method"].as_str().unwrap_or_default();
+        log::debug!("Received {}", method);
+        if method == "exit" {
+            break;
+        }
+
+        for response in server.handle(&message).await {
+            write_message(&mut stdout, &response).await?;
+        }
+    }
+
+    Ok(())
+}
+
+struct Server<'a> {
+    config: &'a Config,
+    repo_path: PathBuf,
+    regexes: HolderRegexes,
+    history_cutoffs: HistoryCutoffs,
+    ignored_revs: IgnoredRevs,
+    documents: HashMap<String, String>,
+    years: HashMap<String, String>,
+}
+
+/// Result of checking an open document.
+struct DocumentCheck {
+    outcome: FileOutcome,
+    copyright_line: String,
+    line_nr: usize,
+    line_len: usize,
+}
+
+impl<'a> Server<'a> {
+    fn new(
+        repo_path: &str,
+        name: &str,
+        config: &'a Config,
+        history_cutoffs: HistoryCutoffs,
+        ignored_revs: IgnoredRevs,
+    ) -> Result<Self, CError> {
+        Ok(Server {
+            config,
+            repo_path: Path::new(repo_path).canonicalize()?,
+            regexes: HolderRegexes::new(name, config),
+            history_cutoffs,
+            ignored_revs,
+            documents: HashMap::new(),
+            years: HashMap::new(),
+        })
+    }
+
+    async fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let id = &message["id"];
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+
+        match message["method"].as_str().unwrap_or_default() {
+            "initialize" => vec![response(
+                id,
+                json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "codeActionProvider": true,
+                    },
+                    "serverInfo": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }),
+            )],
+            "shutdown" => vec![response(id, Value::Null)],
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_owned());
+                vec![self.publish_diagnostics(&uri).await]
+            }
+            "textDocument/didChange" => {
+                // We announce full document sync, so the last change holds the full text.
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.clone(), text.to_owned());
+                }
+                vec![self.publish_diagnostics(&uri).await]
+            }
+            "textDocument/didSave" => {
+                // The history might have changed, e.g. after a commit
+                self.years.remove(&uri);
+                vec![self.publish_diagnostics(&uri).await]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.years.remove(&uri);
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({"uri": uri, "diagnostics": []}),
+                )]
+            }
+            "textDocument/codeAction" => {
+                let actions = match self.check_document(&uri).await {
+                    Some(check) => code_actions(&uri, &check),
+                    None => vec![],
+                };
+                vec![response(id, Value::Array(actions))]
+            }
+            method if !id.is_null() => vec![json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {
+                    "code": METHOD_NOT_FOUND,
+                    "message": format!("Method {} is not supported", method),
+                },
+            })],
+            _ => vec![],
+        }
+    }
+
+    async fn publish_diagnostics(&mut self, uri: &str) -> Value {
+        let diagnostics = match self.check_document(uri).await {
+            Some(check) => diagnostics(&check),
+            None => vec![],
+        };
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({"uri": uri, "diagnostics": diagnostics}),
+        )
+    }
+
+    async fn check_document(&mut self, uri: &str) -> Option<DocumentCheck> {
+        if !self.documents.contains_key(uri) {
+            return None;
+        }
+        let filepath = uri_to_path(uri)?;
+        let rel_path = filepath
+            .strip_prefix(&self.repo_path)
+            .ok()?
+            .to_str()?
+            .to_owned();
+
+        let config = self.config;
+        if config.filter_files(std::iter::once(&rel_path)).is_empty() {
+            return None;
+        }
+        let comment_signs = config.get_comment_signs(&rel_path).ok()?;
+        let (holder, regex_cache) = self.regexes.for_file(config, &rel_path);
+        let regexes = comment_signs
+            .iter()
+            .map(|comment_sign| regex_cache.get_regex(comment_sign))
+            .collect::<Result<Vec<_>, CError>>()
+            .ok()?;
+
+        if policy::forbids_header(config.policies(), &rel_path, holder) {
+            return None;
+        }
+
+        let fixed_years = policy::fixed_years(config.policies(), &rel_path, holder);
+        let years = match (fixed_years, self.years.get(uri)) {
+            (Some(years), _) => years.to_owned(),
+            (None, Some(years)) => years.clone(),
+            (None, None) => {
+                let repo_path = self.repo_path.to_str()?;
+                let cutoff = self.history_cutoffs.cutoff_for(&rel_path);
+                let log_options = LogOptions::from_config(config);
+                let years = match config.year_source() {
+                    YearSource::Log => {
+                        get_added_mod_times_for_file(
+                            &rel_path,
+                            repo_path,
+                            cutoff,
+                            config.mtime_fallback(),
+                            config.year_format(),
+                            &log_options,
+                            &self.ignored_revs,
+                        )
+                        .await
+                    }
+                    YearSource::Blame => get_blame_years_for_file(
+                        &rel_path,
+                        repo_path,
+                        cutoff,
+                        config.mtime_fallback(),
+                        config.year_format(),
+                        &log_options,
+                        log_options.author_regex().ok()?.as_ref(),
+                        &self.ignored_revs,
+                    )
+                    .await
+                    .ok()?,
+                };
+                let years = open_years(clamp_years(years, config.min_year()), config.open_end());
+                self.years.insert(uri.to_owned(), years.clone());
+                years
+            }
+        };
+        let text = self.documents.get(uri)?;
+        let lines: Vec<&str> = text.lines().collect();
+        let banner_sign = config.insert_after_banner().then_some(comment_signs[0]);
+        let insert_after = config.get_insert_after(&rel_path);
+        let header_lines = header_lines(
+            &lines,
+            banner_sign,
+            insert_after,
+            config.get_scan_lines(&rel_path),
+        );
+        // Exempt documents get neither diagnostics nor code actions
+        if find_keep_pattern(&lines, header_lines, config.keep_regexes()).is_some() {
+            return None;
+        }
+        let (outcome, sign_idx) = check_header(
+            filepath,
+            lines.iter(),
+            header_lines,
+            &regexes,
+            years.clone(),
+        );
+        let mut copyright_line = generate_copyright_line(
+            holder,
+            &rel_path,
+            comment_signs[sign_idx],
+            config,
+            futures::future::ready(years),
+        )
+        .await;
+        let line_nr = outcome
+            .line_nr
+            .unwrap_or_else(|| insertion_line_nr(&lines, banner_sign, insert_after));
+        if let FileStatus::Outdated { .. } = outcome.status {
+            if let Some(updated) =
+                replace_years(lines[line_nr], &regexes[sign_idx], &outcome.expected_years)
+            {
+                copyright_line = updated;
+            }
+        }
+        let line_len = text
+            .lines()
+            .nth(line_nr)
+            .map(|line_| line_.encode_utf16().count())
+            .unwrap_or_default();
+
+        Some(DocumentCheck {
+            outcome,
+            copyright_line,
+            line_nr,
+            line_len,
+        })
+    }
+}
+
+fn diagnostics(check: &DocumentCheck) -> Vec<Value> {
+    if !check.outcome.status.needs_fix() {
+        return vec![];
+    }
+
+    vec![json!({
+        "range": line_range(check),
+        "severity": 2,
+        "source": env!("CARGO_PKG_NAME"),
+        "message": check.outcome.short_message(),
+    })]
+}
+
+fn code_actions(uri: &str, check: &DocumentCheck) -> Vec<Value> {
+    let (title, edit) = match check.outcome.status {
+        FileStatus::Correct
+        | FileStatus::Exempt { .. }
+        | FileStatus::ReadOnly
+        | FileStatus::NoHeader
+        | FileStatus::TemplateDrift { .. }
+        | FileStatus::SpdxMismatch { .. } => return vec![],
+        FileStatus::Outdated { .. } => (
+            "Update copyright",
+            json!({
+                "range": line_range(check),
+                // Only the copyright line of an existing header is updated
+                "newText": check.copyright_line.lines().next().unwrap_or_default(),
+            }),
+        ),
+        FileStatus::Missing => {
+            let position = json!({"line": check.line_nr, "character": 0});
+            (
+                "Add copyright",
+                json!({
+                    "range": {"start": position, "end": position},
+                    "newText": format!("{}\n", check.copyright_line),
+                }),
+            )
+        }
+    };
+
+    vec![json!({
+        "title": title,
+        "kind": "quickfix",
+        "diagnostics": diagnostics(check),
+        "edit": {"changes": {uri: [edit]}},
+    })]
+}
+
+fn line_range(check: &DocumentCheck) -> Value {
+    json!({
+        "start": {"line": check.line_nr, "character": 0},
+        "end": {"line": check.line_nr, "character": check.line_len},
+    })
+}
+
+fn response(id: &Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+/// Convert a `file://` URI to a path, decoding percent-encoded bytes.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            other => bytes.push(other),
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+
+    // Windows URIs look like `file:///C:/path`
+    match path.as_bytes() {
+        [b'/', _, b':', ..] => Some(PathBuf::from(&path[1..])),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+async fn read_message<R>(reader: &mut R) -> Result<Option<Value>, CError>
+where
+    R: AsyncBufReadExt + AsyncReadExt + Unpin,
+{
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+
+    let content_length = content_length
+        .ok_or_else(|| CError::LspError("Message without Content-Length header".into()))?;
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| CError::LspError(format!("Could not parse message: {}", e)))
+}
+
+async fn write_message<W>(writer: &mut W, message: &Value) -> Result<(), CError>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    let body = message.to_string();
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+        .await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{read_message, uri_to_path, write_message};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_uri_to_path() {
+        assert_eq!(
+            uri_to_path("file:///home/user/my%20repo/main.rs"),
+            Some(PathBuf::from("/home/user/my repo/main.rs"))
+        );
+        assert_eq!(
+            uri_to_path("file:///C:/repo/main.rs"),
+            Some(PathBuf::from("C:/repo/main.rs"))
+        );
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_message_roundtrip() {
+        let message = json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"});
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &message).await.unwrap();
+
+        let mut reader = tokio::io::BufReader::new(buffer.as_slice());
+        assert_eq!(read_message(&mut reader).await.unwrap(), Some(message));
+        assert_eq!(read_message(&mut reader).await.unwrap(), None);
+    }
+}
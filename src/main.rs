@@ -1,56 +1,498 @@
 //! Add/update copyright notes according to history.
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use env_logger::TimestampPrecision;
-use git_copyright::{check_repo_copyright, Config};
+use clap::{CommandFactory, ErrorKind, Parser, Subcommand};
+use env_logger::Target;
+#[cfg(feature = "archive")]
+use git_copyright::archive::ExtractedArchive;
+use git_copyright::config::{DateSource, GitBackend};
+use git_copyright::doctor::Severity;
+use git_copyright::fs::{find_repos, normalize_repo_path};
+#[cfg(feature = "remote")]
+use git_copyright::remote::RemoteRepo;
+use git_copyright::report::FileOutcome;
+use git_copyright::{
+    check_repos_copyright, doctor, lsp, CheckOptions, Config, CopyrightChecker, OutputFormat,
+    WriteMode,
+};
+use log::{Level, LevelFilter};
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 struct Args {
     /// Path to repository to check
-    #[clap(short, long, default_value = "./")]
+    #[clap(short, long, global = true, default_value = "./")]
     repo: String,
 
-    /// Name in copyright
-    #[clap(short, long)]
-    name: String,
+    /// Name in copyright, defaults to `name` of the config
+    #[clap(short, long, global = true)]
+    name: Option<String>,
 
-    /// YAML file with config to use
-    #[clap(short, long, default_value = "")]
+    /// YAML, TOML or JSON file with config to use, defaults to `.git-copyright.yml` at the repository root
+    #[clap(short, long, global = true, default_value = "")]
     config: String,
 
     /// Do not fail even if tracked files changed
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     ignore_changes: bool,
+
+    /// List the files changed by the run and fail if there are any (default)
+    #[clap(long, global = true, conflicts_with = "ignore-changes")]
+    fail_on_diff: bool,
+
+    /// Output format of the file results
+    #[clap(
+        short,
+        long,
+        visible_alias = "output-format",
+        global = true,
+        value_enum,
+        default_value = "human"
+    )]
+    format: OutputFormat,
+
+    /// Write the results in the output format to this file and print human-readable results
+    #[clap(long, global = true)]
+    report_file: Option<PathBuf>,
+
+    /// Fail only if less than this percentage of files has a correct copyright
+    #[clap(long, global = true)]
+    min_coverage: Option<f64>,
+
+    /// Never write files, only report missing or outdated copyrights and fail if there are any
+    #[clap(long, visible_alias = "check", global = true)]
+    dry_run: bool,
+
+    /// Only add missing copyrights, never update existing ones
+    #[clap(long, global = true)]
+    add_missing_only: bool,
+
+    /// Check all files first, then list the pending modifications and write them after confirmation
+    #[clap(long, global = true)]
+    defer_writes: bool,
+
+    /// Write deferred modifications without asking for confirmation
+    #[clap(short, long, global = true)]
+    yes: bool,
+
+    /// Only check files with these extensions, e.g. `rs,py`
+    #[clap(long, global = true, value_delimiter = ',')]
+    ext: Vec<String>,
+
+    /// Do not check files with these extensions, e.g. `md,yml`
+    #[clap(long, global = true, value_delimiter = ',')]
+    exclude_ext: Vec<String>,
+
+    /// Append a markdown summary to `$GITHUB_STEP_SUMMARY` when running in GitHub Actions
+    #[clap(long, global = true)]
+    step_summary: bool,
+
+    /// Only check files changed since the last fully checked commit, tracked in git notes
+    #[clap(long, global = true)]
+    incremental: bool,
+
+    /// Only check files added or modified since `HEAD` diverged from this ref, e.g. `origin/main`
+    #[clap(long, global = true)]
+    since_ref: Option<String>,
+
+    /// Run the git binary or use libgit2 to list files and read the history, overriding the config
+    #[clap(long, global = true, value_enum)]
+    git_backend: Option<GitBackend>,
+
+    /// Take years from the committer or author dates of commits, overriding the config
+    #[clap(long, global = true, value_enum)]
+    date_source: Option<DateSource>,
+
+    /// Check the files on this ref and take years only from its history, e.g. a release branch,
+    /// overriding the config
+    #[clap(long, global = true)]
+    history_ref: Option<String>,
+
+    /// Only count the commits of authors matching this regex for the years, matched against
+    /// `Name <email>`, e.g. `@example\.com>$`, overriding the config
+    #[clap(long, global = true)]
+    author_filter: Option<String>,
+
+    /// Fail instead of warning if replace refs, grafts or rewritten history are detected
+    #[clap(long, global = true)]
+    fail_on_rewritten_history: bool,
+
+    /// Check a source release archive (.tar, .tar.gz, .tgz or .zip) with years from its entry timestamps
+    #[cfg(feature = "archive")]
+    #[clap(long, global = true, conflicts_with = "recursive-repos")]
+    archive: Option<PathBuf>,
+
+    /// Write the fixed files of `--archive` to this archive
+    #[cfg(feature = "archive")]
+    #[clap(long, global = true, requires = "archive")]
+    archive_out: Option<PathBuf>,
+
+    /// Check a repository on GitHub or GitLab via its API, e.g. `https://github.com/owner/repo`
+    #[cfg(feature = "remote")]
+    #[clap(long, global = true, conflicts_with = "recursive-repos")]
+    remote: Option<String>,
+
+    /// Branch, tag or commit of `--remote` to check, defaults to the default branch
+    #[cfg(feature = "remote")]
+    #[clap(long, global = true, requires = "remote")]
+    remote_ref: Option<String>,
+
+    /// Check every git repository in and below `--repo` with its own config, skipping submodules
+    #[clap(long, global = true)]
+    recursive_repos: bool,
+
+    /// Check that the lines of the `header` template follow copyrights verbatim and restore them on fix
+    #[clap(long, global = true)]
+    verify_template: bool,
+
+    /// Check at most this many files at once, bounding git processes and open files
+    #[clap(short, long, global = true)]
+    jobs: Option<NonZeroUsize>,
+
+    /// Do not show a progress bar while checking files, e.g. in CI logs
+    #[clap(long, global = true)]
+    no_progress: bool,
+
+    /// Only print warnings and errors, e.g. no progress or results of files
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print debug output like the config used and ignored files, twice for trace output
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Run as language server on stdin/stdout
+    #[clap(long)]
+    lsp: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check copyrights without changing files and fail if any is missing or outdated
+    Check,
+    /// Add missing and update outdated copyrights
+    Fix,
+    /// Update the years in the top-level LICENSE/COPYING file from the repository history
+    License,
+    /// Diagnose git, the repository, the config and write permissions
+    Doctor,
+    /// List every copyright holder found in file headers with the number of files
+    Holders,
+    /// Remove the copyright lines of the name from file headers, e.g. when moving to a central
+    /// LICENSE file
+    Remove,
+    /// Rewrite the copyrights of a former holder to a new one keeping the years, e.g. after a
+    /// corporate rename
+    Migrate {
+        /// Holder named in the copyrights to rewrite
+        #[clap(long)]
+        from: String,
+        /// Holder to name instead
+        #[clap(long)]
+        to: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    args.repo = normalize_repo_path(&args.repo);
 
-    env_logger::builder()
-        .format_timestamp(Some(TimestampPrecision::Millis))
-        .init();
+    init_logger(&args);
 
-    match args.config.as_str() {
-        "" => {
-            log::info!("Using default configuration");
-            Config::default().assign();
+    #[cfg(feature = "archive")]
+    let archive = match &args.archive {
+        Some(archive) => {
+            if args.archive_out.is_none()
+                && !args.dry_run
+                && !matches!(args.command, Some(Command::Check))
+            {
+                Args::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "The argument '--archive-out <ARCHIVE_OUT>' is required to fix an archive",
+                    )
+                    .exit()
+            }
+            let extracted = ExtractedArchive::extract(archive)
+                .with_context(|| format!("Unable to extract archive {}", archive.display()))?;
+            args.repo = extracted.dir().display().to_string();
+            Some(extracted)
         }
-        cfg_file => {
-            log::info!("Using config {}", cfg_file);
-            Config::from_file(cfg_file)
-                .context(format!("Unable to get config from file {}", cfg_file))?
-                .assign();
+        None => None,
+    };
+
+    let cfg_file = match args.config.as_str() {
+        "" => Config::discover(&args.repo),
+        cfg_file => Some(PathBuf::from(cfg_file)),
+    };
+    match &cfg_file {
+        Some(cfg_file) => log::debug!("Using config {}", cfg_file.display()),
+        None => log::debug!("Using default configuration"),
+    }
+    // The doctor reports an invalid config instead of failing on it
+    if let Some(Command::Doctor) = args.command {
+        let findings = doctor::diagnose(&args.repo, cfg_file.as_deref()).await;
+        for finding in findings
+            .iter()
+            .filter(|finding| !args.quiet || finding.severity != Severity::Ok)
+        {
+            println!("{}", finding.human_message());
         }
+        return Ok(doctor::check_findings(&findings)?);
     }
 
+    let mut cfg = Config::load(cfg_file.as_deref()).context(match &cfg_file {
+        Some(cfg_file) => format!("Unable to get config from file {}", cfg_file.display()),
+        None => "Unable to load the default configuration".to_owned(),
+    })?;
+    // Archives have no history, their years are taken from the entry timestamps
+    #[cfg(feature = "archive")]
+    if archive.is_some() {
+        cfg = cfg.with_mtime_fallback(true);
+    }
+    if let Some(git_backend) = args.git_backend {
+        cfg = cfg.with_git_backend(git_backend);
+    }
+    if let Some(date_source) = args.date_source {
+        cfg = cfg.with_date_source(date_source);
+    }
+    if let Some(history_ref) = &args.history_ref {
+        cfg = cfg.with_history_ref(Some(history_ref.clone()));
+    }
+    if let Some(author_filter) = &args.author_filter {
+        cfg = cfg.with_author_filter(Some(author_filter.clone()));
+    }
+    let mut checker = CopyrightChecker::new(cfg);
+    if let Some(name) = &args.name {
+        checker = checker.with_name(name.as_str());
+    }
+
+    if args.verify_template && checker.config().header_template().is_none() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "Verifying the header template requires `header` in the config",
+            )
+            .exit()
+    }
+
+    if args.lsp {
+        log::debug!("Starting language server");
+        return Ok(lsp::run_server(&args.repo, required_name(&checker), checker.config()).await?);
+    }
+
+    let command = args.command.take().unwrap_or_else(|| {
+        log::warn!("Running without subcommand is deprecated, use `git_copyright fix` instead");
+        Command::Fix
+    });
+
     let start = Instant::now();
-    check_repo_copyright(&args.repo, &args.name, !args.ignore_changes).await?;
+    let options = CheckOptions {
+        fail_on_diff: args.fail_on_diff || !args.ignore_changes,
+        format: args.format,
+        write_mode: match (&command, args.add_missing_only) {
+            (Command::Check, _) => WriteMode::Never,
+            _ if args.dry_run => WriteMode::Never,
+            (_, true) => WriteMode::MissingOnly,
+            (_, false) => WriteMode::All,
+        },
+        report_file: args.report_file.clone(),
+        min_coverage: args.min_coverage,
+        defer_writes: args.defer_writes,
+        assume_yes: args.yes,
+        confirm_writes: Some(confirm_writes),
+        extensions: args.ext.clone(),
+        excluded_extensions: args.exclude_ext.clone(),
+        fail_on_rewritten_history: args.fail_on_rewritten_history,
+        incremental: args.incremental,
+        step_summary: args.step_summary,
+        verify_template: args.verify_template,
+        since_ref: args.since_ref.clone(),
+        quiet: false,
+        print_results: Some(print_results),
+        jobs: args.jobs.map(NonZeroUsize::get),
+        progress: !args.no_progress && !args.quiet,
+    };
+    let checker = checker.with_options(options);
+    #[cfg(feature = "remote")]
+    if let Some(remote) = &args.remote {
+        if !matches!(command, Command::Check) && !args.dry_run {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "Remote repositories can only be checked, use `git_copyright check --remote`",
+                )
+                .exit()
+        }
+        let remote = RemoteRepo::parse(remote, args.remote_ref.clone())?;
+        required_name(&checker);
+        checker.check_remote(remote).await?;
+        return Ok(());
+    }
+    if let Command::Holders = command {
+        for (holder, count) in checker.list_holders(&args.repo).await? {
+            println!("{:>6}  {}", count, holder);
+        }
+        return Ok(());
+    }
+
+    if args.recursive_repos {
+        if !matches!(command, Command::Check | Command::Fix) {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "Nested repositories can only be checked or fixed",
+                )
+                .exit()
+        }
+        let mut repos = Vec::new();
+        for repo in find_repos(Path::new(&args.repo))? {
+            let repo = repo.display().to_string();
+            let cfg_file = match args.config.as_str() {
+                "" => Config::discover(&repo),
+                cfg_file => Some(PathBuf::from(cfg_file)),
+            };
+            let mut cfg = Config::load(cfg_file.as_deref())
+                .with_context(|| format!("Unable to get config of repository {}", repo))?;
+            if let Some(git_backend) = args.git_backend {
+                cfg = cfg.with_git_backend(git_backend);
+            }
+            if let Some(date_source) = args.date_source {
+                cfg = cfg.with_date_source(date_source);
+            }
+            if let Some(history_ref) = &args.history_ref {
+                cfg = cfg.with_history_ref(Some(history_ref.clone()));
+            }
+            if let Some(author_filter) = &args.author_filter {
+                cfg = cfg.with_author_filter(Some(author_filter.clone()));
+            }
+            repos.push((repo, cfg));
+        }
+        check_repos_copyright(repos, args.name.as_deref(), checker.options()).await?;
+    } else {
+        match command {
+            Command::Check | Command::Fix => {
+                required_name(&checker);
+                checker.check_repo(&args.repo).await?
+            }
+            Command::License => checker.check_license(&args.repo).await?,
+            Command::Remove => {
+                required_name(&checker);
+                for (filepath, line_nr) in checker.remove_copyrights(&args.repo).await? {
+                    match checker.options().write_mode {
+                        WriteMode::Never => {
+                            log::info!("Found copyright in {} on line {}", filepath, line_nr + 1)
+                        }
+                        _ => log::info!(
+                            "Removed copyright from {} on line {}",
+                            filepath,
+                            line_nr + 1
+                        ),
+                    }
+                }
+            }
+            Command::Migrate { from, to } => {
+                for (filepath, line_nr) in checker.migrate_holder(&args.repo, &from, &to).await? {
+                    match checker.options().write_mode {
+                        WriteMode::Never => log::info!(
+                            "Found copyright of {} in {} on line {}",
+                            from,
+                            filepath,
+                            line_nr + 1
+                        ),
+                        _ => log::info!(
+                            "Migrated copyright in {} on line {} to {}",
+                            filepath,
+                            line_nr + 1,
+                            to
+                        ),
+                    }
+                }
+            }
+            Command::Holders => unreachable!("holders are listed before checking"),
+            Command::Doctor => unreachable!("doctor runs before loading the config"),
+        }
+    }
+    #[cfg(feature = "archive")]
+    if let (Some(archive), Some(archive_out)) = (&archive, &args.archive_out) {
+        archive
+            .pack(archive_out)
+            .with_context(|| format!("Unable to write archive {}", archive_out.display()))?;
+    }
     let duration_s = start.elapsed().as_millis() as f32 / 1000.0;
-    println!("Copyrights checked and updated in {:0.3}s", duration_s);
+    log::info!("Copyrights checked and updated in {:0.3}s", duration_s);
 
     Ok(())
 }
+
+/// Log progress and results as plain lines at the verbosity of `--quiet` and
+/// `--verbose`, which `RUST_LOG` overrides.
+fn init_logger(args: &Args) {
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    // Keep machine-readable output and the language server protocol on
+    // stdout parseable
+    let human_stdout = args.format == OutputFormat::Human || args.report_file.is_some();
+    let target = match human_stdout && !args.lsp {
+        true => Target::Stdout,
+        false => Target::Stderr,
+    };
+    env_logger::builder()
+        .filter_level(level)
+        .parse_default_env()
+        .target(target)
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            Level::Error => writeln!(buf, "error: {}", record.args()),
+            level => writeln!(
+                buf,
+                "[{} {} {}] {}",
+                buf.timestamp_millis(),
+                buf.default_styled_level(level),
+                record.target(),
+                record.args()
+            ),
+        })
+        .init();
+}
+
+/// Print machine-readable results to stdout.
+fn print_results(text: &str) {
+    print!("{}", text);
+}
+
+/// Ask on stdin whether to write the pending modifications, defaulting to no.
+fn confirm_writes(pending: &[&FileOutcome]) -> bool {
+    print!("Write {} files? [y/N] ", pending.len());
+    let mut answer = String::new();
+    std::io::stdout().flush().is_ok()
+        && std::io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Name in copyright from the arguments or the config, exiting with a usage
+/// error if there is none.
+fn required_name(checker: &CopyrightChecker) -> &str {
+    match checker.name() {
+        Some(name) => name,
+        None => Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "The argument '--name <NAME>' is required",
+            )
+            .exit(),
+    }
+}
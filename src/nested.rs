@@ -0,0 +1,163 @@
+//! Configs in subdirectories of a repository.
+//!
+//! A config file named like one of `REPO_CONFIG_FILES` in a subdirectory
+//! overrides the config of the repository for the files below it, e.g. for
+//! the subprojects of a monorepo. It is layered on the config of the closest
+//! parent directory like a config extending it. Its ignore patterns are
+//! matched against paths relative to its directory, while policies, special
+//! files, the history settings and the year format of the repository config
+//! apply to all files.
+
+use crate::config::Config;
+use crate::CError;
+#[cfg(feature = "yaml")]
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Config of the files below a subdirectory.
+#[derive(Debug)]
+pub struct NestedConfig {
+    /// Directory relative to the repository root with a trailing `/`.
+    pub dir: String,
+    pub config: Config,
+}
+
+impl NestedConfig {
+    /// Path of the file relative to the directory of the config if the file
+    /// is below it.
+    pub fn relative_path<'a>(&self, filepath: &'a str) -> Option<&'a str> {
+        filepath.strip_prefix(self.dir.as_str())
+    }
+}
+
+/// Load the nested configs among the files of the repository.
+///
+/// Config files which the config of their parent directory ignores are
+/// skipped together with the files below them.
+#[cfg(feature = "yaml")]
+pub fn load_nested_configs(
+    config: &Config,
+    repo_path: &Path,
+    files: &[String],
+) -> Result<Vec<NestedConfig>, CError> {
+    use crate::config::REPO_CONFIG_FILES;
+
+    // Config file of each directory by the precedence of its name
+    let mut cfg_files: BTreeMap<&str, (usize, &String)> = BTreeMap::new();
+    for filepath in files {
+        let Some((dir, filename)) = filepath.rsplit_once('/') else {
+            continue;
+        };
+        if let Some(rank) = REPO_CONFIG_FILES.iter().position(|name| *name == filename) {
+            let entry = cfg_files.entry(dir).or_insert((rank, filepath));
+            if rank < entry.0 {
+                *entry = (rank, filepath);
+            }
+        }
+    }
+    let mut cfg_files: Vec<(&str, &String)> = cfg_files
+        .into_iter()
+        .map(|(dir, (_, filepath))| (dir, filepath))
+        .collect();
+    cfg_files.sort_by_key(|(dir, _)| dir.matches('/').count());
+
+    let mut nested: Vec<NestedConfig> = Vec::new();
+    for (dir, filepath) in cfg_files {
+        let (parent_config, parent_path) = match closest_config(&nested, filepath) {
+            Some(parent) => (&parent.config, parent.relative_path(filepath)),
+            None => (config, None),
+        };
+        if parent_config.is_ignored(parent_path.unwrap_or(filepath)) {
+            continue;
+        }
+
+        log::debug!("Using nested config {}", filepath);
+        let config = parent_config
+            .nested(&repo_path.join(filepath))
+            .map_err(|e| {
+                CError::ConfigError(format!("Invalid nested config {}: {}", filepath, e))
+            })?;
+        nested.push(NestedConfig {
+            dir: format!("{}/", dir),
+            config,
+        });
+    }
+    Ok(nested)
+}
+
+/// Without YAML support, config files cannot be read and nested configs are
+/// not used.
+#[cfg(not(feature = "yaml"))]
+pub fn load_nested_configs(
+    _config: &Config,
+    _repo_path: &Path,
+    _files: &[String],
+) -> Result<Vec<NestedConfig>, CError> {
+    Ok(Vec::new())
+}
+
+/// Nested config of the closest directory above the file, if any.
+pub fn closest_config<'a>(nested: &'a [NestedConfig], filepath: &str) -> Option<&'a NestedConfig> {
+    nested
+        .iter()
+        .filter(|nested| filepath.starts_with(nested.dir.as_str()))
+        .max_by_key(|nested| nested.dir.len())
+}
+
+#[cfg(all(test, feature = "yaml"))]
+mod test {
+
+    use super::{closest_config, load_nested_configs};
+    use crate::Config;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_nested_configs() {
+        let repo_dir = std::env::temp_dir().join("git_copyright_test_nested");
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        for dir in ["web", "web/legacy", "vendor/lib"] {
+            std::fs::create_dir_all(repo_dir.join(dir)).unwrap();
+        }
+        std::fs::write(
+            repo_dir.join("web/.git-copyright.yml"),
+            "comment_sign_map:\n  vue: [\"<!--\", \"-->\"]\nignore_files: [\"dist/*\"]\nname: Web",
+        )
+        .unwrap();
+        std::fs::write(
+            repo_dir.join("web/legacy/.git-copyright.json"),
+            r#"{"ignore_files": []}"#,
+        )
+        .unwrap();
+        std::fs::write(repo_dir.join("vendor/lib/.git-copyright.yml"), "name: [").unwrap();
+        let files: Vec<String> = [
+            "web/.git-copyright.yml",
+            "web/legacy/.git-copyright.json",
+            "vendor/lib/.git-copyright.yml",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        let config = Config::from_str(
+            "comment_sign_map:\n  rs: \"//\"\nignore_files: []\nignore_dirs: [\"vendor/*\"]\nname: Root",
+        )
+        .unwrap();
+        let nested = load_nested_configs(&config, &repo_dir, &files).unwrap();
+        assert_eq!(nested.len(), 2);
+
+        let web = closest_config(&nested, "web/src/App.vue").unwrap();
+        assert_eq!(web.dir, "web/");
+        assert_eq!(web.config.name(), Some("Web"));
+        assert!(web.config.get_comment_sign("App.vue").is_ok());
+        assert!(web.config.get_comment_sign("main.rs").is_ok());
+        assert!(web
+            .config
+            .is_ignored(web.relative_path("web/dist/app.js").unwrap()));
+        assert!(!web.config.is_ignored("src/dist/app.js"));
+
+        let legacy = closest_config(&nested, "web/legacy/dist/app.js").unwrap();
+        assert_eq!(legacy.config.name(), Some("Web"));
+        assert!(!legacy.config.is_ignored("dist/app.js"));
+        assert!(closest_config(&nested, "src/main.rs").is_none());
+        std::fs::remove_dir_all(repo_dir).unwrap();
+    }
+}
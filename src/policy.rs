@@ -0,0 +1,201 @@
+//! Policy rules of the config which require actions for some files.
+//!
+//! A rule applies to a file if all of its conditions hold: the path matches
+//! one of the `paths` globs, the extension is one of `extensions` and the
+//! copyright holder of the run is one of `holders`. Empty conditions always
+//! hold. Fixed years replace the years from the history and files which must
+//! not have a header are never fixed, all other actions are verified on the
+//! header after fixing.
+
+use glob::Pattern;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyRule {
+    /// Name of the rule in reported violations.
+    pub name: String,
+    /// Glob patterns of the paths the rule applies to.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Extensions of the files the rule applies to.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Copyright holders the rule applies to.
+    #[serde(default)]
+    pub holders: Vec<String>,
+    pub action: PolicyAction,
+    #[serde(skip)]
+    globs: Vec<Pattern>,
+}
+
+/// Action required by a policy rule.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// The file must have a copyright header.
+    MustHaveHeader,
+    /// The file must not have a copyright header and is never fixed.
+    MustNotHaveHeader,
+    /// The copyright must have these years instead of the ones from the history.
+    FixedYears(String),
+    /// The header must name this license, e.g. as `SPDX-License-Identifier`.
+    License(String),
+}
+
+/// Violation of a policy rule by a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    pub filepath: PathBuf,
+    pub rule: String,
+    pub message: String,
+}
+
+impl PolicyViolation {
+    pub fn human_message(&self) -> String {
+        format!(
+            "File {} violates policy {}: {}",
+            self.filepath.display(),
+            self.rule,
+            self.message
+        )
+    }
+}
+
+impl PolicyRule {
+    pub fn new(name: &str, action: PolicyAction) -> Self {
+        PolicyRule {
+            name: name.to_owned(),
+            paths: Vec::new(),
+            extensions: Vec::new(),
+            holders: Vec::new(),
+            action,
+            globs: Vec::new(),
+        }
+    }
+
+    pub fn with_paths(mut self, paths: Vec<String>) -> Self {
+        self.paths = paths;
+        self.build_globs();
+        self
+    }
+
+    pub fn with_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    pub fn with_holders(mut self, holders: Vec<String>) -> Self {
+        self.holders = holders;
+        self
+    }
+
+    pub(crate) fn build_globs(&mut self) {
+        self.globs = self
+            .paths
+            .iter()
+            .filter_map(|expr| match Pattern::new(expr) {
+                Ok(pattern) => Some(pattern),
+                Err(_) => {
+                    log::error!("Could not compile pattern {}", expr);
+                    None
+                }
+            })
+            .collect();
+    }
+
+    /// Whether the rule applies to the file with the given copyright holder.
+    pub fn applies_to(&self, filepath: &str, holder: &str) -> bool {
+        let ext = Path::new(filepath)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        (self.paths.is_empty() || self.globs.iter().any(|glob| glob.matches(filepath)))
+            && (self.extensions.is_empty() || self.extensions.iter().any(|e| e == ext))
+            && (self.holders.is_empty() || self.holders.iter().any(|h| h == holder))
+    }
+
+    /// Verify the action of the rule on the header lines of a file.
+    pub fn check<S: AsRef<str>>(&self, filepath: &Path, lines: &[S]) -> Option<PolicyViolation> {
+        let has_header = lines
+            .iter()
+            .any(|line_| line_.as_ref().to_lowercase().contains("copyright"));
+        let message = match &self.action {
+            PolicyAction::MustHaveHeader if !has_header => "copyright header is missing".to_owned(),
+            PolicyAction::MustNotHaveHeader if has_header => {
+                "file must not have a copyright header".to_owned()
+            }
+            PolicyAction::License(license)
+                if !lines
+                    .iter()
+                    .any(|line_| line_.as_ref().contains(license.as_str())) =>
+            {
+                format!("header does not name license {}", license)
+            }
+            _ => return None,
+        };
+
+        Some(PolicyViolation {
+            filepath: filepath.to_owned(),
+            rule: self.name.clone(),
+            message,
+        })
+    }
+}
+
+/// Fixed years of the first rule requiring them for the file.
+pub fn fixed_years<'a>(rules: &'a [PolicyRule], filepath: &str, holder: &str) -> Option<&'a str> {
+    rules
+        .iter()
+        .filter(|rule| rule.applies_to(filepath, holder))
+        .find_map(|rule| match &rule.action {
+            PolicyAction::FixedYears(years) => Some(years.as_str()),
+            _ => None,
+        })
+}
+
+/// Whether a rule forbids a copyright header in the file.
+pub fn forbids_header(rules: &[PolicyRule], filepath: &str, holder: &str) -> bool {
+    rules.iter().any(|rule| {
+        rule.action == PolicyAction::MustNotHaveHeader && rule.applies_to(filepath, holder)
+    })
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{fixed_years, forbids_header, PolicyAction, PolicyRule};
+    use std::path::Path;
+
+    #[test]
+    fn test_policy_rules() {
+        let rules = [
+            PolicyRule::new("vendored", PolicyAction::MustNotHaveHeader)
+                .with_paths(vec!["third_party/**".into()]),
+            PolicyRule::new("legacy", PolicyAction::FixedYears("2015".into()))
+                .with_extensions(vec!["py".into()])
+                .with_holders(vec!["Old Corp".into()]),
+            PolicyRule::new("apache", PolicyAction::License("Apache-2.0".into())),
+        ];
+
+        assert!(forbids_header(&rules, "third_party/lib/a.c", "Foo"));
+        assert!(!forbids_header(&rules, "src/a.c", "Foo"));
+        assert_eq!(fixed_years(&rules, "tool.py", "Old Corp"), Some("2015"));
+        assert_eq!(fixed_years(&rules, "tool.py", "Foo"), None);
+        assert_eq!(fixed_years(&rules, "tool.rs", "Old Corp"), None);
+
+        let filepath = Path::new("a.rs");
+        let lines = [
+            "// Copyright (c) Foo 2022",
+            "// SPDX-License-Identifier: MIT",
+        ];
+        assert!(rules[0].check(filepath, &lines).is_some());
+        assert_eq!(
+            rules[2].check(filepath, &lines).unwrap().message,
+            "header does not name license Apache-2.0"
+        );
+        assert!(rules[2]
+            .check(filepath, &["// SPDX-License-Identifier: Apache-2.0"])
+            .is_none());
+    }
+}
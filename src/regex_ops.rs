@@ -1,82 +1,264 @@
 //! Compile and cache copyright regexes.
 //!
 //! This module contains functions to parse existing copyright notes. Regexes
-//! are compiled once per comment sign and stored in a cache.
+//! are compiled up front for every comment sign of the config and served
+//! from an immutable map, so that concurrent checks do not contend on a lock.
 
+use crate::config::{HolderMatch, YearFormat};
 use crate::get_hash;
+use crate::templates::{parse_copyright_template, render_copyright, render_line, TemplatePart};
 use crate::CError;
 use crate::CommentSign;
+use crate::Config;
 use regex::Regex;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::future::Future;
+use std::path::Path;
 use std::sync::Arc;
-use std::sync::RwLock;
+use unicode_normalization::UnicodeNormalization;
 
 pub struct CopyrightCache {
-    regexes: RwLock<HashMap<u64, Arc<Regex>>>,
+    regexes: HashMap<u64, Arc<Regex>>,
     base_regex: String,
 }
 
 impl CopyrightCache {
-    pub fn new(base_regex: &str) -> Self {
+    /// Compile the regexes for all given comment signs.
+    ///
+    /// Regexes which fail to compile are left out so that `get_regex` reports
+    /// the error for each file using the comment sign.
+    pub fn new<'a>(base_regex: &str, comment_signs: impl Iterator<Item = &'a CommentSign>) -> Self {
+        let mut regexes = HashMap::new();
+        for comment_sign in comment_signs {
+            if let Entry::Vacant(entry) = regexes.entry(get_hash(comment_sign)) {
+                log::debug!("Initializing regex for comment sign {:?}", &comment_sign);
+                match generate_comment_regex(base_regex, comment_sign) {
+                    Ok(regex) => {
+                        entry.insert(Arc::new(regex));
+                    }
+                    Err(e) => {
+                        log::debug!("Invalid regex for comment sign {:?}: {}", comment_sign, e)
+                    }
+                }
+            }
+        }
+
         CopyrightCache {
-            regexes: RwLock::new(HashMap::new()),
+            regexes,
             base_regex: base_regex.to_owned(),
         }
     }
 
+    /// Get the regex for a comment sign.
+    ///
+    /// Regexes of comment signs which were not known on creation are compiled
+    /// on every call.
     pub fn get_regex(&self, comment_sign: &CommentSign) -> Result<Arc<Regex>, CError> {
-        let c_sign_hash = get_hash(comment_sign);
+        match self.regexes.get(&get_hash(comment_sign)) {
+            Some(regex) => Ok(Arc::clone(regex)),
+            None => {
+                log::debug!(
+                    "Compiling regex for unknown comment sign {:?}",
+                    &comment_sign
+                );
+                Ok(Arc::new(generate_comment_regex(
+                    &self.base_regex,
+                    comment_sign,
+                )?))
+            }
+        }
+    }
+}
 
-        if let Some(regex) = self.regexes.read().unwrap().get(&c_sign_hash) {
-            return Ok(Arc::clone(regex));
+/// Copyright regexes of a config for the name and each of its `holders`.
+pub struct HolderRegexes {
+    name: String,
+    name_cache: CopyrightCache,
+    holder_caches: HashMap<String, CopyrightCache>,
+}
+
+impl HolderRegexes {
+    pub fn new(name: &str, config: &Config) -> Self {
+        let cache = |holder: &str| {
+            CopyrightCache::new(&generate_base_regex(holder, config), config.comment_signs())
+        };
+        let mut holder_caches = HashMap::new();
+        for holder in config.holders() {
+            if !holder_caches.contains_key(holder) {
+                holder_caches.insert(holder.to_owned(), cache(holder));
+            }
+        }
+        HolderRegexes {
+            name: name.to_owned(),
+            name_cache: cache(name),
+            holder_caches,
         }
+    }
 
-        log::debug!("Initializing regex for comment sign {:?}", &comment_sign);
-        let regex = Arc::new(generate_comment_regex(&self.base_regex, comment_sign)?);
-        self.regexes
-            .write()
-            .unwrap()
-            .insert(get_hash(comment_sign), Arc::clone(&regex));
-        Ok(regex)
+    /// Holder in copyrights of the file and the regexes matching them.
+    pub fn for_file<'a>(
+        &'a self,
+        config: &Config,
+        filepath: &str,
+    ) -> (&'a str, &'a CopyrightCache) {
+        config
+            .holder_for(filepath)
+            .and_then(|holder| self.holder_caches.get_key_value(holder))
+            .map_or((self.name.as_str(), &self.name_cache), |(holder, cache)| {
+                (holder.as_str(), cache)
+            })
     }
 }
 
-pub fn generate_base_regex(name: &str) -> String {
-    [
-        r"Copyright \(c\)",
-        &escape_for_regex(name),
-        r"(\d{4}(-\d{4}){0,1})",
+/// Generate the regex matching the copyright text without comment signs.
+///
+/// `Copyright` and `(c)` are matched case-insensitively so that legacy
+/// copyrights are recognized. Generated lines always use the regular casing.
+///
+/// The name is matched according to the `holder_match` setting, see
+/// `holder_regex`.
+///
+/// The years are captured in the first group. If a suffix is configured, it
+/// is matched optionally in the group `suffix` so that copyrights without
+/// the suffix are recognized and updated instead of duplicated.
+///
+/// With a copyright template, its text is matched literally instead and
+/// `{filename}` matches any file name since the regex is shared by all files.
+pub fn generate_base_regex(name: &str, config: &Config) -> String {
+    if let Some(template) = config.copyright_template() {
+        return parse_copyright_template(template)
+            .into_iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => regex::escape(text),
+                TemplatePart::Holder => holder_regex(name, config.holder_match()),
+                TemplatePart::Years => years_regex(config),
+                TemplatePart::Filename => r".+?".to_owned(),
+                TemplatePart::License => regex::escape(config.license().unwrap_or_default()),
+            })
+            .collect();
+    }
+
+    let base_regex = [
+        r"(?i:Copyright) (?:\([cC]\)|©)",
+        &holder_regex(name, config.holder_match()),
+        &years_regex(config),
     ]
-    .join(" ")
+    .join(" ");
+
+    match config.suffix() {
+        Some(suffix) => format!(r"{}(?P<suffix> {})?", base_regex, regex::escape(suffix)),
+        None => base_regex,
+    }
+}
+
+/// Years captured in the first group of copyright regexes.
+///
+/// Lists also accept ranges as items and single years also accept ranges so
+/// that copyrights written in the range format are updated after switching
+/// the format instead of getting a second copyright. With an open end, ranges
+/// may end with it instead of a year.
+pub fn years_regex(config: &Config) -> String {
+    match (config.year_format(), config.open_end()) {
+        (YearFormat::List, _) => r"(\d{4}(?:-\d{4})?(?:, \d{4}(?:-\d{4})?)*)".to_owned(),
+        (_, Some(open_end)) => format!(
+            r"(\d{{4}}(-(?:\d{{4}}|{})){{0,1}})",
+            regex::escape(open_end)
+        ),
+        (_, None) => r"(\d{4}(-\d{4}){0,1})".to_owned(),
+    }
 }
 
+/// Generate the commented copyright line of the file.
+///
+/// If the config selects a header template, its lines follow the copyright
+/// line, separated by newlines.
 pub async fn generate_copyright_line(
     name: &str,
+    filepath: &str,
     comment_sign: &CommentSign,
+    config: &Config,
     years_fut: impl Future<Output = String>,
 ) -> String {
     let years = years_fut.await;
-    match comment_sign {
-        CommentSign::LeftOnly(ref left) => [left, "Copyright (c)", name, &years].join(" "),
-        CommentSign::Enclosing(ref left, ref right) => {
-            [left, "Copyright (c)", name, &years, right].join(" ")
+    let name = match config.holder_match() {
+        HolderMatch::Exact => name.to_owned(),
+        HolderMatch::Normalized | HolderMatch::Fuzzy => compose(name),
+    };
+    let name = name.as_str();
+    let copyright = match config.copyright_template() {
+        Some(template) => {
+            let filename = Path::new(filepath)
+                .file_name()
+                .map(|filename| filename.to_string_lossy())
+                .unwrap_or_default();
+            render_copyright(
+                template,
+                name,
+                &years,
+                &filename,
+                config.license().unwrap_or_default(),
+            )
         }
+        None => {
+            let copyright = ["Copyright", config.copyright_sign(), name, &years].join(" ");
+            match config.suffix() {
+                Some(suffix) => [&copyright, suffix].join(" "),
+                None => copyright,
+            }
+        }
+    };
+
+    let mut lines = vec![comment_line(comment_sign, &copyright)];
+    if let Some(template) = config.header_template() {
+        lines.extend(
+            template
+                .lines()
+                .map(|line_| comment_line(comment_sign, &render_line(line_, name, &years))),
+        );
     }
+    lines.join("\n")
 }
 
-fn generate_comment_regex(base_regex: &str, comment_sign: &CommentSign) -> Result<Regex, CError> {
-    let full_regex_str = match comment_sign {
-        CommentSign::LeftOnly(left_sign) => {
-            ["^", &escape_for_regex(&left_sign), " ", base_regex, "$"].join("")
+/// Generate the commented `SPDX-License-Identifier` line of the license.
+pub fn generate_spdx_line(comment_sign: &CommentSign, spdx_license: &str) -> String {
+    comment_line(
+        comment_sign,
+        &format!("SPDX-License-Identifier: {}", spdx_license),
+    )
+}
+
+fn comment_line(comment_sign: &CommentSign, text: &str) -> String {
+    let separator = comment_sign.separator();
+    match (comment_sign.right(), text.is_empty()) {
+        (None, true) => comment_sign.left().to_owned(),
+        (None, false) => [comment_sign.left(), separator, text].join(""),
+        (Some(right), true) => [comment_sign.left(), separator, right].join(""),
+        (Some(right), false) => {
+            let right_separator = comment_sign.right_separator();
+            [comment_sign.left(), separator, text, right_separator, right].join("")
         }
-        CommentSign::Enclosing(left_sign, right_sign) => [
+    }
+}
+
+fn generate_comment_regex(base_regex: &str, comment_sign: &CommentSign) -> Result<Regex, CError> {
+    let separator = regex::escape(comment_sign.separator());
+    let full_regex_str = match comment_sign.right() {
+        None => [
             "^",
-            &escape_for_regex(&left_sign),
-            " ",
+            &regex::escape(comment_sign.left()),
+            &separator,
             base_regex,
-            " ",
-            &escape_for_regex(&right_sign),
+            "$",
+        ]
+        .join(""),
+        Some(right_sign) => [
+            "^",
+            &regex::escape(comment_sign.left()),
+            &separator,
+            base_regex,
+            &regex::escape(comment_sign.right_separator()),
+            &regex::escape(right_sign),
             "$",
         ]
         .join(""),
@@ -85,30 +267,126 @@ fn generate_comment_regex(base_regex: &str, comment_sign: &CommentSign) -> Resul
     Ok(Regex::new(&full_regex_str)?)
 }
 
-fn escape_for_regex(text: &str) -> String {
-    text.chars()
-        .map(|char| match char {
-            '*' => String::from(r"\*"),
-            '.' => String::from(r"\."),
-            other => String::from(other),
-        })
-        .collect::<Vec<String>>()
-        .as_slice()
-        .join("")
+/// Compose letters followed by combining marks into precomposed letters
+/// (NFC).
+pub fn compose(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Regex matching the copyright holder `name`.
+///
+/// With `normalized`, precomposed letters also match their decomposed form
+/// and whitespace matches any run of whitespace. With `fuzzy`, the words of
+/// the name are additionally matched case-insensitively and separated by
+/// any run of whitespace, commas and periods, optionally followed by a
+/// period, so that `Foo, Inc.` matches `Foo Inc`. The regex has no capture
+/// groups.
+pub fn holder_regex(name: &str, holder_match: HolderMatch) -> String {
+    let name = match holder_match {
+        HolderMatch::Exact => return regex::escape(name),
+        HolderMatch::Normalized => compose(name),
+        HolderMatch::Fuzzy => compose(name).replace([',', '.'], " "),
+    };
+
+    let words: Vec<String> = name
+        .split_whitespace()
+        .map(|word| word.chars().map(normalized_char_regex).collect())
+        .collect();
+    match holder_match {
+        HolderMatch::Fuzzy => format!(r"(?i:{})\.?", words.join(r"[\s,.]+")),
+        _ => words.join(r"\s+"),
+    }
+}
+
+/// Regex matching a character in composed or decomposed (NFD) form.
+fn normalized_char_regex(char: char) -> String {
+    let composed = char.to_string();
+    let decomposed: String = composed.nfd().collect();
+    match decomposed == composed {
+        true => regex::escape(&composed),
+        false => format!(
+            "(?:{}|{})",
+            regex::escape(&composed),
+            regex::escape(&decomposed)
+        ),
+    }
+}
+
+/// Extract copyright holders from header lines of any format.
+///
+/// A line is taken as copyright notice if `Copyright` is followed by a
+/// copyright sign or a year, so that mentions like `the above copyright
+/// notice` in license texts are skipped.
+pub struct HolderParser {
+    notice: Regex,
+    years: Regex,
+    rights_reserved: Regex,
+}
+
+impl HolderParser {
+    pub fn new() -> Self {
+        HolderParser {
+            notice: Regex::new(r"(?i)\bcopyright\b\s*(\(c\)|©)?\s*(.*)")
+                .expect("notice regex is valid"),
+            years: Regex::new(r"\b\d{4}(?:\s*[-–]\s*(?:\d{4}|(?i:present)))?\b,?")
+                .expect("years regex is valid"),
+            rights_reserved: Regex::new(r"(?i)\s*all rights reserved")
+                .expect("rights reserved regex is valid"),
+        }
+    }
+
+    /// The holder of the copyright notice in the line, if any.
+    ///
+    /// Years, `All rights reserved` and trailing comment signs are removed and
+    /// whitespace is collapsed, e.g. `/* Copyright (c) 2020, 2022 Foo Inc. */`
+    /// yields `Foo Inc.`.
+    pub fn parse(&self, line_: &str) -> Option<String> {
+        let captures = self.notice.captures(line_)?;
+        let rest = &captures[2];
+        if captures.get(1).is_none() && !self.years.is_match(rest) {
+            return None;
+        }
+
+        let rest = self.years.replace_all(rest, " ");
+        let rest = match self.rights_reserved.find(&rest) {
+            Some(found) => &rest[..found.start()],
+            None => &rest[..],
+        };
+        let holder = rest.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let holder = holder
+            .trim_end_matches(|c: char| c.is_whitespace() || "*/-#;!>,".contains(c))
+            .trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        let holder = holder.strip_prefix("by ").unwrap_or(holder).trim();
+        match holder.is_empty() {
+            true => None,
+            false => Some(holder.to_owned()),
+        }
+    }
+}
+
+impl Default for HolderParser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
+
 #[cfg(test)]
 mod test {
 
-    use super::escape_for_regex;
     use super::CommentSign;
-    use super::{generate_base_regex, generate_comment_regex};
+    use super::Config;
+    use super::HolderParser;
+    use super::{generate_base_regex, generate_comment_regex, generate_copyright_line};
+    use super::{CopyrightCache, HolderMatch, YearFormat};
+    use crate::CError;
     use regex::Regex;
+    use std::sync::Arc;
 
     #[test]
     fn test_generate_file_regex() {
         let file_header = "// Copyright (c) DummyCompany Ltd. 2020-2021";
         let regex = generate_comment_regex(
-            &generate_base_regex("DummyCompany Ltd."),
+            &generate_base_regex("DummyCompany Ltd.", &Config::default()),
             &CommentSign::LeftOnly("//".into()),
         )
         .unwrap();
@@ -116,12 +394,421 @@ mod test {
     }
 
     #[test]
-    fn test_escape_for_regex() {
-        assert_eq!(escape_for_regex("/"), r"/");
-        assert_eq!(escape_for_regex("//"), r"//");
-        assert_eq!(escape_for_regex("/*"), r"/\*");
-        assert_eq!(escape_for_regex("*/"), r"\*/");
-        assert_eq!(escape_for_regex("#"), "#");
+    fn test_copyright_cache() {
+        let base_regex = generate_base_regex("DummyCompany Ltd.", &Config::default());
+        let rust = CommentSign::LeftOnly("//".into());
+        let python = CommentSign::LeftOnly("#".into());
+        let comment_signs = [rust.clone(), python.clone(), rust.clone()];
+        let cache = CopyrightCache::new(&base_regex, comment_signs.iter());
+        assert_eq!(cache.regexes.len(), 2);
+
+        // Regexes of known comment signs are compiled once
+        let regex = cache.get_regex(&rust).unwrap();
+        assert!(Arc::ptr_eq(&regex, &cache.get_regex(&rust).unwrap()));
+        assert!(regex.is_match("// Copyright (c) DummyCompany Ltd. 2022"));
+        assert!(cache
+            .get_regex(&python)
+            .unwrap()
+            .is_match("# Copyright (c) DummyCompany Ltd. 2022"));
+
+        let unknown = CommentSign::Enclosing("<!--".into(), "-->".into());
+        let regex = cache.get_regex(&unknown).unwrap();
+        assert!(regex.is_match("<!-- Copyright (c) DummyCompany Ltd. 2022 -->"));
+        assert_eq!(cache.regexes.len(), 2);
+
+        // Comment signs with regex metacharacters are compiled as well
+        let haskell = CommentSign::Enclosing("{-".into(), "-}".into());
+        let cache = CopyrightCache::new(&base_regex, [&haskell].into_iter());
+        let regex = cache.get_regex(&haskell).unwrap();
+        assert!(regex.is_match("{- Copyright (c) DummyCompany Ltd. 2022 -}"));
+        assert_eq!(cache.regexes.len(), 1);
+
+        // Regexes which do not compile are reported when they are used
+        let cache = CopyrightCache::new("(", [&haskell].into_iter());
+        assert!(cache.regexes.is_empty());
+        assert!(cache.get_regex(&haskell).is_err());
+    }
+
+    #[test]
+    fn test_custom_separator() {
+        let comment_sign = CommentSign::Detailed {
+            left: "REM".into(),
+            right: None,
+            separator: "  ".into(),
+            right_separator: None,
+        };
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &Config::default()),
+            &comment_sign,
+        )
+        .unwrap();
+        let line = futures::executor::block_on(generate_copyright_line(
+            "DummyCompany Ltd.",
+            "main.rs",
+            &comment_sign,
+            &Config::default(),
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(line, "REM  Copyright (c) DummyCompany Ltd. 2022");
+        assert!(regex.is_match(&line));
+        assert!(!regex.is_match("REM Copyright (c) DummyCompany Ltd. 2022"));
+
+        let comment_sign = CommentSign::Detailed {
+            left: "(*".into(),
+            right: Some("*)".into()),
+            separator: "".into(),
+            right_separator: None,
+        };
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &Config::default()),
+            &comment_sign,
+        )
+        .unwrap();
+        let line = futures::executor::block_on(generate_copyright_line(
+            "DummyCompany Ltd.",
+            "main.rs",
+            &comment_sign,
+            &Config::default(),
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(line, "(*Copyright (c) DummyCompany Ltd. 2022*)");
+        assert!(regex.is_match(&line));
+        assert!(!regex.is_match("(*Copyright (c) DummyCompany Ltd. 2022"));
+
+        let comment_sign = CommentSign::Detailed {
+            left: "/*".into(),
+            right: Some("*/".into()),
+            separator: "".into(),
+            right_separator: Some("    ".into()),
+        };
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &Config::default()),
+            &comment_sign,
+        )
+        .unwrap();
+        let line = futures::executor::block_on(generate_copyright_line(
+            "DummyCompany Ltd.",
+            "main.rs",
+            &comment_sign,
+            &Config::default(),
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(line, "/*Copyright (c) DummyCompany Ltd. 2022    */");
+        assert!(regex.is_match(&line));
+        let cap = regex
+            .captures("/*Copyright (c) DummyCompany Ltd. 2019-2020    */")
+            .unwrap();
+        assert_eq!(cap.get(1).unwrap().as_str(), "2019-2020");
+        assert!(!regex.is_match("/*Copyright (c) DummyCompany Ltd. 2022*/"));
+
+        // Metacharacters next to the years are matched literally
+        for (left, right) in [("(", ")"), ("[", "]"), ("{", "}"), ("+", "+"), ("?", "?")] {
+            let comment_sign = CommentSign::Detailed {
+                left: left.into(),
+                right: Some(right.into()),
+                separator: "".into(),
+                right_separator: None,
+            };
+            let regex = generate_comment_regex(
+                &generate_base_regex("DummyCompany Ltd.", &Config::default()),
+                &comment_sign,
+            )
+            .unwrap();
+            let line = futures::executor::block_on(generate_copyright_line(
+                "DummyCompany Ltd.",
+                "main.rs",
+                &comment_sign,
+                &Config::default(),
+                futures::future::ready("2022".to_owned()),
+            ));
+            assert_eq!(
+                line,
+                format!("{left}Copyright (c) DummyCompany Ltd. 2022{right}")
+            );
+            let cap = regex.captures(&line).unwrap();
+            assert_eq!(cap.get(1).unwrap().as_str(), "2022");
+        }
+
+        // Without a right separator, the separator is used on both sides
+        let comment_sign = CommentSign::Enclosing("/*".into(), "*/".into());
+        assert_eq!(comment_sign.right_separator(), comment_sign.separator());
+    }
+
+    #[test]
+    fn test_holder_match() {
+        let comment_sign = CommentSign::LeftOnly("//".into());
+        let regex_for = |name: &str, holder_match: HolderMatch| {
+            let config = Config::default().with_holder_match(holder_match);
+            generate_comment_regex(&generate_base_regex(name, &config), &comment_sign).unwrap()
+        };
+
+        let decomposed = "// Copyright (c) Mu\u{308}ller GmbH 2022";
+        assert!(!regex_for("Müller GmbH", HolderMatch::Exact).is_match(decomposed));
+        let regex = regex_for("Müller GmbH", HolderMatch::Normalized);
+        assert!(regex.is_match(decomposed));
+        assert!(regex.is_match("// Copyright (c) Müller  GmbH 2022"));
+        assert!(!regex.is_match("// Copyright (c) Müller, GmbH 2022"));
+        // Letters beyond Latin and with several marks
+        let regex = regex_for("Ἄλφα Công ty", HolderMatch::Normalized);
+        assert!(regex.is_match("// Copyright (c) \u{391}\u{313}\u{301}λφα Co\u{302}ng ty 2022"));
+        let regex = regex_for("И\u{306}ожик", HolderMatch::Normalized);
+        assert!(regex.is_match("// Copyright (c) Йожик 2022"));
+
+        let regex = regex_for("Foo Inc", HolderMatch::Fuzzy);
+        let cap = regex
+            .captures("// Copyright (c) Foo, Inc. 2019-2022")
+            .unwrap();
+        assert_eq!(&cap[1], "2019-2022");
+        assert!(regex.is_match("// Copyright (c) FOO INC 2022"));
+        assert!(!regex.is_match("// Copyright (c) Foo Incorporated 2022"));
+        assert!(
+            regex_for("Foo, Inc.", HolderMatch::Fuzzy).is_match("// Copyright (c) Foo Inc 2022")
+        );
+
+        let config = Config::default().with_holder_match(HolderMatch::Normalized);
+        let line = futures::executor::block_on(generate_copyright_line(
+            "Mu\u{308}ller GmbH",
+            "main.rs",
+            &comment_sign,
+            &config,
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(line, "// Copyright (c) Müller GmbH 2022");
+    }
+
+    #[test]
+    fn test_copyright_symbol() {
+        let comment_sign = CommentSign::LeftOnly("//".into());
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &Config::default()),
+            &comment_sign,
+        )
+        .unwrap();
+        assert!(regex.is_match("// Copyright (c) DummyCompany Ltd. 2022"));
+        assert!(regex.is_match("// Copyright © DummyCompany Ltd. 2022"));
+
+        let config = Config::default().with_copyright_symbol(true);
+        let line = futures::executor::block_on(generate_copyright_line(
+            "DummyCompany Ltd.",
+            "main.rs",
+            &comment_sign,
+            &config,
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(line, "// Copyright © DummyCompany Ltd. 2022");
+    }
+
+    #[test]
+    fn test_suffix() {
+        let config = Config::default().with_suffix(Some("All rights reserved.".into()));
+        let comment_sign = CommentSign::Enclosing("/*".into(), "*/".into());
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &config),
+            &comment_sign,
+        )
+        .unwrap();
+        let line = futures::executor::block_on(generate_copyright_line(
+            "DummyCompany Ltd.",
+            "main.rs",
+            &comment_sign,
+            &config,
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(
+            line,
+            "/* Copyright (c) DummyCompany Ltd. 2022 All rights reserved. */"
+        );
+
+        let cap = regex.captures(&line).unwrap();
+        assert_eq!(&cap[1], "2022");
+        assert!(cap.name("suffix").is_some());
+
+        let cap = regex
+            .captures("/* Copyright (c) DummyCompany Ltd. 2022 */")
+            .unwrap();
+        assert!(cap.name("suffix").is_none());
+
+        // Suffixes are matched literally
+        let config = Config::default().with_suffix(Some("(c) [EU] + Co.|Ltd?".into()));
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &config),
+            &comment_sign,
+        )
+        .unwrap();
+        let cap = regex
+            .captures("/* Copyright (c) DummyCompany Ltd. 2022 (c) [EU] + Co.|Ltd? */")
+            .unwrap();
+        assert!(cap.name("suffix").is_some());
+        assert!(regex
+            .captures("/* Copyright (c) DummyCompany Ltd. 2022 c E + Co.|Lt */")
+            .is_none());
+    }
+
+    #[test]
+    fn test_header_template() {
+        let config = Config::default().with_header(Some("mit".into())).unwrap();
+        let line = futures::executor::block_on(generate_copyright_line(
+            "DummyCompany Ltd.",
+            "main.rs",
+            &CommentSign::LeftOnly("#".into()),
+            &config,
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(
+            line,
+            "# Copyright (c) DummyCompany Ltd. 2022\n#\n# Use of this source code is governed by the MIT license that can be found in\n# the LICENSE file."
+        );
+
+        let config = Config::default()
+            .with_header(Some(
+                "\nProprietary software of {name}\nDo not distribute.\n".into(),
+            ))
+            .unwrap();
+        let line = futures::executor::block_on(generate_copyright_line(
+            "DummyCompany Ltd.",
+            "main.rs",
+            &CommentSign::LeftOnly("#".into()),
+            &config,
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(
+            line,
+            "# Copyright (c) DummyCompany Ltd. 2022\n#\n# Proprietary software of DummyCompany Ltd.\n# Do not distribute."
+        );
+
+        // Unknown template names fail instead of writing no header
+        assert!(matches!(
+            Config::default().with_header(Some("mti".into())),
+            Err(CError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_copyright_template() {
+        let config = Config::default()
+            .with_copyright_template(Some(
+                "Copyright {years} {holder}. Licensed under {license} ({filename}).".into(),
+            ))
+            .unwrap()
+            .with_license(Some("Apache-2.0".into()));
+        let comment_sign = CommentSign::LeftOnly("//".into());
+        let line = futures::executor::block_on(generate_copyright_line(
+            "DummyCompany",
+            "src/main.rs",
+            &comment_sign,
+            &config,
+            futures::future::ready("2022".to_owned()),
+        ));
+        assert_eq!(
+            line,
+            "// Copyright 2022 DummyCompany. Licensed under Apache-2.0 (main.rs)."
+        );
+
+        let regex =
+            generate_comment_regex(&generate_base_regex("DummyCompany", &config), &comment_sign)
+                .unwrap();
+        assert_eq!(&regex.captures(&line).unwrap()[1], "2022");
+        assert!(regex
+            .is_match("// Copyright 2019-2021 DummyCompany. Licensed under Apache-2.0 (lib.rs)."));
+        assert!(!regex.is_match("// Copyright (c) DummyCompany 2022"));
+        assert!(Config::default()
+            .with_copyright_template(Some("Copyright {holder}".into()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_case_insensitive_match() {
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &Config::default()),
+            &CommentSign::LeftOnly("#".into()),
+        )
+        .unwrap();
+        for line in [
+            "# COPYRIGHT (C) DummyCompany Ltd. 2020",
+            "# copyright (c) DummyCompany Ltd. 2020",
+            "# Copyright (C) DummyCompany Ltd. 2020",
+        ] {
+            assert_eq!(&regex.captures(line).unwrap()[1], "2020");
+        }
+        assert!(!regex.is_match("# Copyright (c) DUMMYCOMPANY LTD. 2020"));
+    }
+
+    #[test]
+    fn test_year_list_match() {
+        let config = Config::default().with_year_format(YearFormat::List);
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &config),
+            &CommentSign::LeftOnly("//".into()),
+        )
+        .unwrap();
+        for (line, years) in [
+            (
+                "// Copyright (c) DummyCompany Ltd. 2018, 2020, 2023",
+                "2018, 2020, 2023",
+            ),
+            (
+                "// Copyright (c) DummyCompany Ltd. 2018-2020, 2023",
+                "2018-2020, 2023",
+            ),
+            ("// Copyright (c) DummyCompany Ltd. 2018-2023", "2018-2023"),
+        ] {
+            assert_eq!(&regex.captures(line).unwrap()[1], years);
+        }
+        assert!(!regex.is_match("// Copyright (c) DummyCompany Ltd. 2018,2020"));
+    }
+
+    #[test]
+    fn test_open_end_match() {
+        let config = Config::default().with_open_end(Some("present".into()));
+        let regex = generate_comment_regex(
+            &generate_base_regex("DummyCompany Ltd.", &config),
+            &CommentSign::LeftOnly("//".into()),
+        )
+        .unwrap();
+        for (line, years) in [
+            (
+                "// Copyright (c) DummyCompany Ltd. 2019-present",
+                "2019-present",
+            ),
+            ("// Copyright (c) DummyCompany Ltd. 2019-2022", "2019-2022"),
+        ] {
+            assert_eq!(&regex.captures(line).unwrap()[1], years);
+        }
+        assert!(!regex.is_match("// Copyright (c) DummyCompany Ltd. 2019-now"));
+    }
+
+    #[test]
+    fn test_comment_sign_metacharacters() {
+        let comment_signs = [
+            CommentSign::LeftOnly("//".into()),
+            CommentSign::LeftOnly("+".into()),
+            CommentSign::LeftOnly("?".into()),
+            CommentSign::LeftOnly("$".into()),
+            CommentSign::LeftOnly("|".into()),
+            CommentSign::LeftOnly("\\".into()),
+            CommentSign::Enclosing("/*".into(), "*/".into()),
+            CommentSign::Enclosing("(*".into(), "*)".into()),
+            CommentSign::Enclosing("{-".into(), "-}".into()),
+            CommentSign::Enclosing("[".into(), "]".into()),
+            CommentSign::Enclosing("<!--".into(), "-->".into()),
+        ];
+        let config = Config::default();
+        for comment_sign in comment_signs {
+            let regex = generate_comment_regex(
+                &generate_base_regex("DummyCompany Ltd.", &config),
+                &comment_sign,
+            )
+            .unwrap();
+            let line = futures::executor::block_on(generate_copyright_line(
+                "DummyCompany Ltd.",
+                "main.rs",
+                &comment_sign,
+                &config,
+                futures::future::ready("2022".to_owned()),
+            ));
+            let cap = regex.captures(&line);
+            assert_eq!(&cap.expect(&line)[1], "2022", "{}", line);
+        }
     }
 
     #[test]
@@ -151,10 +838,10 @@ mod test {
     #[test]
     fn test_generate_base_regex() {
         let name = "DummyCompany Ltd.";
-        let base_regex = generate_base_regex(name);
+        let base_regex = generate_base_regex(name, &Config::default());
         assert_eq!(
             base_regex,
-            r"Copyright \(c\) DummyCompany Ltd\. (\d{4}(-\d{4}){0,1})"
+            r"(?i:Copyright) (?:\([cC]\)|©) DummyCompany Ltd\. (\d{4}(-\d{4}){0,1})"
         );
     }
 
@@ -181,4 +868,34 @@ mod test {
             assert!(!copyright_re.is_match(example));
         }
     }
+
+    #[test]
+    fn test_parse_holder() {
+        let parser = HolderParser::new();
+        let examples = [
+            (
+                "// Copyright (c) DummyCompany Ltd. 2020-2021",
+                Some("DummyCompany Ltd."),
+            ),
+            ("/* Copyright © 2019, 2022 Foo, Inc. */", Some("Foo, Inc.")),
+            (
+                "# Copyright 2020 by Jane   Doe. All rights reserved.",
+                Some("Jane Doe."),
+            ),
+            (
+                "<!-- COPYRIGHT (C) Bar GmbH 2018-present -->",
+                Some("Bar GmbH"),
+            ),
+            ("The above copyright notice shall be included", None),
+            ("// Copyright (c) 2021", None),
+            ("Copyright 2020 İ Foo All rights reserved x", Some("İ Foo")),
+            (
+                "# Copyright (c) 2022 Ωmega ΑΕ. ALL RIGHTS RESERVED",
+                Some("Ωmega ΑΕ."),
+            ),
+        ];
+        for (line_, holder) in examples {
+            assert_eq!(parser.parse(line_).as_deref(), holder, "{}", line_);
+        }
+    }
 }
@@ -0,0 +1,392 @@
+//! Check repositories hosted on GitHub or GitLab without a local clone.
+//!
+//! Files are listed, their commit dates queried and their headers fetched via
+//! the API of the hosting service. Remote repositories are only checked and
+//! never written. Tokens are taken from `GITHUB_TOKEN` or `GITLAB_TOKEN` to
+//! access private repositories and raise rate limits.
+
+use crate::config::{DateSource, YearTimezone};
+use crate::fs::{read_header_lines, FileSystem};
+use crate::git_ops::commit_year;
+use crate::CError;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Hosting service of a remote repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Host {
+    GitHub,
+    GitLab,
+}
+
+/// Repository on a hosting service, queried via its API.
+#[derive(Debug, Clone)]
+pub struct RemoteRepo {
+    host: Host,
+    /// Base URL of the API, e.g. `https://api.github.com`.
+    api_url: String,
+    /// `owner/repo` on GitHub or the full path of the project on GitLab.
+    project: String,
+    /// Branch, tag or commit to check, the default branch if not given.
+    ref_name: Option<String>,
+    token: Option<String>,
+}
+
+impl RemoteRepo {
+    /// Parse a repository URL like `https://github.com/owner/repo`.
+    ///
+    /// Hosts other than `github.com` are taken as GitLab instances if their
+    /// name contains `gitlab` and as GitHub Enterprise instances otherwise.
+    pub fn parse(url: &str, ref_name: Option<String>) -> Result<Self, CError> {
+        let invalid = || CError::ConfigError(format!("Invalid repository URL {}", url));
+        let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+        let (domain, project) = rest.split_once('/').ok_or_else(invalid)?;
+        let project = project.trim_end_matches('/').trim_end_matches(".git");
+        if project.split('/').filter(|part| !part.is_empty()).count() < 2 {
+            return Err(invalid());
+        }
+
+        let (host, api_url) = match domain {
+            "github.com" => (Host::GitHub, "https://api.github.com".to_owned()),
+            _ if domain.contains("gitlab") => {
+                (Host::GitLab, format!("{}://{}/api/v4", scheme, domain))
+            }
+            _ => (Host::GitHub, format!("{}://{}/api/v3", scheme, domain)),
+        };
+        let token_var = match host {
+            Host::GitHub => "GITHUB_TOKEN",
+            Host::GitLab => "GITLAB_TOKEN",
+        };
+        Ok(RemoteRepo {
+            host,
+            api_url,
+            project: project.to_owned(),
+            ref_name,
+            token: std::env::var(token_var)
+                .ok()
+                .filter(|token| !token.is_empty()),
+        })
+    }
+
+    pub fn host(&self) -> Host {
+        self.host
+    }
+
+    /// Paths of all files on the ref.
+    pub fn list_files(&self) -> Result<Vec<String>, CError> {
+        let mut files = Vec::new();
+        match self.host {
+            Host::GitHub => {
+                let url = format!(
+                    "{}/repos/{}/git/trees/{}",
+                    self.api_url,
+                    self.project,
+                    encode(self.ref_name.as_deref().unwrap_or("HEAD"))
+                );
+                let (tree, _) = self.get_json(&url, &[("recursive", "1")])?;
+                if tree["truncated"].as_bool() == Some(true) {
+                    log::warn!(
+                        "File list of {} is truncated by the GitHub API",
+                        self.project
+                    );
+                }
+                files.extend(blob_paths(&tree["tree"]));
+            }
+            Host::GitLab => {
+                let url = format!("{}/repository/tree", self.project_url());
+                let mut page = 1;
+                loop {
+                    let page_str = page.to_string();
+                    let mut query = vec![
+                        ("recursive", "true"),
+                        ("per_page", "100"),
+                        ("page", &page_str),
+                    ];
+                    if let Some(ref_name) = &self.ref_name {
+                        query.push(("ref", ref_name));
+                    }
+                    let (entries, last_page) = self.get_json(&url, &query)?;
+                    files.extend(blob_paths(&entries));
+                    if last_page.is_none_or(|last_page| page >= last_page) {
+                        break;
+                    }
+                    page += 1;
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Years of the commits of the file, newest first.
+    ///
+    /// Only the newest and the oldest commit are queried. Renames are not
+    /// followed since the APIs do not support it.
+    pub fn commit_years(
+        &self,
+        filepath: &str,
+        date_source: DateSource,
+        timezone: YearTimezone,
+    ) -> Result<Vec<String>, CError> {
+        let (newest, last_page) = self.commit_date(filepath, 1, date_source)?;
+        let oldest = match last_page {
+            Some(last_page) if last_page > 1 => {
+                self.commit_date(filepath, last_page, date_source)?.0
+            }
+            _ => None,
+        };
+        Ok(newest
+            .into_iter()
+            .chain(oldest)
+            .filter_map(|date| commit_year(&date, timezone))
+            .collect())
+    }
+
+    /// Read up to `max_lines` lines from the start of the file.
+    ///
+    /// The response is only read as far as needed for the lines.
+    pub fn read_header(&self, filepath: &str, max_lines: usize) -> Result<Vec<String>, CError> {
+        let response = self.get_raw(filepath)?;
+        Ok(read_header_lines(response.into_reader(), max_lines)?)
+    }
+
+    /// Read the full content of the file.
+    pub fn read(&self, filepath: &str) -> Result<Vec<u8>, CError> {
+        let mut data = Vec::new();
+        std::io::copy(&mut self.get_raw(filepath)?.into_reader(), &mut data)?;
+        Ok(data)
+    }
+
+    fn project_url(&self) -> String {
+        format!("{}/projects/{}", self.api_url, encode(&self.project))
+    }
+
+    /// Date of the commit to the file on the page with one commit per page
+    /// and the number of pages, which is the number of commits.
+    fn commit_date(
+        &self,
+        filepath: &str,
+        page: usize,
+        date_source: DateSource,
+    ) -> Result<(Option<String>, Option<usize>), CError> {
+        let page_str = page.to_string();
+        let mut query = vec![("path", filepath), ("per_page", "1"), ("page", &page_str)];
+        let (url, ref_key) = match self.host {
+            Host::GitHub => (
+                format!("{}/repos/{}/commits", self.api_url, self.project),
+                "sha",
+            ),
+            Host::GitLab => (
+                format!("{}/repository/commits", self.project_url()),
+                "ref_name",
+            ),
+        };
+        let date_pointer = match (self.host, date_source) {
+            (Host::GitHub, DateSource::Committer) => "/0/commit/committer/date",
+            (Host::GitHub, DateSource::Author) => "/0/commit/author/date",
+            (Host::GitLab, DateSource::Committer) => "/0/committed_date",
+            (Host::GitLab, DateSource::Author) => "/0/authored_date",
+        };
+        if let Some(ref_name) = &self.ref_name {
+            query.push((ref_key, ref_name));
+        }
+        let (commits, last_page) = self.get_json(&url, &query)?;
+        let date = commits
+            .pointer(date_pointer)
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+        Ok((date, last_page))
+    }
+
+    fn get_raw(&self, filepath: &str) -> Result<ureq::Response, CError> {
+        let path = filepath
+            .split('/')
+            .map(encode)
+            .collect::<Vec<_>>()
+            .join("/");
+        let (url, ref_key, accept) = match self.host {
+            Host::GitHub => (
+                format!("{}/repos/{}/contents/{}", self.api_url, self.project, path),
+                "ref",
+                "application/vnd.github.raw",
+            ),
+            Host::GitLab => (
+                format!(
+                    "{}/repository/files/{}/raw",
+                    self.project_url(),
+                    encode(filepath)
+                ),
+                "ref",
+                "*/*",
+            ),
+        };
+        let mut request = self.request(&url).set("Accept", accept);
+        if let Some(ref_name) = &self.ref_name {
+            request = request.query(ref_key, ref_name);
+        }
+        request
+            .call()
+            .map_err(|e| CError::RemoteError(e.to_string()))
+    }
+
+    /// Query JSON from the API and the last page of a paginated result.
+    fn get_json(
+        &self,
+        url: &str,
+        query: &[(&str, &str)],
+    ) -> Result<(Value, Option<usize>), CError> {
+        let mut request = self.request(url);
+        for (key, value) in query {
+            request = request.query(key, value);
+        }
+        let response = request
+            .call()
+            .map_err(|e| CError::RemoteError(e.to_string()))?;
+
+        let last_page = match self.host {
+            Host::GitHub => response.header("link").and_then(github_last_page),
+            Host::GitLab => response
+                .header("x-total-pages")
+                .and_then(|pages| pages.parse().ok()),
+        };
+        let body = response.into_string()?;
+        let value = serde_json::from_str(&body)
+            .map_err(|e| CError::RemoteError(format!("{}: {}", url, e)))?;
+        Ok((value, last_page))
+    }
+
+    fn request(&self, url: &str) -> ureq::Request {
+        let request = ureq::get(url).set("User-Agent", "git_copyright");
+        match (&self.token, self.host) {
+            (Some(token), Host::GitHub) => {
+                request.set("Authorization", &format!("Bearer {}", token))
+            }
+            (Some(token), Host::GitLab) => request.set("PRIVATE-TOKEN", token),
+            (None, _) => request,
+        }
+    }
+}
+
+/// Files of a remote repository, which are never written.
+#[derive(Debug, Clone)]
+pub struct RemoteFs {
+    repo: Arc<RemoteRepo>,
+}
+
+impl RemoteFs {
+    pub fn new(repo: RemoteRepo) -> Self {
+        RemoteFs {
+            repo: Arc::new(repo),
+        }
+    }
+
+    pub fn repo(&self) -> &RemoteRepo {
+        &self.repo
+    }
+
+    /// Run a blocking query on the repository outside of the async runtime.
+    pub async fn query<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&RemoteRepo) -> Result<T, CError> + Send + 'static,
+    ) -> Result<T, CError> {
+        let repo = self.repo.clone();
+        tokio::task::spawn_blocking(move || query(&repo))
+            .await
+            .map_err(|e| CError::RemoteError(e.to_string()))?
+    }
+}
+
+impl FileSystem for RemoteFs {
+    async fn read_header(&self, filepath: &Path, max_lines: usize) -> Result<Vec<String>, CError> {
+        let filepath = remote_path(filepath)?;
+        self.query(move |repo| repo.read_header(&filepath, max_lines))
+            .await
+    }
+
+    async fn read(&self, filepath: &Path) -> Result<Vec<u8>, CError> {
+        let filepath = remote_path(filepath)?;
+        self.query(move |repo| repo.read(&filepath)).await
+    }
+
+    async fn write(&self, filepath: &Path, _data: &[u8]) -> Result<(), CError> {
+        Err(CError::WriteError(filepath.display().to_string()))
+    }
+}
+
+/// Path in the repository with `/` separators.
+fn remote_path(filepath: &Path) -> Result<String, CError> {
+    filepath
+        .to_str()
+        .map(|filepath| filepath.replace('\\', "/"))
+        .ok_or_else(|| CError::ReadError(filepath.display().to_string()))
+}
+
+/// Paths of the blobs in a list of tree entries.
+fn blob_paths(entries: &Value) -> impl Iterator<Item = String> + '_ {
+    entries
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry["type"] == "blob")
+        .filter_map(|entry| entry["path"].as_str().map(str::to_owned))
+}
+
+/// Last page from a GitHub `Link` header like `<...&page=5>; rel="last"`.
+fn github_last_page(link: &str) -> Option<usize> {
+    let last = link.split(',').find(|part| part.contains("rel=\"last\""))?;
+    let url = last.split(['<', '>']).nth(1)?;
+    url.split(['?', '&'])
+        .find_map(|param| param.strip_prefix("page="))?
+        .parse()
+        .ok()
+}
+
+/// Percent-encode everything but unreserved characters.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{encode, github_last_page, Host, RemoteRepo};
+
+    #[test]
+    fn test_parse_remote_repo() {
+        let repo = RemoteRepo::parse("https://github.com/sgasse/git_copyright.git", None).unwrap();
+        assert_eq!(repo.host(), Host::GitHub);
+        assert_eq!(repo.api_url, "https://api.github.com");
+        assert_eq!(repo.project, "sgasse/git_copyright");
+
+        let repo = RemoteRepo::parse("https://gitlab.com/group/sub/project/", None).unwrap();
+        assert_eq!(repo.host(), Host::GitLab);
+        assert_eq!(
+            repo.project_url(),
+            "https://gitlab.com/api/v4/projects/group%2Fsub%2Fproject"
+        );
+
+        let repo = RemoteRepo::parse("https://git.example.com/owner/repo", None).unwrap();
+        assert_eq!(repo.api_url, "https://git.example.com/api/v3");
+
+        assert!(RemoteRepo::parse("https://github.com/owner", None).is_err());
+        assert!(RemoteRepo::parse("github.com/owner/repo", None).is_err());
+    }
+
+    #[test]
+    fn test_github_last_page() {
+        let link = "<https://api.github.com/repositories/1/commits?path=a.rs&per_page=1&page=2>; rel=\"next\", \
+                    <https://api.github.com/repositories/1/commits?path=a.rs&per_page=1&page=37>; rel=\"last\"";
+        assert_eq!(github_last_page(link), Some(37));
+        assert_eq!(github_last_page("<https://x?page=1>; rel=\"prev\""), None);
+        assert_eq!(encode("src/a b.rs"), "src%2Fa%20b.rs");
+    }
+}
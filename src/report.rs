@@ -0,0 +1,781 @@
+//! Describe the outcome of checking a file and print it in different formats.
+
+use crate::policy::PolicyViolation;
+use crate::sarif::sarif_report;
+use crate::{CError, FileFailure};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Output format used to print file outcomes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable sentences.
+    #[default]
+    Human,
+    /// `path:line: message` lines for Vim's quickfix and Emacs compilation mode.
+    Quickfix,
+    /// Reviewdog Diagnostic Format with one diagnostic per line, for `reviewdog -f=rdjsonl`.
+    Rdjsonl,
+    /// JUnit XML with one test case per checked file, for CI test reports.
+    Junit,
+    /// TeamCity service messages reporting violations as inspections.
+    Teamcity,
+    /// Azure Pipelines logging commands reporting violations as warnings.
+    Azure,
+    /// One JSON document with the status of every checked file, for CI
+    /// pipelines and dashboards.
+    Json,
+    /// SARIF log with one result per file needing a fix, for code scanning.
+    Sarif,
+}
+
+impl OutputFormat {
+    /// Whether the format describes all outcomes in one document instead of
+    /// one message per outcome.
+    pub fn is_document(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Junit | OutputFormat::Teamcity | OutputFormat::Json | OutputFormat::Sarif
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    /// The copyright note is present with the expected years.
+    Correct,
+    /// A copyright note is present but carries other years.
+    Outdated { found_years: String },
+    /// No copyright note was found in the file header.
+    Missing,
+    /// The file header matches a keep pattern, so the file is left untouched.
+    Exempt { pattern: String },
+    /// The copyright needs a fix but the file is read-only and was skipped.
+    ReadOnly,
+    /// The file type is mapped to `none` in the comment sign map since it has
+    /// no header by design, e.g. JSON files.
+    NoHeader,
+    /// The copyright is correct but the header template following it differs
+    /// from the configured one, first on the zero-based line `line_nr`.
+    TemplateDrift { line_nr: usize, expected: String },
+    /// The copyright is correct but the `SPDX-License-Identifier` line is
+    /// missing or names another license than the configured one.
+    SpdxMismatch {
+        found: Option<String>,
+        expected: String,
+    },
+}
+
+impl FileStatus {
+    /// Whether the copyright is missing or outdated.
+    pub fn needs_fix(&self) -> bool {
+        matches!(
+            self,
+            FileStatus::Outdated { .. }
+                | FileStatus::Missing
+                | FileStatus::TemplateDrift { .. }
+                | FileStatus::SpdxMismatch { .. }
+        )
+    }
+
+    /// Name of the status in machine-readable reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileStatus::Correct => "correct",
+            FileStatus::Outdated { .. } => "outdated",
+            FileStatus::Missing => "missing",
+            FileStatus::Exempt { .. } => "exempt",
+            FileStatus::ReadOnly => "read_only",
+            FileStatus::NoHeader => "no_header",
+            FileStatus::TemplateDrift { .. } => "template_drift",
+            FileStatus::SpdxMismatch { .. } => "spdx_mismatch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOutcome {
+    pub filepath: PathBuf,
+    pub status: FileStatus,
+    /// Zero-based line number of the existing copyright note.
+    pub line_nr: Option<usize>,
+    pub expected_years: String,
+}
+
+impl FileOutcome {
+    /// Format the outcome as sentence for humans.
+    pub fn human_message(&self) -> String {
+        match &self.status {
+            FileStatus::Correct => format!(
+                "File {} has correct copyright with years {}",
+                self.filepath.display(),
+                self.expected_years
+            ),
+            FileStatus::Outdated { found_years } => format!(
+                "File {} has copyright with year(s) {} on line {} but should have {}",
+                self.filepath.display(),
+                found_years,
+                self.line_nr.unwrap_or_default(),
+                self.expected_years
+            ),
+            FileStatus::Missing => format!(
+                "File {} has no copyright but should have {}",
+                self.filepath.display(),
+                self.expected_years
+            ),
+            FileStatus::Exempt { pattern } => format!(
+                "File {} is exempt from copyrights by keep pattern {}",
+                self.filepath.display(),
+                pattern
+            ),
+            FileStatus::ReadOnly => format!(
+                "File {} is read-only and was skipped but should have copyright {}",
+                self.filepath.display(),
+                self.expected_years
+            ),
+            FileStatus::NoHeader => format!(
+                "File {} has no header by configuration",
+                self.filepath.display()
+            ),
+            FileStatus::TemplateDrift { line_nr, expected } => format!(
+                "File {} deviates from the header template on line {}, expected `{}`",
+                self.filepath.display(),
+                line_nr + 1,
+                expected
+            ),
+            FileStatus::SpdxMismatch {
+                found: Some(found),
+                expected,
+            } => format!(
+                "File {} has SPDX license identifier {} but should have {}",
+                self.filepath.display(),
+                found,
+                expected
+            ),
+            FileStatus::SpdxMismatch {
+                found: None,
+                expected,
+            } => format!(
+                "File {} has no SPDX license identifier but should have {}",
+                self.filepath.display(),
+                expected
+            ),
+        }
+    }
+
+    /// Format the outcome as short message without the file path.
+    pub fn short_message(&self) -> String {
+        match &self.status {
+            FileStatus::Correct => format!("copyright is up to date ({})", self.expected_years),
+            FileStatus::Outdated { found_years } => format!(
+                "copyright has year(s) {} but should have {}",
+                found_years, self.expected_years
+            ),
+            FileStatus::Missing => {
+                format!("copyright is missing, should have {}", self.expected_years)
+            }
+            FileStatus::Exempt { pattern } => format!("exempt by keep pattern {}", pattern),
+            FileStatus::ReadOnly => format!(
+                "read-only file skipped, copyright should have {}",
+                self.expected_years
+            ),
+            FileStatus::NoHeader => "no header by configuration".to_owned(),
+            FileStatus::TemplateDrift { line_nr, expected } => format!(
+                "header deviates from the template on line {}, expected `{}`",
+                line_nr + 1,
+                expected
+            ),
+            FileStatus::SpdxMismatch {
+                found: Some(found),
+                expected,
+            } => format!(
+                "SPDX license identifier is {} but should be {}",
+                found, expected
+            ),
+            FileStatus::SpdxMismatch {
+                found: None,
+                expected,
+            } => format!("SPDX license identifier is missing, should be {}", expected),
+        }
+    }
+
+    /// Format the outcome as `path:line: message` with a one-based line number.
+    pub fn quickfix_message(&self) -> String {
+        format!(
+            "{}:{}: {}",
+            self.filepath.display(),
+            self.line_nr.unwrap_or_default() + 1,
+            self.short_message()
+        )
+    }
+
+    /// Format the outcome as diagnostic of the Reviewdog Diagnostic Format.
+    pub fn rdjson_message(&self) -> String {
+        json!({
+            "message": self.short_message(),
+            "location": {
+                "path": self.filepath,
+                "range": {"start": {"line": self.line_nr.unwrap_or_default() + 1}},
+            },
+            "severity": "WARNING",
+            "source": {"name": env!("CARGO_PKG_NAME")},
+        })
+        .to_string()
+    }
+
+    /// Format the outcome as entry of the JSON report.
+    ///
+    /// The action is `fixed` for files needing a fix if `fixed` is set and
+    /// `reported` otherwise, `skipped` for skipped read-only files and `none`
+    /// for all others.
+    pub fn json_value(&self, fixed: bool) -> Value {
+        let found_years = match &self.status {
+            FileStatus::Outdated { found_years } => Some(found_years),
+            _ => None,
+        };
+        let action = match (&self.status, fixed) {
+            (FileStatus::ReadOnly, _) => "skipped",
+            (status, true) if status.needs_fix() => "fixed",
+            (status, false) if status.needs_fix() => "reported",
+            _ => "none",
+        };
+        json!({
+            "path": self.filepath,
+            "status": self.status.name(),
+            "line": self.line_nr.map(|line_nr| line_nr + 1),
+            "expected_years": self.expected_years,
+            "found_years": found_years,
+            "action": action,
+            "message": self.short_message(),
+        })
+    }
+
+    /// Format the outcome as JUnit test case which fails if the copyright
+    /// needs a fix and is skipped for exempt files.
+    pub fn junit_testcase(&self) -> String {
+        let name = xml_escape(&self.filepath.display().to_string());
+        let element = match self.status {
+            FileStatus::Correct => {
+                return format!(
+                    "    <testcase classname=\"{}\" name=\"{}\"/>\n",
+                    env!("CARGO_PKG_NAME"),
+                    name
+                )
+            }
+            FileStatus::Exempt { .. } | FileStatus::ReadOnly | FileStatus::NoHeader => "skipped",
+            _ => "failure",
+        };
+        format!(
+            "    <testcase classname=\"{}\" name=\"{}\">\n      <{} message=\"{}\"/>\n    </testcase>\n",
+            env!("CARGO_PKG_NAME"),
+            name,
+            element,
+            xml_escape(&self.short_message())
+        )
+    }
+
+    /// Format the outcome as TeamCity inspection of the `copyright` type.
+    pub fn teamcity_message(&self) -> String {
+        format!(
+            "##teamcity[inspection typeId='copyright' message='{}' file='{}' line='{}' SEVERITY='WARNING']",
+            teamcity_escape(&self.short_message()),
+            teamcity_escape(&self.filepath.display().to_string()),
+            self.line_nr.unwrap_or_default() + 1
+        )
+    }
+
+    /// Format the outcome as Azure Pipelines `task.logissue` command.
+    pub fn azure_message(&self) -> String {
+        format!(
+            "##vso[task.logissue type=warning;sourcepath={};linenumber={};]{}",
+            azure_escape_property(&self.filepath.display().to_string()),
+            self.line_nr.unwrap_or_default() + 1,
+            azure_escape(&self.short_message())
+        )
+    }
+
+    /// Format the outcome in the given format.
+    ///
+    /// Files with a correct copyright or without header by configuration are
+    /// not reported and exempt files only in the human-readable format.
+    /// Document formats have no message per outcome, see `format_report`.
+    pub fn message(&self, format: OutputFormat) -> Option<String> {
+        match (&self.status, format) {
+            (FileStatus::Correct | FileStatus::NoHeader, _) => return None,
+            (FileStatus::Exempt { .. }, OutputFormat::Human) => return Some(self.human_message()),
+            (FileStatus::Exempt { .. }, _) => return None,
+            _ => {}
+        }
+
+        match format {
+            OutputFormat::Human => Some(self.human_message()),
+            OutputFormat::Quickfix => Some(self.quickfix_message()),
+            OutputFormat::Rdjsonl => Some(self.rdjson_message()),
+            OutputFormat::Azure => Some(self.azure_message()),
+            OutputFormat::Junit
+            | OutputFormat::Teamcity
+            | OutputFormat::Json
+            | OutputFormat::Sarif => None,
+        }
+    }
+}
+
+/// Outcomes of the files, failures and policy violations of a run, e.g. to
+/// render them or make decisions on them when embedding the library.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub outcomes: Vec<FileOutcome>,
+    /// Files which could not be checked.
+    pub failures: Vec<FileFailure>,
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl CheckReport {
+    /// Outcomes of the files with a missing or outdated copyright.
+    pub fn needing_fix(&self) -> impl Iterator<Item = &FileOutcome> {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.status.needs_fix())
+    }
+
+    /// Percentage of files with a correct copyright, see `coverage`.
+    pub fn coverage(&self) -> f64 {
+        coverage(&self.outcomes)
+    }
+
+    /// Whether all files were checked, have a correct copyright and violate
+    /// no policy.
+    pub fn is_clean(&self) -> bool {
+        self.failures.is_empty()
+            && self.violations.is_empty()
+            && self.needing_fix().next().is_none()
+    }
+}
+
+/// Percentage of outcomes with a correct copyright or exempt from copyrights.
+///
+/// Skipped read-only files count as not correct.
+///
+/// Without any outcomes, the coverage is complete.
+pub fn coverage(outcomes: &[FileOutcome]) -> f64 {
+    if outcomes.is_empty() {
+        return 100.0;
+    }
+
+    let num_correct = outcomes
+        .iter()
+        .filter(|outcome| !outcome.status.needs_fix() && outcome.status != FileStatus::ReadOnly)
+        .count();
+    num_correct as f64 * 100.0 / outcomes.len() as f64
+}
+
+/// Format all outcomes in the given format.
+///
+/// With `fixed`, files needing a fix are reported as fixed in JSON reports.
+pub fn format_report(outcomes: &[FileOutcome], format: OutputFormat, fixed: bool) -> String {
+    match format {
+        OutputFormat::Json => {
+            let files: Vec<Value> = outcomes
+                .iter()
+                .map(|outcome| outcome.json_value(fixed))
+                .collect();
+            let report = json!({
+                "files": files,
+                "coverage": coverage(outcomes),
+            });
+            format!("{:#}\n", report)
+        }
+        OutputFormat::Sarif => sarif_report(outcomes),
+        OutputFormat::Junit => {
+            let num_failures = outcomes
+                .iter()
+                .filter(|outcome| outcome.status.needs_fix())
+                .count();
+            let num_skipped = outcomes
+                .iter()
+                .filter(|outcome| {
+                    matches!(
+                        outcome.status,
+                        FileStatus::Exempt { .. } | FileStatus::ReadOnly | FileStatus::NoHeader
+                    )
+                })
+                .count();
+            let testcases: String = outcomes.iter().map(FileOutcome::junit_testcase).collect();
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n{}  </testsuite>\n</testsuites>\n",
+                env!("CARGO_PKG_NAME"),
+                outcomes.len(),
+                num_failures,
+                num_skipped,
+                testcases
+            )
+        }
+        OutputFormat::Teamcity => {
+            // The inspection type has to be declared before its inspections
+            let mut report = "##teamcity[inspectionType id='copyright' name='copyright' description='Missing or outdated copyright' category='Copyright']\n".to_owned();
+            for outcome in outcomes.iter().filter(|outcome| outcome.status.needs_fix()) {
+                report.push_str(&outcome.teamcity_message());
+                report.push('\n');
+            }
+            report
+        }
+        _ => outcomes
+            .iter()
+            .filter_map(|outcome| outcome.message(format))
+            .map(|message| format!("{}\n", message))
+            .collect(),
+    }
+}
+
+/// Write the outcomes in the given format to a report file.
+pub fn write_report(
+    report_file: &Path,
+    outcomes: &[FileOutcome],
+    format: OutputFormat,
+    fixed: bool,
+) -> Result<(), CError> {
+    std::fs::write(report_file, format_report(outcomes, format, fixed))
+        .map_err(|_| CError::WriteError(report_file.display().to_string()))
+}
+
+/// Markdown summary with a table of the files needing a fix and the policy
+/// violations, e.g. for the step summary of GitHub Actions.
+///
+/// With `fixed`, files needing a fix are listed as fixed.
+pub fn markdown_summary(
+    outcomes: &[FileOutcome],
+    violations: &[PolicyViolation],
+    fixed: bool,
+) -> String {
+    let mut summary = format!(
+        "### Copyright check\n\nChecked {} files, copyright coverage is {:.1}%.\n",
+        outcomes.len(),
+        coverage(outcomes)
+    );
+
+    let rows: Vec<String> = outcomes
+        .iter()
+        .filter(|outcome| outcome.status.needs_fix() || outcome.status == FileStatus::ReadOnly)
+        .map(|outcome| {
+            let result = match (&outcome.status, fixed) {
+                (FileStatus::ReadOnly, _) => "skipped",
+                (_, true) => "fixed",
+                (_, false) => "violation",
+            };
+            (outcome.filepath.display(), result, outcome.short_message())
+        })
+        .map(|(filepath, result, message)| {
+            format!("| `{}` | {} | {} |\n", filepath, result, message)
+        })
+        .chain(violations.iter().map(|violation| {
+            format!(
+                "| `{}` | policy {} | {} |\n",
+                violation.filepath.display(),
+                violation.rule,
+                violation.message
+            )
+        }))
+        .collect();
+    if !rows.is_empty() {
+        summary.push_str("\n| File | Result | Message |\n| --- | --- | --- |\n");
+        summary.push_str(&rows.concat());
+    }
+    summary
+}
+
+/// Append the markdown summary to the file in `$GITHUB_STEP_SUMMARY`.
+pub fn write_step_summary(summary: &str) -> Result<(), CError> {
+    let summary_file = match std::env::var_os("GITHUB_STEP_SUMMARY") {
+        Some(summary_file) => PathBuf::from(summary_file),
+        None => {
+            log::warn!("GITHUB_STEP_SUMMARY is not set, skipping the step summary");
+            return Ok(());
+        }
+    };
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&summary_file)
+        .map_err(|_| CError::WriteError(summary_file.display().to_string()))?;
+    writeln!(file, "{}", summary)
+        .map_err(|_| CError::WriteError(summary_file.display().to_string()))
+}
+
+/// Escape text for values of TeamCity service messages.
+fn teamcity_escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '|' => "||".to_owned(),
+            '\'' => "|'".to_owned(),
+            '[' => "|[".to_owned(),
+            ']' => "|]".to_owned(),
+            '\n' => "|n".to_owned(),
+            '\r' => "|r".to_owned(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Escape the message of Azure Pipelines logging commands.
+fn azure_escape(text: &str) -> String {
+    text.replace('%', "%AZP25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape property values of Azure Pipelines logging commands.
+fn azure_escape_property(text: &str) -> String {
+    azure_escape(text).replace(';', "%3B").replace(']', "%5D")
+}
+
+/// Escape text for XML attribute values.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{
+        coverage, format_report, markdown_summary, write_report, CheckReport, FileOutcome,
+        FileStatus, OutputFormat,
+    };
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_quickfix_message() {
+        let outcome = FileOutcome {
+            filepath: PathBuf::from("./src/main.rs"),
+            status: FileStatus::Outdated {
+                found_years: "2020".into(),
+            },
+            line_nr: Some(0),
+            expected_years: "2020-2022".into(),
+        };
+        assert_eq!(
+            outcome.quickfix_message(),
+            "./src/main.rs:1: copyright has year(s) 2020 but should have 2020-2022"
+        );
+
+        let outcome = FileOutcome {
+            filepath: PathBuf::from("script.sh"),
+            status: FileStatus::Missing,
+            line_nr: None,
+            expected_years: "2022".into(),
+        };
+        assert_eq!(
+            outcome.quickfix_message(),
+            "script.sh:1: copyright is missing, should have 2022"
+        );
+    }
+
+    #[test]
+    fn test_rdjson_message() {
+        let outcome = FileOutcome {
+            filepath: PathBuf::from("src/main.rs"),
+            status: FileStatus::Missing,
+            line_nr: None,
+            expected_years: "2022".into(),
+        };
+        let diagnostic: serde_json::Value =
+            serde_json::from_str(&outcome.rdjson_message()).unwrap();
+        assert_eq!(diagnostic["location"]["path"], "src/main.rs");
+        assert_eq!(diagnostic["location"]["range"]["start"]["line"], 1);
+        assert_eq!(
+            diagnostic["message"],
+            "copyright is missing, should have 2022"
+        );
+    }
+
+    #[test]
+    fn test_teamcity_message() {
+        let outcome = FileOutcome {
+            filepath: PathBuf::from("src/[id].rs"),
+            status: FileStatus::Outdated {
+                found_years: "2020".into(),
+            },
+            line_nr: Some(2),
+            expected_years: "2020-2022".into(),
+        };
+        assert_eq!(
+            outcome.teamcity_message(),
+            "##teamcity[inspection typeId='copyright' message='copyright has year(s) 2020 but should have 2020-2022' file='src/|[id|].rs' line='3' SEVERITY='WARNING']"
+        );
+    }
+
+    #[test]
+    fn test_azure_message() {
+        let outcome = FileOutcome {
+            filepath: PathBuf::from("src/a;b.rs"),
+            status: FileStatus::Missing,
+            line_nr: None,
+            expected_years: "2022".into(),
+        };
+        assert_eq!(
+            outcome.azure_message(),
+            "##vso[task.logissue type=warning;sourcepath=src/a%3Bb.rs;linenumber=1;]copyright is missing, should have 2022"
+        );
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let outcomes = [
+            FileOutcome {
+                filepath: PathBuf::from("main.rs"),
+                status: FileStatus::Correct,
+                line_nr: Some(0),
+                expected_years: "2022".into(),
+            },
+            FileOutcome {
+                filepath: PathBuf::from("a&b.sh"),
+                status: FileStatus::Missing,
+                line_nr: None,
+                expected_years: "2022".into(),
+            },
+        ];
+        let report = format_report(&outcomes, OutputFormat::Junit, false);
+        assert!(report.contains("tests=\"2\" failures=\"1\" skipped=\"0\""));
+        assert!(report.contains("<testcase classname=\"git_copyright\" name=\"main.rs\"/>"));
+        assert!(report.contains(
+            "name=\"a&amp;b.sh\">\n      <failure message=\"copyright is missing, should have 2022\"/>"
+        ));
+    }
+
+    #[test]
+    fn test_json_report() {
+        let outcomes = [
+            FileOutcome {
+                filepath: PathBuf::from("main.rs"),
+                status: FileStatus::Correct,
+                line_nr: Some(0),
+                expected_years: "2022".into(),
+            },
+            FileOutcome {
+                filepath: PathBuf::from("script.sh"),
+                status: FileStatus::Outdated {
+                    found_years: "2020".into(),
+                },
+                line_nr: Some(1),
+                expected_years: "2020-2022".into(),
+            },
+        ];
+        let report: Value =
+            serde_json::from_str(&format_report(&outcomes, OutputFormat::Json, true)).unwrap();
+        assert_eq!(report["coverage"], 50.0);
+        assert_eq!(
+            report["files"][0],
+            json!({
+                "path": "main.rs",
+                "status": "correct",
+                "line": 1,
+                "expected_years": "2022",
+                "found_years": null,
+                "action": "none",
+                "message": "copyright is up to date (2022)",
+            })
+        );
+        assert_eq!(report["files"][1]["status"], "outdated");
+        assert_eq!(report["files"][1]["found_years"], "2020");
+        assert_eq!(report["files"][1]["action"], "fixed");
+    }
+
+    #[test]
+    fn test_markdown_summary() {
+        let outcomes = [
+            FileOutcome {
+                filepath: PathBuf::from("main.rs"),
+                status: FileStatus::Correct,
+                line_nr: Some(0),
+                expected_years: "2022".into(),
+            },
+            FileOutcome {
+                filepath: PathBuf::from("script.sh"),
+                status: FileStatus::Missing,
+                line_nr: None,
+                expected_years: "2022".into(),
+            },
+        ];
+        let summary = markdown_summary(&outcomes, &[], true);
+        assert!(summary.contains("Checked 2 files, copyright coverage is 50.0%."));
+        assert!(
+            summary.ends_with("| `script.sh` | fixed | copyright is missing, should have 2022 |\n")
+        );
+
+        let summary = markdown_summary(&outcomes[..1], &[], false);
+        assert!(!summary.contains("| File |"));
+    }
+
+    #[test]
+    fn test_check_report() {
+        let mut report = CheckReport {
+            outcomes: vec![FileOutcome {
+                filepath: PathBuf::from("main.rs"),
+                status: FileStatus::Correct,
+                line_nr: Some(0),
+                expected_years: "2022".into(),
+            }],
+            ..Default::default()
+        };
+        assert!(report.is_clean());
+
+        report.outcomes.push(FileOutcome {
+            filepath: PathBuf::from("script.sh"),
+            status: FileStatus::Missing,
+            line_nr: None,
+            expected_years: "2022".into(),
+        });
+        assert!(!report.is_clean());
+        assert_eq!(report.coverage(), 50.0);
+        assert_eq!(
+            report
+                .needing_fix()
+                .map(|o| &o.filepath)
+                .collect::<Vec<_>>(),
+            [&PathBuf::from("script.sh")]
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        let outcomes = [
+            FileOutcome {
+                filepath: PathBuf::from("main.rs"),
+                status: FileStatus::Correct,
+                line_nr: Some(0),
+                expected_years: "2022".into(),
+            },
+            FileOutcome {
+                filepath: PathBuf::from("script.sh"),
+                status: FileStatus::Missing,
+                line_nr: None,
+                expected_years: "2022".into(),
+            },
+        ];
+        let report_file = std::env::temp_dir().join("git_copyright_test_report.txt");
+        write_report(&report_file, &outcomes, OutputFormat::Quickfix, false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&report_file).unwrap(),
+            "script.sh:1: copyright is missing, should have 2022\n"
+        );
+        std::fs::remove_file(report_file).unwrap();
+
+        assert_eq!(coverage(&outcomes), 50.0);
+        let exempt = FileOutcome {
+            filepath: PathBuf::from("vendor.c"),
+            status: FileStatus::Exempt {
+                pattern: "Public Domain".into(),
+            },
+            line_nr: Some(0),
+            expected_years: "2022".into(),
+        };
+        assert_eq!(exempt.message(OutputFormat::Quickfix), None);
+        assert_eq!(coverage(&[exempt]), 100.0);
+        assert_eq!(coverage(&[]), 100.0);
+    }
+}
@@ -0,0 +1,142 @@
+//! Report outcomes as SARIF log for code scanning, e.g. of GitHub.
+//!
+//! Each file needing a fix becomes a result of the rule for its status with
+//! the location of the existing copyright, or the first line if there is
+//! none. Paths are relative to the repository root as code scanning expects.
+
+use crate::report::{FileOutcome, FileStatus};
+use serde_json::{json, Value};
+use std::path::{Component, Path};
+
+/// Version of the SARIF format written.
+const SARIF_VERSION: &str = "2.1.0";
+
+/// Schema of the SARIF format written.
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// IDs and descriptions of the rules results are reported for.
+const RULES: [(&str, &str); 4] = [
+    ("missing-copyright", "The file header has no copyright"),
+    (
+        "outdated-copyright",
+        "The copyright does not span the years of the file history",
+    ),
+    (
+        "header-template-drift",
+        "The file header deviates from the configured header template",
+    ),
+    (
+        "spdx-license-identifier",
+        "The SPDX license identifier is missing or names another license",
+    ),
+];
+
+/// ID of the rule violated by a file with the given status.
+fn rule_id(status: &FileStatus) -> Option<&'static str> {
+    match status {
+        FileStatus::Missing => Some(RULES[0].0),
+        FileStatus::Outdated { .. } => Some(RULES[1].0),
+        FileStatus::TemplateDrift { .. } => Some(RULES[2].0),
+        FileStatus::SpdxMismatch { .. } => Some(RULES[3].0),
+        _ => None,
+    }
+}
+
+/// Path with forward slashes and without leading `./` components.
+fn artifact_uri(filepath: &Path) -> String {
+    filepath
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// SARIF result of an outcome if the file needs a fix.
+fn sarif_result(outcome: &FileOutcome) -> Option<Value> {
+    let line = match &outcome.status {
+        FileStatus::TemplateDrift { line_nr, .. } => *line_nr,
+        _ => outcome.line_nr.unwrap_or_default(),
+    };
+    rule_id(&outcome.status).map(|rule_id| {
+        json!({
+            "ruleId": rule_id,
+            "level": "warning",
+            "message": {"text": outcome.short_message()},
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": {"uri": artifact_uri(&outcome.filepath)},
+                    "region": {"startLine": line + 1},
+                },
+            }],
+        })
+    })
+}
+
+/// Format the outcomes as SARIF log with a single run.
+pub fn sarif_report(outcomes: &[FileOutcome]) -> String {
+    let rules: Vec<Value> = RULES
+        .iter()
+        .map(|(id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": {"text": description},
+                "defaultConfiguration": {"level": "warning"},
+            })
+        })
+        .collect();
+    let results: Vec<Value> = outcomes.iter().filter_map(sarif_result).collect();
+    let log = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    });
+    format!("{:#}\n", log)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::sarif_report;
+    use crate::report::{FileOutcome, FileStatus};
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_sarif_report() {
+        let outcomes = [
+            FileOutcome {
+                filepath: PathBuf::from("./src/main.rs"),
+                status: FileStatus::Correct,
+                line_nr: Some(0),
+                expected_years: "2022".into(),
+            },
+            FileOutcome {
+                filepath: PathBuf::from("./scripts/run.sh"),
+                status: FileStatus::Outdated {
+                    found_years: "2020".into(),
+                },
+                line_nr: Some(1),
+                expected_years: "2020-2022".into(),
+            },
+        ];
+        let log: Value = serde_json::from_str(&sarif_report(&outcomes)).unwrap();
+        assert_eq!(log["version"], "2.1.0");
+        let results = log["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["ruleId"], "outdated-copyright");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "scripts/run.sh");
+        assert_eq!(location["region"]["startLine"], 2);
+    }
+}
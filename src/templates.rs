@@ -0,0 +1,176 @@
+//! Bundled header templates selectable by name with `header` in the config.
+//! A `header` spanning several lines is used as custom template instead.
+//!
+//! A template holds the lines following the copyright line. They are only
+//! written when a copyright is missing, existing headers are never rewritten
+//! except for the years of the copyright line unless `--verify-template`
+//! restores drifted template lines. The placeholders `{name}` and
+//! `{years}` are substituted like in special file rules.
+//!
+//! The copyright line itself can follow a `copyright_template` of the config,
+//! e.g. `Copyright {years} {holder}. Licensed under {license}.`, which is
+//! parsed into parts to render lines and to generate the regex matching them.
+
+use crate::CError;
+
+/// Names of the bundled templates.
+pub const TEMPLATE_NAMES: [&str; 4] =
+    ["apache-2.0", "mit", "gpl-3.0-or-later", "proprietary-short"];
+
+const APACHE_2_0: &str = r#"
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License."#;
+
+const MIT: &str = "
+Use of this source code is governed by the MIT license that can be found in
+the LICENSE file.";
+
+const GPL_3_0_OR_LATER: &str = "
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.";
+
+const PROPRIETARY_SHORT: &str = "
+All rights reserved. Proprietary and confidential.
+Unauthorized copying of this file, via any medium, is strictly prohibited.";
+
+/// Lines of the template following the copyright line.
+///
+/// `header` is the name of a bundled template or, if it spans several lines,
+/// a custom template, e.g. the license preamble required by a legal team.
+pub fn header_template(header: &str) -> Option<&str> {
+    if header.contains('\n') {
+        return Some(header.trim_end_matches('\n'));
+    }
+    // The templates start with a newline to separate them from the copyright line
+    match header {
+        "apache-2.0" => Some(APACHE_2_0),
+        "mit" => Some(MIT),
+        "gpl-3.0-or-later" => Some(GPL_3_0_OR_LATER),
+        "proprietary-short" => Some(PROPRIETARY_SHORT),
+        _ => None,
+    }
+}
+
+/// Substitute the placeholders of a template line.
+pub fn render_line(line_: &str, name: &str, years: &str) -> String {
+    line_.replace("{name}", name).replace("{years}", years)
+}
+
+/// Copyright template of the REUSE specification.
+pub const REUSE_COPYRIGHT_TEMPLATE: &str = "SPDX-FileCopyrightText: {years} {holder}";
+
+/// Part of a copyright template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplatePart<'a> {
+    Text(&'a str),
+    Holder,
+    Years,
+    Filename,
+    License,
+}
+
+/// Placeholders of copyright templates.
+const PLACEHOLDERS: [(&str, TemplatePart<'static>); 4] = [
+    ("{holder}", TemplatePart::Holder),
+    ("{years}", TemplatePart::Years),
+    ("{filename}", TemplatePart::Filename),
+    ("{license}", TemplatePart::License),
+];
+
+/// Split a copyright template into text and placeholders.
+///
+/// Braces which do not start a placeholder are kept as text.
+pub fn parse_copyright_template(template: &str) -> Vec<TemplatePart<'_>> {
+    let mut parts = Vec::new();
+    let mut text_start = 0;
+    let mut pos = 0;
+    while let Some(offset) = template[pos..].find('{') {
+        let start = pos + offset;
+        match PLACEHOLDERS
+            .iter()
+            .find(|(placeholder, _)| template[start..].starts_with(placeholder))
+        {
+            Some((placeholder, part)) => {
+                if text_start < start {
+                    parts.push(TemplatePart::Text(&template[text_start..start]));
+                }
+                parts.push(*part);
+                pos = start + placeholder.len();
+                text_start = pos;
+            }
+            None => pos = start + 1,
+        }
+    }
+    if text_start < template.len() {
+        parts.push(TemplatePart::Text(&template[text_start..]));
+    }
+    parts
+}
+
+/// Verify that the header is the name of a bundled template or a custom
+/// template spanning several lines.
+pub fn validate_header(header: &str) -> Result<(), CError> {
+    match header_template(header) {
+        Some(_) => Ok(()),
+        None => Err(CError::ConfigError(format!(
+            "Unknown header template {}, expected one of {}",
+            header,
+            TEMPLATE_NAMES.join(", ")
+        ))),
+    }
+}
+
+/// Verify that the copyright template contains `{years}` exactly once, since
+/// the years are replaced in existing copyrights.
+pub fn validate_copyright_template(template: &str) -> Result<(), CError> {
+    let num_years = parse_copyright_template(template)
+        .into_iter()
+        .filter(|part| *part == TemplatePart::Years)
+        .count();
+    match num_years {
+        1 => Ok(()),
+        _ => Err(CError::ConfigError(format!(
+            "Copyright template {} must contain {{years}} exactly once",
+            template
+        ))),
+    }
+}
+
+/// Substitute the placeholders of a copyright template.
+pub fn render_copyright(
+    template: &str,
+    holder: &str,
+    years: &str,
+    filename: &str,
+    license: &str,
+) -> String {
+    parse_copyright_template(template)
+        .into_iter()
+        .map(|part| match part {
+            TemplatePart::Text(text) => text,
+            TemplatePart::Holder => holder,
+            TemplatePart::Years => years,
+            TemplatePart::Filename => filename,
+            TemplatePart::License => license,
+        })
+        .collect()
+}
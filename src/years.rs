@@ -0,0 +1,242 @@
+//! Sources of the years in copyrights.
+//!
+//! By default, the years of a file span its first to its last commit. Other
+//! sources like an asset database, a CSV export or the metadata service of a
+//! monorepo implement `YearProvider` and are passed to
+//! `check_repo_copyright_with_years`, which reuses the matching and rewriting
+//! of copyrights. `min_year` and `fixed_years` policies of the config apply on
+//! top of any source.
+
+use crate::config::{GitBackend, YearFormat, YearSource, YearTimezone};
+use crate::git_ops::{get_added_mod_times_for_file, get_author_dates_for_file};
+use crate::git_ops::{get_blame_years_for_file, LogOptions};
+use crate::git_ops::{modified_since, years_from_commit_dates, HistoryCutoffs, IgnoredRevs};
+use crate::CError;
+use regex::Regex;
+use std::collections::HashMap;
+use std::future::Future;
+
+/// Source of the years of files.
+pub trait YearProvider: Sync {
+    /// Years of the file given relative to the repository root, e.g.
+    /// `2020-2022` or `2022`.
+    fn years(&self, filepath: &str) -> impl Future<Output = Result<String, CError>> + Send;
+}
+
+/// Years from the git history of the files.
+///
+/// Commits before the cutoff of a file's directory are not taken into
+/// account. With `mtime_fallback`, files without history get the years of
+/// their timestamps. Years are written as range of committer dates of all
+/// commits unless set otherwise with `with_year_format`, `with_year_source`,
+/// `with_log_options` and `with_ignored_revs`.
+#[derive(Debug, Default)]
+pub struct GitYears {
+    repo_path: String,
+    history_cutoffs: HistoryCutoffs,
+    mtime_fallback: bool,
+    year_format: YearFormat,
+    year_source: YearSource,
+    log_options: LogOptions,
+    /// Author filter of the log options, compiled once for blaming files.
+    author_regex: Option<Regex>,
+    ignored_revs: IgnoredRevs,
+}
+
+impl GitYears {
+    pub fn new(repo_path: &str, history_cutoffs: HistoryCutoffs, mtime_fallback: bool) -> Self {
+        GitYears {
+            repo_path: repo_path.to_owned(),
+            history_cutoffs,
+            mtime_fallback,
+            year_format: YearFormat::Range,
+            year_source: YearSource::Log,
+            log_options: LogOptions::default(),
+            author_regex: None,
+            ignored_revs: IgnoredRevs::default(),
+        }
+    }
+
+    pub fn with_year_format(mut self, year_format: YearFormat) -> Self {
+        self.year_format = year_format;
+        self
+    }
+
+    /// Take the years from the commits of the remaining lines of files.
+    pub fn with_year_source(mut self, year_source: YearSource) -> Self {
+        self.year_source = year_source;
+        self
+    }
+
+    pub fn with_log_options(mut self, log_options: LogOptions) -> Self {
+        self.author_regex = log_options.author_regex().unwrap_or_else(|e| {
+            log::warn!("Not filtering authors: {}", e);
+            None
+        });
+        self.log_options = log_options;
+        self
+    }
+
+    /// Skip the commits when computing years, e.g. bulk reformatting.
+    pub fn with_ignored_revs(mut self, ignored_revs: IgnoredRevs) -> Self {
+        self.ignored_revs = ignored_revs;
+        self
+    }
+
+    /// Authors of the file with at least `min_commits` commits since its
+    /// cutoff and the years of their commits, ordered by their first commit.
+    pub async fn author_years(
+        &self,
+        filepath: &str,
+        min_commits: usize,
+    ) -> Result<Vec<(String, String)>, CError> {
+        let cutoff = self.history_cutoffs.cutoff_for(filepath);
+        let authors = get_author_dates_for_file(
+            filepath,
+            &self.repo_path,
+            &self.log_options,
+            &self.ignored_revs,
+        )
+        .await?;
+        Ok(authors
+            .into_iter()
+            .filter_map(|(author, dates)| {
+                let dates: Vec<String> = dates
+                    .into_iter()
+                    .filter(|date| cutoff.is_none_or(|cutoff| modified_since(date, cutoff)))
+                    .collect();
+                (dates.len() >= min_commits.max(1)).then(|| {
+                    let years = years_from_commit_dates(
+                        dates.iter().map(String::as_str),
+                        filepath,
+                        &self.repo_path,
+                        None,
+                        false,
+                        self.year_format,
+                        self.log_options.timezone,
+                    );
+                    (author, years)
+                })
+            })
+            .collect())
+    }
+}
+
+impl YearProvider for GitYears {
+    async fn years(&self, filepath: &str) -> Result<String, CError> {
+        let cutoff = self.history_cutoffs.cutoff_for(filepath);
+        Ok(match self.year_source {
+            YearSource::Log => {
+                get_added_mod_times_for_file(
+                    filepath,
+                    &self.repo_path,
+                    cutoff,
+                    self.mtime_fallback,
+                    self.year_format,
+                    &self.log_options,
+                    &self.ignored_revs,
+                )
+                .await
+            }
+            YearSource::Blame => {
+                get_blame_years_for_file(
+                    filepath,
+                    &self.repo_path,
+                    cutoff,
+                    self.mtime_fallback,
+                    self.year_format,
+                    &self.log_options,
+                    self.author_regex.as_ref(),
+                    &self.ignored_revs,
+                )
+                .await?
+            }
+        })
+    }
+}
+
+/// Years from the git history of all files, traversed once up front.
+///
+/// Yields the same years as `GitYears` without spawning a git process per
+/// file, which dominates the runtime in repositories with many files.
+#[derive(Debug, Default)]
+pub struct BatchedGitYears {
+    repo_path: String,
+    commit_dates: HashMap<String, Vec<String>>,
+    history_cutoffs: HistoryCutoffs,
+    mtime_fallback: bool,
+    year_format: YearFormat,
+    timezone: YearTimezone,
+}
+
+impl BatchedGitYears {
+    /// Traverse the history of the repository with the log options, skipping
+    /// `ignored_revs`.
+    pub async fn load(
+        repo_path: &str,
+        backend: GitBackend,
+        log_options: &LogOptions,
+        ignored_revs: &IgnoredRevs,
+        history_cutoffs: HistoryCutoffs,
+        mtime_fallback: bool,
+    ) -> Result<Self, CError> {
+        Ok(BatchedGitYears {
+            repo_path: repo_path.to_owned(),
+            commit_dates: backend
+                .get_commit_dates_by_file(repo_path, log_options, ignored_revs)
+                .await?,
+            history_cutoffs,
+            mtime_fallback,
+            year_format: YearFormat::Range,
+            timezone: log_options.timezone,
+        })
+    }
+
+    pub fn with_year_format(mut self, year_format: YearFormat) -> Self {
+        self.year_format = year_format;
+        self
+    }
+}
+
+impl YearProvider for BatchedGitYears {
+    async fn years(&self, filepath: &str) -> Result<String, CError> {
+        let commit_dates = self.commit_dates.get(filepath).into_iter().flatten();
+        Ok(years_from_commit_dates(
+            commit_dates.map(String::as_str),
+            filepath,
+            &self.repo_path,
+            self.history_cutoffs.cutoff_for(filepath),
+            self.mtime_fallback,
+            self.year_format,
+            self.timezone,
+        ))
+    }
+}
+
+/// Years looked up by the path of the file, e.g. loaded from an export.
+impl YearProvider for HashMap<String, String> {
+    async fn years(&self, filepath: &str) -> Result<String, CError> {
+        self.get(filepath)
+            .cloned()
+            .ok_or_else(|| CError::NoYears(filepath.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::YearProvider;
+    use crate::CError;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_years_table() {
+        let table = HashMap::from([("src/main.rs".to_owned(), "2019-2021".to_owned())]);
+        let years = futures::executor::block_on(table.years("src/main.rs")).unwrap();
+        assert_eq!(years, "2019-2021");
+        assert!(matches!(
+            futures::executor::block_on(table.years("src/lib.rs")),
+            Err(CError::NoYears(_))
+        ));
+    }
+}